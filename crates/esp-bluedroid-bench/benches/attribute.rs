@@ -0,0 +1,78 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use esp_bluedroid_bench::{
+    Attribute,
+    defaults::{BytesAttr, StringAttr, U32Attr},
+    reassembly::WriteReassembler,
+    telemetry::DateTimeAttr,
+};
+use std::hint::black_box;
+
+// Same as ESP_GATT_MAX_ATTR_LEN
+const MAX_ATTR_LEN: usize = 517;
+
+fn codec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("attribute_codec");
+
+    let value = U32Attr(0xDEADBEEF).get_bytes().unwrap();
+    group.bench_function("u32_from_bytes", |b| {
+        b.iter(|| U32Attr::from_bytes(black_box(&value)))
+    });
+
+    let value = DateTimeAttr {
+        year: 2025,
+        month: 6,
+        day: 1,
+        hours: 12,
+        minutes: 30,
+        seconds: 0,
+    }
+    .get_bytes()
+    .unwrap();
+    group.bench_function("date_time_from_bytes", |b| {
+        b.iter(|| DateTimeAttr::from_bytes(black_box(&value)))
+    });
+
+    let value = StringAttr("x".repeat(MAX_ATTR_LEN)).get_bytes().unwrap();
+    group.bench_function("string_from_bytes", |b| {
+        b.iter(|| StringAttr::from_bytes(black_box(&value)))
+    });
+
+    let value = BytesAttr(vec![0xA5; MAX_ATTR_LEN]);
+    group.bench_function("bytes_get_bytes", |b| {
+        b.iter(|| black_box(&value).get_bytes())
+    });
+
+    group.finish();
+}
+
+fn prepare_write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prepare_write");
+    group.throughput(Throughput::Bytes(MAX_ATTR_LEN as u64));
+
+    // Fragment sizes matching default and maximum negotiated MTU
+    for fragment_len in [18, 509] {
+        let value = vec![0x5A; MAX_ATTR_LEN];
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(fragment_len),
+            &fragment_len,
+            |b, fragment_len| {
+                b.iter(|| {
+                    let mut reassembler = WriteReassembler::new();
+                    for (index, fragment) in value.chunks(*fragment_len).enumerate() {
+                        reassembler
+                            .write((index * fragment_len) as u16, fragment, MAX_ATTR_LEN)
+                            .unwrap();
+                    }
+
+                    reassembler.take()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, codec, prepare_write);
+criterion_main!(benches);
@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    mem::{Discriminant, discriminant},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use crossbeam_channel::{Sender, bounded, unbounded};
+
+// Reduced copy of GattsEvent, routing only depends on the discriminant
+#[allow(dead_code)]
+enum Event {
+    Read { handle: u16, offset: u16 },
+    Write { handle: u16, value: Vec<u8> },
+    Mtu { mtu: u16 },
+}
+
+type EventsMap = RwLock<HashMap<Discriminant<Event>, Sender<Event>>>;
+
+// Same path as the callback registered in Gatts::init_callback
+fn route(events: &EventsMap, event: Event) {
+    let events = events.read().unwrap();
+    if let Some(sender) = events.get(&discriminant(&event)) {
+        sender.send(event).unwrap();
+    }
+}
+
+fn event_dispatch(c: &mut Criterion) {
+    let events: EventsMap = Default::default();
+    let (tx, rx) = unbounded();
+    let (ack_tx, ack_rx) = bounded(1);
+
+    for event in [
+        Event::Read {
+            handle: 0,
+            offset: 0,
+        },
+        Event::Write {
+            handle: 0,
+            value: vec![],
+        },
+        Event::Mtu { mtu: 0 },
+    ] {
+        events
+            .write()
+            .unwrap()
+            .insert(discriminant(&event), tx.clone());
+    }
+
+    // Stands in for the global events thread spawned by Gatts::configure_global_events
+    let handler = thread::spawn(move || {
+        for event in rx.iter() {
+            ack_tx.send(black_box(event)).unwrap();
+        }
+    });
+
+    c.bench_function("callback_to_handler", |b| {
+        b.iter(|| {
+            route(
+                &events,
+                Event::Write {
+                    handle: 42,
+                    value: vec![0; 20],
+                },
+            );
+            ack_rx.recv().unwrap()
+        })
+    });
+
+    events.write().unwrap().clear();
+    drop(tx);
+    handler.join().unwrap();
+}
+
+fn registry_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("attribute_registry");
+
+    for readers in [0, 1, 4] {
+        let registry: Arc<RwLock<HashMap<u16, Arc<Vec<u8>>>>> = Arc::new(RwLock::new(
+            (0..64)
+                .map(|handle| (handle, Arc::new(vec![0; 20])))
+                .collect(),
+        ));
+        let running = Arc::new(AtomicBool::new(true));
+
+        // Readers model concurrent Read events and application reads of the same table
+        let threads = (0..readers)
+            .map(|_| {
+                let registry = registry.clone();
+                let running = running.clone();
+
+                thread::spawn(move || {
+                    while running.load(Ordering::Relaxed) {
+                        black_box(registry.read().unwrap().get(&42).cloned());
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        group.bench_with_input(BenchmarkId::new("lookup", readers), &readers, |b, _| {
+            b.iter(|| registry.read().unwrap().get(black_box(&42)).cloned())
+        });
+        group.bench_with_input(BenchmarkId::new("insert", readers), &readers, |b, _| {
+            b.iter(|| {
+                registry
+                    .write()
+                    .unwrap()
+                    .insert(black_box(42), Arc::new(vec![0; 20]))
+            })
+        });
+
+        running.store(false, Ordering::Relaxed);
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, event_dispatch, registry_contention);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Host-side benchmarks for `esp-bluedroid`.
+//!
+//! The device crate depends on esp-idf and cannot be built for the host, so the
+//! modules without esp-idf dependencies are included directly. Run with
+//! `cargo bench --target x86_64-unknown-linux-gnu` from this directory, the
+//! unit and property tests of the included modules with `cargo test` and the
+//! same target.
+
+pub trait Attribute {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>>;
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized;
+
+    fn value_schema(&self) -> (schema::ValueFormat, schema::ValueSchema) {
+        (schema::ValueFormat::Raw, schema::ValueSchema::Bytes)
+    }
+}
+
+// Resolves `crate::gatts::attribute::Attribute` used by the included modules
+pub mod gatts {
+    pub mod attribute {
+        pub use crate::{Attribute, derive, encoding};
+    }
+
+    pub use crate::schema;
+}
+
+#[path = "../../../src/gatts/checksum.rs"]
+pub mod checksum;
+
+#[path = "../../../src/gatts/chunked.rs"]
+pub mod chunked;
+
+#[path = "../../../src/gatts/attribute/defaults.rs"]
+pub mod defaults;
+
+#[path = "../../../src/gatts/attribute/derive.rs"]
+pub mod derive;
+
+// Only the encoding of derived attributes is used, not the serde blanket one
+#[allow(dead_code)]
+#[path = "../../../src/gatts/attribute/encoding.rs"]
+pub mod encoding;
+
+// Startup warnings of the device crate are not used on the host
+#[allow(dead_code)]
+#[path = "../../../src/guard.rs"]
+pub mod guard;
+
+#[path = "../../../src/lock.rs"]
+pub mod lock;
+
+#[path = "../../../src/gatts/reassembly.rs"]
+pub mod reassembly;
+
+#[path = "../../../src/gatts/schema.rs"]
+pub mod schema;
+
+#[path = "../../../src/gatts/attribute/telemetry.rs"]
+pub mod telemetry;
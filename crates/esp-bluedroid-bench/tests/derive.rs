@@ -0,0 +1,182 @@
+//! Expansion of `#[derive(Attribute)]` against the support code of the device
+//! crate, which the generated implementations reach as `::esp_bluedroid`
+
+extern crate esp_bluedroid_bench as esp_bluedroid;
+
+use esp_bluedroid::{
+    Attribute,
+    schema::{FieldSchema, ValueFormat, ValueSchema},
+};
+use esp_bluedroid_derive::Attribute;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Attribute)]
+#[attribute(endian = "big")]
+struct Measurement {
+    sequence: u16,
+    #[attribute(endian = "little")]
+    value: i32,
+    flags: [u8; 2],
+    valid: bool,
+}
+
+#[derive(Debug, PartialEq, Attribute)]
+struct Message {
+    kind: u8,
+    text: String,
+}
+
+#[derive(Debug, PartialEq, Attribute)]
+struct Blob(u16, Vec<u8>);
+
+#[derive(Debug, PartialEq, Attribute)]
+struct Point(i16, i16);
+
+#[derive(Debug, PartialEq, Attribute)]
+struct Ping;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Limits {
+    min: u16,
+    max: u16,
+}
+
+#[derive(Debug, PartialEq, Attribute)]
+#[attribute(encoding = "bincode", int = "variable")]
+struct Settings {
+    name: String,
+    limits: Limits,
+    timeout: Option<u32>,
+    #[attribute(endian = "big")]
+    id: u32,
+}
+
+#[derive(Debug, PartialEq, Attribute)]
+#[attribute(endian = "big")]
+struct Reading {
+    header: Point,
+    value: u16,
+}
+
+fn round_trip<T: Attribute + PartialEq + std::fmt::Debug>(value: T, bytes: &[u8]) {
+    assert_eq!(value.get_bytes().unwrap(), bytes);
+    assert_eq!(T::from_bytes(bytes).unwrap(), value);
+}
+
+#[test]
+fn packed_struct() {
+    let measurement = Measurement {
+        sequence: 0x0102,
+        value: -2,
+        flags: [0xaa, 0xbb],
+        valid: true,
+    };
+
+    round_trip(
+        measurement,
+        &[0x01, 0x02, 0xfe, 0xff, 0xff, 0xff, 0xaa, 0xbb, 0x01],
+    );
+}
+
+#[test]
+fn packed_rejects_wrong_lengths() {
+    let bytes = [0x01, 0x02, 0xfe, 0xff, 0xff, 0xff, 0xaa, 0xbb, 0x01];
+
+    assert!(Measurement::from_bytes(&bytes[..8]).is_err());
+    assert!(Measurement::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+    assert!(Measurement::from_bytes(&[&bytes[..8], &[2]].concat()).is_err());
+}
+
+#[test]
+fn packed_trailing_rest() {
+    round_trip(
+        Message {
+            kind: 7,
+            text: "hi".into(),
+        },
+        &[7, b'h', b'i'],
+    );
+    round_trip(Blob(0x0201, vec![]), &[0x01, 0x02]);
+    round_trip(Blob(0x0201, vec![3, 4, 5]), &[0x01, 0x02, 3, 4, 5]);
+
+    assert!(Message::from_bytes(&[7, 0xff]).is_err());
+}
+
+#[test]
+fn tuple_struct() {
+    round_trip(Point(-1, 0x0102), &[0xff, 0xff, 0x02, 0x01]);
+}
+
+#[test]
+fn unit_struct() {
+    round_trip(Ping, &[]);
+
+    assert!(Ping::from_bytes(&[0]).is_err());
+}
+
+#[test]
+fn bincode_struct() {
+    let settings = Settings {
+        name: "fan".into(),
+        limits: Limits { min: 1, max: 300 },
+        timeout: Some(5),
+        id: 0x01020304,
+    };
+
+    round_trip(
+        settings,
+        &[
+            3, b'f', b'a', b'n', // varint length prefixed string
+            1, 251, 0x2c, 0x01, // varint numbers, 300 takes a marker and two bytes
+            1, 5, // Some(5)
+            252, 0x01, 0x02, 0x03, 0x04, // u32 marker, big endian override
+        ],
+    );
+}
+
+#[test]
+fn nested_struct_keeps_own_endian() {
+    // Point is little endian, the big endian of Reading does not apply to it
+    round_trip(
+        Reading {
+            header: Point(1, 2),
+            value: 3,
+        },
+        &[0x01, 0x00, 0x02, 0x00, 0x00, 0x03],
+    );
+}
+
+#[test]
+fn schema() {
+    assert_eq!(
+        Point(0, 0).value_schema(),
+        (
+            ValueFormat::Raw,
+            ValueSchema::Struct {
+                name: "Point".into(),
+                fields: vec![
+                    FieldSchema {
+                        name: "0".into(),
+                        value: ValueSchema::I16,
+                    },
+                    FieldSchema {
+                        name: "1".into(),
+                        value: ValueSchema::I16,
+                    },
+                ],
+            }
+        )
+    );
+
+    // Big endian and bincode layouts are exported as plain bytes
+    let measurement = Measurement::from_bytes(&[0; 9]).unwrap();
+    assert_eq!(
+        measurement.value_schema(),
+        (ValueFormat::Raw, ValueSchema::Bytes)
+    );
+}
+
+#[test]
+fn compile_errors() {
+    trybuild::TestCases::new().compile_fail("tests/derive/*.rs");
+}
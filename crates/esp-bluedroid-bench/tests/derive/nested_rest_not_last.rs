@@ -0,0 +1,17 @@
+extern crate esp_bluedroid_bench as esp_bluedroid;
+
+use esp_bluedroid_derive::Attribute;
+
+#[derive(Attribute)]
+struct Trailer {
+    kind: u8,
+    payload: Vec<u8>,
+}
+
+#[derive(Attribute)]
+struct Frame {
+    trailer: Trailer,
+    crc: u32,
+}
+
+fn main() {}
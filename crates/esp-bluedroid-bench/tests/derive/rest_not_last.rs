@@ -0,0 +1,14 @@
+extern crate esp_bluedroid_bench as esp_bluedroid;
+
+use esp_bluedroid_derive::Attribute;
+
+#[derive(Attribute)]
+struct Frame {
+    payload: Vec<u8>,
+    crc: u32,
+}
+
+#[derive(Attribute)]
+struct Label(String, u8);
+
+fn main() {}
@@ -0,0 +1,183 @@
+//! Property tests of the esp-idf free framing modules, chunked transfers and
+//! reassembly of prepared writes
+
+use esp_bluedroid_bench::{
+    chunked::{self, ChunkReassembler, START_HEADER_LEN},
+    reassembly::{PrepareQueues, ReassemblyError, WriteReassembler},
+};
+use proptest::prelude::*;
+
+const MAX_LEN: usize = 2048;
+
+fn push_all<'a>(
+    reassembler: &mut ChunkReassembler,
+    frames: impl IntoIterator<Item = &'a Vec<u8>>,
+) -> Vec<Vec<u8>> {
+    frames
+        .into_iter()
+        .filter_map(|frame| reassembler.push(frame).ok().flatten())
+        .collect()
+}
+
+// Offsets splitting `len` bytes into consecutive fragments
+fn cuts(len: usize, cuts: Vec<prop::sample::Index>) -> Vec<usize> {
+    let mut cuts = cuts
+        .into_iter()
+        .map(|cut| cut.index(len + 1))
+        .chain([0, len])
+        .collect::<Vec<_>>();
+    cuts.sort_unstable();
+    cuts.dedup();
+
+    cuts
+}
+
+proptest! {
+    #[test]
+    fn chunked_round_trip(
+        value in prop::collection::vec(any::<u8>(), 0..MAX_LEN),
+        transfer in any::<u8>(),
+        frame_len in START_HEADER_LEN + 1..600,
+    ) {
+        let frames = chunked::fragments(&value, transfer, frame_len).unwrap();
+        prop_assert!(frames.iter().all(|frame| frame.len() <= frame_len));
+
+        let mut reassembler = ChunkReassembler::new(MAX_LEN);
+        prop_assert_eq!(push_all(&mut reassembler, &frames), vec![value]);
+    }
+
+    #[test]
+    fn chunked_lost_frame_yields_nothing(
+        value in prop::collection::vec(any::<u8>(), 1..MAX_LEN),
+        frame_len in START_HEADER_LEN + 1..100,
+        lost in any::<prop::sample::Index>(),
+    ) {
+        let mut frames = chunked::fragments(&value, 0, frame_len).unwrap();
+        prop_assume!(frames.len() > 1);
+        frames.remove(lost.index(frames.len()));
+
+        let mut reassembler = ChunkReassembler::new(MAX_LEN);
+        prop_assert!(push_all(&mut reassembler, &frames).is_empty());
+    }
+
+    #[test]
+    fn chunked_reordered_frames_yield_nothing(
+        value in prop::collection::vec(any::<u8>(), 1..MAX_LEN),
+        frame_len in START_HEADER_LEN + 1..100,
+        first in any::<prop::sample::Index>(),
+        second in any::<prop::sample::Index>(),
+    ) {
+        let mut frames = chunked::fragments(&value, 0, frame_len).unwrap();
+        let (first, second) = (first.index(frames.len()), second.index(frames.len()));
+        prop_assume!(first != second);
+        frames.swap(first, second);
+
+        let mut reassembler = ChunkReassembler::new(MAX_LEN);
+        prop_assert!(push_all(&mut reassembler, &frames).is_empty());
+    }
+
+    #[test]
+    fn chunked_recovers_after_failed_transfer(
+        failed in prop::collection::vec(any::<u8>(), 0..MAX_LEN),
+        value in prop::collection::vec(any::<u8>(), 0..MAX_LEN),
+        frame_len in START_HEADER_LEN + 1..100,
+        cut in any::<prop::sample::Index>(),
+    ) {
+        let failed = chunked::fragments(&failed, 0, frame_len).unwrap();
+        let frames = chunked::fragments(&value, 1, frame_len).unwrap();
+
+        let mut reassembler = ChunkReassembler::new(MAX_LEN);
+        push_all(&mut reassembler, &failed[..cut.index(failed.len())]);
+        prop_assert_eq!(push_all(&mut reassembler, &frames), vec![value]);
+    }
+
+    #[test]
+    fn reassembly_round_trip(
+        value in prop::collection::vec(any::<u8>(), 0..MAX_LEN),
+        split in prop::collection::vec(any::<prop::sample::Index>(), 0..16),
+    ) {
+        let cuts = cuts(value.len(), split);
+
+        let mut reassembler = WriteReassembler::new();
+        for window in cuts.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            reassembler.write(start as u16, &value[start..end], MAX_LEN).unwrap();
+        }
+
+        prop_assert_eq!(reassembler.take(), value);
+    }
+
+    #[test]
+    fn reassembly_rewrites_keep_value(
+        value in prop::collection::vec(any::<u8>(), 1..MAX_LEN),
+        start in any::<prop::sample::Index>(),
+        len in any::<prop::sample::Index>(),
+    ) {
+        let start = start.index(value.len());
+        let end = start + len.index(value.len() - start + 1);
+
+        let mut reassembler = WriteReassembler::new();
+        reassembler.write(0, &value, MAX_LEN).unwrap();
+        reassembler.write(start as u16, &value[start..end], MAX_LEN).unwrap();
+
+        prop_assert_eq!(reassembler.value(), &value[..]);
+    }
+
+    #[test]
+    fn reassembly_rejects_gaps_and_overflow(
+        value in prop::collection::vec(any::<u8>(), 0..MAX_LEN),
+        gap in 1..64u16,
+        fragment in prop::collection::vec(any::<u8>(), 1..64),
+    ) {
+        let mut reassembler = WriteReassembler::new();
+        reassembler.write(0, &value, MAX_LEN).unwrap();
+
+        prop_assert_eq!(
+            reassembler.write(value.len() as u16 + gap, &fragment, MAX_LEN),
+            Err(ReassemblyError::InvalidOffset)
+        );
+        prop_assert_eq!(
+            reassembler.write(value.len() as u16, &fragment, value.len()),
+            Err(ReassemblyError::InvalidLength)
+        );
+        prop_assert_eq!(reassembler.value(), &value[..]);
+    }
+}
+
+#[test]
+fn prepare_writes_execute_per_connection() {
+    let mut queues = PrepareQueues::<u16, u16>::default();
+
+    // Each prepare write and the execute write come in their own transaction,
+    // two connections write the same attribute at the same time
+    assert_eq!(queues.prepare(1, 42, 0, b"hello ", MAX_LEN), Ok(6));
+    assert_eq!(queues.prepare(2, 42, 0, b"other", MAX_LEN), Ok(5));
+    assert_eq!(queues.prepare(1, 42, 6, b"long ", MAX_LEN), Ok(11));
+    assert_eq!(queues.prepare(1, 42, 11, b"write", MAX_LEN), Ok(16));
+
+    let mut prepared = queues.take(&1).unwrap();
+    assert_eq!(prepared.handle, 42);
+    assert_eq!(prepared.value.take(), b"hello long write");
+    assert!(queues.take(&1).is_none());
+
+    assert_eq!(queues.take(&2).unwrap().value.take(), b"other");
+}
+
+#[test]
+fn prepare_write_errors_discard_queue() {
+    let mut queues = PrepareQueues::<u16, u16>::default();
+
+    queues.prepare(1, 42, 0, b"value", MAX_LEN).unwrap();
+    assert_eq!(
+        queues.prepare(1, 43, 0, b"value", MAX_LEN),
+        Err(ReassemblyError::OtherAttribute)
+    );
+    assert!(queues.take(&1).is_none());
+
+    queues.prepare(1, 42, 0, b"value", MAX_LEN).unwrap();
+    assert_eq!(
+        queues.prepare(1, 42, 10, b"gap", MAX_LEN),
+        Err(ReassemblyError::InvalidOffset)
+    );
+    assert!(queues.take(&1).is_none());
+}
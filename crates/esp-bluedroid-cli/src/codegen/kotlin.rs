@@ -0,0 +1,613 @@
+use std::fmt::Write;
+
+use super::{Accessor, NamedType, accessors, camel_case, named_types, pascal_case};
+use crate::schema::{GattSchema, ValueFormat, ValueSchema};
+
+const KEYWORDS: &[&str] = &[
+    "as",
+    "break",
+    "class",
+    "continue",
+    "do",
+    "else",
+    "false",
+    "for",
+    "fun",
+    "if",
+    "in",
+    "interface",
+    "is",
+    "null",
+    "object",
+    "package",
+    "return",
+    "super",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typealias",
+    "typeof",
+    "val",
+    "var",
+    "when",
+    "while",
+];
+
+const RUNTIME: &str = r#"class Reader(
+    private val data: ByteArray,
+    private val fixedInt: Boolean,
+    private val bigEndian: Boolean,
+) {
+    private var offset = 0
+
+    private fun take(len: Int): ByteArray {
+        if (offset + len > data.size) {
+            throw IllegalArgumentException("Unexpected end of value")
+        }
+        val bytes = data.copyOfRange(offset, offset + len)
+        offset += len
+        return bytes
+    }
+
+    private fun fixed(len: Int): ULong {
+        val bytes = take(len)
+        var value = 0UL
+        for (i in 0 until len) {
+            value = (value shl 8) or bytes[if (bigEndian) i else len - 1 - i].toUByte().toULong()
+        }
+        return value
+    }
+
+    private fun unsigned(len: Int): ULong {
+        if (fixedInt) {
+            return fixed(len)
+        }
+        val tag = u8().toInt()
+        if (tag < 251) {
+            return tag.toULong()
+        }
+        val width = when (tag) {
+            251 -> 2
+            252 -> 4
+            253 -> 8
+            else -> 0
+        }
+        if (width == 0 || width > len) {
+            throw IllegalArgumentException("Invalid integer tag $tag")
+        }
+        return fixed(width)
+    }
+
+    private fun signed(len: Int): Long {
+        val value = unsigned(len)
+        if (fixedInt) {
+            val shift = 64 - len * 8
+            return (value.toLong() shl shift) shr shift
+        }
+        return (value shr 1).toLong() xor -(value and 1UL).toLong()
+    }
+
+    fun bool(): Boolean = u8().toInt() != 0
+
+    fun u8(): UByte = take(1)[0].toUByte()
+
+    fun u16(): UShort = unsigned(2).toUShort()
+
+    fun u32(): UInt = unsigned(4).toUInt()
+
+    fun u64(): ULong = unsigned(8)
+
+    fun i8(): Byte = take(1)[0]
+
+    fun i16(): Short = signed(2).toShort()
+
+    fun i32(): Int = signed(4).toInt()
+
+    fun i64(): Long = signed(8)
+
+    fun f32(): Float = Float.fromBits(fixed(4).toInt())
+
+    fun f64(): Double = Double.fromBits(fixed(8).toLong())
+
+    fun len(): Int = unsigned(8).toInt()
+
+    fun variant(): Int = u32().toInt()
+
+    fun bytes(): ByteArray = take(len())
+
+    fun string(): String = bytes().decodeToString()
+
+    fun rest(): ByteArray = take(data.size - offset)
+
+    fun restString(): String = rest().decodeToString()
+
+    fun <T> option(read: () -> T): T? = if (u8().toInt() == 1) read() else null
+
+    fun <T> seq(read: () -> T): List<T> = List(len()) { read() }
+
+    fun <K, V> map(readKey: () -> K, readValue: () -> V): Map<K, V> {
+        val items = LinkedHashMap<K, V>()
+        repeat(len()) {
+            val key = readKey()
+            items[key] = readValue()
+        }
+        return items
+    }
+
+    fun unknown(): Nothing = throw IllegalArgumentException("Type of this value is not known from the schema")
+}
+
+class Writer(
+    private val fixedInt: Boolean,
+    private val bigEndian: Boolean,
+) {
+    private val buffer = java.io.ByteArrayOutputStream()
+
+    private fun fixed(value: ULong, len: Int) {
+        val bytes = ByteArray(len) { i -> (value shr (8 * i)).toByte() }
+        if (bigEndian) {
+            bytes.reverse()
+        }
+        buffer.write(bytes)
+    }
+
+    private fun unsigned(value: ULong, len: Int) {
+        when {
+            fixedInt -> fixed(value, len)
+            value < 251UL -> buffer.write(value.toInt())
+            value <= 0xffffUL -> {
+                buffer.write(251)
+                fixed(value, 2)
+            }
+            value <= 0xffffffffUL -> {
+                buffer.write(252)
+                fixed(value, 4)
+            }
+            else -> {
+                buffer.write(253)
+                fixed(value, 8)
+            }
+        }
+    }
+
+    private fun signed(value: Long, len: Int) {
+        if (fixedInt) {
+            fixed(value.toULong(), len)
+        } else {
+            unsigned(((value shl 1) xor (value shr 63)).toULong(), len)
+        }
+    }
+
+    fun bool(value: Boolean) = buffer.write(if (value) 1 else 0)
+
+    fun u8(value: UByte) = buffer.write(value.toInt())
+
+    fun u16(value: UShort) = unsigned(value.toULong(), 2)
+
+    fun u32(value: UInt) = unsigned(value.toULong(), 4)
+
+    fun u64(value: ULong) = unsigned(value, 8)
+
+    fun i8(value: Byte) = buffer.write(value.toInt())
+
+    fun i16(value: Short) = signed(value.toLong(), 2)
+
+    fun i32(value: Int) = signed(value.toLong(), 4)
+
+    fun i64(value: Long) = signed(value, 8)
+
+    fun f32(value: Float) = fixed(value.toRawBits().toUInt().toULong(), 4)
+
+    fun f64(value: Double) = fixed(value.toRawBits().toULong(), 8)
+
+    fun len(value: Int) = unsigned(value.toULong(), 8)
+
+    fun variant(value: Int) = u32(value.toUInt())
+
+    fun bytes(value: ByteArray) {
+        len(value.size)
+        buffer.write(value)
+    }
+
+    fun string(value: String) = bytes(value.encodeToByteArray())
+
+    fun rest(value: ByteArray) = buffer.write(value)
+
+    fun restString(value: String) = rest(value.encodeToByteArray())
+
+    fun <T> option(value: T?, write: (T) -> Unit) {
+        if (value == null) {
+            buffer.write(0)
+        } else {
+            buffer.write(1)
+            write(value)
+        }
+    }
+
+    fun <T> seq(value: List<T>, write: (T) -> Unit) {
+        len(value.size)
+        value.forEach(write)
+    }
+
+    fun <K, V> map(value: Map<K, V>, writeKey: (K) -> Unit, writeValue: (V) -> Unit) {
+        len(value.size)
+        value.forEach { (key, item) ->
+            writeKey(key)
+            writeValue(item)
+        }
+    }
+
+    fun unknown(): Nothing = throw IllegalArgumentException("Type of this value is not known from the schema")
+
+    fun finish(): ByteArray = buffer.toByteArray()
+}
+"#;
+
+/// Generates Kotlin types and codecs, one object with UUIDs per characteristic
+pub fn generate(schema: &GattSchema, package: &str) -> anyhow::Result<String> {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "// Generated by esp-bluedroid-cli from the GATT schema, do not edit.\n"
+    )?;
+    writeln!(out, "package {}\n", package)?;
+    writeln!(out, "const val FIXED_INT = {}", schema.encoding.fixed_int)?;
+    writeln!(
+        out,
+        "const val BIG_ENDIAN = {}\n",
+        schema.encoding.big_endian
+    )?;
+    out.push_str(RUNTIME);
+
+    for named in named_types(schema) {
+        out.push('\n');
+        named_type(&mut out, &named)?;
+    }
+
+    for accessor in accessors(schema) {
+        out.push('\n');
+        characteristic(&mut out, &accessor)?;
+    }
+
+    Ok(out)
+}
+
+fn identifier(name: &str) -> String {
+    let name = camel_case(name);
+
+    match KEYWORDS.contains(&name.as_str()) {
+        true => format!("`{}`", name),
+        false => name,
+    }
+}
+
+fn named_type(out: &mut String, named: &NamedType) -> anyhow::Result<()> {
+    match named {
+        NamedType::Struct { name, fields } if fields.is_empty() => {
+            let name = pascal_case(name);
+
+            writeln!(out, "class {}\n", name)?;
+            writeln!(
+                out,
+                "private fun decode{}(r: Reader): {} = {}()\n",
+                name, name, name
+            )?;
+            writeln!(out, "@Suppress(\"UNUSED_PARAMETER\")")?;
+            writeln!(
+                out,
+                "private fun encode{}(w: Writer, v: {}) {{}}",
+                name, name
+            )?;
+        }
+        NamedType::Struct { name, fields } => {
+            let name = pascal_case(name);
+
+            writeln!(out, "data class {}(", name)?;
+            for field in fields {
+                writeln!(
+                    out,
+                    "    val {}: {},",
+                    identifier(&field.name),
+                    type_name(&field.value)?
+                )?;
+            }
+            writeln!(out, ")\n")?;
+
+            writeln!(
+                out,
+                "private fun decode{}(r: Reader): {} = {}(",
+                name, name, name
+            )?;
+            for field in fields {
+                writeln!(
+                    out,
+                    "    {} = {},",
+                    identifier(&field.name),
+                    read(&field.value, false)?
+                )?;
+            }
+            writeln!(out, ")\n")?;
+
+            writeln!(out, "private fun encode{}(w: Writer, v: {}) {{", name, name)?;
+            for field in fields {
+                let value = format!("v.{}", identifier(&field.name));
+                writeln!(out, "    {}", write(&field.value, &value, false, 0)?)?;
+            }
+            writeln!(out, "}}")?;
+        }
+        NamedType::Enum { name, variants } => {
+            let name = pascal_case(name);
+
+            writeln!(out, "sealed class {} {{", name)?;
+            for variant in variants {
+                match variant.value {
+                    ValueSchema::Unit => writeln!(
+                        out,
+                        "    object {} : {}()",
+                        pascal_case(&variant.name),
+                        name
+                    )?,
+                    _ => writeln!(
+                        out,
+                        "    data class {}(val value: {}) : {}()",
+                        pascal_case(&variant.name),
+                        type_name(&variant.value)?,
+                        name
+                    )?,
+                }
+            }
+            writeln!(out, "}}\n")?;
+
+            writeln!(
+                out,
+                "private fun decode{}(r: Reader): {} = when (val index = r.variant()) {{",
+                name, name
+            )?;
+            for variant in variants {
+                match variant.value {
+                    ValueSchema::Unit => writeln!(
+                        out,
+                        "    {} -> {}.{}",
+                        variant.index,
+                        name,
+                        pascal_case(&variant.name)
+                    )?,
+                    _ => writeln!(
+                        out,
+                        "    {} -> {}.{}({})",
+                        variant.index,
+                        name,
+                        pascal_case(&variant.name),
+                        read(&variant.value, false)?
+                    )?,
+                }
+            }
+            writeln!(
+                out,
+                "    else -> throw IllegalArgumentException(\"Unknown {} variant $index\")",
+                name
+            )?;
+            writeln!(out, "}}\n")?;
+
+            writeln!(out, "private fun encode{}(w: Writer, v: {}) {{", name, name)?;
+            writeln!(out, "    when (v) {{")?;
+            for variant in variants {
+                let variant_name = pascal_case(&variant.name);
+                match variant.value {
+                    ValueSchema::Unit => writeln!(
+                        out,
+                        "        is {}.{} -> w.variant({})",
+                        name, variant_name, variant.index
+                    )?,
+                    _ => {
+                        writeln!(out, "        is {}.{} -> {{", name, variant_name)?;
+                        writeln!(out, "            w.variant({})", variant.index)?;
+                        writeln!(
+                            out,
+                            "            {}",
+                            write(&variant.value, "v.value", false, 0)?
+                        )?;
+                        writeln!(out, "        }}")?;
+                    }
+                }
+            }
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn characteristic(out: &mut String, accessor: &Accessor) -> anyhow::Result<()> {
+    let characteristic = accessor.characteristic;
+    let value_type = type_name(&characteristic.value)?;
+    let raw = characteristic.format == ValueFormat::Raw;
+    let (fixed_int, big_endian) = match raw {
+        true => ("true", "false"),
+        false => ("FIXED_INT", "BIG_ENDIAN"),
+    };
+
+    writeln!(out, "object {}Characteristic {{", accessor.name)?;
+    writeln!(
+        out,
+        "    val SERVICE_UUID: java.util.UUID = java.util.UUID.fromString(\"{}\")",
+        accessor.service_uuid
+    )?;
+    writeln!(
+        out,
+        "    val UUID: java.util.UUID = java.util.UUID.fromString(\"{}\")",
+        characteristic.uuid
+    )?;
+    writeln!(out, "    const val READABLE = {}", characteristic.readable)?;
+    writeln!(out, "    const val WRITABLE = {}", characteristic.writable)?;
+    writeln!(out, "    const val NOTIFY = {}\n", characteristic.notify)?;
+
+    writeln!(out, "    fun decode(bytes: ByteArray): {} {{", value_type)?;
+    writeln!(
+        out,
+        "        val r = Reader(bytes, {}, {})",
+        fixed_int, big_endian
+    )?;
+    writeln!(out, "        return {}", read(&characteristic.value, raw)?)?;
+    writeln!(out, "    }}\n")?;
+
+    writeln!(out, "    fun encode(v: {}): ByteArray {{", value_type)?;
+    writeln!(out, "        val w = Writer({}, {})", fixed_int, big_endian)?;
+    writeln!(
+        out,
+        "        {}",
+        write(&characteristic.value, "v", raw, 0)?
+    )?;
+    writeln!(out, "        return w.finish()")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
+
+fn type_name(value: &ValueSchema) -> anyhow::Result<String> {
+    Ok(match value {
+        ValueSchema::Unit => "Unit".to_string(),
+        ValueSchema::Bool => "Boolean".to_string(),
+        ValueSchema::U8 => "UByte".to_string(),
+        ValueSchema::U16 => "UShort".to_string(),
+        ValueSchema::U32 => "UInt".to_string(),
+        ValueSchema::U64 => "ULong".to_string(),
+        ValueSchema::I8 => "Byte".to_string(),
+        ValueSchema::I16 => "Short".to_string(),
+        ValueSchema::I32 => "Int".to_string(),
+        ValueSchema::I64 => "Long".to_string(),
+        ValueSchema::F32 => "Float".to_string(),
+        ValueSchema::F64 => "Double".to_string(),
+        ValueSchema::String => "String".to_string(),
+        ValueSchema::Bytes => "ByteArray".to_string(),
+        ValueSchema::Option(inner) => format!("{}?", type_name(inner)?),
+        ValueSchema::Seq(inner) => format!("List<{}>", type_name(inner)?),
+        ValueSchema::Map(key, value) => format!("Map<{}, {}>", type_name(key)?, type_name(value)?),
+        ValueSchema::Tuple(items) => match items.as_slice() {
+            [first, second] => format!("Pair<{}, {}>", type_name(first)?, type_name(second)?),
+            [first, second, third] => format!(
+                "Triple<{}, {}, {}>",
+                type_name(first)?,
+                type_name(second)?,
+                type_name(third)?
+            ),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Tuples of {} items are not supported in Kotlin",
+                    items.len()
+                ));
+            }
+        },
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => pascal_case(name),
+        ValueSchema::Unknown => "Nothing".to_string(),
+    })
+}
+
+// Expression reading value from reader `r`, raw values take the rest of the bytes
+fn read(value: &ValueSchema, raw: bool) -> anyhow::Result<String> {
+    Ok(match value {
+        ValueSchema::Unit => "Unit".to_string(),
+        ValueSchema::Bool => "r.bool()".to_string(),
+        ValueSchema::U8 => "r.u8()".to_string(),
+        ValueSchema::U16 => "r.u16()".to_string(),
+        ValueSchema::U32 => "r.u32()".to_string(),
+        ValueSchema::U64 => "r.u64()".to_string(),
+        ValueSchema::I8 => "r.i8()".to_string(),
+        ValueSchema::I16 => "r.i16()".to_string(),
+        ValueSchema::I32 => "r.i32()".to_string(),
+        ValueSchema::I64 => "r.i64()".to_string(),
+        ValueSchema::F32 => "r.f32()".to_string(),
+        ValueSchema::F64 => "r.f64()".to_string(),
+        ValueSchema::String if raw => "r.restString()".to_string(),
+        ValueSchema::String => "r.string()".to_string(),
+        ValueSchema::Bytes if raw => "r.rest()".to_string(),
+        ValueSchema::Bytes => "r.bytes()".to_string(),
+        ValueSchema::Option(inner) => format!("r.option {{ {} }}", read(inner, false)?),
+        ValueSchema::Seq(inner) => format!("r.seq {{ {} }}", read(inner, false)?),
+        ValueSchema::Map(key, value) => format!(
+            "r.map({{ {} }}, {{ {} }})",
+            read(key, false)?,
+            read(value, false)?
+        ),
+        ValueSchema::Tuple(items) => {
+            // Validates arity
+            let kind = type_name(value)?;
+            let items = items
+                .iter()
+                .map(|item| read(item, false))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let constructor = kind.split('<').next().unwrap_or_default().to_string();
+
+            format!("{}({})", constructor, items.join(", "))
+        }
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => {
+            format!("decode{}(r)", pascal_case(name))
+        }
+        ValueSchema::Unknown => "r.unknown()".to_string(),
+    })
+}
+
+// Statement writing `expr` into writer `w`
+fn write(value: &ValueSchema, expr: &str, raw: bool, depth: usize) -> anyhow::Result<String> {
+    let item = format!("v{}", depth + 1);
+
+    Ok(match value {
+        ValueSchema::Unit => String::new(),
+        ValueSchema::Bool => format!("w.bool({})", expr),
+        ValueSchema::U8 => format!("w.u8({})", expr),
+        ValueSchema::U16 => format!("w.u16({})", expr),
+        ValueSchema::U32 => format!("w.u32({})", expr),
+        ValueSchema::U64 => format!("w.u64({})", expr),
+        ValueSchema::I8 => format!("w.i8({})", expr),
+        ValueSchema::I16 => format!("w.i16({})", expr),
+        ValueSchema::I32 => format!("w.i32({})", expr),
+        ValueSchema::I64 => format!("w.i64({})", expr),
+        ValueSchema::F32 => format!("w.f32({})", expr),
+        ValueSchema::F64 => format!("w.f64({})", expr),
+        ValueSchema::String if raw => format!("w.restString({})", expr),
+        ValueSchema::String => format!("w.string({})", expr),
+        ValueSchema::Bytes if raw => format!("w.rest({})", expr),
+        ValueSchema::Bytes => format!("w.bytes({})", expr),
+        ValueSchema::Option(inner) => format!(
+            "w.option({}) {{ {} -> {} }}",
+            expr,
+            item,
+            write(inner, &item, false, depth + 1)?
+        ),
+        ValueSchema::Seq(inner) => format!(
+            "w.seq({}) {{ {} -> {} }}",
+            expr,
+            item,
+            write(inner, &item, false, depth + 1)?
+        ),
+        ValueSchema::Map(key, value) => {
+            let key_item = format!("k{}", depth + 1);
+            format!(
+                "w.map({}, {{ {} -> {} }}, {{ {} -> {} }})",
+                expr,
+                key_item,
+                write(key, &key_item, false, depth + 1)?,
+                item,
+                write(value, &item, false, depth + 1)?
+            )
+        }
+        ValueSchema::Tuple(items) => {
+            type_name(value)?;
+
+            items
+                .iter()
+                .zip(["first", "second", "third"])
+                .map(|(value, field)| write(value, &format!("{}.{}", expr, field), false, depth))
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .join("; ")
+        }
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => {
+            format!("encode{}(w, {})", pascal_case(name), expr)
+        }
+        ValueSchema::Unknown => "w.unknown()".to_string(),
+    })
+}
@@ -0,0 +1,227 @@
+pub mod kotlin;
+pub mod swift;
+pub mod typescript;
+
+use std::{collections::HashMap, str::FromStr};
+
+use crate::schema::{
+    CharacteristicSchema, FieldSchema, GattSchema, ValueFormat, ValueSchema, VariantSchema,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    TypeScript,
+    Kotlin,
+    Swift,
+}
+
+impl FromStr for Language {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "ts" | "typescript" => Ok(Self::TypeScript),
+            "kt" | "kotlin" => Ok(Self::Kotlin),
+            "swift" => Ok(Self::Swift),
+            _ => Err(anyhow::anyhow!(
+                "Unknown language {:?}, expected typescript, kotlin or swift",
+                value
+            )),
+        }
+    }
+}
+
+pub fn generate(schema: &GattSchema, language: Language, package: &str) -> anyhow::Result<String> {
+    let schema = &with_supported_formats(schema);
+
+    match language {
+        Language::TypeScript => typescript::generate(schema),
+        Language::Kotlin => kotlin::generate(schema, package),
+        Language::Swift => swift::generate(schema),
+    }
+}
+
+// Generated codecs read bincode and raw values only, CBOR and postcard values
+// are exposed as bytes for a library of the app to decode
+fn with_supported_formats(schema: &GattSchema) -> GattSchema {
+    let mut schema = schema.clone();
+    for characteristic in schema
+        .services
+        .iter_mut()
+        .flat_map(|service| service.characteristics.iter_mut())
+    {
+        if matches!(
+            characteristic.format,
+            ValueFormat::Cbor | ValueFormat::Postcard
+        ) {
+            characteristic.format = ValueFormat::Raw;
+            characteristic.value = ValueSchema::Bytes;
+        }
+    }
+
+    schema
+}
+
+/// Characteristic together with its unique name in generated code
+pub struct Accessor<'a> {
+    pub name: String,
+    pub service_uuid: &'a str,
+    pub characteristic: &'a CharacteristicSchema,
+}
+
+pub fn accessors(schema: &GattSchema) -> Vec<Accessor<'_>> {
+    let mut used: HashMap<String, usize> = HashMap::new();
+
+    schema
+        .services
+        .iter()
+        .flat_map(|service| {
+            service
+                .characteristics
+                .iter()
+                .map(move |characteristic| (service.uuid.as_str(), characteristic))
+        })
+        .map(|(service_uuid, characteristic)| {
+            let base = match &characteristic.name {
+                Some(name) if !pascal_case(name).is_empty() => pascal_case(name),
+                _ => format!("Characteristic{}", &characteristic.uuid[..8]),
+            };
+
+            let count = used.entry(base.clone()).or_default();
+            *count += 1;
+            let name = if *count == 1 {
+                base
+            } else {
+                format!("{}{}", base, count)
+            };
+
+            Accessor {
+                name,
+                service_uuid,
+                characteristic,
+            }
+        })
+        .collect()
+}
+
+/// Struct or enum which needs a type declaration in generated code
+pub enum NamedType {
+    Struct {
+        name: String,
+        fields: Vec<FieldSchema>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<VariantSchema>,
+    },
+}
+
+/// Collects named types used by all characteristics, variants of the same enum
+/// seen in different values are merged
+pub fn named_types(schema: &GattSchema) -> Vec<NamedType> {
+    let mut types = Vec::new();
+
+    for accessor in accessors(schema) {
+        collect_named_types(&accessor.characteristic.value, &mut types);
+    }
+
+    types
+}
+
+fn collect_named_types(value: &ValueSchema, types: &mut Vec<NamedType>) {
+    match value {
+        ValueSchema::Option(inner) | ValueSchema::Seq(inner) => collect_named_types(inner, types),
+        ValueSchema::Map(key, value) => {
+            collect_named_types(key, types);
+            collect_named_types(value, types);
+        }
+        ValueSchema::Tuple(items) => {
+            items
+                .iter()
+                .for_each(|item| collect_named_types(item, types));
+        }
+        ValueSchema::Struct { name, fields } => {
+            fields
+                .iter()
+                .for_each(|field| collect_named_types(&field.value, types));
+
+            let exists = types.iter().any(|named| match named {
+                NamedType::Struct { name: other, .. } => other == name,
+                _ => false,
+            });
+            if !exists {
+                types.push(NamedType::Struct {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                });
+            }
+        }
+        ValueSchema::Enum { name, variants } => {
+            variants
+                .iter()
+                .for_each(|variant| collect_named_types(&variant.value, types));
+
+            let existing = types.iter_mut().find_map(|named| match named {
+                NamedType::Enum {
+                    name: other,
+                    variants,
+                } if other == name => Some(variants),
+                _ => None,
+            });
+
+            match existing {
+                Some(existing) => {
+                    for variant in variants {
+                        if !existing.iter().any(|other| other.index == variant.index) {
+                            existing.push(variant.clone());
+                        }
+                    }
+                    existing.sort_by_key(|variant| variant.index);
+                }
+                None => types.push(NamedType::Enum {
+                    name: name.clone(),
+                    variants: variants.clone(),
+                }),
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn pascal_case(value: &str) -> String {
+    value
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .to_string()
+}
+
+pub fn camel_case(value: &str) -> String {
+    let pascal = pascal_case(value);
+    let mut chars = pascal.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+pub fn upper_snake_case(value: &str) -> String {
+    let mut result = String::new();
+
+    for (i, c) in value.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            result.push('_');
+        }
+        result.push(c.to_ascii_uppercase());
+    }
+
+    result
+}
@@ -0,0 +1,648 @@
+use std::fmt::Write;
+
+use super::{Accessor, NamedType, accessors, camel_case, named_types, pascal_case};
+use crate::schema::{GattSchema, ValueFormat, ValueSchema};
+
+const KEYWORDS: &[&str] = &[
+    "as",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "continue",
+    "default",
+    "defer",
+    "do",
+    "else",
+    "enum",
+    "extension",
+    "false",
+    "for",
+    "func",
+    "guard",
+    "if",
+    "import",
+    "in",
+    "init",
+    "is",
+    "let",
+    "nil",
+    "operator",
+    "protocol",
+    "repeat",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "subscript",
+    "super",
+    "switch",
+    "throw",
+    "throws",
+    "true",
+    "try",
+    "var",
+    "where",
+    "while",
+];
+
+const RUNTIME: &str = r#"public enum DecodeError: Error {
+    case unexpectedEnd
+    case invalidTag(UInt8)
+    case unknownVariant(String, UInt32)
+    case unknownType
+}
+
+public final class Reader {
+    private let data: [UInt8]
+    private let fixedInt: Bool
+    private let bigEndian: Bool
+    private var offset = 0
+
+    public init(_ data: [UInt8], fixedInt: Bool, bigEndian: Bool) {
+        self.data = data
+        self.fixedInt = fixedInt
+        self.bigEndian = bigEndian
+    }
+
+    private func take(_ len: Int) throws -> [UInt8] {
+        guard offset + len <= data.count else {
+            throw DecodeError.unexpectedEnd
+        }
+        let bytes = Array(data[offset..<offset + len])
+        offset += len
+        return bytes
+    }
+
+    private func fixed(_ len: Int) throws -> UInt64 {
+        let bytes = try take(len)
+        var value: UInt64 = 0
+        for i in 0..<len {
+            value = (value << 8) | UInt64(bytes[bigEndian ? i : len - 1 - i])
+        }
+        return value
+    }
+
+    private func unsigned(_ len: Int) throws -> UInt64 {
+        if fixedInt {
+            return try fixed(len)
+        }
+        let tag = try u8()
+        if tag < 251 {
+            return UInt64(tag)
+        }
+        let width: Int
+        switch tag {
+        case 251: width = 2
+        case 252: width = 4
+        case 253: width = 8
+        default: throw DecodeError.invalidTag(tag)
+        }
+        guard width <= len else {
+            throw DecodeError.invalidTag(tag)
+        }
+        return try fixed(width)
+    }
+
+    private func signed(_ len: Int) throws -> Int64 {
+        let value = try unsigned(len)
+        if fixedInt {
+            let shift = UInt64(64 - len * 8)
+            return Int64(bitPattern: value << shift) >> shift
+        }
+        return Int64(bitPattern: value >> 1) ^ -Int64(bitPattern: value & 1)
+    }
+
+    public func bool() throws -> Bool { try u8() != 0 }
+
+    public func u8() throws -> UInt8 { try take(1)[0] }
+
+    public func u16() throws -> UInt16 { try UInt16(truncatingIfNeeded: unsigned(2)) }
+
+    public func u32() throws -> UInt32 { try UInt32(truncatingIfNeeded: unsigned(4)) }
+
+    public func u64() throws -> UInt64 { try unsigned(8) }
+
+    public func i8() throws -> Int8 { try Int8(bitPattern: u8()) }
+
+    public func i16() throws -> Int16 { try Int16(truncatingIfNeeded: signed(2)) }
+
+    public func i32() throws -> Int32 { try Int32(truncatingIfNeeded: signed(4)) }
+
+    public func i64() throws -> Int64 { try signed(8) }
+
+    public func f32() throws -> Float { try Float(bitPattern: UInt32(truncatingIfNeeded: fixed(4))) }
+
+    public func f64() throws -> Double { try Double(bitPattern: fixed(8)) }
+
+    public func len() throws -> Int { try Int(unsigned(8)) }
+
+    public func variant() throws -> UInt32 { try u32() }
+
+    public func bytes() throws -> [UInt8] { try take(len()) }
+
+    public func string() throws -> String { try String(decoding: bytes(), as: UTF8.self) }
+
+    public func rest() throws -> [UInt8] { try take(data.count - offset) }
+
+    public func restString() throws -> String { try String(decoding: rest(), as: UTF8.self) }
+
+    public func option<T>(_ read: () throws -> T) throws -> T? {
+        if try u8() == 1 {
+            return try read()
+        }
+        return nil
+    }
+
+    public func seq<T>(_ read: () throws -> T) throws -> [T] {
+        var items: [T] = []
+        for _ in 0..<(try len()) {
+            items.append(try read())
+        }
+        return items
+    }
+
+    public func map<K: Hashable, V>(_ readKey: () throws -> K, _ readValue: () throws -> V) throws -> [K: V] {
+        var items: [K: V] = [:]
+        for _ in 0..<(try len()) {
+            let key = try readKey()
+            items[key] = try readValue()
+        }
+        return items
+    }
+
+    public func unknown() throws -> Never {
+        throw DecodeError.unknownType
+    }
+}
+
+public final class Writer {
+    private let fixedInt: Bool
+    private let bigEndian: Bool
+    private var buffer: [UInt8] = []
+
+    public init(fixedInt: Bool, bigEndian: Bool) {
+        self.fixedInt = fixedInt
+        self.bigEndian = bigEndian
+    }
+
+    private func fixed(_ value: UInt64, _ len: Int) {
+        var bytes = (0..<len).map { UInt8(truncatingIfNeeded: value >> (8 * UInt64($0))) }
+        if bigEndian {
+            bytes.reverse()
+        }
+        buffer.append(contentsOf: bytes)
+    }
+
+    private func unsigned(_ value: UInt64, _ len: Int) {
+        if fixedInt {
+            fixed(value, len)
+        } else if value < 251 {
+            buffer.append(UInt8(value))
+        } else if value <= 0xffff {
+            buffer.append(251)
+            fixed(value, 2)
+        } else if value <= 0xffff_ffff {
+            buffer.append(252)
+            fixed(value, 4)
+        } else {
+            buffer.append(253)
+            fixed(value, 8)
+        }
+    }
+
+    private func signed(_ value: Int64, _ len: Int) {
+        if fixedInt {
+            fixed(UInt64(bitPattern: value), len)
+        } else {
+            unsigned(UInt64(bitPattern: (value << 1) ^ (value >> 63)), len)
+        }
+    }
+
+    public func bool(_ value: Bool) { buffer.append(value ? 1 : 0) }
+
+    public func u8(_ value: UInt8) { buffer.append(value) }
+
+    public func u16(_ value: UInt16) { unsigned(UInt64(value), 2) }
+
+    public func u32(_ value: UInt32) { unsigned(UInt64(value), 4) }
+
+    public func u64(_ value: UInt64) { unsigned(value, 8) }
+
+    public func i8(_ value: Int8) { buffer.append(UInt8(bitPattern: value)) }
+
+    public func i16(_ value: Int16) { signed(Int64(value), 2) }
+
+    public func i32(_ value: Int32) { signed(Int64(value), 4) }
+
+    public func i64(_ value: Int64) { signed(value, 8) }
+
+    public func f32(_ value: Float) { fixed(UInt64(value.bitPattern), 4) }
+
+    public func f64(_ value: Double) { fixed(value.bitPattern, 8) }
+
+    public func len(_ value: Int) { unsigned(UInt64(value), 8) }
+
+    public func variant(_ value: UInt32) { u32(value) }
+
+    public func bytes(_ value: [UInt8]) {
+        len(value.count)
+        buffer.append(contentsOf: value)
+    }
+
+    public func string(_ value: String) { bytes(Array(value.utf8)) }
+
+    public func rest(_ value: [UInt8]) { buffer.append(contentsOf: value) }
+
+    public func restString(_ value: String) { rest(Array(value.utf8)) }
+
+    public func option<T>(_ value: T?, _ write: (T) -> Void) {
+        if let value {
+            buffer.append(1)
+            write(value)
+        } else {
+            buffer.append(0)
+        }
+    }
+
+    public func seq<T>(_ value: [T], _ write: (T) -> Void) {
+        len(value.count)
+        value.forEach(write)
+    }
+
+    public func map<K, V>(_ value: [K: V], _ writeKey: (K) -> Void, _ writeValue: (V) -> Void) {
+        len(value.count)
+        for (key, item) in value {
+            writeKey(key)
+            writeValue(item)
+        }
+    }
+
+    public func unknown(_ value: Never) {}
+
+    public func finish() -> [UInt8] { buffer }
+}
+"#;
+
+/// Generates Swift types and codecs, one namespace with UUIDs per characteristic
+pub fn generate(schema: &GattSchema) -> anyhow::Result<String> {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "// Generated by esp-bluedroid-cli from the GATT schema, do not edit.\n"
+    )?;
+    writeln!(
+        out,
+        "public let gattFixedInt = {}",
+        schema.encoding.fixed_int
+    )?;
+    writeln!(
+        out,
+        "public let gattBigEndian = {}\n",
+        schema.encoding.big_endian
+    )?;
+    out.push_str(RUNTIME);
+
+    for named in named_types(schema) {
+        out.push('\n');
+        named_type(&mut out, &named)?;
+    }
+
+    for accessor in accessors(schema) {
+        out.push('\n');
+        characteristic(&mut out, &accessor)?;
+    }
+
+    Ok(out)
+}
+
+fn identifier(name: &str) -> String {
+    let name = camel_case(name);
+
+    match KEYWORDS.contains(&name.as_str()) {
+        true => format!("`{}`", name),
+        false => name,
+    }
+}
+
+fn named_type(out: &mut String, named: &NamedType) -> anyhow::Result<()> {
+    match named {
+        NamedType::Struct { name, fields } => {
+            let name = pascal_case(name);
+
+            writeln!(out, "public struct {} {{", name)?;
+            for field in fields {
+                writeln!(
+                    out,
+                    "    public var {}: {}",
+                    identifier(&field.name),
+                    type_name(&field.value)
+                )?;
+            }
+
+            let params = fields
+                .iter()
+                .map(|field| format!("{}: {}", identifier(&field.name), type_name(&field.value)))
+                .collect::<Vec<_>>();
+            writeln!(out, "\n    public init({}) {{", params.join(", "))?;
+            for field in fields {
+                let field = identifier(&field.name);
+                writeln!(out, "        self.{} = {}", field, field)?;
+            }
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}\n")?;
+
+            writeln!(out, "extension {} {{", name)?;
+            writeln!(
+                out,
+                "    static func decode(_ r: Reader) throws -> {} {{",
+                name
+            )?;
+            let args = fields
+                .iter()
+                .map(|field| format!("{}: {}", identifier(&field.name), read(&field.value, false)))
+                .collect::<Vec<_>>();
+            writeln!(out, "        try {}({})", name, args.join(", "))?;
+            writeln!(out, "    }}\n")?;
+
+            writeln!(out, "    func encode(_ w: Writer) {{")?;
+            for field in fields {
+                let value = format!("self.{}", identifier(&field.name));
+                writeln!(out, "        {}", write(&field.value, &value, false, 0))?;
+            }
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}")?;
+        }
+        NamedType::Enum { name, variants } => {
+            let name = pascal_case(name);
+
+            writeln!(out, "public enum {} {{", name)?;
+            for variant in variants {
+                match variant.value {
+                    ValueSchema::Unit => writeln!(out, "    case {}", identifier(&variant.name))?,
+                    _ => writeln!(
+                        out,
+                        "    case {}({})",
+                        identifier(&variant.name),
+                        type_name(&variant.value)
+                    )?,
+                }
+            }
+            writeln!(out, "}}\n")?;
+
+            writeln!(out, "extension {} {{", name)?;
+            writeln!(
+                out,
+                "    static func decode(_ r: Reader) throws -> {} {{",
+                name
+            )?;
+            writeln!(out, "        let index = try r.variant()")?;
+            writeln!(out, "        switch index {{")?;
+            for variant in variants {
+                match variant.value {
+                    ValueSchema::Unit => writeln!(
+                        out,
+                        "        case {}: return .{}",
+                        variant.index,
+                        identifier(&variant.name)
+                    )?,
+                    _ => writeln!(
+                        out,
+                        "        case {}: return try .{}({})",
+                        variant.index,
+                        identifier(&variant.name),
+                        read(&variant.value, false)
+                    )?,
+                }
+            }
+            writeln!(
+                out,
+                "        default: throw DecodeError.unknownVariant(\"{}\", index)",
+                name
+            )?;
+            writeln!(out, "        }}")?;
+            writeln!(out, "    }}\n")?;
+
+            writeln!(out, "    func encode(_ w: Writer) {{")?;
+            writeln!(out, "        switch self {{")?;
+            for variant in variants {
+                match variant.value {
+                    ValueSchema::Unit => writeln!(
+                        out,
+                        "        case .{}: w.variant({})",
+                        identifier(&variant.name),
+                        variant.index
+                    )?,
+                    _ => writeln!(
+                        out,
+                        "        case .{}(let value): w.variant({}); {}",
+                        identifier(&variant.name),
+                        variant.index,
+                        write(&variant.value, "value", false, 0)
+                    )?,
+                }
+            }
+            writeln!(out, "        }}")?;
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn characteristic(out: &mut String, accessor: &Accessor) -> anyhow::Result<()> {
+    let characteristic = accessor.characteristic;
+    let value_type = type_name(&characteristic.value);
+    let raw = characteristic.format == ValueFormat::Raw;
+    let (fixed_int, big_endian) = match raw {
+        true => ("true", "false"),
+        false => ("gattFixedInt", "gattBigEndian"),
+    };
+
+    writeln!(out, "public enum {}Characteristic {{", accessor.name)?;
+    writeln!(
+        out,
+        "    public static let serviceUUID = \"{}\"",
+        accessor.service_uuid
+    )?;
+    writeln!(
+        out,
+        "    public static let uuid = \"{}\"",
+        characteristic.uuid
+    )?;
+    writeln!(
+        out,
+        "    public static let readable = {}",
+        characteristic.readable
+    )?;
+    writeln!(
+        out,
+        "    public static let writable = {}",
+        characteristic.writable
+    )?;
+    writeln!(
+        out,
+        "    public static let notify = {}\n",
+        characteristic.notify
+    )?;
+
+    writeln!(
+        out,
+        "    public static func decode(_ bytes: [UInt8]) throws -> {} {{",
+        value_type
+    )?;
+    writeln!(
+        out,
+        "        let r = Reader(bytes, fixedInt: {}, bigEndian: {})",
+        fixed_int, big_endian
+    )?;
+    writeln!(
+        out,
+        "        return try {}",
+        read(&characteristic.value, raw)
+    )?;
+    writeln!(out, "    }}\n")?;
+
+    writeln!(
+        out,
+        "    public static func encode(_ v: {}) -> [UInt8] {{",
+        value_type
+    )?;
+    writeln!(
+        out,
+        "        let w = Writer(fixedInt: {}, bigEndian: {})",
+        fixed_int, big_endian
+    )?;
+    writeln!(out, "        {}", write(&characteristic.value, "v", raw, 0))?;
+    writeln!(out, "        return w.finish()")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
+
+fn type_name(value: &ValueSchema) -> String {
+    match value {
+        ValueSchema::Unit => "Void".to_string(),
+        ValueSchema::Bool => "Bool".to_string(),
+        ValueSchema::U8 => "UInt8".to_string(),
+        ValueSchema::U16 => "UInt16".to_string(),
+        ValueSchema::U32 => "UInt32".to_string(),
+        ValueSchema::U64 => "UInt64".to_string(),
+        ValueSchema::I8 => "Int8".to_string(),
+        ValueSchema::I16 => "Int16".to_string(),
+        ValueSchema::I32 => "Int32".to_string(),
+        ValueSchema::I64 => "Int64".to_string(),
+        ValueSchema::F32 => "Float".to_string(),
+        ValueSchema::F64 => "Double".to_string(),
+        ValueSchema::String => "String".to_string(),
+        ValueSchema::Bytes => "[UInt8]".to_string(),
+        ValueSchema::Option(inner) => format!("{}?", type_name(inner)),
+        ValueSchema::Seq(inner) => format!("[{}]", type_name(inner)),
+        ValueSchema::Map(key, value) => format!("[{}: {}]", type_name(key), type_name(value)),
+        ValueSchema::Tuple(items) => format!(
+            "({})",
+            items.iter().map(type_name).collect::<Vec<_>>().join(", ")
+        ),
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => pascal_case(name),
+        ValueSchema::Unknown => "Never".to_string(),
+    }
+}
+
+// Expression reading value from reader `r`, covered by a single leading `try`
+fn read(value: &ValueSchema, raw: bool) -> String {
+    match value {
+        ValueSchema::Unit => "()".to_string(),
+        ValueSchema::Bool => "r.bool()".to_string(),
+        ValueSchema::U8 => "r.u8()".to_string(),
+        ValueSchema::U16 => "r.u16()".to_string(),
+        ValueSchema::U32 => "r.u32()".to_string(),
+        ValueSchema::U64 => "r.u64()".to_string(),
+        ValueSchema::I8 => "r.i8()".to_string(),
+        ValueSchema::I16 => "r.i16()".to_string(),
+        ValueSchema::I32 => "r.i32()".to_string(),
+        ValueSchema::I64 => "r.i64()".to_string(),
+        ValueSchema::F32 => "r.f32()".to_string(),
+        ValueSchema::F64 => "r.f64()".to_string(),
+        ValueSchema::String if raw => "r.restString()".to_string(),
+        ValueSchema::String => "r.string()".to_string(),
+        ValueSchema::Bytes if raw => "r.rest()".to_string(),
+        ValueSchema::Bytes => "r.bytes()".to_string(),
+        ValueSchema::Option(inner) => format!("r.option {{ try {} }}", read(inner, false)),
+        ValueSchema::Seq(inner) => format!("r.seq {{ try {} }}", read(inner, false)),
+        ValueSchema::Map(key, value) => format!(
+            "r.map({{ try {} }}, {{ try {} }})",
+            read(key, false),
+            read(value, false)
+        ),
+        ValueSchema::Tuple(items) => format!(
+            "({})",
+            items
+                .iter()
+                .map(|item| read(item, false))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => {
+            format!("{}.decode(r)", pascal_case(name))
+        }
+        ValueSchema::Unknown => "r.unknown()".to_string(),
+    }
+}
+
+// Statement writing `expr` into writer `w`
+fn write(value: &ValueSchema, expr: &str, raw: bool, depth: usize) -> String {
+    let item = format!("v{}", depth + 1);
+
+    match value {
+        ValueSchema::Unit => String::new(),
+        ValueSchema::Bool => format!("w.bool({})", expr),
+        ValueSchema::U8 => format!("w.u8({})", expr),
+        ValueSchema::U16 => format!("w.u16({})", expr),
+        ValueSchema::U32 => format!("w.u32({})", expr),
+        ValueSchema::U64 => format!("w.u64({})", expr),
+        ValueSchema::I8 => format!("w.i8({})", expr),
+        ValueSchema::I16 => format!("w.i16({})", expr),
+        ValueSchema::I32 => format!("w.i32({})", expr),
+        ValueSchema::I64 => format!("w.i64({})", expr),
+        ValueSchema::F32 => format!("w.f32({})", expr),
+        ValueSchema::F64 => format!("w.f64({})", expr),
+        ValueSchema::String if raw => format!("w.restString({})", expr),
+        ValueSchema::String => format!("w.string({})", expr),
+        ValueSchema::Bytes if raw => format!("w.rest({})", expr),
+        ValueSchema::Bytes => format!("w.bytes({})", expr),
+        ValueSchema::Option(inner) => format!(
+            "w.option({}) {{ {} in {} }}",
+            expr,
+            item,
+            write(inner, &item, false, depth + 1)
+        ),
+        ValueSchema::Seq(inner) => format!(
+            "w.seq({}) {{ {} in {} }}",
+            expr,
+            item,
+            write(inner, &item, false, depth + 1)
+        ),
+        ValueSchema::Map(key, value) => {
+            let key_item = format!("k{}", depth + 1);
+            format!(
+                "w.map({}, {{ {} in {} }}, {{ {} in {} }})",
+                expr,
+                key_item,
+                write(key, &key_item, false, depth + 1),
+                item,
+                write(value, &item, false, depth + 1)
+            )
+        }
+        ValueSchema::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, value)| write(value, &format!("{}.{}", expr, i), false, depth))
+            .collect::<Vec<_>>()
+            .join("; "),
+        ValueSchema::Struct { .. } | ValueSchema::Enum { .. } => format!("{}.encode(w)", expr),
+        ValueSchema::Unknown => format!("w.unknown({})", expr),
+    }
+}
@@ -0,0 +1,686 @@
+use std::fmt::Write;
+
+use super::{Accessor, NamedType, accessors, named_types, pascal_case, upper_snake_case};
+use crate::schema::{GattSchema, ValueFormat, ValueSchema};
+
+const RUNTIME: &str = r#"export class Reader {
+  private offset = 0;
+
+  constructor(
+    private readonly data: Uint8Array,
+    private readonly fixedInt: boolean,
+    private readonly bigEndian: boolean,
+  ) {}
+
+  private take(len: number): Uint8Array {
+    if (this.offset + len > this.data.length) {
+      throw new Error("Unexpected end of value");
+    }
+    const bytes = this.data.subarray(this.offset, this.offset + len);
+    this.offset += len;
+    return bytes;
+  }
+
+  private fixed(len: number): bigint {
+    const bytes = this.take(len);
+    let value = 0n;
+    for (let i = 0; i < len; i++) {
+      value = (value << 8n) | BigInt(bytes[this.bigEndian ? i : len - 1 - i]);
+    }
+    return value;
+  }
+
+  private unsigned(len: number): bigint {
+    if (this.fixedInt) {
+      return this.fixed(len);
+    }
+    const tag = this.take(1)[0];
+    if (tag < 251) {
+      return BigInt(tag);
+    }
+    const width = tag === 251 ? 2 : tag === 252 ? 4 : tag === 253 ? 8 : 0;
+    if (width === 0 || width > len) {
+      throw new Error(`Invalid integer tag ${tag}`);
+    }
+    return this.fixed(width);
+  }
+
+  private signed(len: number): bigint {
+    const value = this.unsigned(len);
+    if (this.fixedInt) {
+      return BigInt.asIntN(len * 8, value);
+    }
+    return (value >> 1n) ^ -(value & 1n);
+  }
+
+  private float(len: number): DataView {
+    return new DataView(this.take(len).slice().buffer);
+  }
+
+  bool(): boolean {
+    return this.u8() !== 0;
+  }
+
+  u8(): number {
+    return this.take(1)[0];
+  }
+
+  u16(): number {
+    return Number(this.unsigned(2));
+  }
+
+  u32(): number {
+    return Number(this.unsigned(4));
+  }
+
+  u64(): bigint {
+    return this.unsigned(8);
+  }
+
+  i8(): number {
+    return (this.u8() << 24) >> 24;
+  }
+
+  i16(): number {
+    return Number(this.signed(2));
+  }
+
+  i32(): number {
+    return Number(this.signed(4));
+  }
+
+  i64(): bigint {
+    return this.signed(8);
+  }
+
+  f32(): number {
+    return this.float(4).getFloat32(0, !this.bigEndian);
+  }
+
+  f64(): number {
+    return this.float(8).getFloat64(0, !this.bigEndian);
+  }
+
+  len(): number {
+    return Number(this.unsigned(8));
+  }
+
+  variant(): number {
+    return this.u32();
+  }
+
+  bytes(): Uint8Array {
+    return this.take(this.len()).slice();
+  }
+
+  string(): string {
+    return new TextDecoder().decode(this.bytes());
+  }
+
+  rest(): Uint8Array {
+    return this.take(this.data.length - this.offset).slice();
+  }
+
+  restString(): string {
+    return new TextDecoder().decode(this.rest());
+  }
+
+  option<T>(read: () => T): T | null {
+    return this.u8() === 1 ? read() : null;
+  }
+
+  seq<T>(read: () => T): Array<T> {
+    const len = this.len();
+    const items: Array<T> = [];
+    for (let i = 0; i < len; i++) {
+      items.push(read());
+    }
+    return items;
+  }
+
+  map<K, V>(readKey: () => K, readValue: () => V): Map<K, V> {
+    const len = this.len();
+    const items = new Map<K, V>();
+    for (let i = 0; i < len; i++) {
+      const key = readKey();
+      items.set(key, readValue());
+    }
+    return items;
+  }
+
+  unknown(): never {
+    throw new Error("Type of this value is not known from the schema");
+  }
+}
+
+export class Writer {
+  private readonly buffer: Array<number> = [];
+
+  constructor(
+    private readonly fixedInt: boolean,
+    private readonly bigEndian: boolean,
+  ) {}
+
+  private fixed(value: bigint, len: number): void {
+    const bytes: Array<number> = [];
+    for (let i = 0; i < len; i++) {
+      bytes.push(Number((value >> BigInt(8 * i)) & 0xffn));
+    }
+    if (this.bigEndian) {
+      bytes.reverse();
+    }
+    this.buffer.push(...bytes);
+  }
+
+  private unsigned(value: bigint, len: number): void {
+    if (this.fixedInt) {
+      this.fixed(value, len);
+    } else if (value < 251n) {
+      this.buffer.push(Number(value));
+    } else if (value <= 0xffffn) {
+      this.buffer.push(251);
+      this.fixed(value, 2);
+    } else if (value <= 0xffffffffn) {
+      this.buffer.push(252);
+      this.fixed(value, 4);
+    } else {
+      this.buffer.push(253);
+      this.fixed(value, 8);
+    }
+  }
+
+  private signed(value: bigint, len: number): void {
+    if (this.fixedInt) {
+      this.fixed(BigInt.asUintN(len * 8, value), len);
+    } else {
+      this.unsigned(value >= 0n ? value << 1n : (-value << 1n) - 1n, len);
+    }
+  }
+
+  private float(len: number, set: (view: DataView) => void): void {
+    const bytes = new Uint8Array(len);
+    set(new DataView(bytes.buffer));
+    this.buffer.push(...bytes);
+  }
+
+  bool(value: boolean): void {
+    this.u8(value ? 1 : 0);
+  }
+
+  u8(value: number): void {
+    this.buffer.push(value & 0xff);
+  }
+
+  u16(value: number): void {
+    this.unsigned(BigInt(value), 2);
+  }
+
+  u32(value: number): void {
+    this.unsigned(BigInt(value), 4);
+  }
+
+  u64(value: bigint): void {
+    this.unsigned(value, 8);
+  }
+
+  i8(value: number): void {
+    this.u8(value);
+  }
+
+  i16(value: number): void {
+    this.signed(BigInt(value), 2);
+  }
+
+  i32(value: number): void {
+    this.signed(BigInt(value), 4);
+  }
+
+  i64(value: bigint): void {
+    this.signed(value, 8);
+  }
+
+  f32(value: number): void {
+    this.float(4, (view) => view.setFloat32(0, value, !this.bigEndian));
+  }
+
+  f64(value: number): void {
+    this.float(8, (view) => view.setFloat64(0, value, !this.bigEndian));
+  }
+
+  len(value: number): void {
+    this.unsigned(BigInt(value), 8);
+  }
+
+  variant(value: number): void {
+    this.u32(value);
+  }
+
+  bytes(value: Uint8Array): void {
+    this.len(value.length);
+    this.buffer.push(...value);
+  }
+
+  string(value: string): void {
+    this.bytes(new TextEncoder().encode(value));
+  }
+
+  rest(value: Uint8Array): void {
+    this.buffer.push(...value);
+  }
+
+  restString(value: string): void {
+    this.rest(new TextEncoder().encode(value));
+  }
+
+  option<T>(value: T | null, write: (value: T) => void): void {
+    if (value === null) {
+      this.u8(0);
+    } else {
+      this.u8(1);
+      write(value);
+    }
+  }
+
+  seq<T>(value: Array<T>, write: (value: T) => void): void {
+    this.len(value.length);
+    value.forEach((item) => write(item));
+  }
+
+  map<K, V>(value: Map<K, V>, writeKey: (key: K) => void, writeValue: (value: V) => void): void {
+    this.len(value.size);
+    value.forEach((item, key) => {
+      writeKey(key);
+      writeValue(item);
+    });
+  }
+
+  unknown(): never {
+    throw new Error("Type of this value is not known from the schema");
+  }
+
+  finish(): Uint8Array {
+    return Uint8Array.from(this.buffer);
+  }
+}
+
+async function characteristic(
+  server: BluetoothRemoteGATTServer,
+  service: string,
+  uuid: string,
+): Promise<BluetoothRemoteGATTCharacteristic> {
+  const primary = await server.getPrimaryService(service);
+  return primary.getCharacteristic(uuid);
+}
+
+function view(value: DataView): Uint8Array {
+  return new Uint8Array(value.buffer, value.byteOffset, value.byteLength);
+}
+"#;
+
+/// Generates TypeScript types, codecs and Web Bluetooth accessors
+pub fn generate(schema: &GattSchema) -> anyhow::Result<String> {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "// Generated by esp-bluedroid-cli from the GATT schema, do not edit.\n"
+    )?;
+    writeln!(
+        out,
+        "export const FIXED_INT = {};",
+        schema.encoding.fixed_int
+    )?;
+    writeln!(
+        out,
+        "export const BIG_ENDIAN = {};\n",
+        schema.encoding.big_endian
+    )?;
+    out.push_str(RUNTIME);
+
+    for named in named_types(schema) {
+        out.push('\n');
+        named_type(&mut out, &named)?;
+    }
+
+    for accessor in accessors(schema) {
+        out.push('\n');
+        characteristic(&mut out, &accessor)?;
+    }
+
+    Ok(out)
+}
+
+fn named_type(out: &mut String, named: &NamedType) -> anyhow::Result<()> {
+    match named {
+        NamedType::Struct { name, fields } => {
+            let name = pascal_case(name);
+
+            writeln!(out, "export interface {} {{", name)?;
+            for field in fields {
+                writeln!(out, "  {}: {};", field.name, type_name(&field.value))?;
+            }
+            writeln!(out, "}}\n")?;
+
+            writeln!(out, "function decode{}(r: Reader): {} {{", name, name)?;
+            writeln!(out, "  return {{")?;
+            for field in fields {
+                writeln!(out, "    {}: {},", field.name, read(&field.value, false))?;
+            }
+            writeln!(out, "  }};")?;
+            writeln!(out, "}}\n")?;
+
+            writeln!(
+                out,
+                "function encode{}(w: Writer, v: {}): void {{",
+                name, name
+            )?;
+            for field in fields {
+                let value = format!("v.{}", field.name);
+                writeln!(out, "  {}", write(&field.value, &value, false, 0))?;
+            }
+            writeln!(out, "}}")?;
+        }
+        NamedType::Enum { name, variants } => {
+            let name = pascal_case(name);
+
+            writeln!(out, "export type {} =", name)?;
+            for variant in variants {
+                match variant.value {
+                    ValueSchema::Unit => writeln!(out, "  | {{ variant: \"{}\" }}", variant.name)?,
+                    _ => writeln!(
+                        out,
+                        "  | {{ variant: \"{}\"; value: {} }}",
+                        variant.name,
+                        type_name(&variant.value)
+                    )?,
+                }
+            }
+            writeln!(out, "  ;\n")?;
+
+            writeln!(out, "function decode{}(r: Reader): {} {{", name, name)?;
+            writeln!(out, "  const index = r.variant();")?;
+            writeln!(out, "  switch (index) {{")?;
+            for variant in variants {
+                match variant.value {
+                    ValueSchema::Unit => writeln!(
+                        out,
+                        "    case {}:\n      return {{ variant: \"{}\" }};",
+                        variant.index, variant.name
+                    )?,
+                    _ => writeln!(
+                        out,
+                        "    case {}:\n      return {{ variant: \"{}\", value: {} }};",
+                        variant.index,
+                        variant.name,
+                        read(&variant.value, false)
+                    )?,
+                }
+            }
+            writeln!(out, "    default:")?;
+            writeln!(
+                out,
+                "      throw new Error(`Unknown {} variant ${{index}}`);",
+                name
+            )?;
+            writeln!(out, "  }}")?;
+            writeln!(out, "}}\n")?;
+
+            writeln!(
+                out,
+                "function encode{}(w: Writer, v: {}): void {{",
+                name, name
+            )?;
+            writeln!(out, "  switch (v.variant) {{")?;
+            for variant in variants {
+                writeln!(out, "    case \"{}\":", variant.name)?;
+                writeln!(out, "      w.variant({});", variant.index)?;
+                if variant.value != ValueSchema::Unit {
+                    writeln!(out, "      {}", write(&variant.value, "v.value", false, 0))?;
+                }
+                writeln!(out, "      break;")?;
+            }
+            writeln!(out, "  }}")?;
+            writeln!(out, "}}")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn characteristic(out: &mut String, accessor: &Accessor) -> anyhow::Result<()> {
+    let name = &accessor.name;
+    let constant = upper_snake_case(name);
+    let characteristic = accessor.characteristic;
+    let value_type = type_name(&characteristic.value);
+    let raw = characteristic.format == ValueFormat::Raw;
+    let (fixed_int, big_endian) = match raw {
+        true => ("true", "false"),
+        false => ("FIXED_INT", "BIG_ENDIAN"),
+    };
+
+    writeln!(
+        out,
+        "export const {}_SERVICE_UUID = \"{}\";",
+        constant, accessor.service_uuid
+    )?;
+    writeln!(
+        out,
+        "export const {}_UUID = \"{}\";\n",
+        constant, characteristic.uuid
+    )?;
+
+    writeln!(
+        out,
+        "export function decode{}Value(bytes: Uint8Array): {} {{",
+        name, value_type
+    )?;
+    writeln!(
+        out,
+        "  const r = new Reader(bytes, {}, {});",
+        fixed_int, big_endian
+    )?;
+    writeln!(out, "  return {};", read(&characteristic.value, raw))?;
+    writeln!(out, "}}\n")?;
+
+    writeln!(
+        out,
+        "export function encode{}Value(v: {}): Uint8Array {{",
+        name, value_type
+    )?;
+    writeln!(
+        out,
+        "  const w = new Writer({}, {});",
+        fixed_int, big_endian
+    )?;
+    writeln!(out, "  {}", write(&characteristic.value, "v", raw, 0))?;
+    writeln!(out, "  return w.finish();")?;
+    writeln!(out, "}}")?;
+
+    let lookup = format!(
+        "await characteristic(server, {}_SERVICE_UUID, {}_UUID)",
+        constant, constant
+    );
+
+    if characteristic.readable {
+        writeln!(
+            out,
+            "\nexport async function read{}(server: BluetoothRemoteGATTServer): Promise<{}> {{",
+            name, value_type
+        )?;
+        writeln!(out, "  const target = {};", lookup)?;
+        writeln!(
+            out,
+            "  return decode{}Value(view(await target.readValue()));",
+            name
+        )?;
+        writeln!(out, "}}")?;
+    }
+
+    if characteristic.writable {
+        writeln!(
+            out,
+            "\nexport async function write{}(server: BluetoothRemoteGATTServer, value: {}): Promise<void> {{",
+            name, value_type
+        )?;
+        writeln!(out, "  const target = {};", lookup)?;
+        writeln!(
+            out,
+            "  await target.writeValueWithResponse(encode{}Value(value));",
+            name
+        )?;
+        writeln!(out, "}}")?;
+    }
+
+    if characteristic.notify {
+        writeln!(
+            out,
+            "\nexport async function subscribe{}(\n  server: BluetoothRemoteGATTServer,\n  listener: (value: {}) => void,\n): Promise<BluetoothRemoteGATTCharacteristic> {{",
+            name, value_type
+        )?;
+        writeln!(out, "  const target = {};", lookup)?;
+        writeln!(
+            out,
+            "  target.addEventListener(\"characteristicvaluechanged\", () => {{"
+        )?;
+        writeln!(out, "    if (target.value) {{")?;
+        writeln!(
+            out,
+            "      listener(decode{}Value(view(target.value)));",
+            name
+        )?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "  }});")?;
+        writeln!(out, "  return target.startNotifications();")?;
+        writeln!(out, "}}")?;
+    }
+
+    Ok(())
+}
+
+fn type_name(value: &ValueSchema) -> String {
+    match value {
+        ValueSchema::Unit => "null".to_string(),
+        ValueSchema::Bool => "boolean".to_string(),
+        ValueSchema::U64 | ValueSchema::I64 => "bigint".to_string(),
+        ValueSchema::U8
+        | ValueSchema::U16
+        | ValueSchema::U32
+        | ValueSchema::I8
+        | ValueSchema::I16
+        | ValueSchema::I32
+        | ValueSchema::F32
+        | ValueSchema::F64 => "number".to_string(),
+        ValueSchema::String => "string".to_string(),
+        ValueSchema::Bytes => "Uint8Array".to_string(),
+        ValueSchema::Option(inner) => match type_name(inner) {
+            inner if inner.contains(" | ") => format!("({}) | null", inner),
+            inner => format!("{} | null", inner),
+        },
+        ValueSchema::Seq(inner) => format!("Array<{}>", type_name(inner)),
+        ValueSchema::Map(key, value) => format!("Map<{}, {}>", type_name(key), type_name(value)),
+        ValueSchema::Tuple(items) => format!(
+            "[{}]",
+            items.iter().map(type_name).collect::<Vec<_>>().join(", ")
+        ),
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => pascal_case(name),
+        ValueSchema::Unknown => "never".to_string(),
+    }
+}
+
+// Expression reading value from reader `r`, raw values take the rest of the bytes
+fn read(value: &ValueSchema, raw: bool) -> String {
+    match value {
+        ValueSchema::Unit => "null".to_string(),
+        ValueSchema::Bool => "r.bool()".to_string(),
+        ValueSchema::U8 => "r.u8()".to_string(),
+        ValueSchema::U16 => "r.u16()".to_string(),
+        ValueSchema::U32 => "r.u32()".to_string(),
+        ValueSchema::U64 => "r.u64()".to_string(),
+        ValueSchema::I8 => "r.i8()".to_string(),
+        ValueSchema::I16 => "r.i16()".to_string(),
+        ValueSchema::I32 => "r.i32()".to_string(),
+        ValueSchema::I64 => "r.i64()".to_string(),
+        ValueSchema::F32 => "r.f32()".to_string(),
+        ValueSchema::F64 => "r.f64()".to_string(),
+        ValueSchema::String if raw => "r.restString()".to_string(),
+        ValueSchema::String => "r.string()".to_string(),
+        ValueSchema::Bytes if raw => "r.rest()".to_string(),
+        ValueSchema::Bytes => "r.bytes()".to_string(),
+        ValueSchema::Option(inner) => format!("r.option(() => {})", read(inner, false)),
+        ValueSchema::Seq(inner) => format!("r.seq(() => {})", read(inner, false)),
+        ValueSchema::Map(key, value) => format!(
+            "r.map(() => {}, () => {})",
+            read(key, false),
+            read(value, false)
+        ),
+        ValueSchema::Tuple(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(|item| read(item, false))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => {
+            format!("decode{}(r)", pascal_case(name))
+        }
+        ValueSchema::Unknown => "r.unknown()".to_string(),
+    }
+}
+
+// Statement writing `value` expression into writer `w`
+fn write(value: &ValueSchema, expr: &str, raw: bool, depth: usize) -> String {
+    let item = format!("v{}", depth + 1);
+
+    match value {
+        ValueSchema::Unit => String::new(),
+        ValueSchema::Bool => format!("w.bool({});", expr),
+        ValueSchema::U8 => format!("w.u8({});", expr),
+        ValueSchema::U16 => format!("w.u16({});", expr),
+        ValueSchema::U32 => format!("w.u32({});", expr),
+        ValueSchema::U64 => format!("w.u64({});", expr),
+        ValueSchema::I8 => format!("w.i8({});", expr),
+        ValueSchema::I16 => format!("w.i16({});", expr),
+        ValueSchema::I32 => format!("w.i32({});", expr),
+        ValueSchema::I64 => format!("w.i64({});", expr),
+        ValueSchema::F32 => format!("w.f32({});", expr),
+        ValueSchema::F64 => format!("w.f64({});", expr),
+        ValueSchema::String if raw => format!("w.restString({});", expr),
+        ValueSchema::String => format!("w.string({});", expr),
+        ValueSchema::Bytes if raw => format!("w.rest({});", expr),
+        ValueSchema::Bytes => format!("w.bytes({});", expr),
+        ValueSchema::Option(inner) => format!(
+            "w.option({}, ({}) => {{ {} }});",
+            expr,
+            item,
+            write(inner, &item, false, depth + 1)
+        ),
+        ValueSchema::Seq(inner) => format!(
+            "w.seq({}, ({}) => {{ {} }});",
+            expr,
+            item,
+            write(inner, &item, false, depth + 1)
+        ),
+        ValueSchema::Map(key, value) => {
+            let key_item = format!("k{}", depth + 1);
+            format!(
+                "w.map({}, ({}) => {{ {} }}, ({}) => {{ {} }});",
+                expr,
+                key_item,
+                write(key, &key_item, false, depth + 1),
+                item,
+                write(value, &item, false, depth + 1)
+            )
+        }
+        ValueSchema::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, value)| write(value, &format!("{}[{}]", expr, i), false, depth))
+            .collect::<Vec<_>>()
+            .join(" "),
+        ValueSchema::Struct { name, .. } | ValueSchema::Enum { name, .. } => {
+            format!("encode{}(w, {});", pascal_case(name), expr)
+        }
+        ValueSchema::Unknown => "w.unknown();".to_string(),
+    }
+}
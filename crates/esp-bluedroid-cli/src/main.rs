@@ -1,3 +1,96 @@
-fn main() {
-    println!("Hello, world!");
+//! Workspace tooling for `esp-bluedroid`.
+//!
+//! `codegen` turns the GATT schema exported by the firmware (`Gatts::export_schema`,
+//! requires the `json` feature) into TypeScript (Web Bluetooth), Kotlin or Swift
+//! types and codecs, so app code follows the firmware structs.
+//!
+//! The repository root configures an Xtensa target, so run this crate for the host
+//! explicitly, e.g. `cargo run -p esp-bluedroid-cli --target x86_64-unknown-linux-gnu`.
+
+mod codegen;
+
+#[path = "../../../src/gatts/schema.rs"]
+#[allow(dead_code)]
+mod schema;
+
+use std::path::PathBuf;
+
+use codegen::Language;
+use schema::{GattSchema, SCHEMA_VERSION};
+
+const USAGE: &str = "Usage: esp-bluedroid-cli codegen <schema.json> --lang <typescript|kotlin|swift> [--package <name>] [--out <file>]";
+
+struct CodegenArgs {
+    schema: PathBuf,
+    language: Language,
+    package: String,
+    out: Option<PathBuf>,
+}
+
+impl CodegenArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut schema = None;
+        let mut language = None;
+        let mut package = "esp.bluedroid".to_string();
+        let mut out = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing value for {}\n{}", arg, USAGE))
+            };
+
+            match arg.as_str() {
+                "--lang" => language = Some(value()?.parse()?),
+                "--package" => package = value()?,
+                "--out" => out = Some(PathBuf::from(value()?)),
+                _ if !arg.starts_with("--") && schema.is_none() => {
+                    schema = Some(PathBuf::from(arg))
+                }
+                _ => return Err(anyhow::anyhow!("Unexpected argument {:?}\n{}", arg, USAGE)),
+            }
+        }
+
+        Ok(Self {
+            schema: schema.ok_or_else(|| anyhow::anyhow!("Missing schema file\n{}", USAGE))?,
+            language: language.ok_or_else(|| anyhow::anyhow!("Missing --lang\n{}", USAGE))?,
+            package,
+            out,
+        })
+    }
+}
+
+fn codegen(args: CodegenArgs) -> anyhow::Result<()> {
+    let schema = std::fs::read_to_string(&args.schema)
+        .map_err(|err| anyhow::anyhow!("Failed to read {:?}: {:?}", args.schema, err))?;
+    let schema: GattSchema = serde_json::from_str(&schema)
+        .map_err(|err| anyhow::anyhow!("Failed to parse GATT schema: {:?}", err))?;
+
+    if schema.schema_version != SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported schema version {}, expected {}",
+            schema.schema_version,
+            SCHEMA_VERSION
+        ));
+    }
+
+    let code = codegen::generate(&schema, args.language, &args.package)?;
+
+    match args.out {
+        Some(out) => std::fs::write(&out, code)
+            .map_err(|err| anyhow::anyhow!("Failed to write {:?}: {:?}", out, err)),
+        None => {
+            print!("{}", code);
+            Ok(())
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+
+    match args.next().as_deref() {
+        Some("codegen") => codegen(CodegenArgs::parse(args)?),
+        _ => Err(anyhow::anyhow!(USAGE)),
+    }
 }
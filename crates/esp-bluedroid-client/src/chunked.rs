@@ -0,0 +1,166 @@
+/// Tag of the first frame of a value.
+pub const START: u8 = 0x00;
+/// Tag of the following frames of a value.
+pub const CONTINUATION: u8 = 0x01;
+
+const START_HEADER_LEN: usize = 10;
+const CONTINUATION_HEADER_LEN: usize = 4;
+
+/// Reassembles values of a characteristic with `chunked` set, see
+/// `esp_bluedroid::gatts::chunked` for the frame layout.
+#[derive(Debug, Clone)]
+pub struct ChunkState {
+    max_len: usize,
+    transfer: Option<Transfer>,
+}
+
+#[derive(Debug, Clone)]
+struct Transfer {
+    id: u8,
+    total_len: usize,
+    crc: u32,
+    next_index: u16,
+    value: Vec<u8>,
+}
+
+impl ChunkState {
+    /// `max_len` limits size of reassembled values.
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            transfer: None,
+        }
+    }
+
+    /// Applies a received frame and returns the value once its last frame arrived.
+    /// A failed transfer is dropped, the next start frame begins a new one.
+    pub fn apply(&mut self, frame: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        match frame {
+            [START, id, ..] if frame.len() >= START_HEADER_LEN => {
+                let total_len = u32::from_le_bytes([frame[2], frame[3], frame[4], frame[5]]);
+                let crc = u32::from_le_bytes([frame[6], frame[7], frame[8], frame[9]]);
+
+                self.transfer = None;
+                let total_len = total_len as usize;
+                if total_len > self.max_len {
+                    return Err(anyhow::anyhow!(
+                        "Chunked value of {} bytes exceeds limit of {} bytes",
+                        total_len,
+                        self.max_len
+                    ));
+                }
+
+                self.transfer = Some(Transfer {
+                    id: *id,
+                    total_len,
+                    crc,
+                    next_index: 1,
+                    value: Vec::with_capacity(total_len),
+                });
+
+                self.append(&frame[START_HEADER_LEN..])
+            }
+            [CONTINUATION, id, low, high, bytes @ ..] => {
+                let index = u16::from_le_bytes([*low, *high]);
+
+                match self.transfer.as_mut() {
+                    Some(transfer) if transfer.id == *id && transfer.next_index == index => {
+                        transfer.next_index = transfer.next_index.wrapping_add(1);
+                    }
+                    _ => {
+                        self.transfer = None;
+                        return Err(anyhow::anyhow!(
+                            "Unexpected chunk {} of transfer {}",
+                            index,
+                            id
+                        ));
+                    }
+                }
+
+                self.append(bytes)
+            }
+            _ => Err(anyhow::anyhow!(
+                "Malformed chunk frame of {} bytes",
+                frame.len()
+            )),
+        }
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let transfer = self
+            .transfer
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("No chunked transfer in progress"))?;
+
+        if transfer.value.len() + bytes.len() > transfer.total_len {
+            self.transfer = None;
+            return Err(anyhow::anyhow!("Chunks exceed announced value length"));
+        }
+
+        transfer.value.extend_from_slice(bytes);
+        if transfer.value.len() < transfer.total_len {
+            return Ok(None);
+        }
+
+        let Some(transfer) = self.transfer.take() else {
+            return Ok(None);
+        };
+
+        if crc32(&transfer.value) != transfer.crc {
+            return Err(anyhow::anyhow!("Chunked value failed CRC-32 check"));
+        }
+
+        Ok(Some(transfer.value))
+    }
+}
+
+/// Splits a value into frames of at most `frame_len` bytes, each written to the
+/// characteristic in turn. `frame_len` is usually the MTU minus 3.
+pub fn fragments(value: &[u8], transfer: u8, frame_len: usize) -> anyhow::Result<Vec<Vec<u8>>> {
+    if frame_len <= START_HEADER_LEN {
+        return Err(anyhow::anyhow!(
+            "Frame length {} leaves no room for value bytes",
+            frame_len
+        ));
+    }
+
+    let total_len = u32::try_from(value.len())
+        .map_err(|_| anyhow::anyhow!("Value of {} bytes is too large", value.len()))?;
+    let (first, rest) = value.split_at(value.len().min(frame_len - START_HEADER_LEN));
+
+    let mut frames = vec![
+        [
+            [START, transfer].as_slice(),
+            total_len.to_le_bytes().as_slice(),
+            crc32(value).to_le_bytes().as_slice(),
+            first,
+        ]
+        .concat(),
+    ];
+
+    for (index, chunk) in rest.chunks(frame_len - CONTINUATION_HEADER_LEN).enumerate() {
+        let index = u16::try_from(index + 1)
+            .map_err(|_| anyhow::anyhow!("Value needs more than {} frames", u16::MAX))?;
+
+        frames.push(
+            [
+                [CONTINUATION, transfer].as_slice(),
+                index.to_le_bytes().as_slice(),
+                chunk,
+            ]
+            .concat(),
+        );
+    }
+
+    Ok(frames)
+}
+
+/// CRC-32 with the zlib parameters, as computed by the device.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xEDB88320,
+            _ => crc >> 1,
+        })
+    })
+}
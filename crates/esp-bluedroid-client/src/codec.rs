@@ -0,0 +1,126 @@
+use bincode::config::{BigEndian, Configuration, Fixint, LittleEndian, Varint};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Integer encoding used for serde values, mirrors `esp_bluedroid::gatts::attribute::encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntEncoding {
+    Fixed,
+    Variable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Must match the encoding configured on the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingConfig {
+    pub int_encoding: IntEncoding,
+    pub endianness: Endianness,
+    pub limit: Option<usize>,
+}
+
+impl EncodingConfig {
+    /// Default encoding of the device, same as `bincode::config::standard()`
+    pub const STANDARD: Self = Self {
+        int_encoding: IntEncoding::Variable,
+        endianness: Endianness::Little,
+        limit: None,
+    };
+
+    /// Same as `bincode::config::legacy()`
+    pub const LEGACY: Self = Self {
+        int_encoding: IntEncoding::Fixed,
+        endianness: Endianness::Little,
+        limit: None,
+    };
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+macro_rules! with_bincode_config {
+    ($config:expr, |$bincode:ident| $body:expr) => {
+        match ($config.int_encoding, $config.endianness) {
+            (IntEncoding::Variable, Endianness::Little) => {
+                let $bincode: Configuration<LittleEndian, Varint> = bincode::config::standard();
+                $body
+            }
+            (IntEncoding::Variable, Endianness::Big) => {
+                let $bincode: Configuration<BigEndian, Varint> =
+                    bincode::config::standard().with_big_endian();
+                $body
+            }
+            (IntEncoding::Fixed, Endianness::Little) => {
+                let $bincode: Configuration<LittleEndian, Fixint> =
+                    bincode::config::standard().with_fixed_int_encoding();
+                $body
+            }
+            (IntEncoding::Fixed, Endianness::Big) => {
+                let $bincode: Configuration<BigEndian, Fixint> = bincode::config::standard()
+                    .with_fixed_int_encoding()
+                    .with_big_endian();
+                $body
+            }
+        }
+    };
+}
+
+/// Encodes value the same way as the blanket serde `Attribute` implementation on the device.
+pub fn encode<T: Serialize>(value: &T) -> anyhow::Result<Vec<u8>> {
+    encode_with(value, EncodingConfig::STANDARD)
+}
+
+/// Decodes bytes produced by the blanket serde `Attribute` implementation on the device.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> anyhow::Result<T> {
+    decode_with(bytes, EncodingConfig::STANDARD)
+}
+
+pub fn encode_with<T: Serialize>(value: &T, config: EncodingConfig) -> anyhow::Result<Vec<u8>> {
+    let bytes = with_bincode_config!(config, |bincode| {
+        bincode::serde::encode_to_vec(value, bincode)
+    })
+    .map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to serialize characteristic value to bytes: {:?}",
+            err
+        )
+    })?;
+
+    if let Some(limit) = config.limit.filter(|limit| bytes.len() > *limit) {
+        return Err(anyhow::anyhow!(
+            "Serialized characteristic value is {} bytes, over the limit of {} bytes",
+            bytes.len(),
+            limit
+        ));
+    }
+
+    Ok(bytes)
+}
+
+pub fn decode_with<T: DeserializeOwned>(bytes: &[u8], config: EncodingConfig) -> anyhow::Result<T> {
+    if let Some(limit) = config.limit.filter(|limit| bytes.len() > *limit) {
+        return Err(anyhow::anyhow!(
+            "Characteristic value is {} bytes, over the limit of {} bytes",
+            bytes.len(),
+            limit
+        ));
+    }
+
+    let (value, _): (T, usize) = with_bincode_config!(config, |bincode| {
+        bincode::serde::decode_from_slice(bytes, bincode)
+    })
+    .map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to deserialize bytes to characteristic value: {:?}",
+            err
+        )
+    })?;
+
+    Ok(value)
+}
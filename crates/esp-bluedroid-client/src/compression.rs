@@ -0,0 +1,33 @@
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+/// Header byte of a frame holding uncompressed payload.
+pub const RAW: u8 = 0x00;
+/// Header byte of a frame holding raw DEFLATE compressed payload.
+pub const DEFLATE: u8 = 0x01;
+
+/// Wraps payload the same way as `esp_bluedroid::gatts::compression::compress`.
+pub fn compress(bytes: &[u8], threshold: usize) -> Vec<u8> {
+    if bytes.len() >= threshold {
+        let compressed = compress_to_vec(bytes, 6);
+
+        if compressed.len() < bytes.len() {
+            return [&[DEFLATE], compressed.as_slice()].concat();
+        }
+    }
+
+    [&[RAW], bytes].concat()
+}
+
+/// Unwraps frame produced by the device, `max_len` limits size of decompressed payload.
+pub fn decompress(frame: &[u8], max_len: usize) -> anyhow::Result<Vec<u8>> {
+    match frame.split_first() {
+        Some((&RAW, payload)) => Ok(payload.to_vec()),
+        Some((&DEFLATE, payload)) => decompress_to_vec_with_limit(payload, max_len)
+            .map_err(|err| anyhow::anyhow!("Failed to decompress frame: {:?}", err)),
+        Some((header, _)) => Err(anyhow::anyhow!(
+            "Unknown compression frame header: {:#04x}",
+            header
+        )),
+        None => Err(anyhow::anyhow!("Empty compression frame")),
+    }
+}
@@ -0,0 +1,217 @@
+use std::{pin::Pin, time::Duration};
+
+use btleplug::{
+    api::{Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType},
+    platform::{Manager, Peripheral},
+};
+use futures::{Stream, StreamExt, stream};
+use serde::{Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+use crate::{
+    codec::{self, EncodingConfig},
+    logger::{FramedLogAssembler, LOGGER_SERVICE_UUID, LOGGER_TX_UUID, LogAssembler, LogFrame},
+    protocol::{PROTOCOL_INFO_UUID, ProtocolFeatures, ProtocolInfo},
+};
+
+pub type ValueStream<T> = Pin<Box<dyn Stream<Item = anyhow::Result<T>> + Send>>;
+
+/// Connected device exposing `esp-bluedroid` characteristics.
+pub struct Device {
+    peripheral: Peripheral,
+    encoding: EncodingConfig,
+}
+
+impl Device {
+    /// Scans on the first available adapter until a device advertising `name` is found and connects to it.
+    pub async fn connect_by_name(name: &str, timeout: Duration) -> anyhow::Result<Self> {
+        let manager = Manager::new().await?;
+        let adapter = manager
+            .adapters()
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No Bluetooth adapter found"))?;
+
+        adapter.start_scan(ScanFilter::default()).await?;
+
+        let peripheral = tokio::time::timeout(timeout, async {
+            loop {
+                for peripheral in adapter.peripherals().await? {
+                    let local_name = peripheral
+                        .properties()
+                        .await?
+                        .and_then(|properties| properties.local_name);
+
+                    if local_name.as_deref() == Some(name) {
+                        return anyhow::Ok(peripheral);
+                    }
+                }
+
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out scanning for device {:?}", name))??;
+
+        adapter.stop_scan().await?;
+
+        Self::connect(peripheral).await
+    }
+
+    /// Connects to already discovered peripheral.
+    pub async fn connect(peripheral: Peripheral) -> anyhow::Result<Self> {
+        peripheral.connect().await?;
+        peripheral.discover_services().await?;
+
+        Ok(Self {
+            peripheral,
+            encoding: EncodingConfig::default(),
+        })
+    }
+
+    /// Reads protocol info characteristic and switches to the encoding reported by the device.
+    pub async fn negotiate(&mut self) -> anyhow::Result<ProtocolInfo> {
+        let info = self.protocol_info().await?;
+        self.encoding = info.encoding;
+
+        Ok(info)
+    }
+
+    pub async fn protocol_info(&self) -> anyhow::Result<ProtocolInfo> {
+        ProtocolInfo::parse(&self.read_bytes(PROTOCOL_INFO_UUID).await?)
+    }
+
+    /// Sets encoding of serde values, must match the one configured on the device.
+    pub fn with_encoding(mut self, encoding: EncodingConfig) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub async fn disconnect(&self) -> anyhow::Result<()> {
+        self.peripheral.disconnect().await?;
+
+        Ok(())
+    }
+
+    pub fn peripheral(&self) -> &Peripheral {
+        &self.peripheral
+    }
+
+    fn characteristic(&self, uuid: Uuid) -> anyhow::Result<Characteristic> {
+        self.peripheral
+            .characteristics()
+            .into_iter()
+            .find(|characteristic| characteristic.uuid == uuid)
+            .ok_or_else(|| anyhow::anyhow!("No found characteristic with given uuid: {}", uuid))
+    }
+
+    pub async fn read_bytes(&self, uuid: Uuid) -> anyhow::Result<Vec<u8>> {
+        Ok(self.peripheral.read(&self.characteristic(uuid)?).await?)
+    }
+
+    pub async fn write_bytes(&self, uuid: Uuid, bytes: &[u8]) -> anyhow::Result<()> {
+        self.peripheral
+            .write(&self.characteristic(uuid)?, bytes, WriteType::WithResponse)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reads characteristic holding a serde value.
+    pub async fn read<T: DeserializeOwned>(&self, uuid: Uuid) -> anyhow::Result<T> {
+        codec::decode_with(&self.read_bytes(uuid).await?, self.encoding)
+    }
+
+    /// Writes serde value to characteristic.
+    pub async fn write<T: Serialize>(&self, uuid: Uuid, value: &T) -> anyhow::Result<()> {
+        self.write_bytes(uuid, &codec::encode_with(value, self.encoding)?)
+            .await
+    }
+
+    /// Subscribes to characteristic and returns raw notified values.
+    pub async fn subscribe_bytes(&self, uuid: Uuid) -> anyhow::Result<ValueStream<Vec<u8>>> {
+        self.peripheral
+            .subscribe(&self.characteristic(uuid)?)
+            .await?;
+
+        let notifications = self.peripheral.notifications().await?;
+
+        Ok(Box::pin(notifications.filter_map(move |notification| {
+            futures::future::ready((notification.uuid == uuid).then(|| Ok(notification.value)))
+        })))
+    }
+
+    /// Subscribes to characteristic holding a serde value.
+    pub async fn subscribe<T: DeserializeOwned + Send + 'static>(
+        &self,
+        uuid: Uuid,
+    ) -> anyhow::Result<ValueStream<T>> {
+        let values = self.subscribe_bytes(uuid).await?;
+        let encoding = self.encoding;
+
+        Ok(Box::pin(
+            values.map(move |bytes| codec::decode_with(&bytes?, encoding)),
+        ))
+    }
+
+    /// Subscribes to `esp-bluedroid-logger` output and returns parsed log lines.
+    ///
+    /// Compressed output is detected by protocol info characteristic registered in the logger service.
+    pub async fn logs(&self) -> anyhow::Result<ValueStream<LogFrame>> {
+        let compressed = match self.service_protocol_info(LOGGER_SERVICE_UUID).await? {
+            Some(info) => info.features.contains(ProtocolFeatures::COMPRESSION),
+            None => false,
+        };
+        let chunks = self.subscribe_bytes(LOGGER_TX_UUID).await?;
+
+        if compressed {
+            Ok(Box::pin(Self::assemble(
+                chunks,
+                FramedLogAssembler::new(),
+                FramedLogAssembler::push,
+            )))
+        } else {
+            Ok(Box::pin(Self::assemble(
+                chunks,
+                LogAssembler::new(),
+                LogAssembler::push,
+            )))
+        }
+    }
+
+    async fn service_protocol_info(&self, service: Uuid) -> anyhow::Result<Option<ProtocolInfo>> {
+        let Some(characteristic) =
+            self.peripheral
+                .characteristics()
+                .into_iter()
+                .find(|characteristic| {
+                    characteristic.uuid == PROTOCOL_INFO_UUID
+                        && characteristic.service_uuid == service
+                })
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(ProtocolInfo::parse(
+            &self.peripheral.read(&characteristic).await?,
+        )?))
+    }
+
+    fn assemble<A: Send + 'static>(
+        chunks: ValueStream<Vec<u8>>,
+        assembler: A,
+        push: fn(&mut A, &[u8]) -> Vec<anyhow::Result<LogFrame>>,
+    ) -> impl Stream<Item = anyhow::Result<LogFrame>> + Send {
+        chunks
+            .scan(assembler, move |assembler, chunk| {
+                let frames = match chunk {
+                    Ok(chunk) => push(assembler, &chunk),
+                    Err(err) => vec![Err(err)],
+                };
+
+                futures::future::ready(Some(stream::iter(frames)))
+            })
+            .flatten()
+    }
+}
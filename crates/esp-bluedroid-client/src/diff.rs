@@ -0,0 +1,93 @@
+/// Header byte of a frame holding every field of the value.
+pub const FULL: u8 = 0x00;
+/// Header byte of a frame holding changed fields only.
+pub const PATCH: u8 = 0x01;
+
+/// Tracks value of a characteristic notified with `diff_notify`, see
+/// `esp_bluedroid::gatts::diff` for the frame layout.
+#[derive(Debug, Clone, Default)]
+pub struct DiffState {
+    fields: Option<Vec<Vec<u8>>>,
+}
+
+impl DiffState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a received frame and returns the bincode encoded value, which can be
+    /// decoded with `codec::decode`. Patches received before the full value fail.
+    pub fn apply(&mut self, frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match frame.split_first() {
+            Some((&FULL, rest)) => {
+                let (&count, mut rest) = rest
+                    .split_first()
+                    .ok_or_else(|| anyhow::anyhow!("Full diff frame is missing field count"))?;
+
+                let mut fields = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let (field, tail) = take_field(rest)?;
+                    fields.push(field.to_vec());
+                    rest = tail;
+                }
+
+                if !rest.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Full diff frame has {} trailing bytes",
+                        rest.len()
+                    ));
+                }
+
+                self.fields = Some(fields);
+            }
+            Some((&PATCH, mut rest)) => {
+                let fields = self
+                    .fields
+                    .as_mut()
+                    .ok_or_else(|| anyhow::anyhow!("Received diff patch before full value"))?;
+
+                while let Some((&index, tail)) = rest.split_first() {
+                    let (field, tail) = take_field(tail)?;
+                    let slot = fields.get_mut(index as usize).ok_or_else(|| {
+                        anyhow::anyhow!("Diff patch references unknown field {}", index)
+                    })?;
+
+                    *slot = field.to_vec();
+                    rest = tail;
+                }
+            }
+            Some((header, _)) => {
+                return Err(anyhow::anyhow!(
+                    "Unknown diff frame header: {:#04x}",
+                    header
+                ));
+            }
+            None => return Err(anyhow::anyhow!("Empty diff frame")),
+        }
+
+        self.value()
+            .ok_or_else(|| anyhow::anyhow!("Diff state has no value"))
+    }
+
+    /// Latest bincode encoded value, None until a full frame was received.
+    pub fn value(&self) -> Option<Vec<u8>> {
+        self.fields.as_ref().map(|fields| fields.concat())
+    }
+}
+
+fn take_field(bytes: &[u8]) -> anyhow::Result<(&[u8], &[u8])> {
+    let (len, rest) = bytes
+        .split_first_chunk::<2>()
+        .ok_or_else(|| anyhow::anyhow!("Diff frame field is missing its length"))?;
+    let len = u16::from_le_bytes(*len) as usize;
+
+    if rest.len() < len {
+        return Err(anyhow::anyhow!(
+            "Diff frame field of {} bytes is truncated to {}",
+            len,
+            rest.len()
+        ));
+    }
+
+    Ok(rest.split_at(len))
+}
@@ -0,0 +1,71 @@
+//! Decoder of the device identity set by `Gap::set_identity_scan_response`,
+//! read from the scan response service data without connecting.
+
+const TYPE_HARDWARE_REVISION: u8 = 0x01;
+const TYPE_FIRMWARE_VERSION: u8 = 0x02;
+const TYPE_SERIAL_HASH: u8 = 0x03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeviceIdentity {
+    pub hardware_revision: Option<u8>,
+    pub firmware_version: Option<(u8, u8, u8)>,
+    pub serial_hash: Option<u32>,
+}
+
+impl DeviceIdentity {
+    /// Parses the TLV entries of the service data, entries of unknown types are
+    /// skipped so newer devices stay readable
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut identity = Self::default();
+        let mut rest = bytes;
+
+        while let [tlv_type, len, tail @ ..] = rest {
+            let len = *len as usize;
+            if tail.len() < len {
+                return Err(anyhow::anyhow!(
+                    "Identity entry {:#04x} of {} bytes is truncated to {} bytes",
+                    tlv_type,
+                    len,
+                    tail.len()
+                ));
+            }
+
+            let (value, tail) = tail.split_at(len);
+            match (*tlv_type, value) {
+                (TYPE_HARDWARE_REVISION, [revision]) => {
+                    identity.hardware_revision = Some(*revision);
+                }
+                (TYPE_FIRMWARE_VERSION, [major, minor, patch]) => {
+                    identity.firmware_version = Some((*major, *minor, *patch));
+                }
+                (TYPE_SERIAL_HASH, [a, b, c, d]) => {
+                    identity.serial_hash = Some(u32::from_le_bytes([*a, *b, *c, *d]));
+                }
+                (TYPE_HARDWARE_REVISION | TYPE_FIRMWARE_VERSION | TYPE_SERIAL_HASH, _) => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid length {} of identity entry {:#04x}",
+                        len,
+                        tlv_type
+                    ));
+                }
+                _ => {}
+            }
+
+            rest = tail;
+        }
+
+        if !rest.is_empty() {
+            return Err(anyhow::anyhow!("Trailing byte in identity payload"));
+        }
+
+        Ok(identity)
+    }
+}
+
+/// Same hash as the device computes, to match a scanned identity against a
+/// known serial number
+pub fn serial_hash(serial: &[u8]) -> u32 {
+    serial.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
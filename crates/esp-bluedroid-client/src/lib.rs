@@ -0,0 +1,19 @@
+//! Host-side client for devices running `esp-bluedroid`.
+//!
+//! Understands the wire encoding used by the device crate (bincode encoded serde
+//! characteristic values) and the frames sent by `esp-bluedroid-logger`, so tooling
+//! and integration tests can talk to devices end-to-end.
+//!
+//! The repository root configures an Xtensa target, so build this crate for the host
+//! explicitly, e.g. `cargo build --target x86_64-unknown-linux-gnu`.
+
+pub mod codec;
+pub mod chunked;
+pub mod compression;
+pub mod device;
+pub mod diff;
+pub mod identity;
+pub mod logger;
+pub mod protocol;
+
+pub use device::Device;
@@ -0,0 +1,105 @@
+use uuid::Uuid;
+
+use crate::compression;
+
+/// Nordic UART Service used by `esp-bluedroid-logger`.
+pub const LOGGER_SERVICE_UUID: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+/// Characteristic on which the logger notifies log output.
+pub const LOGGER_TX_UUID: Uuid = Uuid::from_u128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e);
+
+/// Single log line sent by the device, formatted as `<marker> (<timestamp>) <target>: <message>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFrame {
+    pub marker: String,
+    pub timestamp: String,
+    pub target: String,
+    pub message: String,
+}
+
+impl LogFrame {
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let (marker, rest) = line
+            .split_once(" (")
+            .ok_or_else(|| anyhow::anyhow!("Missing log marker in line: {:?}", line))?;
+        let (timestamp, rest) = rest
+            .split_once(") ")
+            .ok_or_else(|| anyhow::anyhow!("Missing log timestamp in line: {:?}", line))?;
+        let (target, message) = rest
+            .split_once(": ")
+            .ok_or_else(|| anyhow::anyhow!("Missing log target in line: {:?}", line))?;
+
+        Ok(LogFrame {
+            marker: marker.to_string(),
+            timestamp: timestamp.to_string(),
+            target: target.to_string(),
+            message: message.to_string(),
+        })
+    }
+}
+
+/// Reassembles log lines from notification chunks, which are cut without regard to line boundaries.
+#[derive(Debug, Default)]
+pub struct LogAssembler {
+    buffer: Vec<u8>,
+}
+
+impl LogAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds received chunk and returns every line completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<anyhow::Result<LogFrame>> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut frames = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..line.len() - 1]);
+
+            if !line.trim().is_empty() {
+                frames.push(LogFrame::parse(&line));
+            }
+        }
+
+        frames
+    }
+}
+
+/// Reassembles log lines sent by the logger with compression enabled, where output is
+/// split into `[len: u16 LE][frame]` records, each holding a compression frame.
+#[derive(Debug, Default)]
+pub struct FramedLogAssembler {
+    buffer: Vec<u8>,
+    lines: LogAssembler,
+}
+
+impl FramedLogAssembler {
+    // Logger drains at most its whole ring buffer into a single record
+    const MAX_RECORD_LEN: usize = 4096;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds received chunk and returns every line completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<anyhow::Result<LogFrame>> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut frames = Vec::new();
+        while self.buffer.len() >= 2 {
+            let len = u16::from_le_bytes([self.buffer[0], self.buffer[1]]) as usize;
+            if self.buffer.len() < len + 2 {
+                break;
+            }
+
+            let record: Vec<u8> = self.buffer.drain(..len + 2).skip(2).collect();
+            match compression::decompress(&record, Self::MAX_RECORD_LEN) {
+                Ok(text) => frames.extend(self.lines.push(&text)),
+                Err(err) => frames.push(Err(err)),
+            }
+        }
+
+        frames
+    }
+}
@@ -0,0 +1,77 @@
+use uuid::Uuid;
+
+use crate::codec::{EncodingConfig, Endianness, IntEncoding};
+
+/// Characteristic registered by `Service::register_protocol_info` on the device.
+pub const PROTOCOL_INFO_UUID: Uuid = Uuid::from_u128(0x6a1f0002_8d3c_4b6e_9f2a_3c5e7b9d1e0f);
+
+/// Highest protocol version this client understands.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProtocolFeatures(pub u32);
+
+impl ProtocolFeatures {
+    pub const JSON: Self = Self(1 << 0);
+    pub const CHUNKING: Self = Self(1 << 1);
+    pub const RELIABLE: Self = Self(1 << 2);
+    pub const COMPRESSION: Self = Self(1 << 3);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Framing/encoding description reported by the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolInfo {
+    pub protocol_version: u8,
+    pub crate_version: (u8, u8, u8),
+    pub encoding: EncodingConfig,
+    pub features: ProtocolFeatures,
+}
+
+impl ProtocolInfo {
+    pub const LEN: usize = 13;
+
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != Self::LEN {
+            return Err(anyhow::anyhow!(
+                "Invalid length for ProtocolInfo: expected {} bytes, got {}",
+                Self::LEN,
+                bytes.len()
+            ));
+        }
+
+        if bytes[0] > PROTOCOL_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported protocol version {}, client supports up to {}",
+                bytes[0],
+                PROTOCOL_VERSION
+            ));
+        }
+
+        let limit = u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]);
+
+        Ok(Self {
+            protocol_version: bytes[0],
+            crate_version: (bytes[1], bytes[2], bytes[3]),
+            encoding: EncodingConfig {
+                int_encoding: if bytes[4] & (1 << 0) != 0 {
+                    IntEncoding::Fixed
+                } else {
+                    IntEncoding::Variable
+                },
+                endianness: if bytes[4] & (1 << 1) != 0 {
+                    Endianness::Big
+                } else {
+                    Endianness::Little
+                },
+                limit: (limit != 0).then_some(limit as usize),
+            },
+            features: ProtocolFeatures(u32::from_le_bytes([
+                bytes[9], bytes[10], bytes[11], bytes[12],
+            ])),
+        })
+    }
+}
@@ -0,0 +1,330 @@
+//! `#[derive(Attribute)]` for `esp-bluedroid`, enabled with its `derive` feature.
+//!
+//! Generates `Attribute` for structs which do not implement serde traits, so the
+//! wire format is chosen per struct instead of by the blanket serde implementation:
+//!
+//! ```ignore
+//! #[derive(Attribute)]
+//! #[attribute(endian = "big")]
+//! struct Measurement {
+//!     sequence: u16,
+//!     #[attribute(endian = "little")]
+//!     value: i32,
+//!     flags: [u8; 2],
+//! }
+//! ```
+//!
+//! Struct options, all optional:
+//!
+//! - `encoding = "packed"` (default) - fields are concatenated without padding or
+//!   length prefixes, numbers take the width of their type, a trailing `Vec<u8>` or
+//!   `String` takes the rest of the value, in any other position it fails to compile
+//! - `encoding = "bincode"` - every field is encoded with bincode on its own, so
+//!   fields may be any serde type, independent of the global `EncodingConfig`
+//! - `endian = "little"` (default) or `"big"` - byte order of every field
+//! - `int = "fixed"` (default) or `"variable"` - integer encoding of bincode fields
+//!
+//! Fields accept `endian` to override the byte order of the struct.
+//!
+//! Packed fields may be derived structs themselves. A nested struct is encoded with
+//! its own options, the `endian` of the outer struct or of the field holding it
+//! does not apply, so its byte order is set on its own declaration. A nested struct
+//! whose last field takes the rest of the value may only be the last field as well.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{Data, DeriveInput, Fields, LitStr, parse_macro_input, spanned::Spanned};
+
+#[proc_macro_derive(Attribute, attributes(attribute))]
+pub fn derive_attribute(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Packed,
+    Bincode,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Endian {
+    Little,
+    Big,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Int {
+    Fixed,
+    Variable,
+}
+
+struct Options {
+    encoding: Encoding,
+    endian: Endian,
+    int: Int,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.span(),
+            "#[derive(Attribute)] supports structs only",
+        ));
+    };
+
+    let mut options = Options {
+        encoding: Encoding::Packed,
+        endian: Endian::Little,
+        int: Int::Fixed,
+    };
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("attribute"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let value = meta.value()?.parse::<LitStr>()?;
+
+            match (
+                meta.path.get_ident().map(|ident| ident.to_string()),
+                value.value().as_str(),
+            ) {
+                (Some(key), "packed") if key == "encoding" => options.encoding = Encoding::Packed,
+                (Some(key), "bincode") if key == "encoding" => options.encoding = Encoding::Bincode,
+                (Some(key), endian) if key == "endian" => {
+                    options.endian = parse_endian(&value, endian)?
+                }
+                (Some(key), "fixed") if key == "int" => options.int = Int::Fixed,
+                (Some(key), "variable") if key == "int" => options.int = Int::Variable,
+                _ => return Err(meta.error("unsupported attribute option")),
+            }
+
+            Ok(())
+        })?;
+    }
+
+    let krate = quote!(::esp_bluedroid::gatts::attribute);
+    let runtime = quote!(#krate::derive);
+
+    let mut encode_fields = Vec::new();
+    let mut decode_fields = Vec::new();
+    let mut field_schemas = Vec::new();
+    // Fields before the last one which must not take the rest through a nested struct
+    let mut rest_checks = Vec::new();
+    // Raw schemas are little endian, other layouts are exported as plain bytes
+    let mut raw_schema = options.encoding == Encoding::Packed;
+
+    for (index, field) in data.fields.iter().enumerate() {
+        if options.encoding == Encoding::Packed
+            && index + 1 < data.fields.len()
+            && takes_rest(&field.ty)
+        {
+            return Err(syn::Error::new(
+                field.ty.span(),
+                "`Vec<u8>` and `String` take the rest of a packed value, only the last field may be one",
+            ));
+        }
+
+        if options.encoding == Encoding::Packed && index + 1 < data.fields.len() {
+            let ty = &field.ty;
+            let message = format!(
+                "`{}` takes the rest of a packed value, only the last field may be one",
+                quote!(#ty)
+            );
+            rest_checks.push(quote_spanned! {ty.span()=>
+                if <#ty as #runtime::PackedField>::HAS_REST {
+                    ::std::panic!(#message);
+                }
+            });
+        }
+
+        let mut endian = options.endian;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("attribute"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if !meta.path.is_ident("endian") {
+                    return Err(meta.error("unsupported field attribute option"));
+                }
+
+                let value = meta.value()?.parse::<LitStr>()?;
+                endian = parse_endian(&value, &value.value())?;
+
+                Ok(())
+            })?;
+        }
+
+        raw_schema &= endian == Endian::Little;
+
+        let endianness = match endian {
+            Endian::Little => quote!(#runtime::Endianness::Little),
+            Endian::Big => quote!(#runtime::Endianness::Big),
+        };
+        let int_encoding = match options.int {
+            Int::Fixed => quote!(#runtime::IntEncoding::Fixed),
+            Int::Variable => quote!(#runtime::IntEncoding::Variable),
+        };
+
+        let ty = &field.ty;
+        let (member, binding, name) = match &field.ident {
+            Some(ident) => (quote!(#ident), ident.clone(), ident.to_string()),
+            None => {
+                let index = syn::Index::from(index);
+                (
+                    quote!(#index),
+                    format_ident!("field_{}", index),
+                    index.index.to_string(),
+                )
+            }
+        };
+
+        match options.encoding {
+            Encoding::Packed => {
+                encode_fields.push(quote! {
+                    #runtime::PackedField::encode_packed(&self.#member, #endianness, out)?;
+                });
+                decode_fields.push(quote! {
+                    let #binding = <#ty as #runtime::PackedField>::decode_packed(bytes, #endianness)?;
+                });
+            }
+            Encoding::Bincode => {
+                encode_fields.push(quote! {
+                    #runtime::encode_bincode(&self.#member, #int_encoding, #endianness, out)?;
+                });
+                decode_fields.push(quote! {
+                    let #binding: #ty = #runtime::decode_bincode(bytes, #int_encoding, #endianness)?;
+                });
+            }
+        }
+
+        field_schemas.push(quote! {
+            #runtime::FieldSchema {
+                name: ::std::string::String::from(#name),
+                value: <#ty as #runtime::PackedField>::schema()?,
+            }
+        });
+    }
+
+    let bindings = data
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| match &field.ident {
+            Some(ident) => ident.clone(),
+            None => format_ident!("field_{}", index),
+        });
+    let construct = match &data.fields {
+        Fields::Named(_) => quote!(Self { #(#bindings),* }),
+        Fields::Unnamed(_) => quote!(Self ( #(#bindings),* )),
+        Fields::Unit => quote!(Self),
+    };
+
+    let ident = &input.ident;
+    let struct_name = ident.to_string();
+    let schema = match raw_schema {
+        true => quote! {
+            ::std::option::Option::Some(#runtime::ValueSchema::Struct {
+                name: ::std::string::String::from(#struct_name),
+                fields: ::std::vec![#(#field_schemas),*],
+            })
+        },
+        false => quote!(::std::option::Option::None),
+    };
+
+    // A nested struct takes the rest when its own last field does
+    let has_rest = match (options.encoding, data.fields.iter().last()) {
+        (Encoding::Packed, Some(field)) => {
+            let ty = &field.ty;
+            quote!(<#ty as #runtime::PackedField>::HAS_REST)
+        }
+        _ => quote!(false),
+    };
+
+    // Checked when the crate compiles, generic structs once they are instantiated
+    let (rest_assert, rest_check) = match (rest_checks.is_empty(), input.generics.params.is_empty())
+    {
+        (true, _) => (quote!(), quote!()),
+        (false, true) => (quote!(const _: () = { #(#rest_checks)* };), quote!()),
+        (false, false) => (quote!(), quote!(const { #(#rest_checks)* })),
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        #rest_assert
+
+        impl #impl_generics #runtime::PackedField for #ident #ty_generics #where_clause {
+            const HAS_REST: bool = #has_rest;
+
+            #[allow(unused_variables)]
+            fn encode_packed(
+                &self,
+                _endianness: #runtime::Endianness,
+                out: &mut ::std::vec::Vec<u8>,
+            ) -> #runtime::Result<()> {
+                #rest_check
+                #(#encode_fields)*
+                ::std::result::Result::Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn decode_packed(
+                bytes: &mut &[u8],
+                _endianness: #runtime::Endianness,
+            ) -> #runtime::Result<Self> {
+                #rest_check
+                #(#decode_fields)*
+                ::std::result::Result::Ok(#construct)
+            }
+
+            fn schema() -> ::std::option::Option<#runtime::ValueSchema> {
+                #schema
+            }
+        }
+
+        impl #impl_generics #krate::Attribute for #ident #ty_generics #where_clause {
+            fn get_bytes(&self) -> #runtime::Result<::std::vec::Vec<u8>> {
+                #runtime::get_bytes(self)
+            }
+
+            fn from_bytes(bytes: &[u8]) -> #runtime::Result<Self> {
+                #runtime::from_bytes(bytes)
+            }
+
+            fn value_schema(&self) -> (#runtime::ValueFormat, #runtime::ValueSchema) {
+                #runtime::value_schema::<Self>()
+            }
+        }
+    })
+}
+
+// Packed fields without a length of their own, see `PackedField` of `Vec<u8>` and `String`
+fn takes_rest(ty: &syn::Type) -> bool {
+    let syn::Type::Path(path) = ty else {
+        return false;
+    };
+
+    path.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Vec" || segment.ident == "String")
+}
+
+fn parse_endian(lit: &LitStr, value: &str) -> syn::Result<Endian> {
+    match value {
+        "little" => Ok(Endian::Little),
+        "big" => Ok(Endian::Big),
+        _ => Err(syn::Error::new(
+            lit.span(),
+            "endian must be \"little\" or \"big\"",
+        )),
+    }
+}
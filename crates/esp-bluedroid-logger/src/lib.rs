@@ -3,22 +3,17 @@ use std::{
     ops::Add,
     sync::{
         Arc, Mutex, RwLock,
-        atomic::{AtomicI32, AtomicUsize},
+        atomic::{AtomicI32, AtomicUsize, Ordering},
     },
 };
 
 use crossbeam::{channel::Sender, queue::ArrayQueue};
 use esp_bluedroid::{
     gatts::{
-        attribute::defaults::BytesAttr,
-        characteristic::{Characteristic, CharacteristicConfig},
         service::Service,
+        standard::{UartConfig, UartService},
     },
     svc::{
-        bt::{
-            BtUuid,
-            ble::gatt::{GattId, GattServiceId},
-        },
         log::EspLogger,
         sys::{esp_log_system_timestamp, esp_log_timestamp},
     },
@@ -35,6 +30,9 @@ static BLE_LOGGER: BleLogger = BleLogger();
 
 pub struct BleLoggerService {
     pub service: Service,
+    uart: UartService,
+    // If Some, log output is sent as length-prefixed frames compressed above this threshold
+    compression_threshold: Option<usize>,
 }
 
 lazy_static! {
@@ -53,6 +51,7 @@ lazy_static! {
 }
 
 static EEE: AtomicUsize = AtomicUsize::new(666);
+static SEND_ERRORS: AtomicUsize = AtomicUsize::new(0);
 
 struct LoggerQueue {
     buffer: Mutex<SharedRb<Heap<u8>>>,
@@ -63,18 +62,30 @@ struct LoggerQueue {
 
 impl BleLoggerService {
     pub fn new() -> Self {
-        let service = Service::new(
-            GattServiceId {
-                id: GattId {
-                    uuid: BtUuid::uuid128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e), // Nordic UART Service
-                    inst_id: 0,
-                },
-                is_primary: true,
-            },
-            10,
-        );
-
-        Self { service }
+        let uart = UartService::new(UartConfig {
+            description: Some("esp-bluedriod-logger".to_string()),
+            ..Default::default()
+        });
+
+        Self {
+            service: uart.0.service.clone(),
+            uart,
+            compression_threshold: None,
+        }
+    }
+
+    /// Sends log output as `[len: u16 LE][frame]` records, where frame is produced by
+    /// `esp_bluedroid::gatts::compression::compress`. Protocol info characteristic is
+    /// registered in the logger service, so clients can detect the framed format
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, threshold: usize) -> Self {
+        self.compression_threshold = Some(threshold);
+        self
+    }
+
+    /// Log messages which failed to reach at least one subscribed peer
+    pub fn send_errors(&self) -> usize {
+        SEND_ERRORS.load(Ordering::Relaxed)
     }
 
     pub fn logger(&self) -> &EspLogger {
@@ -89,37 +100,14 @@ impl BleLoggerService {
     }
 
     pub fn register(&self) -> anyhow::Result<()> {
-        let tx_characteristic = Characteristic::new(
-            BytesAttr(vec![0x00; 20]),
-            CharacteristicConfig {
-                uuid: BtUuid::uuid128(0x6e400002_b5a3_f393_e0a9_e50e24dcca9e),
-                value_max_len: 20,
-                readable: true,
-                writable: true,
-                broadcasted: false,
-                enable_notify: false,
-                description: None,
-            },
-            None,
-        );
-
-        let rx_characteristic = Characteristic::new(
-            BytesAttr(vec![0x00; 20]),
-            CharacteristicConfig {
-                uuid: BtUuid::uuid128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e),
-                value_max_len: 20,
-                readable: true,
-                writable: false,
-                broadcasted: false,
-                enable_notify: true,
-                description: Some("esp-bluedriod-logger".to_string()),
-            },
-            None,
-        );
-
-        self.service.register_characteristic(&tx_characteristic)?;
-        self.service.register_characteristic(&rx_characteristic)?;
+        self.uart.register()?;
+
+        let compression_threshold = self.compression_threshold;
+        if compression_threshold.is_some() {
+            self.service.register_protocol_info()?;
+        }
 
+        let uart = self.uart.clone();
         std::thread::spawn(move || {
             let mut i = 0;
             for _ in LOGGER_QUEUE.notify_receiver.iter() {
@@ -136,21 +124,26 @@ impl BleLoggerService {
                     continue;
                 }
 
-                let errors: Vec<anyhow::Error> = message
-                    .chunks(20)
-                    .filter_map(|chunk| {
-                        i += 1;
-                        EEE.store(i, std::sync::atomic::Ordering::Relaxed);
-
-                        rx_characteristic
-                            .update_value(BytesAttr(chunk.to_vec()))
-                            .err()
-                    })
-                    .collect();
-
-                // if !errors.is_empty() {
-                //     log::error!("Failed to send log message: {:?}", errors);
-                // }
+                let message = match compression_threshold {
+                    Some(threshold) => frame(&message, threshold),
+                    None => message,
+                };
+
+                i += 1;
+                EEE.store(i, std::sync::atomic::Ordering::Relaxed);
+
+                // Logged to the console only, through BLE the error would be sent again
+                if let Err(err) = uart.send(&message) {
+                    SEND_ERRORS.fetch_add(1, Ordering::Relaxed);
+                    log::Log::log(
+                        &ESP_LOGGER,
+                        &log::Record::builder()
+                            .level(log::Level::Warn)
+                            .target("esp-bluedroid-logger")
+                            .args(format_args!("Failed to send log message: {:?}", err))
+                            .build(),
+                    );
+                }
             }
 
             log::info!("Sender thread: finished");
@@ -174,6 +167,22 @@ impl BleLoggerService {
     }
 }
 
+#[cfg(feature = "compression")]
+fn frame(message: &[u8], threshold: usize) -> Vec<u8> {
+    let frame = esp_bluedroid::gatts::compression::compress(message, threshold);
+
+    let mut record = Vec::with_capacity(frame.len() + 2);
+    record.extend_from_slice(&(frame.len() as u16).to_le_bytes());
+    record.extend_from_slice(&frame);
+
+    record
+}
+
+#[cfg(not(feature = "compression"))]
+fn frame(message: &[u8], _threshold: usize) -> Vec<u8> {
+    message.to_vec()
+}
+
 struct BleLogger();
 
 impl log::Log for BleLogger {
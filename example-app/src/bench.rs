@@ -0,0 +1,132 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use esp_bluedroid::{
+    ble,
+    gap::GapConfig,
+    gatts::{
+        app::App,
+        attribute::defaults::BytesAttr,
+        characteristic::{Characteristic, CharacteristicConfig},
+        connection::ConnectionStatus,
+        service::Service,
+    },
+    svc::{
+        bt::{
+            BtUuid,
+            ble::gatt::{GattId, GattServiceId},
+        },
+        hal::prelude::Peripherals,
+    },
+};
+
+const ITERATIONS: usize = 200;
+const PAYLOAD_LEN: usize = 20;
+
+// On-target counterpart of the host benchmarks in crates/esp-bluedroid-bench,
+// connect with any central and enable indications, results are logged
+pub fn main() -> anyhow::Result<()> {
+    esp_bluedroid::svc::sys::link_patches();
+    esp_bluedroid::svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take()?;
+    let ble = ble::Ble::new(peripherals.modem)?;
+    let app = ble.gatts.register_app(&App::new(0))?;
+
+    let service = app.register_service(&Service::new(
+        GattServiceId {
+            id: GattId {
+                uuid: BtUuid::uuid128(0xB3_4C_4D_00),
+                inst_id: 0,
+            },
+            is_primary: true,
+        },
+        10,
+    ))?;
+
+    let characteristic = service.register_characteristic(&Characteristic::new(
+        BytesAttr(vec![0; PAYLOAD_LEN]),
+        CharacteristicConfig {
+            uuid: BtUuid::uuid128(0xB3_4C_4D_01),
+            value_max_len: PAYLOAD_LEN,
+            readable: true,
+            enable_notify: true,
+            ..Default::default()
+        },
+        None,
+    ))?;
+
+    service.start()?;
+    ble.gap.set_config(GapConfig {
+        device_name: "esp-bluedroid bench".to_string(),
+        ..GapConfig::default()
+    })?;
+    ble.gap.start_advertising()?;
+
+    // Updates channel is bounded, keep it drained so updates never block
+    let updates_rx = characteristic.0.attribute.updates_rx.clone();
+    std::thread::spawn(move || for _ in updates_rx.iter() {});
+
+    for event in ble.gatts.0.connections_rx.iter() {
+        if let ConnectionStatus::Connected(connection) = event.status {
+            log::info!(
+                "Peer {:?} connected, starting benchmark",
+                connection.peer_addr()
+            );
+            break;
+        }
+    }
+
+    // Give the peer time to negotiate MTU and subscribe
+    std::thread::sleep(Duration::from_secs(5));
+
+    let elapsed = run_updates(&characteristic)?;
+    log::info!(
+        "Indication round trip: {:?} per update, {:.1} B/s",
+        elapsed / ITERATIONS as u32,
+        (ITERATIONS * PAYLOAD_LEN) as f32 / elapsed.as_secs_f32()
+    );
+
+    // Same updates while other tasks read the value, measuring lock contention
+    let running = Arc::new(AtomicBool::new(true));
+    let readers = (0..2)
+        .map(|_| {
+            let characteristic = characteristic.clone();
+            let running = running.clone();
+
+            std::thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    let _ = characteristic.value();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let elapsed = run_updates(&characteristic)?;
+    log::info!(
+        "Indication round trip with concurrent readers: {:?} per update",
+        elapsed / ITERATIONS as u32
+    );
+
+    running.store(false, Ordering::Relaxed);
+    readers.into_iter().for_each(|reader| {
+        let _ = reader.join();
+    });
+
+    Ok(())
+}
+
+fn run_updates(characteristic: &Characteristic<BytesAttr>) -> anyhow::Result<Duration> {
+    let start = Instant::now();
+
+    for iteration in 0..ITERATIONS {
+        characteristic.update_value(BytesAttr(vec![iteration as u8; PAYLOAD_LEN]))?;
+    }
+
+    Ok(start.elapsed())
+}
@@ -79,6 +79,7 @@ fn run_ble_example() -> anyhow::Result<()> {
             broadcasted: true,
             enable_notify: true,
             description: Some("LEDs Configuration".to_string()),
+            ..Default::default()
         },
         None,
     ))?;
@@ -1,3 +1,5 @@
+#[cfg(feature = "esp-bluedroid-bench")]
+pub mod bench;
 pub mod esp_bluedroid_example;
 pub mod esp_idf_example;
 pub mod hello_world;
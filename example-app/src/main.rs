@@ -5,6 +5,9 @@ fn main() {
     #[cfg(feature = "esp-idf")]
     example_app::esp_idf_example::example::main().unwrap();
 
+    #[cfg(feature = "esp-bluedroid-bench")]
+    example_app::bench::main().unwrap();
+
     #[cfg(feature = "esp-hello-world")]
     example_app::hello_world::main().unwrap();
 }
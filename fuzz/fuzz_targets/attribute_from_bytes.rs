@@ -0,0 +1,74 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The device crate depends on esp-idf and cannot be built for the host, so the
+// attribute modules are included directly against a minimal copy of the trait
+pub trait Attribute {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>>;
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized;
+
+    fn value_schema(&self) -> (schema::ValueFormat, schema::ValueSchema) {
+        (schema::ValueFormat::Raw, schema::ValueSchema::Bytes)
+    }
+}
+
+// Resolves `crate::gatts::attribute::Attribute` used by the included modules
+mod gatts {
+    pub mod attribute {
+        pub use crate::Attribute;
+    }
+
+    pub(crate) use crate::schema;
+}
+
+#[allow(dead_code)]
+#[path = "../../src/gatts/attribute/defaults.rs"]
+mod defaults;
+
+#[allow(dead_code)]
+#[path = "../../src/gatts/attribute/telemetry.rs"]
+mod telemetry;
+
+#[allow(dead_code)]
+#[path = "../../src/gatts/schema.rs"]
+mod schema;
+
+use defaults::*;
+use telemetry::*;
+
+// Every value accepted from the peer must encode back to bytes which decode to the same value
+fn check<T: Attribute>(data: &[u8]) {
+    let Ok(value) = T::from_bytes(data) else {
+        return;
+    };
+
+    let bytes = value.get_bytes().expect("decoded value must encode");
+    let decoded = T::from_bytes(&bytes).expect("encoded value must decode");
+
+    assert_eq!(
+        bytes,
+        decoded.get_bytes().expect("decoded value must encode")
+    );
+}
+
+fuzz_target!(|data: &[u8]| {
+    check::<U8Attr>(data);
+    check::<U16Attr>(data);
+    check::<U32Attr>(data);
+    check::<I8Attr>(data);
+    check::<I16Attr>(data);
+    check::<I32Attr>(data);
+    check::<BoolAttr>(data);
+    check::<F32Attr>(data);
+    check::<StringAttr>(data);
+    check::<BytesAttr>(data);
+
+    check::<TemperatureAttr>(data);
+    check::<HumidityAttr>(data);
+    check::<BatteryLevelAttr>(data);
+    check::<Percentage8Attr>(data);
+    check::<DateTimeAttr>(data);
+});
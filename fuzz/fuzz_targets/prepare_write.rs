@@ -0,0 +1,53 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/gatts/reassembly.rs"]
+mod reassembly;
+
+use reassembly::{ReassemblyError, WriteReassembler};
+
+// Same as ESP_GATT_MAX_ATTR_LEN
+const MAX_ATTR_LEN: usize = 517;
+
+#[derive(Debug, Arbitrary)]
+struct Fragment {
+    offset: u16,
+    value: Vec<u8>,
+}
+
+fuzz_target!(|fragments: Vec<Fragment>| {
+    let mut reassembler = WriteReassembler::new();
+
+    for fragment in fragments {
+        let previous = reassembler.value().to_vec();
+
+        match reassembler.write(fragment.offset, &fragment.value, MAX_ATTR_LEN) {
+            Ok(()) => {
+                let offset = fragment.offset as usize;
+                let value = reassembler.value();
+
+                assert!(offset <= previous.len());
+                assert_eq!(
+                    &value[offset..offset + fragment.value.len()],
+                    fragment.value
+                );
+                assert_eq!(value[..offset], previous[..offset]);
+                assert!(value.len() <= MAX_ATTR_LEN);
+            }
+            Err(ReassemblyError::InvalidOffset) => {
+                assert!(fragment.offset as usize > previous.len());
+                assert_eq!(reassembler.value(), previous);
+            }
+            Err(ReassemblyError::InvalidLength) => {
+                assert!(fragment.offset as usize + fragment.value.len() > MAX_ATTR_LEN);
+                assert_eq!(reassembler.value(), previous);
+            }
+            Err(ReassemblyError::OtherAttribute) => {
+                unreachable!("a single reassembler has no attribute")
+            }
+        }
+    }
+});
@@ -0,0 +1,388 @@
+//! Well-known numbers assigned by the Bluetooth SIG: 16-bit UUIDs of services,
+//! characteristics and descriptors, appearance values and company identifiers.
+//! Covers the common subset, lookups return None for anything else.
+
+use esp_idf_svc::bt::BtUuid;
+
+pub mod service {
+    pub const GENERIC_ACCESS: u16 = 0x1800;
+    pub const GENERIC_ATTRIBUTE: u16 = 0x1801;
+    pub const IMMEDIATE_ALERT: u16 = 0x1802;
+    pub const LINK_LOSS: u16 = 0x1803;
+    pub const TX_POWER: u16 = 0x1804;
+    pub const CURRENT_TIME: u16 = 0x1805;
+    pub const HEALTH_THERMOMETER: u16 = 0x1809;
+    pub const DEVICE_INFORMATION: u16 = 0x180A;
+    pub const HEART_RATE: u16 = 0x180D;
+    pub const BATTERY: u16 = 0x180F;
+    pub const BLOOD_PRESSURE: u16 = 0x1810;
+    pub const HUMAN_INTERFACE_DEVICE: u16 = 0x1812;
+    pub const SCAN_PARAMETERS: u16 = 0x1813;
+    pub const RUNNING_SPEED_AND_CADENCE: u16 = 0x1814;
+    pub const CYCLING_SPEED_AND_CADENCE: u16 = 0x1816;
+    pub const CYCLING_POWER: u16 = 0x1818;
+    pub const LOCATION_AND_NAVIGATION: u16 = 0x1819;
+    pub const ENVIRONMENTAL_SENSING: u16 = 0x181A;
+    pub const USER_DATA: u16 = 0x181C;
+    pub const WEIGHT_SCALE: u16 = 0x181D;
+    pub const FITNESS_MACHINE: u16 = 0x1826;
+}
+
+pub mod characteristic {
+    pub const DEVICE_NAME: u16 = 0x2A00;
+    pub const APPEARANCE: u16 = 0x2A01;
+    pub const PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS: u16 = 0x2A04;
+    pub const SERVICE_CHANGED: u16 = 0x2A05;
+    pub const ALERT_LEVEL: u16 = 0x2A06;
+    pub const TX_POWER_LEVEL: u16 = 0x2A07;
+    pub const BATTERY_LEVEL: u16 = 0x2A19;
+    pub const TEMPERATURE_MEASUREMENT: u16 = 0x2A1C;
+    pub const SYSTEM_ID: u16 = 0x2A23;
+    pub const MODEL_NUMBER_STRING: u16 = 0x2A24;
+    pub const SERIAL_NUMBER_STRING: u16 = 0x2A25;
+    pub const FIRMWARE_REVISION_STRING: u16 = 0x2A26;
+    pub const HARDWARE_REVISION_STRING: u16 = 0x2A27;
+    pub const SOFTWARE_REVISION_STRING: u16 = 0x2A28;
+    pub const MANUFACTURER_NAME_STRING: u16 = 0x2A29;
+    pub const CURRENT_TIME: u16 = 0x2A2B;
+    pub const BOOT_KEYBOARD_INPUT_REPORT: u16 = 0x2A22;
+    pub const HEART_RATE_MEASUREMENT: u16 = 0x2A37;
+    pub const BODY_SENSOR_LOCATION: u16 = 0x2A38;
+    pub const HID_INFORMATION: u16 = 0x2A4A;
+    pub const REPORT_MAP: u16 = 0x2A4B;
+    pub const HID_CONTROL_POINT: u16 = 0x2A4C;
+    pub const REPORT: u16 = 0x2A4D;
+    pub const PROTOCOL_MODE: u16 = 0x2A4E;
+    pub const PNP_ID: u16 = 0x2A50;
+    pub const PRESSURE: u16 = 0x2A6D;
+    pub const TEMPERATURE: u16 = 0x2A6E;
+    pub const HUMIDITY: u16 = 0x2A6F;
+    pub const CENTRAL_ADDRESS_RESOLUTION: u16 = 0x2AA6;
+}
+
+pub mod descriptor {
+    pub const EXTENDED_PROPERTIES: u16 = 0x2900;
+    pub const USER_DESCRIPTION: u16 = 0x2901;
+    pub const CLIENT_CHARACTERISTIC_CONFIGURATION: u16 = 0x2902;
+    pub const SERVER_CHARACTERISTIC_CONFIGURATION: u16 = 0x2903;
+    pub const PRESENTATION_FORMAT: u16 = 0x2904;
+    pub const AGGREGATE_FORMAT: u16 = 0x2905;
+    pub const VALID_RANGE: u16 = 0x2906;
+    pub const REPORT_REFERENCE: u16 = 0x2908;
+}
+
+/// Attribute type of declarations in the attribute table
+pub mod declaration {
+    pub const PRIMARY_SERVICE: u16 = 0x2800;
+    pub const SECONDARY_SERVICE: u16 = 0x2801;
+    pub const INCLUDE: u16 = 0x2802;
+    pub const CHARACTERISTIC: u16 = 0x2803;
+}
+
+/// Company identifiers sent first in manufacturer specific data
+pub mod company {
+    pub const ERICSSON: u16 = 0x0000;
+    pub const NOKIA: u16 = 0x0001;
+    pub const INTEL: u16 = 0x0002;
+    pub const IBM: u16 = 0x0003;
+    pub const TOSHIBA: u16 = 0x0004;
+    pub const MICROSOFT: u16 = 0x0006;
+    pub const MOTOROLA: u16 = 0x0008;
+    pub const INFINEON: u16 = 0x0009;
+    pub const TEXAS_INSTRUMENTS: u16 = 0x000D;
+    pub const BROADCOM: u16 = 0x000F;
+    pub const QUALCOMM: u16 = 0x001D;
+    pub const APPLE: u16 = 0x004C;
+    pub const NORDIC_SEMICONDUCTOR: u16 = 0x0059;
+    pub const SAMSUNG: u16 = 0x0075;
+    pub const GARMIN: u16 = 0x0087;
+    pub const BOSE: u16 = 0x009E;
+    pub const GOOGLE: u16 = 0x00E0;
+    pub const SONY: u16 = 0x012D;
+    pub const AMAZON: u16 = 0x0171;
+    pub const HUAWEI: u16 = 0x027D;
+    pub const ESPRESSIF: u16 = 0x02E5;
+    pub const XIAOMI: u16 = 0x038F;
+}
+
+/// Appearance values, the category takes the upper 10 bits and the subcategory
+/// the lower 6 bits
+pub mod appearance {
+    pub const UNKNOWN: u16 = 0x0000;
+    pub const PHONE: u16 = 0x0040;
+    pub const COMPUTER: u16 = 0x0080;
+    pub const WATCH: u16 = 0x00C0;
+    pub const SPORTS_WATCH: u16 = 0x00C1;
+    pub const CLOCK: u16 = 0x0100;
+    pub const DISPLAY: u16 = 0x0140;
+    pub const REMOTE_CONTROL: u16 = 0x0180;
+    pub const EYE_GLASSES: u16 = 0x01C0;
+    pub const TAG: u16 = 0x0200;
+    pub const KEYRING: u16 = 0x0240;
+    pub const MEDIA_PLAYER: u16 = 0x0280;
+    pub const BARCODE_SCANNER: u16 = 0x02C0;
+    pub const THERMOMETER: u16 = 0x0300;
+    pub const HEART_RATE_SENSOR: u16 = 0x0340;
+    pub const HEART_RATE_BELT: u16 = 0x0341;
+    pub const BLOOD_PRESSURE: u16 = 0x0380;
+    pub const HUMAN_INTERFACE_DEVICE: u16 = 0x03C0;
+    pub const KEYBOARD: u16 = 0x03C1;
+    pub const MOUSE: u16 = 0x03C2;
+    pub const JOYSTICK: u16 = 0x03C3;
+    pub const GAMEPAD: u16 = 0x03C4;
+    pub const GLUCOSE_METER: u16 = 0x0400;
+    pub const RUNNING_WALKING_SENSOR: u16 = 0x0440;
+    pub const CYCLING: u16 = 0x0480;
+    pub const CONTROL_DEVICE: u16 = 0x04C0;
+    pub const NETWORK_DEVICE: u16 = 0x0500;
+    pub const SENSOR: u16 = 0x0540;
+    pub const LIGHT_FIXTURES: u16 = 0x0580;
+    pub const FAN: u16 = 0x05C0;
+    pub const HVAC: u16 = 0x0600;
+    pub const ACCESS_CONTROL: u16 = 0x0700;
+    pub const POWER_DEVICE: u16 = 0x0780;
+    pub const LIGHT_SOURCE: u16 = 0x07C0;
+    pub const AUDIO_SINK: u16 = 0x0840;
+    pub const AUDIO_SOURCE: u16 = 0x0880;
+    pub const WEARABLE_AUDIO_DEVICE: u16 = 0x0940;
+    pub const HEARING_AID: u16 = 0x0A40;
+    pub const GAMING: u16 = 0x0A80;
+    pub const PULSE_OXIMETER: u16 = 0x0C40;
+    pub const WEIGHT_SCALE: u16 = 0x0C80;
+    pub const OUTDOOR_SPORTS_ACTIVITY: u16 = 0x1440;
+}
+
+const UUID16_NAMES: &[(u16, &str)] = &[
+    (service::GENERIC_ACCESS, "Generic Access"),
+    (service::GENERIC_ATTRIBUTE, "Generic Attribute"),
+    (service::IMMEDIATE_ALERT, "Immediate Alert"),
+    (service::LINK_LOSS, "Link Loss"),
+    (service::TX_POWER, "Tx Power"),
+    (service::CURRENT_TIME, "Current Time Service"),
+    (service::HEALTH_THERMOMETER, "Health Thermometer"),
+    (service::DEVICE_INFORMATION, "Device Information"),
+    (service::HEART_RATE, "Heart Rate"),
+    (service::BATTERY, "Battery Service"),
+    (service::BLOOD_PRESSURE, "Blood Pressure"),
+    (service::HUMAN_INTERFACE_DEVICE, "Human Interface Device"),
+    (service::SCAN_PARAMETERS, "Scan Parameters"),
+    (
+        service::RUNNING_SPEED_AND_CADENCE,
+        "Running Speed and Cadence",
+    ),
+    (
+        service::CYCLING_SPEED_AND_CADENCE,
+        "Cycling Speed and Cadence",
+    ),
+    (service::CYCLING_POWER, "Cycling Power"),
+    (service::LOCATION_AND_NAVIGATION, "Location and Navigation"),
+    (service::ENVIRONMENTAL_SENSING, "Environmental Sensing"),
+    (service::USER_DATA, "User Data"),
+    (service::WEIGHT_SCALE, "Weight Scale"),
+    (service::FITNESS_MACHINE, "Fitness Machine"),
+    (characteristic::DEVICE_NAME, "Device Name"),
+    (characteristic::APPEARANCE, "Appearance"),
+    (
+        characteristic::PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS,
+        "Peripheral Preferred Connection Parameters",
+    ),
+    (characteristic::SERVICE_CHANGED, "Service Changed"),
+    (characteristic::ALERT_LEVEL, "Alert Level"),
+    (characteristic::TX_POWER_LEVEL, "Tx Power Level"),
+    (characteristic::BATTERY_LEVEL, "Battery Level"),
+    (
+        characteristic::TEMPERATURE_MEASUREMENT,
+        "Temperature Measurement",
+    ),
+    (characteristic::SYSTEM_ID, "System ID"),
+    (characteristic::MODEL_NUMBER_STRING, "Model Number String"),
+    (characteristic::SERIAL_NUMBER_STRING, "Serial Number String"),
+    (
+        characteristic::FIRMWARE_REVISION_STRING,
+        "Firmware Revision String",
+    ),
+    (
+        characteristic::HARDWARE_REVISION_STRING,
+        "Hardware Revision String",
+    ),
+    (
+        characteristic::SOFTWARE_REVISION_STRING,
+        "Software Revision String",
+    ),
+    (
+        characteristic::MANUFACTURER_NAME_STRING,
+        "Manufacturer Name String",
+    ),
+    (characteristic::CURRENT_TIME, "Current Time"),
+    (
+        characteristic::BOOT_KEYBOARD_INPUT_REPORT,
+        "Boot Keyboard Input Report",
+    ),
+    (
+        characteristic::HEART_RATE_MEASUREMENT,
+        "Heart Rate Measurement",
+    ),
+    (characteristic::BODY_SENSOR_LOCATION, "Body Sensor Location"),
+    (characteristic::HID_INFORMATION, "HID Information"),
+    (characteristic::REPORT_MAP, "Report Map"),
+    (characteristic::HID_CONTROL_POINT, "HID Control Point"),
+    (characteristic::REPORT, "Report"),
+    (characteristic::PROTOCOL_MODE, "Protocol Mode"),
+    (characteristic::PNP_ID, "PnP ID"),
+    (characteristic::PRESSURE, "Pressure"),
+    (characteristic::TEMPERATURE, "Temperature"),
+    (characteristic::HUMIDITY, "Humidity"),
+    (
+        characteristic::CENTRAL_ADDRESS_RESOLUTION,
+        "Central Address Resolution",
+    ),
+    (
+        descriptor::EXTENDED_PROPERTIES,
+        "Characteristic Extended Properties",
+    ),
+    (
+        descriptor::USER_DESCRIPTION,
+        "Characteristic User Description",
+    ),
+    (
+        descriptor::CLIENT_CHARACTERISTIC_CONFIGURATION,
+        "Client Characteristic Configuration",
+    ),
+    (
+        descriptor::SERVER_CHARACTERISTIC_CONFIGURATION,
+        "Server Characteristic Configuration",
+    ),
+    (
+        descriptor::PRESENTATION_FORMAT,
+        "Characteristic Presentation Format",
+    ),
+    (
+        descriptor::AGGREGATE_FORMAT,
+        "Characteristic Aggregate Format",
+    ),
+    (descriptor::VALID_RANGE, "Valid Range"),
+    (descriptor::REPORT_REFERENCE, "Report Reference"),
+    (declaration::PRIMARY_SERVICE, "Primary Service"),
+    (declaration::SECONDARY_SERVICE, "Secondary Service"),
+    (declaration::INCLUDE, "Include"),
+    (declaration::CHARACTERISTIC, "Characteristic"),
+];
+
+const COMPANY_NAMES: &[(u16, &str)] = &[
+    (company::ERICSSON, "Ericsson"),
+    (company::NOKIA, "Nokia"),
+    (company::INTEL, "Intel"),
+    (company::IBM, "IBM"),
+    (company::TOSHIBA, "Toshiba"),
+    (company::MICROSOFT, "Microsoft"),
+    (company::MOTOROLA, "Motorola"),
+    (company::INFINEON, "Infineon"),
+    (company::TEXAS_INSTRUMENTS, "Texas Instruments"),
+    (company::BROADCOM, "Broadcom"),
+    (company::QUALCOMM, "Qualcomm"),
+    (company::APPLE, "Apple"),
+    (company::NORDIC_SEMICONDUCTOR, "Nordic Semiconductor"),
+    (company::SAMSUNG, "Samsung"),
+    (company::GARMIN, "Garmin"),
+    (company::BOSE, "Bose"),
+    (company::GOOGLE, "Google"),
+    (company::SONY, "Sony"),
+    (company::AMAZON, "Amazon"),
+    (company::HUAWEI, "Huawei"),
+    (company::ESPRESSIF, "Espressif"),
+    (company::XIAOMI, "Xiaomi"),
+];
+
+const APPEARANCE_NAMES: &[(u16, &str)] = &[
+    (appearance::UNKNOWN, "Unknown"),
+    (appearance::PHONE, "Phone"),
+    (appearance::COMPUTER, "Computer"),
+    (appearance::WATCH, "Watch"),
+    (appearance::SPORTS_WATCH, "Sports Watch"),
+    (appearance::CLOCK, "Clock"),
+    (appearance::DISPLAY, "Display"),
+    (appearance::REMOTE_CONTROL, "Remote Control"),
+    (appearance::EYE_GLASSES, "Eye-glasses"),
+    (appearance::TAG, "Tag"),
+    (appearance::KEYRING, "Keyring"),
+    (appearance::MEDIA_PLAYER, "Media Player"),
+    (appearance::BARCODE_SCANNER, "Barcode Scanner"),
+    (appearance::THERMOMETER, "Thermometer"),
+    (appearance::HEART_RATE_SENSOR, "Heart Rate Sensor"),
+    (appearance::HEART_RATE_BELT, "Heart Rate Belt"),
+    (appearance::BLOOD_PRESSURE, "Blood Pressure"),
+    (appearance::HUMAN_INTERFACE_DEVICE, "Human Interface Device"),
+    (appearance::KEYBOARD, "Keyboard"),
+    (appearance::MOUSE, "Mouse"),
+    (appearance::JOYSTICK, "Joystick"),
+    (appearance::GAMEPAD, "Gamepad"),
+    (appearance::GLUCOSE_METER, "Glucose Meter"),
+    (appearance::RUNNING_WALKING_SENSOR, "Running Walking Sensor"),
+    (appearance::CYCLING, "Cycling"),
+    (appearance::CONTROL_DEVICE, "Control Device"),
+    (appearance::NETWORK_DEVICE, "Network Device"),
+    (appearance::SENSOR, "Sensor"),
+    (appearance::LIGHT_FIXTURES, "Light Fixtures"),
+    (appearance::FAN, "Fan"),
+    (appearance::HVAC, "HVAC"),
+    (appearance::ACCESS_CONTROL, "Access Control"),
+    (appearance::POWER_DEVICE, "Power Device"),
+    (appearance::LIGHT_SOURCE, "Light Source"),
+    (appearance::AUDIO_SINK, "Audio Sink"),
+    (appearance::AUDIO_SOURCE, "Audio Source"),
+    (appearance::WEARABLE_AUDIO_DEVICE, "Wearable Audio Device"),
+    (appearance::HEARING_AID, "Hearing Aid"),
+    (appearance::GAMING, "Gaming"),
+    (appearance::PULSE_OXIMETER, "Pulse Oximeter"),
+    (appearance::WEIGHT_SCALE, "Weight Scale"),
+    (
+        appearance::OUTDOOR_SPORTS_ACTIVITY,
+        "Outdoor Sports Activity",
+    ),
+];
+
+// Bluetooth Base UUID, 16 and 32-bit UUIDs are shortened forms of it
+const BASE_UUID: u128 = 0x00000000_0000_1000_8000_00805f9b34fb;
+
+fn lookup(table: &[(u16, &'static str)], value: u16) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(number, _)| *number == value)
+        .map(|(_, name)| *name)
+}
+
+/// Name of a 16-bit service, characteristic, descriptor or declaration UUID
+pub fn uuid16_name(uuid: u16) -> Option<&'static str> {
+    lookup(UUID16_NAMES, uuid)
+}
+
+/// Name of a UUID, 128-bit UUIDs are looked up when they are built on the
+/// Bluetooth Base UUID
+pub fn uuid_name(uuid: &BtUuid) -> Option<&'static str> {
+    let bytes = uuid.as_bytes();
+
+    match bytes.len() {
+        2 => uuid16_name(u16::from_le_bytes([bytes[0], bytes[1]])),
+        16 => {
+            let value = u128::from_le_bytes(bytes.try_into().ok()?);
+            let short = u16::try_from(value >> 96).ok()?;
+
+            if value & !(u128::from(u32::MAX) << 96) != BASE_UUID {
+                return None;
+            }
+
+            uuid16_name(short)
+        }
+        _ => None,
+    }
+}
+
+/// Name of a company identifier, e.g. of scanned manufacturer data
+pub fn company_name(company_id: u16) -> Option<&'static str> {
+    lookup(COMPANY_NAMES, company_id)
+}
+
+/// Name of an appearance value, unknown subcategories fall back to their category
+pub fn appearance_name(appearance: u16) -> Option<&'static str> {
+    lookup(APPEARANCE_NAMES, appearance).or_else(|| lookup(APPEARANCE_NAMES, appearance & !0x3F))
+}
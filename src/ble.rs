@@ -6,31 +6,145 @@ use esp_idf_svc::hal::modem::Modem;
 use svc::bt::BtDriver;
 use svc::nvs::EspDefaultNvsPartition;
 
+use crate::controller::ControllerConfig;
 use crate::gap::Gap;
 use crate::gatts::Gatts;
+use crate::health::Health;
+use crate::power::{self, PowerMode, WakeCause, WakeConfig};
+use crate::suspend::{self, SuspendState};
 
 pub type ExtBtDriver = Arc<BtDriver<'static, svc::bt::Ble>>;
 
+#[derive(Debug, Clone, Default)]
+pub struct BleConfig {
+    pub power_mode: PowerMode,
+    pub controller: ControllerConfig,
+    // MTU offered to every peer in MTU exchange, e.g. `connection::MAX_MTU` for
+    // bulk transfers, None keeps the stack default of 23
+    pub local_mtu: Option<u16>,
+}
+
 pub struct Ble {
-    _bt: ExtBtDriver,
+    bt: ExtBtDriver,
     pub gap: Gap,
     pub gatts: Gatts,
 }
 
 impl Ble {
     pub fn new(modem: Modem) -> anyhow::Result<Self> {
+        Self::with_config(modem, BleConfig::default())
+    }
+
+    pub fn with_config(modem: Modem, config: BleConfig) -> anyhow::Result<Self> {
+        config.controller.check()?;
+
         let nvs = EspDefaultNvsPartition::take()?;
         let bt = Arc::new(BtDriver::<svc::bt::Ble>::new(modem, Some(nvs.clone()))?);
 
-        let gatts = Gatts::new(bt.clone())?;
+        let gatts = Gatts::new(bt.clone(), Some(nvs))?;
         let gap = Gap::new(bt.clone(), &gatts.0)?;
 
-        let ble = Ble {
-            _bt: bt,
-            gap,
-            gatts,
-        };
+        config.power_mode.apply()?;
+
+        if let Some(mtu) = config.local_mtu {
+            gatts.set_local_mtu(mtu)?;
+        }
+
+        if let Some(max_connections) = config.controller.max_connections {
+            let mut gap_config = gap.config()?;
+            gap_config.max_connections = Some(max_connections.into());
+            gap.set_config(gap_config)?;
+        }
+
+        let ble = Ble { bt, gap, gatts };
 
         Ok(ble)
     }
+
+    /// Underlying esp-idf-svc Bluetooth driver, e.g. to create another stack client.
+    ///
+    /// Gap and Gatts hold clones of it, the controller stays enabled until all of
+    /// them are dropped. Only one client of each kind may exist, creating another
+    /// `EspBleGap` or `EspGatts` replaces the callback of this crate. See `Gap::raw`
+    /// and `Gatts::raw` for the existing clients
+    pub fn raw_driver(&self) -> &ExtBtDriver {
+        &self.bt
+    }
+
+    /// Event dispatching statistics of Gap and Gatts, e.g. to find out whether events
+    /// are dropped or handlers block the event loop
+    pub fn health(&self) -> anyhow::Result<Health> {
+        Ok(Health {
+            gap: self.gap.0.health.report()?,
+            gatts: self.gatts.0.health.report()?,
+        })
+    }
+
+    /// Saves advertising state and subscriptions of bonded peers to NVS and stops
+    /// advertising, call right before entering deep sleep
+    pub fn suspend(&self) -> anyhow::Result<()> {
+        let state = SuspendState {
+            advertising: self.gap.0.is_advertising()?,
+            throttle_step: self.gap.0.throttle_step_index()?.map(|step| step as u8),
+            subscriptions: self.gatts.0.bonded_subscriptions()?,
+        };
+
+        if state.advertising {
+            self.gap.stop_advertising()?;
+        }
+
+        self.gatts
+            .0
+            .persistence()?
+            .store(suspend::NVS_KEY, &state.encode())
+    }
+
+    /// Sleeps until a source of `config` wakes the chip, e.g. a peer connecting
+    /// or sending a scan request, a button press or a timeout. Without Bluetooth
+    /// wake up advertising is stopped for the sleep and started again after it.
+    /// Memory is kept in light sleep, so nothing is written to NVS
+    pub fn light_sleep(&self, config: &WakeConfig) -> anyhow::Result<WakeCause> {
+        let advertising = !config.bluetooth && self.gap.0.is_advertising()?;
+        if advertising {
+            self.gap.stop_advertising()?;
+        }
+
+        let cause = power::light_sleep(config);
+
+        if advertising {
+            self.gap.start_advertising()?;
+        }
+
+        let cause = cause?;
+        log::info!("Woken from light sleep by {:?}", cause);
+
+        Ok(cause)
+    }
+
+    /// Restores state saved by `suspend` after wake up, apps and services have to be
+    /// registered in the same order as before, so attribute handles match, and battery
+    /// policy set beforehand.
+    /// Returns false if there was no saved state, e.g. after a cold boot
+    pub fn resume(&self) -> anyhow::Result<bool> {
+        let persistence = self.gatts.0.persistence()?;
+        let Some(bytes) = persistence.load(suspend::NVS_KEY)? else {
+            return Ok(false);
+        };
+        // State is used once, a failed resume should not be retried on every boot
+        persistence.remove(suspend::NVS_KEY)?;
+
+        let state = SuspendState::decode(&bytes)?;
+        // Applied once each bonded peer reconnects
+        self.gatts.0.restore_subscriptions(&state.subscriptions)?;
+
+        self.gap
+            .0
+            .set_throttle_step_index(state.throttle_step.map(usize::from))?;
+
+        if state.advertising {
+            self.gap.start_advertising()?;
+        }
+
+        Ok(true)
+    }
 }
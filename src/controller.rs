@@ -0,0 +1,90 @@
+use esp_idf_svc::sys::{
+    CONFIG_BT_ACL_CONNECTIONS, CONFIG_BT_CTRL_BLE_LL_RESOLV_LIST_SIZE, CONFIG_BT_CTRL_BLE_MAX_ACT,
+    CONFIG_BT_CTRL_BLE_STATIC_ACL_TX_BUF_NB,
+};
+
+/// Controller resources the application depends on, None leaves a setting unchecked.
+///
+/// `BtDriver` initializes the controller from sdkconfig and offers no way to override
+/// it at runtime, so these are checked against the build when `Ble::with_config` runs,
+/// failing early with the sdkconfig option to change instead of misbehaving once
+/// the limit is hit:
+///
+/// ```text
+/// # Simultaneous links of the host
+/// CONFIG_BT_ACL_CONNECTIONS=4
+/// # Links, advertising and scanning together
+/// CONFIG_BT_CTRL_BLE_MAX_ACT=6
+/// # Static ACL TX buffers, 0 allocates them dynamically
+/// CONFIG_BT_CTRL_BLE_STATIC_ACL_TX_BUF_NB=0
+/// # Bonded peers whose resolvable private addresses the controller resolves
+/// CONFIG_BT_CTRL_BLE_LL_RESOLV_LIST_SIZE=4
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ControllerConfig {
+    // Simultaneous connections, also used as `GapConfig::max_connections`
+    // for auto advertising
+    pub max_connections: Option<u8>,
+    // Static ACL TX buffers shared by all links
+    pub acl_tx_buffers: Option<u16>,
+    pub resolving_list_size: Option<u16>,
+}
+
+impl ControllerConfig {
+    /// Checks the requirements against the controller configuration of the build
+    pub fn check(&self) -> anyhow::Result<()> {
+        if let Some(max_connections) = self.max_connections {
+            let max_connections = u32::from(max_connections);
+
+            if max_connections > CONFIG_BT_ACL_CONNECTIONS {
+                return Err(anyhow::anyhow!(
+                    "{} connections requested, but host supports {}, set CONFIG_BT_ACL_CONNECTIONS={}",
+                    max_connections,
+                    CONFIG_BT_ACL_CONNECTIONS,
+                    max_connections
+                ));
+            }
+
+            // One activity is kept for advertising, so the device stays connectable
+            if max_connections + 1 > CONFIG_BT_CTRL_BLE_MAX_ACT {
+                return Err(anyhow::anyhow!(
+                    "{} connections requested, but controller supports {} activities, set CONFIG_BT_CTRL_BLE_MAX_ACT={}",
+                    max_connections,
+                    CONFIG_BT_CTRL_BLE_MAX_ACT,
+                    max_connections + 1
+                ));
+            }
+        }
+
+        if let Some(acl_tx_buffers) = self.acl_tx_buffers {
+            let acl_tx_buffers = u32::from(acl_tx_buffers);
+
+            // Dynamic buffers are bounded only by free heap
+            if CONFIG_BT_CTRL_BLE_STATIC_ACL_TX_BUF_NB != 0
+                && acl_tx_buffers > CONFIG_BT_CTRL_BLE_STATIC_ACL_TX_BUF_NB
+            {
+                return Err(anyhow::anyhow!(
+                    "{} ACL TX buffers requested, but controller has {}, set CONFIG_BT_CTRL_BLE_STATIC_ACL_TX_BUF_NB={}",
+                    acl_tx_buffers,
+                    CONFIG_BT_CTRL_BLE_STATIC_ACL_TX_BUF_NB,
+                    acl_tx_buffers
+                ));
+            }
+        }
+
+        if let Some(resolving_list_size) = self.resolving_list_size {
+            let resolving_list_size = u32::from(resolving_list_size);
+
+            if resolving_list_size > CONFIG_BT_CTRL_BLE_LL_RESOLV_LIST_SIZE {
+                return Err(anyhow::anyhow!(
+                    "Resolving list of {} requested, but controller has {}, set CONFIG_BT_CTRL_BLE_LL_RESOLV_LIST_SIZE={}",
+                    resolving_list_size,
+                    CONFIG_BT_CTRL_BLE_LL_RESOLV_LIST_SIZE,
+                    resolving_list_size
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,451 @@
+use esp_idf_svc::bt::BtUuid;
+
+use super::GapConfig;
+
+// AD types, Bluetooth Assigned Numbers 2.3
+const AD_FLAGS: u8 = 0x01;
+const AD_INCOMPLETE_SERVICE_UUIDS_16: u8 = 0x02;
+const AD_SERVICE_UUIDS_16: u8 = 0x03;
+const AD_INCOMPLETE_SERVICE_UUIDS_32: u8 = 0x04;
+const AD_SERVICE_UUIDS_32: u8 = 0x05;
+const AD_INCOMPLETE_SERVICE_UUIDS_128: u8 = 0x06;
+const AD_SERVICE_UUIDS_128: u8 = 0x07;
+const AD_SHORTENED_NAME: u8 = 0x08;
+const AD_COMPLETE_NAME: u8 = 0x09;
+const AD_TX_POWER: u8 = 0x0A;
+const AD_CONN_INTERVAL_RANGE: u8 = 0x12;
+const AD_SERVICE_DATA_16: u8 = 0x16;
+const AD_SERVICE_DATA_32: u8 = 0x20;
+const AD_SERVICE_DATA_128: u8 = 0x21;
+const AD_APPEARANCE: u8 = 0x19;
+const AD_MANUFACTURER_DATA: u8 = 0xFF;
+
+/// Manufacturer specific data, the payload is sent after the company identifier
+/// assigned by the Bluetooth SIG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManufacturerData {
+    pub company_id: u16,
+    pub payload: Vec<u8>,
+}
+
+impl ManufacturerData {
+    pub fn new(company_id: u16, payload: &[u8]) -> Self {
+        Self {
+            company_id,
+            payload: payload.to_vec(),
+        }
+    }
+
+    /// Data of the AD structure, company identifier in little endian first
+    pub fn encode(&self) -> Vec<u8> {
+        [&self.company_id.to_le_bytes()[..], &self.payload].concat()
+    }
+
+    /// Parses data of a manufacturer specific AD structure, e.g. of a scanned device
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        match bytes {
+            [low, high, payload @ ..] => Ok(Self {
+                company_id: u16::from_le_bytes([*low, *high]),
+                payload: payload.to_vec(),
+            }),
+            _ => Err(anyhow::anyhow!(
+                "Manufacturer data of {} bytes has no company identifier",
+                bytes.len()
+            )),
+        }
+    }
+}
+
+/// Field of the advertising payload generated from `GapConfig`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdvField {
+    Flags,
+    Name,
+    TxPower,
+    ConnIntervalRange,
+    Appearance,
+    ManufacturerData,
+    ServiceData,
+    ServiceUuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdvPlacement {
+    Advertising,
+    ScanResponse,
+    // Name advertised shortened, complete in the scan response
+    Shortened,
+}
+
+/// Where each field generated from `GapConfig` was placed, see `Gap::adv_layout`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdvLayout {
+    pub fields: Vec<(AdvField, AdvPlacement)>,
+}
+
+impl AdvLayout {
+    pub fn placement(&self, field: AdvField) -> Option<AdvPlacement> {
+        self.fields
+            .iter()
+            .find(|(placed, _)| *placed == field)
+            .map(|(_, placement)| *placement)
+    }
+
+    /// True when the payload did not fit advertising and some fields went to
+    /// the scan response
+    pub fn overflowed(&self) -> bool {
+        self.fields
+            .iter()
+            .any(|(_, placement)| *placement != AdvPlacement::Advertising)
+    }
+}
+
+// Fields moved to the scan response, in this order, while the advertising
+// payload generated from `GapConfig` exceeds `AdvData::MAX_LEN`
+const OVERFLOW_ORDER: [AdvField; 2] = [AdvField::Name, AdvField::ServiceData];
+
+/// Builder of a raw advertising or scan response payload, a sequence of AD
+/// structures (length, type, data) set with `Gap::set_raw_adv_data`. Structures
+/// are written in the order they are added, the size limit is checked by `build`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdvData {
+    structures: Vec<(u8, Vec<u8>)>,
+}
+
+impl AdvData {
+    /// Size limit of legacy advertising and scan response payloads
+    pub const MAX_LEN: usize = 31;
+    /// Size limit of extended advertising payloads, see `ExtAdvertising`
+    pub const EXT_MAX_LEN: usize = 1650;
+
+    // Flags of the Flags AD structure
+    pub const LE_LIMITED_DISCOVERABLE: u8 = 0x01;
+    pub const LE_GENERAL_DISCOVERABLE: u8 = 0x02;
+    pub const BR_EDR_NOT_SUPPORTED: u8 = 0x04;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn flags(self, flags: u8) -> Self {
+        self.raw(AD_FLAGS, &[flags])
+    }
+
+    pub fn complete_name(self, name: &str) -> Self {
+        self.raw(AD_COMPLETE_NAME, name.as_bytes())
+    }
+
+    /// Name cut to `len` bytes, backing off to a char boundary
+    pub fn shortened_name(self, name: &str, len: usize) -> Self {
+        let mut end = len.min(name.len());
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        self.raw(AD_SHORTENED_NAME, &name.as_bytes()[..end])
+    }
+
+    pub fn tx_power(self, dbm: i8) -> Self {
+        self.raw(AD_TX_POWER, &dbm.to_le_bytes())
+    }
+
+    /// Complete list of service UUIDs holding a single UUID
+    pub fn service_uuid(self, uuid: &BtUuid) -> Self {
+        let uuid_bytes = uuid.as_bytes();
+        let ad_type = match uuid_bytes.len() {
+            2 => AD_SERVICE_UUIDS_16,
+            4 => AD_SERVICE_UUIDS_32,
+            _ => AD_SERVICE_UUIDS_128,
+        };
+
+        self.raw(ad_type, uuid_bytes)
+    }
+
+    /// Service data, AD type follows the size of the UUID
+    pub fn service_data(self, uuid: &BtUuid, data: &[u8]) -> Self {
+        let uuid_bytes = uuid.as_bytes();
+        let ad_type = match uuid_bytes.len() {
+            2 => AD_SERVICE_DATA_16,
+            4 => AD_SERVICE_DATA_32,
+            _ => AD_SERVICE_DATA_128,
+        };
+
+        self.raw(ad_type, &[uuid_bytes, data].concat())
+    }
+
+    pub fn manufacturer_data(self, company_id: u16, data: &[u8]) -> Self {
+        self.raw(
+            AD_MANUFACTURER_DATA,
+            &ManufacturerData::new(company_id, data).encode(),
+        )
+    }
+
+    pub fn appearance(self, appearance: u16) -> Self {
+        self.raw(AD_APPEARANCE, &appearance.to_le_bytes())
+    }
+
+    /// Peripheral preferred connection interval range, in units of 1.25 ms
+    pub fn conn_interval_range(self, min: u16, max: u16) -> Self {
+        self.raw(
+            AD_CONN_INTERVAL_RANGE,
+            &[min.to_le_bytes(), max.to_le_bytes()].concat(),
+        )
+    }
+
+    /// AD structure of any type, for types without a dedicated method
+    pub fn raw(mut self, ad_type: u8, data: &[u8]) -> Self {
+        self.structures.push((ad_type, data.to_vec()));
+        self
+    }
+
+    /// Parses a received payload, e.g. of a scanned device. Parsing stops at the
+    /// first zero length, which pads the rest of legacy payloads
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut data = Self::new();
+        let mut rest = bytes;
+
+        while let [len, tail @ ..] = rest {
+            let len = *len as usize;
+            if len == 0 {
+                break;
+            }
+
+            let Some((structure, tail)) = tail.split_at_checked(len) else {
+                return Err(anyhow::anyhow!(
+                    "AD structure of {} bytes exceeds the {} bytes left in the payload",
+                    len,
+                    tail.len()
+                ));
+            };
+
+            data = data.raw(structure[0], &structure[1..]);
+            rest = tail;
+        }
+
+        Ok(data)
+    }
+
+    /// Data of the first AD structure of the given type
+    pub fn get(&self, ad_type: u8) -> Option<&[u8]> {
+        self.structures
+            .iter()
+            .find(|(structure_type, _)| *structure_type == ad_type)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Complete name, or the shortened one when only that was sent
+    pub fn name(&self) -> Option<String> {
+        self.get(AD_COMPLETE_NAME)
+            .or_else(|| self.get(AD_SHORTENED_NAME))
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    }
+
+    /// Manufacturer specific data of the first such AD structure
+    pub fn get_manufacturer_data(&self) -> Option<ManufacturerData> {
+        self.get(AD_MANUFACTURER_DATA)
+            .and_then(|data| ManufacturerData::decode(data).ok())
+    }
+
+    pub fn get_appearance(&self) -> Option<u16> {
+        match self.get(AD_APPEARANCE)? {
+            [low, high] => Some(u16::from_le_bytes([*low, *high])),
+            _ => None,
+        }
+    }
+
+    /// Service UUIDs of complete and incomplete lists of every size
+    pub fn service_uuids(&self) -> Vec<BtUuid> {
+        let mut uuids = Vec::new();
+
+        for (ad_type, data) in &self.structures {
+            match *ad_type {
+                AD_INCOMPLETE_SERVICE_UUIDS_16 | AD_SERVICE_UUIDS_16 => uuids.extend(
+                    data.chunks_exact(2)
+                        .map(|uuid| BtUuid::uuid16(u16::from_le_bytes([uuid[0], uuid[1]]))),
+                ),
+                AD_INCOMPLETE_SERVICE_UUIDS_32 | AD_SERVICE_UUIDS_32 => {
+                    uuids.extend(data.chunks_exact(4).map(|uuid| {
+                        BtUuid::uuid32(u32::from_le_bytes([uuid[0], uuid[1], uuid[2], uuid[3]]))
+                    }))
+                }
+                AD_INCOMPLETE_SERVICE_UUIDS_128 | AD_SERVICE_UUIDS_128 => {
+                    uuids.extend(data.chunks_exact(16).map(|uuid| {
+                        let mut bytes = [0; 16];
+                        bytes.copy_from_slice(uuid);
+                        BtUuid::uuid128(u128::from_le_bytes(bytes))
+                    }))
+                }
+                _ => {}
+            }
+        }
+
+        uuids
+    }
+
+    /// Size of the built payload
+    pub fn len(&self) -> usize {
+        self.structures.iter().map(|(_, data)| 2 + data.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.structures.is_empty()
+    }
+
+    pub fn build(&self) -> anyhow::Result<Vec<u8>> {
+        self.build_limited(Self::MAX_LEN)
+    }
+
+    /// Builds payload of extended advertising, which may exceed the legacy limit
+    pub fn build_extended(&self) -> anyhow::Result<Vec<u8>> {
+        self.build_limited(Self::EXT_MAX_LEN)
+    }
+
+    /// Splits the payload the stack generates from `config` into advertising
+    /// and scan response. Fields are moved to the scan response in
+    /// `OVERFLOW_ORDER` until the advertising payload fits, a moved name is
+    /// also advertised shortened to the space left when `shorten_name` is set.
+    /// `tx_power` is the advertising TX power, when included, `flags` the content
+    /// of the Flags AD structure
+    pub(super) fn split_config(
+        config: &GapConfig,
+        tx_power: Option<i8>,
+        flags: u8,
+    ) -> anyhow::Result<(Self, Self, AdvLayout)> {
+        let mut fields = Self::config_fields(config, tx_power, flags)
+            .into_iter()
+            .map(|(field, data)| (field, data, AdvPlacement::Advertising))
+            .collect::<Vec<_>>();
+
+        let adv_len = |fields: &[(AdvField, AdvData, AdvPlacement)]| -> usize {
+            fields
+                .iter()
+                .filter(|(_, _, placement)| *placement == AdvPlacement::Advertising)
+                .map(|(_, data, _)| data.len())
+                .sum()
+        };
+
+        for moved in OVERFLOW_ORDER {
+            if adv_len(&fields) <= Self::MAX_LEN {
+                break;
+            }
+
+            if let Some((_, _, placement)) = fields.iter_mut().find(|(field, ..)| *field == moved) {
+                *placement = AdvPlacement::ScanResponse;
+            }
+        }
+
+        let space = Self::MAX_LEN.saturating_sub(adv_len(&fields) + 2);
+        let mut adv = Self::new();
+        let mut scan_response = Self::new();
+
+        for (field, data, placement) in &mut fields {
+            match placement {
+                AdvPlacement::Advertising => adv.structures.extend(data.structures.clone()),
+                _ => {
+                    if *field == AdvField::Name && config.shorten_name && space > 0 {
+                        adv = adv.shortened_name(&config.device_name, space);
+                        *placement = AdvPlacement::Shortened;
+                    }
+
+                    scan_response.structures.extend(data.structures.clone());
+                }
+            }
+        }
+
+        if adv.len() > Self::MAX_LEN || scan_response.len() > Self::MAX_LEN {
+            return Err(anyhow::anyhow!(
+                "Advertising payload of {} bytes does not fit advertising and scan response",
+                fields.iter().map(|(_, data, _)| data.len()).sum::<usize>()
+            ));
+        }
+
+        let layout = AdvLayout {
+            fields: fields
+                .into_iter()
+                .map(|(field, _, placement)| (field, placement))
+                .collect(),
+        };
+
+        Ok((adv, scan_response, layout))
+    }
+
+    // AD structures the stack generates from `config`, in its order
+    fn config_fields(config: &GapConfig, tx_power: Option<i8>, flags: u8) -> Vec<(AdvField, Self)> {
+        let mut fields = vec![(AdvField::Flags, Self::new().flags(flags))];
+
+        if config.include_name_in_advertising {
+            fields.push((
+                AdvField::Name,
+                Self::new().complete_name(&config.device_name),
+            ));
+        }
+
+        if let Some(dbm) = tx_power {
+            fields.push((AdvField::TxPower, Self::new().tx_power(dbm)));
+        }
+
+        if config.preffered_min_interval > 0 && config.preffered_max_interval > 0 {
+            fields.push((
+                AdvField::ConnIntervalRange,
+                Self::new().conn_interval_range(
+                    config.preffered_min_interval as u16,
+                    config.preffered_max_interval as u16,
+                ),
+            ));
+        }
+
+        // Appearance value of the category, without sub-category
+        let appearance = (config.appearance as u16) << 6;
+        if appearance != 0 {
+            fields.push((AdvField::Appearance, Self::new().appearance(appearance)));
+        }
+
+        if let Some(manufacturer_data) = &config.manufacturer_data {
+            fields.push((
+                AdvField::ManufacturerData,
+                Self::new().raw(AD_MANUFACTURER_DATA, &manufacturer_data.encode()),
+            ));
+        }
+
+        if let Some(service_data) = &config.service_data {
+            fields.push((
+                AdvField::ServiceData,
+                Self::new().raw(AD_SERVICE_DATA_16, service_data),
+            ));
+        }
+
+        if let Some(uuid) = &config.service_uuid {
+            fields.push((AdvField::ServiceUuid, Self::new().service_uuid(uuid)));
+        }
+
+        fields
+    }
+
+    fn build_limited(&self, max_len: usize) -> anyhow::Result<Vec<u8>> {
+        let mut payload = Vec::with_capacity(self.len());
+
+        for (ad_type, data) in &self.structures {
+            let len = u8::try_from(data.len() + 1).map_err(|_| {
+                anyhow::anyhow!(
+                    "AD structure {:#04x} of {} bytes is too long",
+                    ad_type,
+                    data.len()
+                )
+            })?;
+
+            payload.push(len);
+            payload.push(*ad_type);
+            payload.extend(data);
+        }
+
+        if payload.len() > max_len {
+            return Err(anyhow::anyhow!(
+                "Advertising payload of {} bytes exceeds {} bytes",
+                payload.len(),
+                max_len
+            ));
+        }
+
+        Ok(payload)
+    }
+}
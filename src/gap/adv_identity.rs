@@ -0,0 +1,106 @@
+use std::sync::{Arc, Weak};
+
+use esp_idf_svc::bt::{BdAddr, ble::gatt::GattInterface};
+
+use super::{
+    GapInner,
+    adv_data::AdvData,
+    ext_advertising::{ExtAdvConfig, ExtAdvertising},
+};
+
+/// Advertising identity of an app, a connectable extended advertising set peers
+/// see as a device of its own
+pub struct AdvIdentityConfig {
+    // Set parameters, `instance` must be unique among identities and sets
+    pub adv: ExtAdvConfig,
+    pub adv_data: AdvData,
+    // Sent for legacy sets only, extended connectable sets are not scannable
+    pub scan_response: Option<AdvData>,
+    // Static random address of the identity, None advertises with the device address
+    pub address: Option<BdAddr>,
+}
+
+/// Identity advertised by `Gap::advertise_identity`. Peers connecting to it are
+/// routed to its app only, other apps neither see the connection nor serve its
+/// requests. All apps share the attribute table of the stack, so peers still
+/// discover services of every app
+pub struct AdvIdentity {
+    gap: Weak<GapInner>,
+    set: ExtAdvertising,
+    interface: GattInterface,
+}
+
+impl AdvIdentity {
+    pub(crate) fn start(
+        gap: &Arc<GapInner>,
+        interface: GattInterface,
+        config: AdvIdentityConfig,
+    ) -> anyhow::Result<Self> {
+        if !config.adv.connectable {
+            return Err(anyhow::anyhow!(
+                "Advertising identity requires a connectable set"
+            ));
+        }
+
+        let instance = config.adv.instance;
+        gap.bind_identity(instance, interface)?;
+
+        let identity = (|| {
+            let identity = Self {
+                gap: Arc::downgrade(gap),
+                set: ExtAdvertising::new(gap, config.adv)?,
+                interface,
+            };
+
+            if let Some(address) = config.address {
+                identity.set.set_random_address(address)?;
+            }
+
+            identity.set.set_data(&config.adv_data)?;
+
+            if let Some(scan_response) = &config.scan_response {
+                identity.set.set_scan_response(scan_response)?;
+            }
+
+            identity.start_advertising()?;
+
+            Ok(identity)
+        })();
+
+        if identity.is_err() {
+            gap.unbind_identity(instance)?;
+        }
+
+        identity
+    }
+
+    pub fn set(&self) -> &ExtAdvertising {
+        &self.set
+    }
+
+    /// Interface of the app connections of this identity are routed to
+    pub fn interface(&self) -> GattInterface {
+        self.interface
+    }
+
+    /// Advertises the identity again, the controller stops a set once a peer
+    /// connects to it
+    pub fn start_advertising(&self) -> anyhow::Result<()> {
+        self.set.start(None, None)
+    }
+
+    /// Stops advertising and removes the set, connections made through the
+    /// identity stay routed to its app
+    pub fn remove(self) -> anyhow::Result<()> {
+        if let Err(err) = self.set.stop() {
+            log::debug!("Advertising identity was not advertising: {:?}", err);
+        }
+
+        self.gap
+            .upgrade()
+            .ok_or(anyhow::anyhow!("Failed to upgrade Gap"))?
+            .unbind_identity(self.set.instance())?;
+
+        self.set.remove()
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use esp_idf_svc::sys::{
+    esp, esp_ble_addr_type_t, esp_ble_adv_channel_t, esp_ble_adv_channel_t_ADV_CHNL_37,
+    esp_ble_adv_channel_t_ADV_CHNL_38, esp_ble_adv_channel_t_ADV_CHNL_39,
+    esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY,
+    esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_WLST_CON_WLST, esp_ble_adv_params_t,
+    esp_ble_adv_type_t, esp_ble_adv_type_t_ADV_TYPE_IND, esp_ble_adv_type_t_ADV_TYPE_NONCONN_IND,
+    esp_ble_adv_type_t_ADV_TYPE_SCAN_IND, esp_ble_gap_start_advertising,
+};
+
+/// Kind of undirected advertising
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdvType {
+    // Peers can connect and send scan requests
+    #[default]
+    Connectable,
+    // Peers can send scan requests only, e.g. for a beacon with a scan response
+    Scannable,
+    // Broadcast only, lowest consumption as the radio does not listen after packets
+    NonConnectable,
+}
+
+impl From<AdvType> for esp_ble_adv_type_t {
+    fn from(value: AdvType) -> Self {
+        match value {
+            AdvType::Connectable => esp_ble_adv_type_t_ADV_TYPE_IND,
+            AdvType::Scannable => esp_ble_adv_type_t_ADV_TYPE_SCAN_IND,
+            AdvType::NonConnectable => esp_ble_adv_type_t_ADV_TYPE_NONCONN_IND,
+        }
+    }
+}
+
+/// Primary advertising channels used, fewer channels save power at the cost
+/// of being discovered slower
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdvChannels {
+    pub ch37: bool,
+    pub ch38: bool,
+    pub ch39: bool,
+}
+
+impl AdvChannels {
+    pub const ALL: Self = Self {
+        ch37: true,
+        ch38: true,
+        ch39: true,
+    };
+
+    pub(crate) fn raw(&self) -> anyhow::Result<esp_ble_adv_channel_t> {
+        let channel_map = [
+            (self.ch37, esp_ble_adv_channel_t_ADV_CHNL_37),
+            (self.ch38, esp_ble_adv_channel_t_ADV_CHNL_38),
+            (self.ch39, esp_ble_adv_channel_t_ADV_CHNL_39),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |map, (_, channel)| map | channel);
+
+        if channel_map == 0 {
+            return Err(anyhow::anyhow!(
+                "At least one advertising channel is needed"
+            ));
+        }
+
+        Ok(channel_map)
+    }
+}
+
+impl Default for AdvChannels {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Sent once advertising started by `Gap::start_advertising_for` ran out of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdvertisingTimeout {
+    pub duration: Duration,
+    // False when a peer connected before the timeout, so advertising was
+    // already stopped by the controller
+    pub stopped: bool,
+}
+
+/// Parameters of undirected advertising, taken from `GapConfig`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AdvParams {
+    // Interval range in units of 0.625 ms
+    pub min_interval: u16,
+    pub max_interval: u16,
+    pub adv_type: AdvType,
+    pub channels: AdvChannels,
+    pub own_addr_type: esp_ble_addr_type_t,
+    // Scan and connection requests are accepted from whitelisted peers only
+    pub whitelist_only: bool,
+}
+
+pub(crate) fn start_advertising(params: &AdvParams) -> anyhow::Result<()> {
+    if params.min_interval > params.max_interval {
+        return Err(anyhow::anyhow!(
+            "Advertising min interval {:#x} is above max interval {:#x}",
+            params.min_interval,
+            params.max_interval
+        ));
+    }
+
+    let mut raw_params = esp_ble_adv_params_t {
+        adv_int_min: params.min_interval,
+        adv_int_max: params.max_interval,
+        adv_type: params.adv_type.into(),
+        own_addr_type: params.own_addr_type,
+        channel_map: params.channels.raw()?,
+        adv_filter_policy: match params.whitelist_only {
+            true => esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_WLST_CON_WLST,
+            false => esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY,
+        },
+        ..Default::default()
+    };
+
+    esp!(unsafe { esp_ble_gap_start_advertising(&mut raw_params) })
+        .map_err(|err| anyhow::anyhow!("Failed to start advertising: {:?}", err))
+}
@@ -0,0 +1,34 @@
+use super::adv_data::AdvData;
+
+// Apple company identifier and iBeacon type and length prefix
+const APPLE_COMPANY_ID: u16 = 0x004C;
+const IBEACON_PREFIX: [u8; 2] = [0x02, 0x15];
+
+/// iBeacon advertisement, advertised with `Gap::advertise_ibeacon`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IBeacon {
+    // Proximity UUID, shared by the beacons of one deployment
+    pub uuid: u128,
+    pub major: u16,
+    pub minor: u16,
+    // Measured RSSI at 1 m in dBm, used by receivers to estimate distance
+    pub tx_power: i8,
+}
+
+impl IBeacon {
+    /// Advertising payload, 30 bytes so it takes the whole legacy payload
+    pub fn adv_data(&self) -> AdvData {
+        let data = [
+            &IBEACON_PREFIX[..],
+            &self.uuid.to_be_bytes(),
+            &self.major.to_be_bytes(),
+            &self.minor.to_be_bytes(),
+            &self.tx_power.to_be_bytes(),
+        ]
+        .concat();
+
+        AdvData::new()
+            .flags(AdvData::LE_GENERAL_DISCOVERABLE | AdvData::BR_EDR_NOT_SUPPORTED)
+            .manufacturer_data(APPLE_COMPANY_ID, &data)
+    }
+}
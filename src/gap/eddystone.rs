@@ -0,0 +1,304 @@
+use std::{sync::Arc, time::Duration};
+
+use crossbeam_channel::{RecvTimeoutError, Sender, bounded};
+use esp_idf_svc::{bt::BtUuid, sys::esp_timer_get_time};
+
+use super::{GapInner, adv_data::AdvData};
+use crate::guard;
+
+/// Service UUID Eddystone frames are sent as service data of
+pub const EDDYSTONE_UUID: u16 = 0xFEAA;
+
+const FRAME_UID: u8 = 0x00;
+const FRAME_URL: u8 = 0x10;
+const FRAME_TLM: u8 = 0x20;
+const TLM_VERSION: u8 = 0x00;
+// Temperature field of TLM frames when no sensor is available
+const TLM_NO_TEMPERATURE: u16 = 0x8000;
+
+const URL_MAX_LEN: usize = 17;
+// Scheme prefixes, longer ones first so `www.` is folded into the prefix
+const URL_SCHEMES: [(&str, u8); 4] = [
+    ("http://www.", 0x00),
+    ("https://www.", 0x01),
+    ("http://", 0x02),
+    ("https://", 0x03),
+];
+// Expansion codes, the ones with a trailing slash first
+const URL_EXPANSIONS: [(&str, u8); 14] = [
+    (".com/", 0x00),
+    (".org/", 0x01),
+    (".edu/", 0x02),
+    (".net/", 0x03),
+    (".info/", 0x04),
+    (".biz/", 0x05),
+    (".gov/", 0x06),
+    (".com", 0x07),
+    (".org", 0x08),
+    (".edu", 0x09),
+    (".net", 0x0a),
+    (".info", 0x0b),
+    (".biz", 0x0c),
+    (".gov", 0x0d),
+];
+
+/// Eddystone frame, sent on its own or rotated with others by `Gap::start_eddystone`
+#[derive(Debug, Clone, PartialEq)]
+pub enum EddystoneFrame {
+    Uid {
+        // Calibrated TX power at 0 m in dBm
+        tx_power: i8,
+        namespace: [u8; 10],
+        instance: [u8; 6],
+    },
+    Url {
+        // Calibrated TX power at 0 m in dBm
+        tx_power: i8,
+        url: String,
+    },
+    Tlm(Telemetry),
+}
+
+/// Unencrypted telemetry of a TLM frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Telemetry {
+    pub battery_mv: u16,
+    // Beacon temperature in degrees Celsius, None without a sensor
+    pub temperature: Option<f32>,
+    pub adv_count: u32,
+    // Time since power on, sent in units of 100 ms
+    pub uptime: Duration,
+}
+
+impl EddystoneFrame {
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            EddystoneFrame::Uid {
+                tx_power,
+                namespace,
+                instance,
+            } => Ok([
+                &[FRAME_UID, *tx_power as u8][..],
+                namespace,
+                instance,
+                &[0x00, 0x00],
+            ]
+            .concat()),
+            EddystoneFrame::Url { tx_power, url } => {
+                Ok([&[FRAME_URL, *tx_power as u8][..], &encode_url(url)?].concat())
+            }
+            EddystoneFrame::Tlm(telemetry) => {
+                let temperature = match telemetry.temperature {
+                    Some(celsius) => (celsius * 256.0).round() as i16 as u16,
+                    None => TLM_NO_TEMPERATURE,
+                };
+                let uptime = (telemetry.uptime.as_millis() / 100).min(u32::MAX as u128) as u32;
+
+                Ok([
+                    &[FRAME_TLM, TLM_VERSION][..],
+                    &telemetry.battery_mv.to_be_bytes(),
+                    &temperature.to_be_bytes(),
+                    &telemetry.adv_count.to_be_bytes(),
+                    &uptime.to_be_bytes(),
+                ]
+                .concat())
+            }
+        }
+    }
+
+    /// Advertising payload carrying the frame
+    pub fn adv_data(&self) -> anyhow::Result<AdvData> {
+        let uuid = BtUuid::uuid16(EDDYSTONE_UUID);
+
+        Ok(AdvData::new()
+            .flags(AdvData::LE_GENERAL_DISCOVERABLE | AdvData::BR_EDR_NOT_SUPPORTED)
+            .service_uuid(&uuid)
+            .service_data(&uuid, &self.encode()?))
+    }
+}
+
+fn encode_url(url: &str) -> anyhow::Result<Vec<u8>> {
+    let (scheme, code) = URL_SCHEMES
+        .iter()
+        .find(|(scheme, _)| url.starts_with(scheme))
+        .ok_or(anyhow::anyhow!(
+            "Unsupported scheme of Eddystone URL {:?}",
+            url
+        ))?;
+
+    let mut encoded = vec![*code];
+    let mut rest = &url[scheme.len()..];
+    while let Some(char) = rest.chars().next() {
+        if let Some((expansion, code)) = URL_EXPANSIONS
+            .iter()
+            .find(|(expansion, _)| rest.starts_with(expansion))
+        {
+            encoded.push(*code);
+            rest = &rest[expansion.len()..];
+            continue;
+        }
+
+        if !char.is_ascii_graphic() {
+            return Err(anyhow::anyhow!(
+                "Eddystone URL {:?} contains unsupported character {:?}",
+                url,
+                char
+            ));
+        }
+
+        encoded.push(char as u8);
+        rest = &rest[1..];
+    }
+
+    if encoded.len() > URL_MAX_LEN {
+        return Err(anyhow::anyhow!(
+            "Eddystone URL {:?} encodes to {} bytes, at most {} fit",
+            url,
+            encoded.len(),
+            URL_MAX_LEN
+        ));
+    }
+
+    Ok(encoded)
+}
+
+/// Reading of the sensors reported in TLM frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelemetryReading {
+    pub battery_mv: u16,
+    pub temperature: Option<f32>,
+}
+
+pub type TelemetrySource = Arc<dyn Fn() -> TelemetryReading + Send + Sync>;
+
+#[derive(Clone)]
+pub struct EddystoneConfig {
+    // UID and URL frames advertised in turn
+    pub frames: Vec<EddystoneFrame>,
+    // When set, a TLM frame with a fresh reading follows the other frames
+    pub telemetry: Option<TelemetrySource>,
+    // How long each frame is advertised
+    pub interval: Duration,
+}
+
+impl Default for EddystoneConfig {
+    fn default() -> Self {
+        Self {
+            frames: Vec::new(),
+            telemetry: None,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Running frame rotation, started by `Gap::start_eddystone`. Rotation ends when
+/// it is stopped or dropped, advertising keeps running with the last frame
+pub struct Eddystone {
+    stop_tx: Sender<()>,
+}
+
+impl Eddystone {
+    pub(crate) fn start(gap: &Arc<GapInner>, config: EddystoneConfig) -> anyhow::Result<Self> {
+        if config.frames.is_empty() && config.telemetry.is_none() {
+            return Err(anyhow::anyhow!(
+                "Eddystone rotation needs at least one frame"
+            ));
+        }
+
+        if config
+            .frames
+            .iter()
+            .any(|frame| matches!(frame, EddystoneFrame::Tlm(_)))
+        {
+            return Err(anyhow::anyhow!(
+                "TLM frames are generated from the telemetry source, not passed as frames"
+            ));
+        }
+
+        // Fail early on frames which do not encode or fit
+        let payloads = config
+            .frames
+            .iter()
+            .map(|frame| frame.adv_data()?.build())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let (stop_tx, stop_rx) = bounded(1);
+        let gap = Arc::downgrade(gap);
+
+        std::thread::spawn(move || {
+            let mut adv_count = 0u32;
+
+            for slot in (0..payloads.len() + config.telemetry.is_some() as usize).cycle() {
+                // Gap is not kept alive while waiting for the next slot
+                {
+                    let Some(gap) = gap.upgrade() else {
+                        log::warn!("Failed to upgrade Gap, exiting Eddystone thread");
+                        return;
+                    };
+
+                    if let Err(err) = advertise_slot(&gap, &config, &payloads, slot, adv_count) {
+                        log::error!("Failed to advertise Eddystone frame: {:?}", err);
+                    }
+
+                    adv_count = adv_count.wrapping_add(adv_events(&gap, config.interval));
+                }
+
+                match stop_rx.recv_timeout(config.interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+            }
+        });
+
+        Ok(Self { stop_tx })
+    }
+
+    pub fn stop(self) {
+        let _ = self.stop_tx.send(());
+    }
+}
+
+fn advertise_slot(
+    gap: &GapInner,
+    config: &EddystoneConfig,
+    payloads: &[Vec<u8>],
+    slot: usize,
+    adv_count: u32,
+) -> anyhow::Result<()> {
+    let payload = match (payloads.get(slot), &config.telemetry) {
+        (Some(payload), _) => payload.clone(),
+        (None, Some(telemetry)) => {
+            let telemetry = telemetry.clone();
+            let reading = guard::run_hook("eddystone telemetry", move || telemetry())?;
+            let uptime = Duration::from_micros(unsafe { esp_timer_get_time() } as u64);
+
+            EddystoneFrame::Tlm(Telemetry {
+                battery_mv: reading.battery_mv,
+                temperature: reading.temperature,
+                adv_count,
+                uptime,
+            })
+            .adv_data()?
+            .build()?
+        }
+        (None, None) => return Err(anyhow::anyhow!("Eddystone slot {} is out of frames", slot)),
+    };
+
+    gap.set_raw_adv_data(&payload)?;
+
+    match gap.is_advertising()? {
+        true => Ok(()),
+        false => gap.start_advertising(),
+    }
+}
+
+// Advertising events sent within `interval`, estimated from the mean advertising
+// interval as the controller does not report them
+fn adv_events(gap: &GapInner, interval: Duration) -> u32 {
+    let Ok(config) = gap.config.read() else {
+        return 0;
+    };
+
+    let mean_us = (config.adv_min_interval as u64 + config.adv_max_interval as u64) * 625 / 2;
+    (interval.as_micros() as u64 / mean_us.max(1)) as u32
+}
@@ -1,4 +1,13 @@
-use esp_idf_svc::bt::{ble::gap::BleGapEvent, BdAddr, BtStatus};
+use esp_idf_svc::{
+    bt::{ble::gap::BleGapEvent, BdAddr, BtStatus},
+    sys::{
+        esp_ble_addr_type_t, esp_ble_gap_phy_t, esp_bt_status_t,
+        esp_gap_ble_cb_event_t_ESP_GAP_BLE_ADV_TERMINATED_EVT,
+        esp_gap_ble_cb_event_t_ESP_GAP_BLE_PHY_UPDATE_COMPLETE_EVT,
+        esp_gap_search_evt_t_ESP_GAP_SEARCH_INQ_CMPL_EVT,
+        esp_gap_search_evt_t_ESP_GAP_SEARCH_INQ_RES_EVT,
+    },
+};
 
 #[derive(Debug, Clone)]
 pub enum GapEvent {
@@ -9,6 +18,17 @@ pub enum GapEvent {
     RawScanResponseConfigured(BtStatus),
     AdvertisingStarted(BtStatus),
     ScanStarted(BtStatus),
+    // Advertising report of a scanned device, the scan response is empty
+    // unless the report answers an active scan request
+    ScanResult {
+        addr: BdAddr,
+        addr_type: esp_ble_addr_type_t,
+        rssi: i8,
+        adv_data: Vec<u8>,
+        scan_response: Vec<u8>,
+    },
+    // Scan started for a limited duration ended
+    ScanCompleted,
     AuthenticationComplete {
         bd_addr: BdAddr,
         status: BtStatus,
@@ -80,6 +100,20 @@ pub enum GapEvent {
     ExtendedAdvertisingScanStarted(BtStatus),
     ExtendedAdvertisingScanStopped(BtStatus),
     ExtendedAdvertisingExtendedConnectionParamsConfigured(BtStatus),
+    // Not decoded by esp-idf-svc, taken from the raw event
+    PhyUpdated {
+        addr: BdAddr,
+        status: esp_bt_status_t,
+        tx_phy: esp_ble_gap_phy_t,
+        rx_phy: esp_ble_gap_phy_t,
+    },
+    // Not decoded by esp-idf-svc, status 0 means a peer connected to the set
+    // and `conn_handle` is the HCI handle of the new link
+    ExtendedAdvertisingTerminated {
+        status: u8,
+        instance: u8,
+        conn_handle: u16,
+    },
 
     Other,
 }
@@ -104,6 +138,26 @@ impl<'d> From<BleGapEvent<'d>> for GapEvent {
             }
             BleGapEvent::AdvertisingStarted(bt_status) => GapEvent::AdvertisingStarted(bt_status),
             BleGapEvent::ScanStarted(bt_status) => GapEvent::ScanStarted(bt_status),
+            BleGapEvent::ScanResult(param)
+                if param.search_evt == esp_gap_search_evt_t_ESP_GAP_SEARCH_INQ_RES_EVT =>
+            {
+                let adv_len = param.adv_data_len as usize;
+                let len = (adv_len + param.scan_rsp_len as usize).min(param.ble_adv.len());
+                let (adv_data, scan_response) = param.ble_adv[..len].split_at(adv_len.min(len));
+
+                GapEvent::ScanResult {
+                    addr: BdAddr::from_bytes(param.bda),
+                    addr_type: param.ble_addr_type,
+                    rssi: param.rssi as i8,
+                    adv_data: adv_data.to_vec(),
+                    scan_response: scan_response.to_vec(),
+                }
+            }
+            BleGapEvent::ScanResult(param)
+                if param.search_evt == esp_gap_search_evt_t_ESP_GAP_SEARCH_INQ_CMPL_EVT =>
+            {
+                GapEvent::ScanCompleted
+            }
             BleGapEvent::AuthenticationComplete { bd_addr, status } => {
                 GapEvent::AuthenticationComplete { bd_addr, status }
             }
@@ -243,6 +297,29 @@ impl<'d> From<BleGapEvent<'d>> for GapEvent {
             BleGapEvent::ExtendedAdvertisingExtendedConnectionParamsConfigured(bt_status) => {
                 GapEvent::ExtendedAdvertisingExtendedConnectionParamsConfigured(bt_status)
             }
+            BleGapEvent::Other {
+                raw_event,
+                raw_data,
+            } if raw_event == esp_gap_ble_cb_event_t_ESP_GAP_BLE_PHY_UPDATE_COMPLETE_EVT => {
+                let param = unsafe { raw_data.phy_update };
+                GapEvent::PhyUpdated {
+                    addr: BdAddr::from_bytes(param.bda),
+                    status: param.status,
+                    tx_phy: param.tx_phy,
+                    rx_phy: param.rx_phy,
+                }
+            }
+            BleGapEvent::Other {
+                raw_event,
+                raw_data,
+            } if raw_event == esp_gap_ble_cb_event_t_ESP_GAP_BLE_ADV_TERMINATED_EVT => {
+                let param = unsafe { raw_data.adv_terminate };
+                GapEvent::ExtendedAdvertisingTerminated {
+                    status: param.status,
+                    instance: param.adv_instance,
+                    conn_handle: param.conn_idx,
+                }
+            }
 
             _ => GapEvent::Other,
         }
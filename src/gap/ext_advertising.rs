@@ -0,0 +1,365 @@
+use std::{
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use esp_idf_svc::{
+    bt::{BdAddr, BtStatus},
+    sys::{
+        ESP_BLE_GAP_SET_EXT_ADV_PROP_ANON, ESP_BLE_GAP_SET_EXT_ADV_PROP_CONNECTABLE,
+        ESP_BLE_GAP_SET_EXT_ADV_PROP_INCLUDE_TX_PWR, ESP_BLE_GAP_SET_EXT_ADV_PROP_LEGACY,
+        ESP_BLE_GAP_SET_EXT_ADV_PROP_SCANNABLE, EXT_ADV_TX_PWR_NO_PREFERENCE, esp,
+        esp_ble_addr_type_t, esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY,
+        esp_ble_gap_config_ext_adv_data_raw, esp_ble_gap_config_ext_scan_rsp_data_raw,
+        esp_ble_gap_ext_adv_params_t, esp_ble_gap_ext_adv_set_clear,
+        esp_ble_gap_ext_adv_set_params, esp_ble_gap_ext_adv_set_rand_addr,
+        esp_ble_gap_ext_adv_set_remove, esp_ble_gap_ext_adv_start, esp_ble_gap_ext_adv_stop,
+        esp_ble_gap_ext_adv_t, esp_ble_gap_pri_phy_t,
+    },
+};
+
+use super::{GapInner, adv_data::AdvData, advertising::AdvChannels, event::GapEvent, phy::Phy};
+
+// Largest advertising duration, in units of 10 ms
+const MAX_DURATION: u128 = u16::MAX as u128;
+
+/// Parameters of an extended (BLE 5) advertising set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtAdvConfig {
+    // Handle of the set, sets with different instances advertise concurrently,
+    // limited by CONFIG_BT_CTRL_BLE_MAX_ACT
+    pub instance: u8,
+
+    // Extended PDUs can be either connectable or scannable, not both
+    pub connectable: bool,
+    pub scannable: bool,
+    // Use legacy PDUs, so BLE 4.x scanners see the set, payloads are limited
+    // to 31 bytes then and connectable sets are scannable as well
+    pub legacy: bool,
+    // Omit own address from the PDUs, not allowed for connectable sets
+    pub anonymous: bool,
+    pub include_tx_power: bool,
+
+    // Interval range in units of 0.625 ms, up to 24 bits
+    pub min_interval: u32,
+    pub max_interval: u32,
+    pub channels: AdvChannels,
+
+    // TX power in dBm, None lets the controller choose
+    pub tx_power: Option<i8>,
+    // Primary channels support `Le1M` and `Coded` only, `Coded` for long range
+    pub primary_phy: Phy,
+    pub secondary_phy: Phy,
+    // Advertising SID, lets scanners tell sets of one device apart
+    pub sid: u8,
+    pub scan_request_notify: bool,
+}
+
+impl Default for ExtAdvConfig {
+    fn default() -> Self {
+        Self {
+            instance: 0,
+            connectable: true,
+            scannable: false,
+            legacy: false,
+            anonymous: false,
+            include_tx_power: false,
+            min_interval: 0x20,
+            max_interval: 0x40,
+            channels: AdvChannels::ALL,
+            tx_power: None,
+            primary_phy: Phy::Le1M,
+            secondary_phy: Phy::Le1M,
+            sid: 0,
+            scan_request_notify: false,
+        }
+    }
+}
+
+impl ExtAdvConfig {
+    fn raw(
+        &self,
+        own_addr_type: esp_ble_addr_type_t,
+    ) -> anyhow::Result<esp_ble_gap_ext_adv_params_t> {
+        if self.connectable && self.scannable && !self.legacy {
+            return Err(anyhow::anyhow!(
+                "Extended advertising set can not be both connectable and scannable"
+            ));
+        }
+
+        if self.anonymous && (self.connectable || self.legacy) {
+            return Err(anyhow::anyhow!(
+                "Anonymous advertising requires non connectable extended PDUs"
+            ));
+        }
+
+        if self.primary_phy == Phy::Le2M {
+            return Err(anyhow::anyhow!(
+                "2M PHY is not allowed on primary advertising channels"
+            ));
+        }
+
+        if self.min_interval > self.max_interval {
+            return Err(anyhow::anyhow!(
+                "Advertising min interval {:#x} is above max interval {:#x}",
+                self.min_interval,
+                self.max_interval
+            ));
+        }
+
+        let properties = [
+            (self.connectable, ESP_BLE_GAP_SET_EXT_ADV_PROP_CONNECTABLE),
+            (
+                self.scannable || (self.legacy && self.connectable),
+                ESP_BLE_GAP_SET_EXT_ADV_PROP_SCANNABLE,
+            ),
+            (self.legacy, ESP_BLE_GAP_SET_EXT_ADV_PROP_LEGACY),
+            (self.anonymous, ESP_BLE_GAP_SET_EXT_ADV_PROP_ANON),
+            (
+                self.include_tx_power,
+                ESP_BLE_GAP_SET_EXT_ADV_PROP_INCLUDE_TX_PWR,
+            ),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |mask, (_, property)| mask | property);
+
+        Ok(esp_ble_gap_ext_adv_params_t {
+            type_: properties as u16,
+            interval_min: self.min_interval,
+            interval_max: self.max_interval,
+            channel_map: self.channels.raw()?,
+            own_addr_type,
+            filter_policy: esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY,
+            tx_power: self.tx_power.unwrap_or(EXT_ADV_TX_PWR_NO_PREFERENCE as i8),
+            primary_phy: self.primary_phy.raw() as esp_ble_gap_pri_phy_t,
+            max_skip: 0,
+            secondary_phy: self.secondary_phy.raw(),
+            sid: self.sid,
+            scan_req_notif: self.scan_request_notify,
+            ..Default::default()
+        })
+    }
+}
+
+/// Extended advertising set, created by `Gap::ext_advertising`. Sets are
+/// independent of the legacy advertising controlled by `GapConfig`, so auto
+/// advertising does not restart them after a peer connects
+#[derive(Clone)]
+pub struct ExtAdvertising(pub Arc<ExtAdvertisingInner>);
+
+pub struct ExtAdvertisingInner {
+    gap: Weak<GapInner>,
+    pub config: ExtAdvConfig,
+}
+
+impl ExtAdvertising {
+    pub(crate) fn new(gap: &Arc<GapInner>, config: ExtAdvConfig) -> anyhow::Result<Self> {
+        let params = config.raw(super::privacy::own_addr_type(gap.is_private()?))?;
+
+        let event = gap.wait_event(
+            &GapEvent::ExtendedAdvertisingParametersConfigured(BtStatus::Done),
+            || {
+                esp!(unsafe { esp_ble_gap_ext_adv_set_params(config.instance, &params) }).map_err(
+                    |err| anyhow::anyhow!("Failed to set extended advertising params: {:?}", err),
+                )
+            },
+        )?;
+        check_status(event, "set extended advertising params")?;
+
+        Ok(Self(Arc::new(ExtAdvertisingInner {
+            gap: Arc::downgrade(gap),
+            config,
+        })))
+    }
+
+    pub fn instance(&self) -> u8 {
+        self.0.config.instance
+    }
+
+    pub fn set_data(&self, data: &AdvData) -> anyhow::Result<()> {
+        let payload = self.build(data)?;
+        let instance = self.instance();
+
+        let event = self.0.gap()?.wait_event(
+            &GapEvent::ExtendedAdvertisingConfigured(BtStatus::Done),
+            || {
+                esp!(unsafe {
+                    esp_ble_gap_config_ext_adv_data_raw(
+                        instance,
+                        payload.len() as u16,
+                        payload.as_ptr(),
+                    )
+                })
+                .map_err(|err| {
+                    anyhow::anyhow!("Failed to set extended advertising data: {:?}", err)
+                })
+            },
+        )?;
+
+        check_status(event, "set extended advertising data")
+    }
+
+    /// Static random address the set advertises with instead of the device
+    /// address, the two most significant bits must be set
+    pub fn set_random_address(&self, addr: BdAddr) -> anyhow::Result<()> {
+        if addr.raw()[0] & 0xC0 != 0xC0 {
+            return Err(anyhow::anyhow!("{:?} is not a static random address", addr));
+        }
+
+        let instance = self.instance();
+
+        let event = self.0.gap()?.wait_event(
+            &GapEvent::ExtendedAdvertisingRandomAddressConfigured(BtStatus::Done),
+            || {
+                esp!(unsafe {
+                    esp_ble_gap_ext_adv_set_rand_addr(instance, addr.raw().as_mut_ptr())
+                })
+                .map_err(|err| {
+                    anyhow::anyhow!("Failed to set extended advertising address: {:?}", err)
+                })
+            },
+        )?;
+
+        check_status(event, "set extended advertising address")
+    }
+
+    /// Scan response of a scannable set
+    pub fn set_scan_response(&self, data: &AdvData) -> anyhow::Result<()> {
+        let payload = self.build(data)?;
+        let instance = self.instance();
+
+        let event = self.0.gap()?.wait_event(
+            &GapEvent::ExtendedAdvertisingScanResponseConfigured(BtStatus::Done),
+            || {
+                esp!(unsafe {
+                    esp_ble_gap_config_ext_scan_rsp_data_raw(
+                        instance,
+                        payload.len() as u16,
+                        payload.as_ptr(),
+                    )
+                })
+                .map_err(|err| {
+                    anyhow::anyhow!("Failed to set extended scan response data: {:?}", err)
+                })
+            },
+        )?;
+
+        check_status(event, "set extended scan response data")
+    }
+
+    /// Starts advertising the set, until stopped when `duration` and `max_events`
+    /// are None. The controller ends it after whichever limit is reached first
+    pub fn start(&self, duration: Option<Duration>, max_events: Option<u8>) -> anyhow::Result<()> {
+        let duration = match duration {
+            Some(duration) => {
+                let units = duration.as_millis() / 10;
+                if units == 0 || units > MAX_DURATION {
+                    return Err(anyhow::anyhow!(
+                        "Extended advertising duration must be within 10 ms..=655350 ms, got {:?}",
+                        duration
+                    ));
+                }
+
+                units as i32
+            }
+            None => 0,
+        };
+
+        let set = esp_ble_gap_ext_adv_t {
+            instance: self.instance(),
+            duration,
+            max_events: max_events.unwrap_or(0).into(),
+        };
+
+        let event = self.0.gap()?.wait_event(
+            &GapEvent::ExtendedAdvertisingStarted(BtStatus::Done),
+            || {
+                esp!(unsafe { esp_ble_gap_ext_adv_start(1, &set) }).map_err(|err| {
+                    anyhow::anyhow!("Failed to start extended advertising: {:?}", err)
+                })
+            },
+        )?;
+
+        check_status(event, "start extended advertising")
+    }
+
+    pub fn stop(&self) -> anyhow::Result<()> {
+        let instance = self.instance();
+
+        let event = self.0.gap()?.wait_event(
+            &GapEvent::ExtendedAdvertisingStopped(BtStatus::Done),
+            || {
+                esp!(unsafe { esp_ble_gap_ext_adv_stop(1, &instance) }).map_err(|err| {
+                    anyhow::anyhow!("Failed to stop extended advertising: {:?}", err)
+                })
+            },
+        )?;
+
+        check_status(event, "stop extended advertising")
+    }
+
+    /// Removes the set from the controller, it has to be stopped first
+    pub fn remove(self) -> anyhow::Result<()> {
+        let instance = self.instance();
+
+        let event = self.0.gap()?.wait_event(
+            &GapEvent::ExtendedAdvertisingRemoved(BtStatus::Done),
+            || {
+                esp!(unsafe { esp_ble_gap_ext_adv_set_remove(instance) }).map_err(|err| {
+                    anyhow::anyhow!("Failed to remove extended advertising set: {:?}", err)
+                })
+            },
+        )?;
+
+        check_status(event, "remove extended advertising set")
+    }
+
+    fn build(&self, data: &AdvData) -> anyhow::Result<Vec<u8>> {
+        if self.0.config.legacy {
+            data.build()
+        } else {
+            data.build_extended()
+        }
+    }
+}
+
+impl ExtAdvertisingInner {
+    fn gap(&self) -> anyhow::Result<Arc<GapInner>> {
+        self.gap
+            .upgrade()
+            .ok_or(anyhow::anyhow!("Failed to upgrade Gap"))
+    }
+}
+
+pub(crate) fn clear_sets(gap: &GapInner) -> anyhow::Result<()> {
+    let event = gap.wait_event(
+        &GapEvent::ExtendedAdvertisingCleared(BtStatus::Done),
+        || {
+            esp!(unsafe { esp_ble_gap_ext_adv_set_clear() }).map_err(|err| {
+                anyhow::anyhow!("Failed to clear extended advertising sets: {:?}", err)
+            })
+        },
+    )?;
+
+    check_status(event, "clear extended advertising sets")
+}
+
+fn check_status(event: GapEvent, operation: &str) -> anyhow::Result<()> {
+    let status = match event {
+        GapEvent::ExtendedAdvertisingParametersConfigured(status)
+        | GapEvent::ExtendedAdvertisingRandomAddressConfigured(status)
+        | GapEvent::ExtendedAdvertisingConfigured(status)
+        | GapEvent::ExtendedAdvertisingScanResponseConfigured(status)
+        | GapEvent::ExtendedAdvertisingStarted(status)
+        | GapEvent::ExtendedAdvertisingStopped(status)
+        | GapEvent::ExtendedAdvertisingRemoved(status)
+        | GapEvent::ExtendedAdvertisingCleared(status) => status,
+        event => return Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+    };
+
+    match status {
+        BtStatus::Success => Ok(()),
+        _ => Err(anyhow::anyhow!("Failed to {}: {:?}", operation, status)),
+    }
+}
@@ -0,0 +1,61 @@
+use esp_idf_svc::bt::BtUuid;
+
+use super::adv_data::AdvData;
+
+// TLV types of the identity payload, unknown types are skipped by readers so
+// fields can be added later
+const TYPE_HARDWARE_REVISION: u8 = 0x01;
+const TYPE_FIRMWARE_VERSION: u8 = 0x02;
+const TYPE_SERIAL_HASH: u8 = 0x03;
+
+/// Compact identity of the device, placed in the scan response service data by
+/// `Gap::set_identity_scan_response` so fleet management apps can inventory
+/// devices from active scans without connecting.
+///
+/// Encoded as a sequence of (type, length, value) entries, 14 bytes in total.
+/// With a 16 bit service UUID the scan response takes 18 of its 31 bytes, a
+/// 128 bit UUID does not fit alongside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub hardware_revision: u8,
+    // Major, minor and patch version of the application firmware
+    pub firmware_version: (u8, u8, u8),
+    // Hash of the serial number, see `serial_hash`
+    pub serial_hash: u32,
+}
+
+impl DeviceIdentity {
+    pub fn new(hardware_revision: u8, firmware_version: (u8, u8, u8), serial: &str) -> Self {
+        Self {
+            hardware_revision,
+            firmware_version,
+            serial_hash: serial_hash(serial.as_bytes()),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let (major, minor, patch) = self.firmware_version;
+
+        [
+            (TYPE_HARDWARE_REVISION, &[self.hardware_revision][..]),
+            (TYPE_FIRMWARE_VERSION, &[major, minor, patch][..]),
+            (TYPE_SERIAL_HASH, &self.serial_hash.to_le_bytes()[..]),
+        ]
+        .into_iter()
+        .flat_map(|(tlv_type, value)| [&[tlv_type, value.len() as u8][..], value].concat())
+        .collect()
+    }
+
+    /// Scan response payload carrying the identity as service data of `uuid`
+    pub fn adv_data(&self, uuid: &BtUuid) -> AdvData {
+        AdvData::new().service_data(uuid, &self.encode())
+    }
+}
+
+/// 32 bit FNV-1a hash of a serial number, identifies the device without
+/// broadcasting the serial itself
+pub fn serial_hash(serial: &[u8]) -> u32 {
+    serial.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
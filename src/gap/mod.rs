@@ -1,20 +1,60 @@
+pub mod adv_data;
+pub mod adv_identity;
+pub mod advertising;
+pub mod beacon;
+pub mod eddystone;
 mod event;
+pub mod ext_advertising;
+pub mod identity;
+pub mod peers;
+pub mod phy;
+pub mod power;
+pub mod privacy;
+pub mod scan;
+pub mod security;
 
 use std::{
     collections::HashMap,
     mem::{Discriminant, discriminant},
     sync::{Arc, RwLock, Weak},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use crossbeam_channel::{Sender, unbounded};
-use esp_idf_svc::bt::{
-    BtStatus, BtUuid,
-    ble::gap::{AdvConfiguration, AppearanceCategory, EspBleGap},
+use adv_data::{AdvData, AdvLayout, ManufacturerData};
+use adv_identity::{AdvIdentity, AdvIdentityConfig};
+use advertising::{AdvChannels, AdvParams, AdvType, AdvertisingTimeout};
+use beacon::IBeacon;
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use eddystone::{Eddystone, EddystoneConfig};
+use esp_idf_svc::{
+    bt::{
+        BdAddr, BtStatus, BtUuid,
+        ble::{
+            gap::{AdvConfiguration, AppearanceCategory, EspBleGap},
+            gatt::{GattConnParams, GattInterface},
+        },
+    },
+    sys::{
+        esp, esp_ble_gap_read_rssi, esp_ble_gap_set_pkt_data_len,
+        esp_bt_status_t_ESP_BT_STATUS_SUCCESS,
+    },
 };
 use event::GapEvent;
+use ext_advertising::{ExtAdvConfig, ExtAdvertising};
+use identity::DeviceIdentity;
+use peers::{AccessMode, DirectedDuty, KnownPeer};
+use phy::{Phy, PhyOptions, PhyUpdate};
+use power::BatteryPolicy;
+use scan::{Advertisement, ScanConfig, ScanFilter, ScanState};
+use security::{PasskeyDisplayHandler, PasskeyRequestHandler, SecurityConfig};
 
-use crate::{ble::ExtBtDriver, gatts::GattsInner};
+use crate::{
+    ble::ExtBtDriver,
+    gatts::{GattsInner, app::App, connection::ConnectionStatus},
+    guard,
+    health::DispatcherHealth,
+    waiters::EventWaiters,
+};
 use esp_idf_svc as svc;
 
 #[derive(Debug, Clone)]
@@ -23,12 +63,22 @@ pub struct GapConfig {
 
     pub include_name_in_advertising: bool,
     pub include_txpower_in_advertising: bool,
+    // When the name is moved to the scan response as the advertising payload
+    // overflows, also advertise it shortened to the space left, see `Gap::adv_layout`
+    pub shorten_name: bool,
 
     pub preffered_min_interval: i32,
     pub preffered_max_interval: i32,
 
+    // Advertising interval range in units of 0.625 ms, overridden by the active
+    // step of the battery policy
+    pub adv_min_interval: u16,
+    pub adv_max_interval: u16,
+    pub adv_type: AdvType,
+    pub adv_channels: AdvChannels,
+
     pub appearance: AppearanceCategory,
-    pub manufacturer_data: Option<Vec<u8>>,
+    pub manufacturer_data: Option<ManufacturerData>,
 
     pub service_data: Option<Vec<u8>>,
     pub service_uuid: Option<BtUuid>,
@@ -44,8 +94,13 @@ impl Default for GapConfig {
             device_name: String::from("ESP32"),
             include_name_in_advertising: true,
             include_txpower_in_advertising: true,
+            shorten_name: true,
             preffered_min_interval: 0,
             preffered_max_interval: 0,
+            adv_min_interval: 0x20,
+            adv_max_interval: 0x40,
+            adv_type: AdvType::Connectable,
+            adv_channels: AdvChannels::ALL,
             appearance: AppearanceCategory::Unknown,
             manufacturer_data: None,
             service_data: None,
@@ -67,11 +122,32 @@ impl<'a> Into<AdvConfiguration<'a>> for &'a GapConfig {
             flag: 0,
             service_uuid: self.service_uuid.clone(),
             service_data: self.service_data.as_ref().map(|data| data.as_slice()),
-            manufacturer_data: self.manufacturer_data.as_ref().map(|data| data.as_slice()),
+            // Encoded with the company identifier into a new buffer, set by `apply_config`
+            manufacturer_data: None,
         }
     }
 }
 
+impl GapConfig {
+    /// Advertising and scan response payloads generated from the config, with
+    /// low priority fields moved to the scan response when advertising overflows
+    fn adv_split(&self, access_mode: AccessMode) -> anyhow::Result<(AdvData, AdvData, AdvLayout)> {
+        let tx_power = match self.include_txpower_in_advertising {
+            true => Some(power::adv_tx_power()?.dbm()),
+            false => None,
+        };
+
+        // Not discoverable while only bonded peers may connect, they reconnect
+        // without discovering the device first
+        let flags = match access_mode {
+            AccessMode::Open => AdvData::LE_GENERAL_DISCOVERABLE | AdvData::BR_EDR_NOT_SUPPORTED,
+            AccessMode::BondedOnly => AdvData::BR_EDR_NOT_SUPPORTED,
+        };
+
+        AdvData::split_config(self, tx_power, flags)
+    }
+}
+
 #[derive(Clone)]
 pub struct Gap(pub Arc<GapInner>);
 
@@ -79,30 +155,118 @@ pub struct GapInner {
     gatts: Weak<GattsInner>,
     gap: EspBleGap<'static, svc::bt::Ble, ExtBtDriver>,
     config: RwLock<GapConfig>,
+    security: RwLock<Option<SecurityConfig>>,
+    passkey_display: RwLock<Option<PasskeyDisplayHandler>>,
+    passkey_request: RwLock<Option<PasskeyRequestHandler>>,
+    // Connected peers which did not complete authentication yet
+    pairing_peers: RwLock<Vec<BdAddr>>,
+    known_peers: RwLock<Vec<KnownPeer>>,
+    access_mode: RwLock<AccessMode>,
+    battery_policy: RwLock<Option<BatteryPolicy>>,
+    // Index of the active step of the battery policy
+    throttle_step: RwLock<Option<usize>>,
+    advertising: RwLock<bool>,
+    // End of bounded advertising, kept after it passed so auto advertising does
+    // not restart until advertising is started again
+    adv_deadline: RwLock<Option<Instant>>,
+    privacy: RwLock<bool>,
+    // Placement of the fields of the last applied config
+    adv_layout: RwLock<AdvLayout>,
+    // App of each advertising identity, by instance of its set
+    identities: RwLock<HashMap<u8, GattInterface>>,
+    scan_config: RwLock<ScanConfig>,
+    // None while not scanning
+    scan_state: RwLock<Option<ScanState>>,
+
+    // Completed PHY updates of all links, including those started by peers
+    pub phy_updates_rx: Receiver<PhyUpdate>,
+    phy_updates_tx: Sender<PhyUpdate>,
+
+    pub adv_timeouts_rx: Receiver<AdvertisingTimeout>,
+    adv_timeouts_tx: Sender<AdvertisingTimeout>,
 
-    gap_events: Arc<RwLock<HashMap<Discriminant<GapEvent>, Sender<GapEvent>>>>,
+    // Names set with `Gap::set_device_name`, sent once the stack advertises them
+    pub name_updates_rx: Receiver<String>,
+    name_updates_tx: Sender<String>,
+
+    // Advertisements of scanned devices which passed the scan filter
+    pub scan_results_rx: Receiver<Advertisement>,
+    scan_results_tx: Sender<Advertisement>,
+
+    gap_events: Arc<RwLock<EventWaiters<Discriminant<GapEvent>, GapEvent>>>,
+    pub(crate) health: Arc<DispatcherHealth>,
 }
 
 impl Gap {
     pub fn new(bt: ExtBtDriver, gatts: &Arc<GattsInner>) -> anyhow::Result<Self> {
         let gap = EspBleGap::new(bt)?;
+        let (phy_updates_tx, phy_updates_rx) = unbounded();
+        let (adv_timeouts_tx, adv_timeouts_rx) = unbounded();
+        let (name_updates_tx, name_updates_rx) = unbounded();
+        let (scan_results_tx, scan_results_rx) = unbounded();
 
         let gap = GapInner {
             gap,
-            gap_events: Arc::new(RwLock::new(HashMap::new())),
+            gap_events: Default::default(),
             gatts: Arc::downgrade(gatts),
             config: RwLock::new(GapConfig::default()),
+            security: RwLock::new(None),
+            passkey_display: RwLock::new(None),
+            passkey_request: RwLock::new(None),
+            pairing_peers: RwLock::new(Vec::new()),
+            known_peers: RwLock::new(Vec::new()),
+            access_mode: RwLock::new(AccessMode::Open),
+            battery_policy: RwLock::new(None),
+            throttle_step: RwLock::new(None),
+            advertising: RwLock::new(false),
+            adv_deadline: RwLock::new(None),
+            privacy: RwLock::new(false),
+            adv_layout: RwLock::new(AdvLayout::default()),
+            identities: RwLock::new(HashMap::new()),
+            scan_config: RwLock::new(ScanConfig::default()),
+            scan_state: RwLock::new(None),
+            phy_updates_rx,
+            phy_updates_tx,
+            adv_timeouts_rx,
+            adv_timeouts_tx,
+            name_updates_rx,
+            name_updates_tx,
+            scan_results_rx,
+            scan_results_tx,
+            health: Arc::new(DispatcherHealth::new()),
         };
         let gap = Self(Arc::new(gap));
 
+        *gatts
+            .gap
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to acquire write lock for gap: {:?}", err))? =
+            Arc::downgrade(&gap.0);
+
         gap.init_callbacks()?;
+        gap.init_security_events()?;
+        gap.init_phy_events()?;
+        gap.init_identity_events()?;
+        gap.init_scan_events()?;
         gap.apply_config()?;
 
         Ok(gap)
     }
 
+    /// Underlying esp-idf-svc GAP, for stack features this crate does not wrap.
+    ///
+    /// Events are routed by the callback this crate subscribed, calling `subscribe`
+    /// or `unsubscribe` on it breaks advertising, security and connection handling.
+    /// Advertising, scan response and security settings changed through it are not
+    /// seen by `Gap` and are overwritten by the next `set_config`, `set_security`
+    /// or advertising restart
+    pub fn raw(&self) -> &EspBleGap<'static, svc::bt::Ble, ExtBtDriver> {
+        &self.0.gap
+    }
+
     pub fn init_callbacks(&self) -> anyhow::Result<()> {
         let callback_channels_map = Arc::downgrade(&self.0.gap_events);
+        let health = self.0.health.clone();
         self.0.gap.subscribe(move |e| {
             log::info!("Received event {:?}", e);
 
@@ -111,20 +275,30 @@ impl Gap {
                 return;
             };
 
-            let Ok(map_lock) = callback_channels.read() else {
-                log::error!("Failed to acquire write lock for events map");
-                return;
-            };
-
             let event = GapEvent::from(e);
-            let Some(callback_channel) = map_lock.get(&discriminant(&event)) else {
-                log::warn!("No callback channel found for event: {:?}", event);
-                return;
+            let key = discriminant(&event);
+
+            let undelivered = {
+                let Ok(map_lock) = callback_channels.read() else {
+                    log::error!("Failed to acquire read lock for events map");
+                    return;
+                };
+
+                let callback_channel = map_lock.get(&key);
+                if callback_channel.is_none() {
+                    log::warn!("No callback channel found for event: {:?}", event);
+                }
+
+                health.dispatch(callback_channel, event)
             };
 
-            callback_channel.send(event).unwrap_or_else(|err| {
-                log::error!("Failed to send event to callback channel: {:?}", err);
-            });
+            // Waiter may register right after the event arrived, keep it for replay
+            if let Some(event) = undelivered {
+                match callback_channels.write() {
+                    Ok(mut map_lock) => map_lock.buffer(key, event),
+                    Err(err) => log::error!("Failed to buffer event: {:?}", err),
+                }
+            }
         })?;
 
         let gap = self.0.clone();
@@ -137,6 +311,17 @@ impl Gap {
                     break;
                 }
 
+                if let Err(err) = gap.track_pairing_peer(&event) {
+                    log::error!("Failed to track pairing peer: {:?}", err);
+                }
+
+                // Controller stops advertising once a peer connects
+                if let ConnectionStatus::Connected(_) = event
+                    && let Ok(mut advertising) = gap.advertising.write()
+                {
+                    *advertising = false;
+                }
+
                 match event {
                     _ => {
                         let Ok(need_advertise) = gap.check_if_need_start_advertising() else {
@@ -157,36 +342,357 @@ impl Gap {
         Ok(())
     }
 
+    fn init_security_events(&self) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+
+        let mut gap_events = self
+            .0
+            .gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?;
+
+        gap_events.insert(
+            discriminant(&GapEvent::PasskeyNotification {
+                addr: BdAddr::from_bytes([0; 6]),
+                passkey: 0,
+            }),
+            tx.clone(),
+        );
+        gap_events.insert(discriminant(&GapEvent::PasskeyRequest), tx.clone());
+        gap_events.insert(discriminant(&GapEvent::SecurityRequest), tx.clone());
+        gap_events.insert(
+            discriminant(&GapEvent::AuthenticationComplete {
+                bd_addr: BdAddr::from_bytes([0; 6]),
+                status: BtStatus::Success,
+            }),
+            tx,
+        );
+
+        let gap = Arc::downgrade(&self.0);
+        std::thread::spawn(move || {
+            for event in rx.iter() {
+                let Some(gap) = gap.upgrade() else {
+                    log::warn!("Failed to upgrade Gap, exiting security events thread");
+                    return;
+                };
+
+                if let Err(err) = gap.health.time(|| gap.handle_security_event(event)) {
+                    log::error!("Failed to handle security event: {:?}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn init_phy_events(&self) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+        self.0
+            .gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::PhyUpdated {
+                    addr: BdAddr::from_bytes([0; 6]),
+                    status: 0,
+                    tx_phy: 0,
+                    rx_phy: 0,
+                }),
+                tx,
+            );
+
+        let gap = Arc::downgrade(&self.0);
+        std::thread::spawn(move || {
+            for event in rx.iter() {
+                let Some(gap) = gap.upgrade() else {
+                    log::warn!("Failed to upgrade Gap, exiting PHY events thread");
+                    return;
+                };
+
+                if let Err(err) = gap.health.time(|| gap.handle_phy_update(event)) {
+                    log::error!("Failed to handle PHY update: {:?}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn init_identity_events(&self) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+        self.0
+            .gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::ExtendedAdvertisingTerminated {
+                    status: 0,
+                    instance: 0,
+                    conn_handle: 0,
+                }),
+                tx,
+            );
+
+        let gap = Arc::downgrade(&self.0);
+        std::thread::spawn(move || {
+            for event in rx.iter() {
+                let Some(gap) = gap.upgrade() else {
+                    log::warn!("Failed to upgrade Gap, exiting identity events thread");
+                    return;
+                };
+
+                if let Err(err) = gap.health.time(|| gap.handle_adv_terminated(event)) {
+                    log::error!("Failed to handle advertising set termination: {:?}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn init_scan_events(&self) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+
+        let mut gap_events = self
+            .0
+            .gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?;
+
+        gap_events.insert(
+            discriminant(&GapEvent::ScanResult {
+                addr: BdAddr::from_bytes([0; 6]),
+                addr_type: 0,
+                rssi: 0,
+                adv_data: Vec::new(),
+                scan_response: Vec::new(),
+            }),
+            tx.clone(),
+        );
+        gap_events.insert(discriminant(&GapEvent::ScanCompleted), tx);
+
+        let gap = Arc::downgrade(&self.0);
+        std::thread::spawn(move || {
+            for event in rx.iter() {
+                let Some(gap) = gap.upgrade() else {
+                    log::warn!("Failed to upgrade Gap, exiting scan events thread");
+                    return;
+                };
+
+                if let Err(err) = gap.health.time(|| gap.handle_scan_event(event)) {
+                    log::error!("Failed to handle scan event: {:?}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Starts advertising until stopped, ending bounded advertising if it runs
     pub fn start_advertising(&self) -> anyhow::Result<()> {
+        self.0.set_adv_deadline(None)?;
         self.0.start_advertising()
     }
 
+    /// Starts advertising which stops by itself after the given duration, e.g. to
+    /// be discoverable for a while after a button press. Auto advertising resumes
+    /// within that window only. Once it ends an `AdvertisingTimeout` is sent to
+    /// `adv_timeouts_rx`
+    pub fn start_advertising_for(&self, duration: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + duration;
+        self.0.set_adv_deadline(Some(deadline))?;
+
+        if !self.0.is_advertising()? {
+            self.0.start_advertising()?;
+        }
+
+        let gap = Arc::downgrade(&self.0);
+        std::thread::spawn(move || {
+            std::thread::sleep(duration);
+
+            let Some(gap) = gap.upgrade() else {
+                return;
+            };
+
+            if let Err(err) = gap.handle_adv_deadline(deadline, duration) {
+                log::error!("Failed to stop bounded advertising: {:?}", err);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn stop_advertising(&self) -> anyhow::Result<()> {
+        self.0.stop_advertising()
+    }
+
+    /// Scans for advertising devices with the parameters of `set_scan_config`,
+    /// for the given duration rounded up to whole seconds or until stopped when
+    /// None. Advertisements passing `filter` are sent to `scan_results_rx`
+    pub fn start_scanning(
+        &self,
+        duration: Option<Duration>,
+        filter: ScanFilter,
+    ) -> anyhow::Result<()> {
+        let config = self.scan_config()?;
+        self.0
+            .set_scan_state(Some(ScanState::new(filter, config)))?;
+
+        let started = scan::start(&self.0, &config, duration);
+        if started.is_err() {
+            self.0.set_scan_state(None)?;
+        }
+
+        started
+    }
+
+    /// Sets scan parameters used from the next `start_scanning`, a running scan
+    /// keeps its parameters
+    pub fn set_scan_config(&self, config: ScanConfig) -> anyhow::Result<()> {
+        config.validate()?;
+
+        *self.0.scan_config.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for scan config: {:?}", err)
+        })? = config;
+
+        Ok(())
+    }
+
+    pub fn scan_config(&self) -> anyhow::Result<ScanConfig> {
+        Ok(*self.0.scan_config.read().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire read lock for scan config: {:?}", err)
+        })?)
+    }
+
+    pub fn stop_scanning(&self) -> anyhow::Result<()> {
+        scan::stop(&self.0)?;
+        self.0.set_scan_state(None)
+    }
+
+    pub fn is_scanning(&self) -> anyhow::Result<bool> {
+        Ok(self
+            .0
+            .scan_state
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for scan state: {:?}", err)
+            })?
+            .is_some())
+    }
+
+    /// Enables throttling of advertising by battery level, takes effect with
+    /// the next `report_battery_level`
+    pub fn set_battery_policy(&self, policy: BatteryPolicy) -> anyhow::Result<()> {
+        *self.0.battery_policy.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for battery policy: {:?}", err)
+        })? = Some(policy);
+
+        Ok(())
+    }
+
+    /// Reports battery level in percent. When it crosses a step of the battery policy,
+    /// TX power is changed and running advertising is restarted with the new interval
+    pub fn report_battery_level(&self, percent: u8) -> anyhow::Result<()> {
+        let Some(policy) = self
+            .0
+            .battery_policy
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for battery policy: {:?}", err)
+            })?
+            .clone()
+        else {
+            return Ok(());
+        };
+
+        let mut throttle_step = self.0.throttle_step.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for throttle step: {:?}", err)
+        })?;
+
+        let step = policy.step_for(percent.min(100), *throttle_step);
+        if step == *throttle_step {
+            return Ok(());
+        }
+
+        log::info!(
+            "Battery at {}%, advertising throttle step {:?} -> {:?}",
+            percent,
+            *throttle_step,
+            step
+        );
+        *throttle_step = step;
+        drop(throttle_step);
+
+        let tx_power = step
+            .and_then(|index| policy.steps[index].tx_power)
+            .unwrap_or(policy.normal_tx_power);
+        power::set_adv_tx_power(tx_power)?;
+
+        if self.0.is_advertising()? {
+            self.0.stop_advertising()?;
+            self.0.start_advertising()?;
+        }
+
+        Ok(())
+    }
+
     fn apply_config(&self) -> anyhow::Result<()> {
+        let config = self
+            .0
+            .config
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for gap config: {:?}", err)
+            })?
+            .clone();
+
         self.0
             .gap
-            .set_device_name(
-                self.0
-                    .config
-                    .read()
-                    .map_err(|err| {
-                        anyhow::anyhow!("Failed to acquire read lock for gap config: {:?}", err)
-                    })?
-                    .device_name
-                    .as_str(),
-            )
+            .set_device_name(config.device_name.as_str())
             .map_err(|err| anyhow::anyhow!("Failed to set device name: {:?}", err))?;
 
-        self.0
-            .gap
-            .set_adv_conf(
-                &(&*self.0.config.read().map_err(|err| {
-                    anyhow::anyhow!("Failed to acquire read lock for gap config: {:?}", err)
-                })?)
-                    .into(),
-            )
-            .map_err(|err| anyhow::anyhow!("Failed to set advertising configuration: {:?}", err))?;
+        let access_mode = self.0.access_mode()?;
+        let (adv_data, scan_response, layout) = config.adv_split(access_mode)?;
+        *self.0.adv_layout.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for adv layout: {:?}", err)
+        })? = layout.clone();
 
-        Ok(())
+        if !layout.overflowed() {
+            let manufacturer_data = config
+                .manufacturer_data
+                .as_ref()
+                .map(ManufacturerData::encode);
+            let mut adv_conf: AdvConfiguration = (&config).into();
+            adv_conf.manufacturer_data = manufacturer_data.as_deref();
+            if access_mode == AccessMode::BondedOnly {
+                adv_conf.flag = AdvData::BR_EDR_NOT_SUPPORTED;
+            }
+
+            return self.0.set_adv_conf(&adv_conf);
+        }
+
+        log::info!(
+            "Advertising payload exceeds {} bytes, fields placed as {:?}",
+            AdvData::MAX_LEN,
+            layout.fields
+        );
+        self.0.set_raw_adv_data(&adv_data.build()?)?;
+
+        // Scan response is sent for scannable advertising types only
+        self.0.set_raw_scan_response(&scan_response.build()?)
+    }
+
+    /// Where each field of the configured advertising payload was placed, fields
+    /// which do not fit the advertising payload are sent in the scan response
+    pub fn adv_layout(&self) -> anyhow::Result<AdvLayout> {
+        Ok(self
+            .0
+            .adv_layout
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for adv layout: {:?}", err)
+            })?
+            .clone())
     }
 
     pub fn set_config(&self, config: GapConfig) -> anyhow::Result<()> {
@@ -198,34 +704,1154 @@ impl Gap {
 
         Ok(())
     }
-}
 
-impl GapInner {
-    fn check_if_need_start_advertising(&self) -> anyhow::Result<bool> {
-        let gatts = self
-            .gatts
-            .upgrade()
-            .ok_or_else(|| anyhow::anyhow!("Failed to upgrade Gatts from Weak reference"))?;
-        let apps = gatts
-            .apps
+    /// Changes the device name, both the GAP name and the advertising payload
+    /// carrying it, without stopping advertising. The name is sent to
+    /// `name_updates_rx` once the stack applied the new payload
+    pub fn set_device_name(&self, name: &str) -> anyhow::Result<()> {
+        self.0
+            .config
+            .write()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire write lock for gap config: {:?}", err)
+            })?
+            .device_name = name.to_string();
+
+        self.apply_config()?;
+
+        log::info!("Device name changed to \"{}\"", name);
+
+        self.0
+            .name_updates_tx
+            .send(name.to_string())
+            .map_err(|err| anyhow::anyhow!("Failed to send name update: {:?}", err))
+    }
+
+    /// Changes the Service Data of the advertising payload without stopping
+    /// advertising, `data` starts with the 16-bit service UUID (little endian)
+    pub fn set_service_data(&self, data: Option<Vec<u8>>) -> anyhow::Result<()> {
+        self.0
+            .config
+            .write()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire write lock for gap config: {:?}", err)
+            })?
+            .service_data = data;
+
+        self.apply_config()
+    }
+
+    /// Replaces the advertising payload with the given AD structures, bypassing the
+    /// payload generated from `GapConfig` until the config is set again
+    pub fn set_raw_adv_data(&self, data: &AdvData) -> anyhow::Result<()> {
+        self.0.set_raw_adv_data(&data.build()?)
+    }
+
+    /// Replaces the scan response payload with the given AD structures, until the
+    /// config is set again. Only sent to active scanners of scannable advertising
+    pub fn set_raw_scan_response(&self, data: &AdvData) -> anyhow::Result<()> {
+        self.0.set_raw_scan_response(&data.build()?)
+    }
+
+    /// Replaces the advertising payload with the iBeacon and starts advertising if
+    /// it is not running. Use `AdvType::NonConnectable` in `GapConfig` for a
+    /// beacon peers can not connect to
+    pub fn advertise_ibeacon(&self, beacon: &IBeacon) -> anyhow::Result<()> {
+        self.set_raw_adv_data(&beacon.adv_data())?;
+
+        if !self.0.is_advertising()? {
+            self.start_advertising()?;
+        }
+
+        Ok(())
+    }
+
+    /// Advertises Eddystone frames in turn, each for `config.interval`, replacing
+    /// the advertising payload. TLM frames read the telemetry source each time
+    pub fn start_eddystone(&self, config: EddystoneConfig) -> anyhow::Result<Eddystone> {
+        Eddystone::start(&self.0, config)
+    }
+
+    /// Sets the scan response to the device identity, as service data of `uuid`
+    pub fn set_identity_scan_response(
+        &self,
+        uuid: &BtUuid,
+        identity: &DeviceIdentity,
+    ) -> anyhow::Result<()> {
+        self.set_raw_scan_response(&identity.adv_data(uuid))
+    }
+
+    /// Configures an extended advertising set, advertised alongside legacy
+    /// advertising once data is set and it is started
+    /// Advertises a separate identity for `app`, so a product can appear as
+    /// several devices, e.g. a setup and a runtime one. Peers connecting to the
+    /// identity are routed to `app` only, see `AdvIdentity`
+    pub fn advertise_identity(
+        &self,
+        app: &App,
+        config: AdvIdentityConfig,
+    ) -> anyhow::Result<AdvIdentity> {
+        AdvIdentity::start(&self.0, app.0.interface()?, config)
+    }
+
+    pub fn ext_advertising(&self, config: ExtAdvConfig) -> anyhow::Result<ExtAdvertising> {
+        ExtAdvertising::new(&self.0, config)
+    }
+
+    /// Stops and removes every extended advertising set
+    pub fn clear_ext_advertising(&self) -> anyhow::Result<()> {
+        ext_advertising::clear_sets(&self.0)
+    }
+
+    pub fn config(&self) -> anyhow::Result<GapConfig> {
+        Ok(self
+            .0
+            .config
             .read()
-            .map_err(|err| anyhow::anyhow!("Failed to acquire read lock for apps: {:?}", err))?;
-        let current_connection = apps
-            .values()
-            .map(|app| app.connections.read().unwrap().len())
-            .sum::<usize>();
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for gap config: {:?}", err)
+            })?
+            .clone())
+    }
 
-        let config = self.config.read().map_err(|err| {
-            anyhow::anyhow!("Failed to acquire read lock for gap config: {:?}", err)
-        })?;
-        let max_connection = config
-            .max_connections
-            .ok_or(anyhow::anyhow!("Max connections not set in gap config"))?;
+    /// Configures pairing and bonding, without it the stack defaults are used
+    pub fn set_security_config(&self, config: SecurityConfig) -> anyhow::Result<()> {
+        config.apply()?;
+
+        *self.0.security.write().map_err(|err| {
+            anyhow::anyhow!(
+                "Failed to acquire write lock for security config: {:?}",
+                err
+            )
+        })? = Some(config);
 
-        Ok(current_connection < max_connection)
+        Ok(())
     }
 
-    pub fn start_advertising(&self) -> anyhow::Result<()> {
+    /// Sets callback receiving the passkey which should be shown to the user,
+    /// who then enters it on the peer. Used when IO capability can display
+    pub fn on_passkey_display(
+        &self,
+        handler: impl Fn(BdAddr, u32) + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        *self.0.passkey_display.write().map_err(|err| {
+            anyhow::anyhow!(
+                "Failed to acquire write lock for passkey display: {:?}",
+                err
+            )
+        })? = Some(Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Sets callback supplying the passkey keyed in by the user, which is displayed
+    /// on the peer. Returning None rejects the pairing, without a callback
+    /// every request is rejected
+    pub fn on_passkey_request(
+        &self,
+        handler: impl Fn(BdAddr) -> Option<u32> + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        *self.0.passkey_request.write().map_err(|err| {
+            anyhow::anyhow!(
+                "Failed to acquire write lock for passkey request: {:?}",
+                err
+            )
+        })? = Some(Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Adds peers to the controller whitelist and remembers them for directed
+    /// advertising, can be called at boot before any connection, e.g. with
+    /// `peers::bonded_peers()`. Whitelist can not be changed while advertising
+    pub fn add_known_peers(&self, peers: &[KnownPeer]) -> anyhow::Result<()> {
+        for peer in peers {
+            self.0.update_whitelist(peer, true)?;
+
+            let mut known_peers = self.0.known_peers.write().map_err(|err| {
+                anyhow::anyhow!("Failed to acquire write lock for known peers: {:?}", err)
+            })?;
+            known_peers.retain(|known| known.address != peer.address);
+            known_peers.push(*peer);
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_known_peer(&self, address: BdAddr) -> anyhow::Result<()> {
+        let peer = self.0.known_peer(address)?;
+        self.0.update_whitelist(&peer, false)?;
+
+        self.0
+            .known_peers
+            .write()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire write lock for known peers: {:?}", err)
+            })?
+            .retain(|known| known.address != address);
+
+        Ok(())
+    }
+
+    /// Switches between open and bonded-only access without restarting the stack.
+    /// `BondedOnly` adds the bonded peers to the whitelist, advertises not
+    /// discoverable to whitelisted peers only and rejects pairing requests, so
+    /// the device can be locked down after initial setup. Advertising is stopped
+    /// while switching and restarted afterwards when it was running
+    pub fn set_access_mode(&self, mode: AccessMode) -> anyhow::Result<()> {
+        if self.0.access_mode()? == mode {
+            return Ok(());
+        }
+
+        let advertising = self.0.is_advertising()?;
+        if advertising {
+            self.0.stop_advertising()?;
+        }
+
+        // Whitelist can not be changed while advertising
+        if mode == AccessMode::BondedOnly {
+            self.add_known_peers(&peers::bonded_peers()?)?;
+        }
+
+        *self.0.access_mode.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for access mode: {:?}", err)
+        })? = mode;
+
+        self.apply_config()?;
+
+        if advertising {
+            self.0.start_advertising()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn access_mode(&self) -> anyhow::Result<AccessMode> {
+        self.0.access_mode()
+    }
+
+    pub fn known_peers(&self) -> anyhow::Result<Vec<KnownPeer>> {
+        Ok(self
+            .0
+            .known_peers
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for known peers: {:?}", err)
+            })?
+            .clone())
+    }
+
+    /// Advertises connectable to the given known peer only, other devices can not
+    /// see or connect to this device while it lasts
+    pub fn start_directed_advertising(
+        &self,
+        address: BdAddr,
+        duty: DirectedDuty,
+    ) -> anyhow::Result<()> {
+        let peer = self.0.known_peer(address)?;
+        let own_addr_type = privacy::own_addr_type(self.0.is_private()?);
+        let channels = self.config()?.adv_channels;
+
+        self.0.wait_advertising_started(|| {
+            peers::start_directed_advertising(&peer, duty, own_addr_type, channels)
+        })
+    }
+
+    /// Enables LE privacy, the device then advertises with a resolvable private
+    /// address rotated by the controller instead of its fixed MAC address.
+    /// Bonded peers still recognize it through the IRK, so pairing should be
+    /// configured with identity key distribution. Applies to advertising started
+    /// afterwards
+    pub fn set_local_privacy(&self, enabled: bool) -> anyhow::Result<()> {
+        self.0.set_local_privacy(enabled)
+    }
+
+    /// Sets how often the resolvable private address changes, the controller
+    /// default is 15 minutes
+    pub fn set_rpa_rotation(&self, interval: Duration) -> anyhow::Result<()> {
+        privacy::set_rpa_rotation(interval)
+    }
+
+    pub fn security_config(&self) -> anyhow::Result<Option<SecurityConfig>> {
+        Ok(self
+            .0
+            .security
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for security config: {:?}", err)
+            })?
+            .clone())
+    }
+}
+
+impl GapInner {
+    fn track_pairing_peer(&self, event: &ConnectionStatus) -> anyhow::Result<()> {
+        let mut pairing_peers = self.pairing_peers.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for pairing peers: {:?}", err)
+        })?;
+
+        match event {
+            ConnectionStatus::Connected(connection) => pairing_peers.push(connection.peer_addr()),
+            ConnectionStatus::Disconnected(connection, _) => {
+                pairing_peers.retain(|addr| *addr != connection.peer_addr())
+            }
+            ConnectionStatus::Rejected(_) => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_security_event(&self, event: GapEvent) -> anyhow::Result<()> {
+        match event {
+            GapEvent::PasskeyNotification { addr, passkey } => {
+                let handler = self
+                    .passkey_display
+                    .read()
+                    .map_err(|err| {
+                        anyhow::anyhow!(
+                            "Failed to acquire read lock for passkey display: {:?}",
+                            err
+                        )
+                    })?
+                    .clone();
+
+                match handler {
+                    Some(handler) => {
+                        guard::run_hook("passkey display", move || handler(addr, passkey))?
+                    }
+                    None => log::warn!(
+                        "No passkey display handler set, passkey for {:?} is not shown",
+                        addr
+                    ),
+                }
+
+                Ok(())
+            }
+            GapEvent::PasskeyRequest => {
+                // Request does not carry peer address, reply to the latest connected
+                // peer which has not completed authentication yet
+                let addr = *self
+                    .pairing_peers
+                    .read()
+                    .map_err(|err| {
+                        anyhow::anyhow!("Failed to acquire read lock for pairing peers: {:?}", err)
+                    })?
+                    .last()
+                    .ok_or(anyhow::anyhow!("No found peer for passkey request"))?;
+
+                // Failed hook rejects the pairing, same as a missing passkey
+                let passkey = self
+                    .passkey_request
+                    .read()
+                    .map_err(|err| {
+                        anyhow::anyhow!(
+                            "Failed to acquire read lock for passkey request: {:?}",
+                            err
+                        )
+                    })?
+                    .clone()
+                    .and_then(|handler| {
+                        guard::run_hook("passkey request", move || handler(addr))
+                            .ok()
+                            .flatten()
+                    });
+
+                security::passkey_reply(addr, passkey)
+            }
+            GapEvent::SecurityRequest => {
+                // Request does not carry peer address either, same as passkey request
+                let addr = *self
+                    .pairing_peers
+                    .read()
+                    .map_err(|err| {
+                        anyhow::anyhow!("Failed to acquire read lock for pairing peers: {:?}", err)
+                    })?
+                    .last()
+                    .ok_or(anyhow::anyhow!("No found peer for security request"))?;
+
+                let accept = self.access_mode()? == AccessMode::Open;
+                if !accept {
+                    log::warn!("Rejecting pairing with {:?} in bonded-only mode", addr);
+                }
+
+                security::security_reply(addr, accept)
+            }
+            GapEvent::AuthenticationComplete { bd_addr, status } => {
+                if status != BtStatus::Success {
+                    log::warn!("Authentication with {:?} failed: {:?}", bd_addr, status);
+                }
+
+                self.pairing_peers
+                    .write()
+                    .map_err(|err| {
+                        anyhow::anyhow!("Failed to acquire write lock for pairing peers: {:?}", err)
+                    })?
+                    .retain(|addr| *addr != bd_addr);
+
+                Ok(())
+            }
+            _ => Err(anyhow::anyhow!("Unexpected security event: {:?}", event)),
+        }
+    }
+
+    fn check_if_need_start_advertising(&self) -> anyhow::Result<bool> {
+        let gatts = self
+            .gatts
+            .upgrade()
+            .ok_or_else(|| anyhow::anyhow!("Failed to upgrade Gatts from Weak reference"))?;
+        let apps = gatts.apps.read()?;
+        let current_connection = apps
+            .values()
+            .map(|app| Ok(app.connections.read()?.len()))
+            .sum::<anyhow::Result<usize>>()?;
+
+        let config = self.config.read().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire read lock for gap config: {:?}", err)
+        })?;
+        let max_connection = config
+            .max_connections
+            .ok_or(anyhow::anyhow!("Max connections not set in gap config"))?;
+
+        let deadline_passed = self
+            .adv_deadline
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!(
+                    "Failed to acquire read lock for advertising deadline: {:?}",
+                    err
+                )
+            })?
+            .is_some_and(|deadline| deadline <= Instant::now());
+
+        Ok(current_connection < max_connection && !deadline_passed)
+    }
+
+    fn known_peer(&self, address: BdAddr) -> anyhow::Result<KnownPeer> {
+        self.known_peers
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for known peers: {:?}", err)
+            })?
+            .iter()
+            .find(|peer| peer.address == address)
+            .copied()
+            .ok_or(anyhow::anyhow!("No found known peer {:?}", address))
+    }
+
+    fn update_whitelist(&self, peer: &KnownPeer, add: bool) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::WhitelistUpdated {
+                    status: BtStatus::Done,
+                    wl_operation: 0,
+                }),
+                tx,
+            );
+
+        peers::update_whitelist(peer, add)?;
+
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(GapEvent::WhitelistUpdated {
+                status,
+                wl_operation,
+            }) => match status {
+                BtStatus::Success => {
+                    log::debug!(
+                        "Whitelist operation {} done for {:?}",
+                        wl_operation,
+                        peer.address
+                    );
+                    Ok(())
+                }
+                _ => Err(anyhow::anyhow!(
+                    "Failed to update whitelist with {:?}: {:?}",
+                    peer.address,
+                    status
+                )),
+            },
+            Ok(event) => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timeout waiting for whitelist updated event"
+            )),
+        }
+    }
+
+    pub(crate) fn read_rssi(&self, addr: BdAddr) -> anyhow::Result<i8> {
+        let (tx, rx) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::ReadRssiConfigured {
+                    bd_addr: BdAddr::from_bytes([0; 6]),
+                    rssdi: 0,
+                    status: BtStatus::Done,
+                }),
+                tx,
+            );
+
+        let mut raw_addr = addr.raw();
+        esp!(unsafe { esp_ble_gap_read_rssi(raw_addr.as_mut_ptr()) })
+            .map_err(|err| anyhow::anyhow!("Failed to read RSSI of {:?}: {:?}", addr, err))?;
+
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(GapEvent::ReadRssiConfigured {
+                bd_addr,
+                rssdi,
+                status,
+            }) => {
+                if bd_addr != addr {
+                    return Err(anyhow::anyhow!(
+                        "Received RSSI of unexpected peer: {:?}",
+                        bd_addr
+                    ));
+                }
+
+                match status {
+                    BtStatus::Success => Ok(rssdi),
+                    _ => Err(anyhow::anyhow!(
+                        "Failed to read RSSI of {:?}: {:?}",
+                        addr,
+                        status
+                    )),
+                }
+            }
+            Ok(event) => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+            Err(_) => Err(anyhow::anyhow!("Timeout waiting for read RSSI event")),
+        }
+    }
+
+    /// Asks the controller to use Data Length Extension with the peer, returns the
+    /// receive and transmit payload lengths the link ended up with
+    pub(crate) fn set_data_len(&self, addr: BdAddr, tx_len: u16) -> anyhow::Result<(u16, u16)> {
+        let kind = GapEvent::PacketLengthConfigured {
+            status: BtStatus::Done,
+            rx_len: 0,
+            tx_len: 0,
+        };
+
+        let event = self.wait_event(&kind, || {
+            let mut raw_addr = addr.raw();
+            esp!(unsafe { esp_ble_gap_set_pkt_data_len(raw_addr.as_mut_ptr(), tx_len) }).map_err(
+                |err| anyhow::anyhow!("Failed to set data length of {:?}: {:?}", addr, err),
+            )
+        })?;
+
+        match event {
+            GapEvent::PacketLengthConfigured {
+                status: BtStatus::Success,
+                rx_len,
+                tx_len,
+            } => Ok((rx_len, tx_len)),
+            GapEvent::PacketLengthConfigured { status, .. } => Err(anyhow::anyhow!(
+                "Failed to set data length of {:?}: {:?}",
+                addr,
+                status
+            )),
+            event => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+        }
+    }
+
+    fn set_scan_state(&self, state: Option<ScanState>) -> anyhow::Result<()> {
+        *self.scan_state.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for scan state: {:?}", err)
+        })? = state;
+
+        Ok(())
+    }
+
+    fn handle_scan_event(&self, event: GapEvent) -> anyhow::Result<()> {
+        match event {
+            GapEvent::ScanResult {
+                addr,
+                addr_type,
+                rssi,
+                adv_data,
+                scan_response,
+            } => {
+                let mut advertisement =
+                    scan::advertisement(addr, addr_type, rssi, &adv_data, &scan_response)?;
+
+                let reported = self
+                    .scan_state
+                    .write()
+                    .map_err(|err| {
+                        anyhow::anyhow!("Failed to acquire write lock for scan state: {:?}", err)
+                    })?
+                    .as_mut()
+                    .is_some_and(|state| state.process(&mut advertisement));
+
+                if !reported {
+                    return Ok(());
+                }
+
+                self.scan_results_tx
+                    .send(advertisement)
+                    .map_err(|err| anyhow::anyhow!("Failed to send scan result: {:?}", err))
+            }
+            GapEvent::ScanCompleted => {
+                log::info!("Scan duration ended");
+                self.set_scan_state(None)
+            }
+            _ => Err(anyhow::anyhow!("Unexpected scan event: {:?}", event)),
+        }
+    }
+
+    fn handle_phy_update(&self, event: GapEvent) -> anyhow::Result<()> {
+        let GapEvent::PhyUpdated {
+            addr,
+            status,
+            tx_phy,
+            rx_phy,
+        } = event
+        else {
+            return Err(anyhow::anyhow!("Unexpected PHY event: {:?}", event));
+        };
+
+        let (Some(tx), Some(rx)) = (Phy::from_raw(tx_phy), Phy::from_raw(rx_phy)) else {
+            return Err(anyhow::anyhow!(
+                "Unknown PHY of {:?}: tx {}, rx {}",
+                addr,
+                tx_phy,
+                rx_phy
+            ));
+        };
+
+        let update = PhyUpdate {
+            address: addr,
+            success: status == esp_bt_status_t_ESP_BT_STATUS_SUCCESS,
+            tx,
+            rx,
+        };
+
+        if update.success {
+            let gatts = self
+                .gatts
+                .upgrade()
+                .ok_or_else(|| anyhow::anyhow!("Failed to upgrade Gatts from Weak reference"))?;
+
+            for app in gatts.apps.read()?.values() {
+                for connection in app.connections.read()?.values() {
+                    if connection.peer_addr() == addr {
+                        connection.set_phy(tx, rx)?;
+                    }
+                }
+            }
+        } else {
+            log::warn!("PHY update of {:?} failed: {}", addr, status);
+        }
+
+        self.phy_updates_tx
+            .send(update)
+            .map_err(|err| anyhow::anyhow!("Failed to send PHY update: {:?}", err))
+    }
+
+    pub(crate) fn set_preferred_phy(
+        &self,
+        addr: BdAddr,
+        tx: &[Phy],
+        rx: &[Phy],
+        options: PhyOptions,
+    ) -> anyhow::Result<()> {
+        let (tx_events, rx_events) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::PreferredPhyConfigured(BtStatus::Done)),
+                tx_events,
+            );
+
+        phy::set_preferred_phy(addr, tx, rx, options)?;
+
+        match rx_events.recv_timeout(Duration::from_secs(5)) {
+            Ok(GapEvent::PreferredPhyConfigured(status)) => match status {
+                BtStatus::Success => Ok(()),
+                _ => Err(anyhow::anyhow!(
+                    "Failed to set preferred PHY of {:?}: {:?}",
+                    addr,
+                    status
+                )),
+            },
+            Ok(event) => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timeout waiting for preferred PHY configured event"
+            )),
+        }
+    }
+
+    pub(crate) fn update_conn_params(
+        &self,
+        addr: BdAddr,
+        min_int_ms: u32,
+        max_int_ms: u32,
+        latency_ms: u32,
+        timeout_ms: u32,
+    ) -> anyhow::Result<GattConnParams> {
+        let (tx, rx) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::ConnectionParamsConfigured {
+                    addr: BdAddr::from_bytes([0; 6]),
+                    status: BtStatus::Done,
+                    min_int_ms: 0,
+                    max_int_ms: 0,
+                    latency_ms: 0,
+                    conn_int: 0,
+                    timeout_ms: 0,
+                }),
+                tx,
+            );
+
+        self.gap
+            .set_conn_params_conf(addr, min_int_ms, max_int_ms, latency_ms, timeout_ms)
+            .map_err(|err| {
+                anyhow::anyhow!(
+                    "Failed to update connection params of {:?}: {:?}",
+                    addr,
+                    err
+                )
+            })?;
+
+        // Peer may take a few connection events to answer
+        match rx.recv_timeout(Duration::from_secs(10)) {
+            Ok(GapEvent::ConnectionParamsConfigured {
+                addr: updated_addr,
+                status,
+                min_int_ms,
+                max_int_ms,
+                latency_ms,
+                conn_int,
+                timeout_ms,
+            }) => {
+                if updated_addr != addr {
+                    return Err(anyhow::anyhow!(
+                        "Received connection params of unexpected peer: {:?}",
+                        updated_addr
+                    ));
+                }
+
+                log::debug!(
+                    "Connection params of {:?} requested {}..={} ms, got interval {}",
+                    addr,
+                    min_int_ms,
+                    max_int_ms,
+                    conn_int
+                );
+
+                match status {
+                    // Interval is reported in units of 1.25 ms
+                    BtStatus::Success => Ok(GattConnParams {
+                        interval_ms: conn_int as u32 * 125 / 100,
+                        latency_ms,
+                        timeout_ms,
+                    }),
+                    _ => Err(anyhow::anyhow!(
+                        "Failed to update connection params of {:?}: {:?}",
+                        addr,
+                        status
+                    )),
+                }
+            }
+            Ok(event) => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timeout waiting for connection params updated event"
+            )),
+        }
+    }
+
+    pub fn start_advertising(&self) -> anyhow::Result<()> {
+        let mut params = {
+            let config = self.config.read().map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for gap config: {:?}", err)
+            })?;
+
+            AdvParams {
+                min_interval: config.adv_min_interval,
+                max_interval: config.adv_max_interval,
+                adv_type: config.adv_type,
+                channels: config.adv_channels,
+                own_addr_type: privacy::own_addr_type(self.is_private()?),
+                whitelist_only: self.access_mode()? == AccessMode::BondedOnly,
+            }
+        };
+
+        if let Some(step) = self.throttle_step()? {
+            params.min_interval = step.min_interval;
+            params.max_interval = step.max_interval;
+        }
+
+        self.wait_advertising_started(|| advertising::start_advertising(&params))
+    }
+
+    /// Runs `start` and waits for the next event of the same kind as `kind`. The
+    /// waiter is deregistered afterwards, so later events of that kind are buffered
+    /// for the next waiter instead of being sent to a dead channel
+    pub(crate) fn wait_event(
+        &self,
+        kind: &GapEvent,
+        start: impl FnOnce() -> anyhow::Result<()>,
+    ) -> anyhow::Result<GapEvent> {
+        let key = discriminant(kind);
+        let (tx, rx) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(key, tx.clone());
+
+        let event = start().and_then(|_| {
+            rx.recv_timeout(Duration::from_secs(5))
+                .map_err(|_| anyhow::anyhow!("Timeout waiting for event {:?}", kind))
+        });
+
+        match self.gap_events.write() {
+            Ok(mut gap_events) => gap_events.remove(&key, &tx),
+            Err(err) => log::error!("Failed to deregister event waiter: {:?}", err),
+        }
+
+        event
+    }
+
+    fn set_raw_adv_data(&self, data: &[u8]) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::RawAdvertisingConfigured(BtStatus::Done)),
+                tx,
+            );
+
+        self.gap
+            .set_raw_adv_conf(data)
+            .map_err(|err| anyhow::anyhow!("Failed to set raw advertising data: {:?}", err))?;
+
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(GapEvent::RawAdvertisingConfigured(status)) => match status {
+                BtStatus::Success => Ok(()),
+                _ => Err(anyhow::anyhow!(
+                    "Failed to configure raw advertising data: {:?}",
+                    status
+                )),
+            },
+            Ok(event) => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timeout waiting for raw advertising configured event"
+            )),
+        }
+    }
+
+    /// Sets the advertising or scan response payload generated by the stack and
+    /// waits until it is applied
+    fn set_adv_conf(&self, conf: &AdvConfiguration) -> anyhow::Result<()> {
+        let kind = match conf.set_scan_rsp {
+            true => GapEvent::ScanResponseConfigured(BtStatus::Done),
+            false => GapEvent::AdvertisingConfigured(BtStatus::Done),
+        };
+
+        let event = self.wait_event(&kind, || {
+            self.gap.set_adv_conf(conf).map_err(|err| {
+                anyhow::anyhow!("Failed to set advertising configuration: {:?}", err)
+            })
+        })?;
+
+        match event {
+            GapEvent::AdvertisingConfigured(BtStatus::Success)
+            | GapEvent::ScanResponseConfigured(BtStatus::Success) => Ok(()),
+            GapEvent::AdvertisingConfigured(status) | GapEvent::ScanResponseConfigured(status) => {
+                Err(anyhow::anyhow!(
+                    "Failed to configure advertising data: {:?}",
+                    status
+                ))
+            }
+            event => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+        }
+    }
+
+    fn set_raw_scan_response(&self, data: &[u8]) -> anyhow::Result<()> {
+        let event =
+            self.wait_event(&GapEvent::RawScanResponseConfigured(BtStatus::Done), || {
+                self.gap.set_raw_scan_rsp_conf(data).map_err(|err| {
+                    anyhow::anyhow!("Failed to set raw scan response data: {:?}", err)
+                })
+            })?;
+
+        match event {
+            GapEvent::RawScanResponseConfigured(BtStatus::Success) => Ok(()),
+            GapEvent::RawScanResponseConfigured(status) => Err(anyhow::anyhow!(
+                "Failed to configure raw scan response data: {:?}",
+                status
+            )),
+            event => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+        }
+    }
+
+    fn set_local_privacy(&self, enabled: bool) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::LocalPrivacyConfigured(BtStatus::Done)),
+                tx,
+            );
+
+        privacy::config_local_privacy(enabled)?;
+
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(GapEvent::LocalPrivacyConfigured(status)) => match status {
+                BtStatus::Success => {
+                    *self.privacy.write().map_err(|err| {
+                        anyhow::anyhow!("Failed to acquire write lock for privacy: {:?}", err)
+                    })? = enabled;
+                    Ok(())
+                }
+                _ => Err(anyhow::anyhow!(
+                    "Failed to configure local privacy: {:?}",
+                    status
+                )),
+            },
+            Ok(event) => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timeout waiting for local privacy configured event"
+            )),
+        }
+    }
+
+    fn is_private(&self) -> anyhow::Result<bool> {
+        Ok(*self
+            .privacy
+            .read()
+            .map_err(|err| anyhow::anyhow!("Failed to acquire read lock for privacy: {:?}", err))?)
+    }
+
+    fn throttle_step(&self) -> anyhow::Result<Option<power::ThrottleStep>> {
+        let Some(index) = *self.throttle_step.read().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire read lock for throttle step: {:?}", err)
+        })?
+        else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .battery_policy
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for battery policy: {:?}", err)
+            })?
+            .as_ref()
+            .and_then(|policy| policy.steps.get(index).copied()))
+    }
+
+    pub fn stop_advertising(&self) -> anyhow::Result<()> {
+        let (tx, rx) = unbounded();
+        self.gap_events
+            .write()
+            .map_err(|err| anyhow::anyhow!("Failed to write gap_events: {:?}", err))?
+            .insert(
+                discriminant(&GapEvent::AdvertisingStopped(BtStatus::Done)),
+                tx,
+            );
+
+        self.gap.stop_advertising()?;
+
+        match rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(GapEvent::AdvertisingStopped(status)) => match status {
+                BtStatus::Success => {
+                    self.set_advertising(false)?;
+                    Ok(())
+                }
+                _ => Err(anyhow::anyhow!("Failed to stop advertising: {:?}", status)),
+            },
+            Ok(event) => Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timeout waiting for advertising stopped event"
+            )),
+        }
+    }
+
+    pub(crate) fn has_identities(&self) -> anyhow::Result<bool> {
+        Ok(!self
+            .identities
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for identities: {:?}", err)
+            })?
+            .is_empty())
+    }
+
+    fn bind_identity(&self, instance: u8, interface: GattInterface) -> anyhow::Result<()> {
+        let mut identities = self.identities.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for identities: {:?}", err)
+        })?;
+
+        if identities.contains_key(&instance) {
+            return Err(anyhow::anyhow!(
+                "Advertising set {} already advertises an identity",
+                instance
+            ));
+        }
+
+        identities.insert(instance, interface);
+
+        Ok(())
+    }
+
+    fn unbind_identity(&self, instance: u8) -> anyhow::Result<()> {
+        self.identities
+            .write()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire write lock for identities: {:?}", err)
+            })?
+            .remove(&instance);
+
+        Ok(())
+    }
+
+    // A peer connecting to a set ends its advertising, the connection is routed
+    // to the app of the identity
+    fn handle_adv_terminated(&self, event: GapEvent) -> anyhow::Result<()> {
+        let GapEvent::ExtendedAdvertisingTerminated {
+            status,
+            instance,
+            conn_handle,
+        } = event
+        else {
+            return Err(anyhow::anyhow!("Unexpected event: {:?}", event));
+        };
+
+        if status != 0 {
+            log::info!(
+                "Advertising set {} ended without connection: {:#04x}",
+                instance,
+                status
+            );
+            return Ok(());
+        }
+
+        let interface = self
+            .identities
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for identities: {:?}", err)
+            })?
+            .get(&instance)
+            .copied();
+
+        let Some(interface) = interface else {
+            return Ok(());
+        };
+
+        log::info!(
+            "Peer connected to identity {} (link {:#x}), routing to app {}",
+            instance,
+            conn_handle,
+            interface
+        );
+
+        self.gatts
+            .upgrade()
+            .ok_or(anyhow::anyhow!("Failed to upgrade Gatts"))?
+            .route_connection(interface, conn_handle)
+    }
+
+    pub(crate) fn access_mode(&self) -> anyhow::Result<AccessMode> {
+        Ok(*self.access_mode.read().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire read lock for access mode: {:?}", err)
+        })?)
+    }
+
+    pub(crate) fn is_advertising(&self) -> anyhow::Result<bool> {
+        Ok(*self.advertising.read().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire read lock for advertising: {:?}", err)
+        })?)
+    }
+
+    pub(crate) fn throttle_step_index(&self) -> anyhow::Result<Option<usize>> {
+        Ok(*self.throttle_step.read().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire read lock for throttle step: {:?}", err)
+        })?)
+    }
+
+    // Restores throttle step, e.g. after deep sleep, TX power of the controller
+    // was reset, so it is applied again
+    pub(crate) fn set_throttle_step_index(&self, step: Option<usize>) -> anyhow::Result<()> {
+        *self.throttle_step.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for throttle step: {:?}", err)
+        })? = step;
+
+        let policy = self
+            .battery_policy
+            .read()
+            .map_err(|err| {
+                anyhow::anyhow!("Failed to acquire read lock for battery policy: {:?}", err)
+            })?
+            .clone();
+
+        if let Some(policy) = policy {
+            let tx_power = step
+                .and_then(|index| policy.steps.get(index))
+                .and_then(|step| step.tx_power)
+                .unwrap_or(policy.normal_tx_power);
+            power::set_adv_tx_power(tx_power)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_adv_deadline(&self, deadline: Option<Instant>) -> anyhow::Result<()> {
+        *self.adv_deadline.write().map_err(|err| {
+            anyhow::anyhow!(
+                "Failed to acquire write lock for advertising deadline: {:?}",
+                err
+            )
+        })? = deadline;
+
+        Ok(())
+    }
+
+    fn handle_adv_deadline(&self, deadline: Instant, duration: Duration) -> anyhow::Result<()> {
+        let current = *self.adv_deadline.read().map_err(|err| {
+            anyhow::anyhow!(
+                "Failed to acquire read lock for advertising deadline: {:?}",
+                err
+            )
+        })?;
+
+        // Advertising was started again since, with another deadline or none
+        if current != Some(deadline) {
+            return Ok(());
+        }
+
+        let stopped = self.is_advertising()?;
+        if stopped {
+            self.stop_advertising()?;
+        }
+
+        log::info!("Bounded advertising of {:?} ended", duration);
+
+        self.adv_timeouts_tx
+            .send(AdvertisingTimeout { duration, stopped })
+            .map_err(|err| anyhow::anyhow!("Failed to send advertising timeout: {:?}", err))
+    }
+
+    fn set_advertising(&self, advertising: bool) -> anyhow::Result<()> {
+        *self.advertising.write().map_err(|err| {
+            anyhow::anyhow!("Failed to acquire write lock for advertising: {:?}", err)
+        })? = advertising;
+
+        Ok(())
+    }
+
+    fn wait_advertising_started(
+        &self,
+        start: impl FnOnce() -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
         let (tx, rx) = unbounded();
         self.gap_events
             .write()
@@ -235,12 +1861,12 @@ impl GapInner {
                 tx.clone(),
             );
 
-        self.gap.start_advertising()?;
+        start()?;
 
         match rx.recv_timeout(Duration::from_secs(5)) {
             Ok(status) => match status {
                 GapEvent::AdvertisingStarted(bt_status) => match bt_status {
-                    BtStatus::Success => Ok(()),
+                    BtStatus::Success => self.set_advertising(true),
                     _ => Err(anyhow::anyhow!(
                         "Failed to start advertising: {:?}",
                         bt_status
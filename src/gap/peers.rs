@@ -0,0 +1,150 @@
+use esp_idf_svc::{
+    bt::BdAddr,
+    sys::{
+        esp, esp_ble_addr_type_t, esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC,
+        esp_ble_addr_type_t_BLE_ADDR_TYPE_RANDOM,
+        esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY, esp_ble_adv_params_t,
+        esp_ble_adv_type_t_ADV_TYPE_DIRECT_IND_HIGH, esp_ble_adv_type_t_ADV_TYPE_DIRECT_IND_LOW,
+        esp_ble_bond_dev_t, esp_ble_gap_start_advertising, esp_ble_gap_update_whitelist,
+        esp_ble_get_bond_device_list, esp_ble_get_bond_device_num, esp_ble_wl_addr_type_t,
+        esp_ble_wl_addr_type_t_BLE_WL_ADDR_TYPE_PUBLIC,
+        esp_ble_wl_addr_type_t_BLE_WL_ADDR_TYPE_RANDOM,
+    },
+};
+
+use super::advertising::AdvChannels;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerAddressType {
+    Public,
+    // Static random or resolvable private address, the latter is resolved by the
+    // controller only for bonded peers whose IRK is known
+    Random,
+}
+
+impl PeerAddressType {
+    fn raw(self) -> esp_ble_addr_type_t {
+        match self {
+            Self::Public => esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC,
+            Self::Random => esp_ble_addr_type_t_BLE_ADDR_TYPE_RANDOM,
+        }
+    }
+
+    fn whitelist(self) -> esp_ble_wl_addr_type_t {
+        match self {
+            Self::Public => esp_ble_wl_addr_type_t_BLE_WL_ADDR_TYPE_PUBLIC,
+            Self::Random => esp_ble_wl_addr_type_t_BLE_WL_ADDR_TYPE_RANDOM,
+        }
+    }
+}
+
+/// Identity of a peer known before it connects, e.g. stored by the application
+/// after a previous pairing or read from the bond list of the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownPeer {
+    pub address: BdAddr,
+    pub address_type: PeerAddressType,
+}
+
+/// Who may discover, connect and pair, switched with `Gap::set_access_mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessMode {
+    // Any device can discover, connect and pair
+    #[default]
+    Open,
+    // Only whitelisted peers, bonded or known, can scan and connect, advertising
+    // is not discoverable and new pairings are rejected
+    BondedOnly,
+}
+
+/// How often directed advertising packets are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectedDuty {
+    // Fastest reconnection, controller stops advertising after 1.28 s
+    High,
+    // Advertises at the given interval range (units of 0.625 ms) until stopped
+    Low {
+        min_interval: u16,
+        max_interval: u16,
+    },
+}
+
+/// Peers bonded with this device, bonds are loaded by the stack from NVS during
+/// initialization, so the list is available right after boot
+pub fn bonded_peers() -> anyhow::Result<Vec<KnownPeer>> {
+    let mut count = unsafe { esp_ble_get_bond_device_num() };
+    if count <= 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut devices = vec![esp_ble_bond_dev_t::default(); count as usize];
+    esp!(unsafe { esp_ble_get_bond_device_list(&mut count, devices.as_mut_ptr()) })
+        .map_err(|err| anyhow::anyhow!("Failed to get bond device list: {:?}", err))?;
+    devices.truncate(count.max(0) as usize);
+
+    Ok(devices
+        .into_iter()
+        .map(|device| KnownPeer {
+            address: BdAddr::from_bytes(device.bd_addr),
+            address_type: if device.bd_addr_type == esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC {
+                PeerAddressType::Public
+            } else {
+                PeerAddressType::Random
+            },
+        })
+        .collect())
+}
+
+pub(crate) fn update_whitelist(peer: &KnownPeer, add: bool) -> anyhow::Result<()> {
+    let mut raw_addr = peer.address.raw();
+
+    esp!(unsafe {
+        esp_ble_gap_update_whitelist(add, raw_addr.as_mut_ptr(), peer.address_type.whitelist())
+    })
+    .map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to update whitelist with {:?}: {:?}",
+            peer.address,
+            err
+        )
+    })
+}
+
+pub(crate) fn start_directed_advertising(
+    peer: &KnownPeer,
+    duty: DirectedDuty,
+    own_addr_type: esp_ble_addr_type_t,
+    channels: AdvChannels,
+) -> anyhow::Result<()> {
+    let (adv_type, adv_int_min, adv_int_max) = match duty {
+        // Interval is ignored by the controller for high duty cycle advertising
+        DirectedDuty::High => (esp_ble_adv_type_t_ADV_TYPE_DIRECT_IND_HIGH, 0x20, 0x20),
+        DirectedDuty::Low {
+            min_interval,
+            max_interval,
+        } => (
+            esp_ble_adv_type_t_ADV_TYPE_DIRECT_IND_LOW,
+            min_interval,
+            max_interval,
+        ),
+    };
+
+    let mut params = esp_ble_adv_params_t {
+        adv_int_min,
+        adv_int_max,
+        adv_type,
+        own_addr_type,
+        peer_addr: peer.address.raw(),
+        peer_addr_type: peer.address_type.raw(),
+        channel_map: channels.raw()?,
+        adv_filter_policy: esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY,
+    };
+
+    esp!(unsafe { esp_ble_gap_start_advertising(&mut params) }).map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to start directed advertising to {:?}: {:?}",
+            peer.address,
+            err
+        )
+    })
+}
@@ -0,0 +1,110 @@
+use esp_idf_svc::{
+    bt::BdAddr,
+    sys::{
+        ESP_BLE_GAP_NO_PREFER_RECEIVE_PHY, ESP_BLE_GAP_NO_PREFER_TRANSMIT_PHY, ESP_BLE_GAP_PHY_1M,
+        ESP_BLE_GAP_PHY_1M_PREF_MASK, ESP_BLE_GAP_PHY_2M, ESP_BLE_GAP_PHY_2M_PREF_MASK,
+        ESP_BLE_GAP_PHY_CODED, ESP_BLE_GAP_PHY_CODED_PREF_MASK, ESP_BLE_GAP_PHY_OPTIONS_NO_PREF,
+        ESP_BLE_GAP_PHY_OPTIONS_PREF_S2_CODING, ESP_BLE_GAP_PHY_OPTIONS_PREF_S8_CODING, esp,
+        esp_ble_gap_phy_t, esp_ble_gap_set_preferred_phy,
+    },
+};
+
+/// Physical layer of a link, every link starts on `Le1M`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phy {
+    Le1M,
+    // Doubles the throughput, at the cost of a slightly shorter range
+    Le2M,
+    // Long range, with a quarter (S2) or eighth (S8) of the 1M throughput
+    Coded,
+}
+
+impl Phy {
+    fn mask(self) -> u8 {
+        (match self {
+            Self::Le1M => ESP_BLE_GAP_PHY_1M_PREF_MASK,
+            Self::Le2M => ESP_BLE_GAP_PHY_2M_PREF_MASK,
+            Self::Coded => ESP_BLE_GAP_PHY_CODED_PREF_MASK,
+        }) as u8
+    }
+
+    pub(crate) fn raw(self) -> esp_ble_gap_phy_t {
+        (match self {
+            Self::Le1M => ESP_BLE_GAP_PHY_1M,
+            Self::Le2M => ESP_BLE_GAP_PHY_2M,
+            Self::Coded => ESP_BLE_GAP_PHY_CODED,
+        }) as esp_ble_gap_phy_t
+    }
+
+    pub(crate) fn from_raw(raw: esp_ble_gap_phy_t) -> Option<Self> {
+        match raw as u32 {
+            ESP_BLE_GAP_PHY_1M => Some(Self::Le1M),
+            ESP_BLE_GAP_PHY_2M => Some(Self::Le2M),
+            ESP_BLE_GAP_PHY_CODED => Some(Self::Coded),
+            _ => None,
+        }
+    }
+}
+
+/// Coding preferred when the link uses `Phy::Coded`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PhyOptions {
+    #[default]
+    NoPreference,
+    // 500 kbps
+    S2,
+    // 125 kbps, longest range
+    S8,
+}
+
+impl PhyOptions {
+    fn raw(self) -> u16 {
+        (match self {
+            Self::NoPreference => ESP_BLE_GAP_PHY_OPTIONS_NO_PREF,
+            Self::S2 => ESP_BLE_GAP_PHY_OPTIONS_PREF_S2_CODING,
+            Self::S8 => ESP_BLE_GAP_PHY_OPTIONS_PREF_S8_CODING,
+        }) as u16
+    }
+}
+
+/// Result of a PHY update procedure, started by either side of the link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhyUpdate {
+    pub address: BdAddr,
+    // False when the controller or the peer rejected the change,
+    // the link then stays on its previous PHY
+    pub success: bool,
+    pub tx: Phy,
+    pub rx: Phy,
+}
+
+pub(crate) fn set_preferred_phy(
+    address: BdAddr,
+    tx: &[Phy],
+    rx: &[Phy],
+    options: PhyOptions,
+) -> anyhow::Result<()> {
+    let mask = |phys: &[Phy]| phys.iter().fold(0, |mask, phy| mask | phy.mask());
+    let (tx_mask, rx_mask) = (mask(tx), mask(rx));
+
+    // Empty preference leaves the choice of that direction to the peer
+    let mut all_phys = 0;
+    if tx_mask == 0 {
+        all_phys |= ESP_BLE_GAP_NO_PREFER_TRANSMIT_PHY as u8;
+    }
+    if rx_mask == 0 {
+        all_phys |= ESP_BLE_GAP_NO_PREFER_RECEIVE_PHY as u8;
+    }
+
+    let mut raw_addr = address.raw();
+    esp!(unsafe {
+        esp_ble_gap_set_preferred_phy(
+            raw_addr.as_mut_ptr(),
+            all_phys,
+            tx_mask,
+            rx_mask,
+            options.raw(),
+        )
+    })
+    .map_err(|err| anyhow::anyhow!("Failed to set preferred PHY of {:?}: {:?}", address, err))
+}
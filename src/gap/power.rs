@@ -0,0 +1,184 @@
+use esp_idf_svc::sys::{
+    esp, esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV, esp_ble_tx_power_get, esp_ble_tx_power_set,
+    esp_power_level_t, esp_power_level_t_ESP_PWR_LVL_N0, esp_power_level_t_ESP_PWR_LVL_N3,
+    esp_power_level_t_ESP_PWR_LVL_N6, esp_power_level_t_ESP_PWR_LVL_N9,
+    esp_power_level_t_ESP_PWR_LVL_N12, esp_power_level_t_ESP_PWR_LVL_N15,
+    esp_power_level_t_ESP_PWR_LVL_N18, esp_power_level_t_ESP_PWR_LVL_N21,
+    esp_power_level_t_ESP_PWR_LVL_N24, esp_power_level_t_ESP_PWR_LVL_P3,
+    esp_power_level_t_ESP_PWR_LVL_P6, esp_power_level_t_ESP_PWR_LVL_P9,
+    esp_power_level_t_ESP_PWR_LVL_P12, esp_power_level_t_ESP_PWR_LVL_P15,
+    esp_power_level_t_ESP_PWR_LVL_P18, esp_power_level_t_ESP_PWR_LVL_P21,
+};
+
+/// Transmit power levels supported by the controller, in dBm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxPower {
+    N24,
+    N21,
+    N18,
+    N15,
+    N12,
+    N9,
+    N6,
+    N3,
+    N0,
+    P3,
+    P6,
+    P9,
+    P12,
+    P15,
+    P18,
+    P21,
+}
+
+impl TxPower {
+    pub const ALL: [TxPower; 16] = [
+        TxPower::N24,
+        TxPower::N21,
+        TxPower::N18,
+        TxPower::N15,
+        TxPower::N12,
+        TxPower::N9,
+        TxPower::N6,
+        TxPower::N3,
+        TxPower::N0,
+        TxPower::P3,
+        TxPower::P6,
+        TxPower::P9,
+        TxPower::P12,
+        TxPower::P15,
+        TxPower::P18,
+        TxPower::P21,
+    ];
+
+    pub fn dbm(&self) -> i8 {
+        match self {
+            TxPower::N24 => -24,
+            TxPower::N21 => -21,
+            TxPower::N18 => -18,
+            TxPower::N15 => -15,
+            TxPower::N12 => -12,
+            TxPower::N9 => -9,
+            TxPower::N6 => -6,
+            TxPower::N3 => -3,
+            TxPower::N0 => 0,
+            TxPower::P3 => 3,
+            TxPower::P6 => 6,
+            TxPower::P9 => 9,
+            TxPower::P12 => 12,
+            TxPower::P15 => 15,
+            TxPower::P18 => 18,
+            TxPower::P21 => 21,
+        }
+    }
+}
+
+impl From<TxPower> for esp_power_level_t {
+    fn from(value: TxPower) -> Self {
+        match value {
+            TxPower::N24 => esp_power_level_t_ESP_PWR_LVL_N24,
+            TxPower::N21 => esp_power_level_t_ESP_PWR_LVL_N21,
+            TxPower::N18 => esp_power_level_t_ESP_PWR_LVL_N18,
+            TxPower::N15 => esp_power_level_t_ESP_PWR_LVL_N15,
+            TxPower::N12 => esp_power_level_t_ESP_PWR_LVL_N12,
+            TxPower::N9 => esp_power_level_t_ESP_PWR_LVL_N9,
+            TxPower::N6 => esp_power_level_t_ESP_PWR_LVL_N6,
+            TxPower::N3 => esp_power_level_t_ESP_PWR_LVL_N3,
+            TxPower::N0 => esp_power_level_t_ESP_PWR_LVL_N0,
+            TxPower::P3 => esp_power_level_t_ESP_PWR_LVL_P3,
+            TxPower::P6 => esp_power_level_t_ESP_PWR_LVL_P6,
+            TxPower::P9 => esp_power_level_t_ESP_PWR_LVL_P9,
+            TxPower::P12 => esp_power_level_t_ESP_PWR_LVL_P12,
+            TxPower::P15 => esp_power_level_t_ESP_PWR_LVL_P15,
+            TxPower::P18 => esp_power_level_t_ESP_PWR_LVL_P18,
+            TxPower::P21 => esp_power_level_t_ESP_PWR_LVL_P21,
+        }
+    }
+}
+
+pub(crate) fn adv_tx_power() -> anyhow::Result<TxPower> {
+    let level = unsafe { esp_ble_tx_power_get(esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV) };
+
+    TxPower::ALL
+        .into_iter()
+        .find(|power| esp_power_level_t::from(*power) == level)
+        .ok_or(anyhow::anyhow!(
+            "Unknown advertising TX power level {}",
+            level
+        ))
+}
+
+pub(crate) fn set_adv_tx_power(power: TxPower) -> anyhow::Result<()> {
+    esp!(unsafe { esp_ble_tx_power_set(esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV, power.into()) })
+        .map_err(|err| anyhow::anyhow!("Failed to set advertising TX power {:?}: {:?}", power, err))
+}
+
+/// Advertising settings used while the battery is below `below_percent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleStep {
+    pub below_percent: u8,
+
+    // Advertising interval range in units of 0.625 ms
+    pub min_interval: u16,
+    pub max_interval: u16,
+
+    // Advertising TX power, None keeps the current one
+    pub tx_power: Option<TxPower>,
+}
+
+/// Stretches advertising intervals and lowers TX power as the battery drains.
+/// Application reports battery level through `Gap::report_battery_level`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatteryPolicy {
+    // Steps with decreasing `below_percent`, later steps throttle more
+    pub steps: Vec<ThrottleStep>,
+
+    // Battery has to rise this many percent above the threshold of the active step
+    // before it is left, so a level bouncing around a threshold does not
+    // restart advertising over and over
+    pub hysteresis_percent: u8,
+
+    // Settings used while no step is active
+    pub normal_tx_power: TxPower,
+}
+
+impl Default for BatteryPolicy {
+    fn default() -> Self {
+        Self {
+            steps: vec![
+                ThrottleStep {
+                    below_percent: 30,
+                    min_interval: 0x320,
+                    max_interval: 0x640,
+                    tx_power: Some(TxPower::N0),
+                },
+                ThrottleStep {
+                    below_percent: 10,
+                    min_interval: 0x1900,
+                    max_interval: 0x3200,
+                    tx_power: Some(TxPower::N12),
+                },
+            ],
+            hysteresis_percent: 5,
+            normal_tx_power: TxPower::P9,
+        }
+    }
+}
+
+impl BatteryPolicy {
+    /// Index of the step active at the given battery level, `current` is the
+    /// step active before, used for hysteresis
+    pub fn step_for(&self, level: u8, current: Option<usize>) -> Option<usize> {
+        let entered = self
+            .steps
+            .iter()
+            .rposition(|step| level < step.below_percent);
+        let kept = self.steps.iter().rposition(|step| {
+            u16::from(level) < u16::from(step.below_percent) + u16::from(self.hysteresis_percent)
+        });
+
+        // None orders before any step, so deeper steps are entered right away,
+        // while leaving a step is delayed by the hysteresis
+        entered.max(current.min(kept))
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use esp_idf_svc::sys::{
+    esp, esp_ble_addr_type_t, esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC,
+    esp_ble_addr_type_t_BLE_ADDR_TYPE_RPA_PUBLIC, esp_ble_gap_config_local_privacy,
+    esp_ble_gap_set_resolvable_private_address_timeout,
+};
+
+// Limits of the RPA timeout accepted by the controller, in seconds
+const MIN_RPA_ROTATION: u64 = 1;
+const MAX_RPA_ROTATION: u64 = 0xA1B8;
+
+/// Own address used for advertising. With privacy the controller generates a
+/// resolvable private address, bonded peers resolve it to the public identity
+/// through the IRK exchanged during pairing
+pub(crate) fn own_addr_type(privacy: bool) -> esp_ble_addr_type_t {
+    if privacy {
+        esp_ble_addr_type_t_BLE_ADDR_TYPE_RPA_PUBLIC
+    } else {
+        esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC
+    }
+}
+
+pub(crate) fn config_local_privacy(enabled: bool) -> anyhow::Result<()> {
+    esp!(unsafe { esp_ble_gap_config_local_privacy(enabled) })
+        .map_err(|err| anyhow::anyhow!("Failed to configure local privacy: {:?}", err))
+}
+
+pub(crate) fn set_rpa_rotation(interval: Duration) -> anyhow::Result<()> {
+    let seconds = interval.as_secs();
+    if !(MIN_RPA_ROTATION..=MAX_RPA_ROTATION).contains(&seconds) {
+        return Err(anyhow::anyhow!(
+            "RPA rotation must be within {}..={} s, got {} s",
+            MIN_RPA_ROTATION,
+            MAX_RPA_ROTATION,
+            seconds
+        ));
+    }
+
+    esp!(unsafe { esp_ble_gap_set_resolvable_private_address_timeout(seconds as u16) })
+        .map_err(|err| anyhow::anyhow!("Failed to set RPA rotation: {:?}", err))
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use esp_idf_svc::{
+    bt::{BdAddr, BtStatus, BtUuid},
+    sys::{
+        esp, esp_ble_addr_type_t, esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC,
+        esp_ble_addr_type_t_BLE_ADDR_TYPE_RANDOM, esp_ble_addr_type_t_BLE_ADDR_TYPE_RPA_PUBLIC,
+        esp_ble_addr_type_t_BLE_ADDR_TYPE_RPA_RANDOM, esp_ble_gap_set_scan_params,
+        esp_ble_gap_start_scanning, esp_ble_gap_stop_scanning,
+        esp_ble_scan_duplicate_t_BLE_SCAN_DUPLICATE_DISABLE,
+        esp_ble_scan_filter_t_BLE_SCAN_FILTER_ALLOW_ALL, esp_ble_scan_params_t,
+        esp_ble_scan_type_t_BLE_SCAN_TYPE_ACTIVE, esp_ble_scan_type_t_BLE_SCAN_TYPE_PASSIVE,
+    },
+};
+
+use super::{
+    GapInner,
+    adv_data::{AdvData, ManufacturerData},
+    event::GapEvent,
+    peers::PeerAddressType,
+    privacy,
+};
+use crate::assigned_numbers;
+
+// Range of scan interval and window, in units of 0.625 ms
+const SCAN_TIMING_RANGE: std::ops::RangeInclusive<u16> = 0x0004..=0x4000;
+// Devices remembered for deduplication and smoothing, the least recently seen
+// one is forgotten beyond that
+const MAX_TRACKED_DEVICES: usize = 128;
+
+/// Address the device scans with, seen by advertisers receiving scan requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnAddressType {
+    // Resolvable private address while `Gap::set_local_privacy` is enabled,
+    // public address otherwise
+    Auto,
+    Public,
+    // Static random address, which must be set beforehand
+    Random,
+    // Resolvable private address, falling back to the public or random one
+    // when the controller has no local IRK
+    RpaPublic,
+    RpaRandom,
+}
+
+impl OwnAddressType {
+    fn raw(self, privacy: bool) -> esp_ble_addr_type_t {
+        match self {
+            Self::Auto => privacy::own_addr_type(privacy),
+            Self::Public => esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC,
+            Self::Random => esp_ble_addr_type_t_BLE_ADDR_TYPE_RANDOM,
+            Self::RpaPublic => esp_ble_addr_type_t_BLE_ADDR_TYPE_RPA_PUBLIC,
+            Self::RpaRandom => esp_ble_addr_type_t_BLE_ADDR_TYPE_RPA_RANDOM,
+        }
+    }
+}
+
+/// Scan parameters, set with `Gap::set_scan_config` and applied when the next
+/// scan starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanConfig {
+    // Active scanning sends scan requests, so scan responses are reported too.
+    // Passive scanning only listens and stays invisible to advertisers
+    pub active: bool,
+    // How often and how long the controller listens, in units of 0.625 ms
+    // within 0x0004..=0x4000. Window equal to interval scans continuously
+    pub interval: u16,
+    pub window: u16,
+    pub own_address_type: OwnAddressType,
+
+    // Reports each device at most once per window, e.g. for presence detection.
+    // None reports every advertisement
+    pub dedup_window: Option<Duration>,
+    // Weight of a new reading in the exponentially smoothed RSSI, within
+    // 0.0..=1.0, where 1.0 reports the last reading as is
+    pub rssi_smoothing: f32,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            active: true,
+            interval: 0x50,
+            window: 0x30,
+            own_address_type: OwnAddressType::Auto,
+            dedup_window: None,
+            rssi_smoothing: 1.0,
+        }
+    }
+}
+
+impl ScanConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !SCAN_TIMING_RANGE.contains(&self.interval) || !SCAN_TIMING_RANGE.contains(&self.window)
+        {
+            return Err(anyhow::anyhow!(
+                "Scan interval {:#x} and window {:#x} must be within {:#x}..={:#x}",
+                self.interval,
+                self.window,
+                SCAN_TIMING_RANGE.start(),
+                SCAN_TIMING_RANGE.end()
+            ));
+        }
+
+        if self.window > self.interval {
+            return Err(anyhow::anyhow!(
+                "Scan window {:#x} exceeds interval {:#x}",
+                self.window,
+                self.interval
+            ));
+        }
+
+        if !(0.0..=1.0).contains(&self.rssi_smoothing) || self.rssi_smoothing == 0.0 {
+            return Err(anyhow::anyhow!(
+                "RSSI smoothing {} must be within 0.0 (exclusive) and 1.0",
+                self.rssi_smoothing
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn raw(&self, privacy: bool) -> esp_ble_scan_params_t {
+        esp_ble_scan_params_t {
+            scan_type: match self.active {
+                true => esp_ble_scan_type_t_BLE_SCAN_TYPE_ACTIVE,
+                false => esp_ble_scan_type_t_BLE_SCAN_TYPE_PASSIVE,
+            },
+            own_addr_type: self.own_address_type.raw(privacy),
+            scan_filter_policy: esp_ble_scan_filter_t_BLE_SCAN_FILTER_ALLOW_ALL,
+            scan_interval: self.interval,
+            scan_window: self.window,
+            scan_duplicate: esp_ble_scan_duplicate_t_BLE_SCAN_DUPLICATE_DISABLE,
+        }
+    }
+}
+
+/// Advertisement of a scanned device which passed the scan filter, sent to
+/// `scan_results_rx`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advertisement {
+    pub address: BdAddr,
+    pub address_type: PeerAddressType,
+    // Signal strength of this report in dBm
+    pub rssi: i8,
+    // Exponentially smoothed signal strength of the device, see `ScanConfig`
+    pub smoothed_rssi: i8,
+    pub data: AdvData,
+    // Empty unless the device answered a scan request
+    pub scan_response: AdvData,
+}
+
+impl Advertisement {
+    /// Name from the advertising payload, or from the scan response
+    pub fn name(&self) -> Option<String> {
+        self.data.name().or_else(|| self.scan_response.name())
+    }
+
+    /// Service UUIDs listed in the advertising payload and the scan response
+    pub fn service_uuids(&self) -> Vec<BtUuid> {
+        [
+            self.data.service_uuids(),
+            self.scan_response.service_uuids(),
+        ]
+        .concat()
+    }
+
+    pub fn manufacturer_data(&self) -> Option<ManufacturerData> {
+        self.data
+            .get_manufacturer_data()
+            .or_else(|| self.scan_response.get_manufacturer_data())
+    }
+
+    pub fn appearance(&self) -> Option<u16> {
+        self.data
+            .get_appearance()
+            .or_else(|| self.scan_response.get_appearance())
+    }
+
+    /// Human readable label for scan result lists: the advertised name, else
+    /// the manufacturer and appearance names known to `assigned_numbers`, else
+    /// the address
+    pub fn label(&self) -> String {
+        if let Some(name) = self.name() {
+            return name;
+        }
+
+        let company = self
+            .manufacturer_data()
+            .and_then(|data| assigned_numbers::company_name(data.company_id));
+        let appearance = self
+            .appearance()
+            .and_then(assigned_numbers::appearance_name);
+
+        match (company, appearance) {
+            (Some(company), Some(appearance)) => format!("{} {}", company, appearance),
+            (Some(name), None) | (None, Some(name)) => String::from(name),
+            (None, None) => format!("{:?}", self.address),
+        }
+    }
+}
+
+/// Declarative filter of scanned devices, evaluated as reports arrive so only
+/// matching advertisements reach `scan_results_rx`. Criteria which are set must
+/// all match, the default filter passes everything
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    // Service UUID the device must list
+    pub service_uuid: Option<BtUuid>,
+    // Start of the complete or shortened name
+    pub name_prefix: Option<String>,
+    // Accepted device addresses, empty accepts any
+    pub addresses: Vec<BdAddr>,
+    // Weakest accepted signal strength in dBm
+    pub min_rssi: Option<i8>,
+}
+
+impl ScanFilter {
+    /// Checks the smoothed signal strength, so a device is not dropped for a
+    /// single weak reading
+    pub fn matches(&self, advertisement: &Advertisement) -> bool {
+        if self
+            .min_rssi
+            .is_some_and(|min_rssi| advertisement.smoothed_rssi < min_rssi)
+        {
+            return false;
+        }
+
+        if !self.addresses.is_empty() && !self.addresses.contains(&advertisement.address) {
+            return false;
+        }
+
+        let name_matches = match &self.name_prefix {
+            Some(prefix) => advertisement
+                .name()
+                .is_some_and(|name| name.starts_with(prefix.as_str())),
+            None => true,
+        };
+
+        if !name_matches {
+            return false;
+        }
+
+        match &self.service_uuid {
+            Some(uuid) => advertisement.service_uuids().contains(uuid),
+            None => true,
+        }
+    }
+}
+
+// Device seen during the running scan
+struct TrackedDevice {
+    smoothed_rssi: f32,
+    last_seen: Instant,
+    reported: Option<Instant>,
+}
+
+/// Running scan, the filter and config it was started with and the devices it saw
+pub(crate) struct ScanState {
+    filter: ScanFilter,
+    config: ScanConfig,
+    devices: HashMap<[u8; 6], TrackedDevice>,
+}
+
+impl ScanState {
+    pub(crate) fn new(filter: ScanFilter, config: ScanConfig) -> Self {
+        Self {
+            filter,
+            config,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// Smooths the RSSI of the advertisement and tells whether it is reported,
+    /// it must pass the filter and its device must not have been reported
+    /// within the deduplication window
+    pub(crate) fn process(&mut self, advertisement: &mut Advertisement) -> bool {
+        let now = Instant::now();
+        let weight = self.config.rssi_smoothing;
+
+        if !self.devices.contains_key(&advertisement.address.raw())
+            && self.devices.len() >= MAX_TRACKED_DEVICES
+        {
+            let oldest = self
+                .devices
+                .iter()
+                .min_by_key(|(_, device)| device.last_seen)
+                .map(|(address, _)| *address);
+
+            if let Some(oldest) = oldest {
+                self.devices.remove(&oldest);
+            }
+        }
+
+        let device = self
+            .devices
+            .entry(advertisement.address.raw())
+            .and_modify(|device| {
+                device.smoothed_rssi += weight * (advertisement.rssi as f32 - device.smoothed_rssi);
+                device.last_seen = now;
+            })
+            .or_insert(TrackedDevice {
+                smoothed_rssi: advertisement.rssi as f32,
+                last_seen: now,
+                reported: None,
+            });
+
+        advertisement.smoothed_rssi = device.smoothed_rssi.round() as i8;
+
+        if !self.filter.matches(advertisement) {
+            return false;
+        }
+
+        let duplicate = match (self.config.dedup_window, device.reported) {
+            (Some(window), Some(reported)) => now.duration_since(reported) < window,
+            _ => false,
+        };
+
+        if !duplicate {
+            device.reported = Some(now);
+        }
+
+        !duplicate
+    }
+}
+
+pub(crate) fn start(
+    gap: &GapInner,
+    config: &ScanConfig,
+    duration: Option<Duration>,
+) -> anyhow::Result<()> {
+    let mut params = config.raw(gap.is_private()?);
+
+    let event = gap.wait_event(&GapEvent::ScanParameterConfigured(BtStatus::Done), || {
+        esp!(unsafe { esp_ble_gap_set_scan_params(&mut params) })
+            .map_err(|err| anyhow::anyhow!("Failed to set scan params: {:?}", err))
+    })?;
+    check_status(event, "set scan params")?;
+
+    // Duration in whole seconds, 0 scans until stopped
+    let seconds = match duration {
+        Some(duration) => duration
+            .as_millis()
+            .div_ceil(1000)
+            .clamp(1, u32::MAX as u128) as u32,
+        None => 0,
+    };
+
+    let event = gap.wait_event(&GapEvent::ScanStarted(BtStatus::Done), || {
+        esp!(unsafe { esp_ble_gap_start_scanning(seconds) })
+            .map_err(|err| anyhow::anyhow!("Failed to start scanning: {:?}", err))
+    })?;
+    check_status(event, "start scanning")
+}
+
+pub(crate) fn stop(gap: &GapInner) -> anyhow::Result<()> {
+    let event = gap.wait_event(&GapEvent::ScanStopped(BtStatus::Done), || {
+        esp!(unsafe { esp_ble_gap_stop_scanning() })
+            .map_err(|err| anyhow::anyhow!("Failed to stop scanning: {:?}", err))
+    })?;
+    check_status(event, "stop scanning")
+}
+
+pub(crate) fn advertisement(
+    addr: BdAddr,
+    addr_type: esp_ble_addr_type_t,
+    rssi: i8,
+    adv_data: &[u8],
+    scan_response: &[u8],
+) -> anyhow::Result<Advertisement> {
+    // Resolved private addresses are reported with the type of the identity
+    let address_type = if addr_type == esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC
+        || addr_type == esp_ble_addr_type_t_BLE_ADDR_TYPE_RPA_PUBLIC
+    {
+        PeerAddressType::Public
+    } else {
+        PeerAddressType::Random
+    };
+
+    Ok(Advertisement {
+        address: addr,
+        address_type,
+        rssi,
+        smoothed_rssi: rssi,
+        data: AdvData::decode(adv_data)?,
+        scan_response: AdvData::decode(scan_response)?,
+    })
+}
+
+fn check_status(event: GapEvent, operation: &str) -> anyhow::Result<()> {
+    let status = match event {
+        GapEvent::ScanParameterConfigured(status)
+        | GapEvent::ScanStarted(status)
+        | GapEvent::ScanStopped(status) => status,
+        event => return Err(anyhow::anyhow!("Unexpected event: {:?}", event)),
+    };
+
+    match status {
+        BtStatus::Success => Ok(()),
+        _ => Err(anyhow::anyhow!("Failed to {}: {:?}", operation, status)),
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use esp_idf_svc::{
+    bt::BdAddr,
+    sys::{
+        ESP_BLE_CSR_KEY_MASK, ESP_BLE_ENC_KEY_MASK, ESP_BLE_ID_KEY_MASK, ESP_BLE_LINK_KEY_MASK,
+        ESP_IO_CAP_IN, ESP_IO_CAP_IO, ESP_IO_CAP_KBDISP, ESP_IO_CAP_NONE, ESP_IO_CAP_OUT,
+        ESP_LE_AUTH_BOND, ESP_LE_AUTH_REQ_MITM, ESP_LE_AUTH_REQ_SC_ONLY, esp,
+        esp_ble_gap_security_rsp, esp_ble_gap_set_security_param, esp_ble_passkey_reply,
+        esp_ble_sm_param_t, esp_ble_sm_param_t_ESP_BLE_SM_AUTHEN_REQ_MODE,
+        esp_ble_sm_param_t_ESP_BLE_SM_IOCAP_MODE, esp_ble_sm_param_t_ESP_BLE_SM_MAX_KEY_SIZE,
+        esp_ble_sm_param_t_ESP_BLE_SM_MIN_KEY_SIZE, esp_ble_sm_param_t_ESP_BLE_SM_SET_INIT_KEY,
+        esp_ble_sm_param_t_ESP_BLE_SM_SET_RSP_KEY,
+    },
+};
+
+/// Input/output capabilities of the device, used by SMP to pick the pairing method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoCapability {
+    DisplayOnly,
+    DisplayYesNo,
+    KeyboardOnly,
+    NoInputNoOutput,
+    KeyboardDisplay,
+}
+
+impl From<IoCapability> for u8 {
+    fn from(value: IoCapability) -> Self {
+        (match value {
+            IoCapability::DisplayOnly => ESP_IO_CAP_OUT,
+            IoCapability::DisplayYesNo => ESP_IO_CAP_IO,
+            IoCapability::KeyboardOnly => ESP_IO_CAP_IN,
+            IoCapability::NoInputNoOutput => ESP_IO_CAP_NONE,
+            IoCapability::KeyboardDisplay => ESP_IO_CAP_KBDISP,
+        }) as u8
+    }
+}
+
+/// Authentication requirements requested during pairing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthRequirements {
+    // Store keys after pairing, so peer can reconnect without pairing again
+    pub bonding: bool,
+    // Require protection against man in the middle attacks (passkey or numeric comparison)
+    pub mitm: bool,
+    // Require LE Secure Connections pairing
+    pub secure_connections: bool,
+}
+
+impl From<AuthRequirements> for u8 {
+    fn from(value: AuthRequirements) -> Self {
+        let mut auth_req = 0;
+
+        if value.bonding {
+            auth_req |= ESP_LE_AUTH_BOND;
+        }
+
+        if value.mitm {
+            auth_req |= ESP_LE_AUTH_REQ_MITM;
+        }
+
+        if value.secure_connections {
+            auth_req |= ESP_LE_AUTH_REQ_SC_ONLY;
+        }
+
+        auth_req as u8
+    }
+}
+
+/// Keys which are distributed after pairing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyDistribution {
+    // Long Term Key
+    pub encryption: bool,
+    // Identity Resolving Key, needed to resolve peer private addresses
+    pub identity: bool,
+    // Connection Signature Resolving Key
+    pub signing: bool,
+    // Derive BR/EDR link key from LTK
+    pub link: bool,
+}
+
+impl From<KeyDistribution> for u8 {
+    fn from(value: KeyDistribution) -> Self {
+        let mut mask = 0;
+
+        if value.encryption {
+            mask |= ESP_BLE_ENC_KEY_MASK;
+        }
+
+        if value.identity {
+            mask |= ESP_BLE_ID_KEY_MASK;
+        }
+
+        if value.signing {
+            mask |= ESP_BLE_CSR_KEY_MASK;
+        }
+
+        if value.link {
+            mask |= ESP_BLE_LINK_KEY_MASK;
+        }
+
+        mask as u8
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    pub io_capability: IoCapability,
+    pub auth_requirements: AuthRequirements,
+
+    // Encryption key size in bytes, must be in range 7..=16
+    pub min_key_size: u8,
+    pub max_key_size: u8,
+
+    // Keys the device asks the peer to distribute / distributes to the peer
+    pub initiator_keys: KeyDistribution,
+    pub responder_keys: KeyDistribution,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        let keys = KeyDistribution {
+            encryption: true,
+            identity: true,
+            signing: false,
+            link: false,
+        };
+
+        Self {
+            io_capability: IoCapability::NoInputNoOutput,
+            auth_requirements: AuthRequirements {
+                bonding: true,
+                mitm: false,
+                secure_connections: true,
+            },
+            min_key_size: 7,
+            max_key_size: 16,
+            initiator_keys: keys,
+            responder_keys: keys,
+        }
+    }
+}
+
+impl SecurityConfig {
+    pub const KEY_SIZE_RANGE: std::ops::RangeInclusive<u8> = 7..=16;
+
+    /// Configures Security Manager of the stack, applies to all following pairings
+    pub fn apply(&self) -> anyhow::Result<()> {
+        if !Self::KEY_SIZE_RANGE.contains(&self.min_key_size)
+            || !Self::KEY_SIZE_RANGE.contains(&self.max_key_size)
+            || self.min_key_size > self.max_key_size
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid key size range {}..={}, must be within 7..=16",
+                self.min_key_size,
+                self.max_key_size
+            ));
+        }
+
+        set_security_param(
+            esp_ble_sm_param_t_ESP_BLE_SM_AUTHEN_REQ_MODE,
+            self.auth_requirements.into(),
+        )?;
+        set_security_param(
+            esp_ble_sm_param_t_ESP_BLE_SM_IOCAP_MODE,
+            self.io_capability.into(),
+        )?;
+        set_security_param(
+            esp_ble_sm_param_t_ESP_BLE_SM_MAX_KEY_SIZE,
+            self.max_key_size,
+        )?;
+        set_security_param(
+            esp_ble_sm_param_t_ESP_BLE_SM_MIN_KEY_SIZE,
+            self.min_key_size,
+        )?;
+        set_security_param(
+            esp_ble_sm_param_t_ESP_BLE_SM_SET_INIT_KEY,
+            self.initiator_keys.into(),
+        )?;
+        set_security_param(
+            esp_ble_sm_param_t_ESP_BLE_SM_SET_RSP_KEY,
+            self.responder_keys.into(),
+        )?;
+
+        Ok(())
+    }
+}
+
+fn set_security_param(param: esp_ble_sm_param_t, mut value: u8) -> anyhow::Result<()> {
+    esp!(unsafe {
+        esp_ble_gap_set_security_param(
+            param,
+            &mut value as *mut u8 as *mut core::ffi::c_void,
+            std::mem::size_of::<u8>() as u8,
+        )
+    })
+    .map_err(|err| anyhow::anyhow!("Failed to set security param {}: {:?}", param, err))
+}
+
+pub type PasskeyDisplayHandler = Arc<dyn Fn(BdAddr, u32) + Send + Sync>;
+pub type PasskeyRequestHandler = Arc<dyn Fn(BdAddr) -> Option<u32> + Send + Sync>;
+
+/// Answers passkey request of the peer, `None` rejects the pairing
+pub fn passkey_reply(addr: BdAddr, passkey: Option<u32>) -> anyhow::Result<()> {
+    let mut raw_addr = addr.raw();
+
+    esp!(unsafe {
+        esp_ble_passkey_reply(
+            raw_addr.as_mut_ptr(),
+            passkey.is_some(),
+            passkey.unwrap_or(0),
+        )
+    })
+    .map_err(|err| anyhow::anyhow!("Failed to reply passkey to {:?}: {:?}", addr, err))
+}
+
+/// Accepts or rejects pairing requested by the peer
+pub fn security_reply(addr: BdAddr, accept: bool) -> anyhow::Result<()> {
+    let mut raw_addr = addr.raw();
+
+    esp!(unsafe { esp_ble_gap_security_rsp(raw_addr.as_mut_ptr(), accept) })
+        .map_err(|err| anyhow::anyhow!("Failed to reply security request of {:?}: {:?}", addr, err))
+}
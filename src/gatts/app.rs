@@ -4,17 +4,23 @@ use std::{
     sync::{Arc, RwLock, Weak},
 };
 
-use crossbeam_channel::bounded;
 use esp_idf_svc::bt::ble::gatt::{
     server::{AppId, ConnectionId},
     GattInterface, GattStatus,
 };
 
 use super::{
-    connection::ConnectionInner,
+    connection::Connection,
+    ident::{AppIdent, AppInterface},
+    middleware::Middleware,
+    schema::ServiceSchema,
     service::{Service, ServiceId, ServiceInner},
     GattsEvent, GattsEventMessage, GattsInner,
 };
+use crate::{
+    lock::{self, OrderedRwLock},
+    trace,
+};
 
 #[derive(Clone)]
 pub struct App(pub Arc<AppInner>);
@@ -22,25 +28,52 @@ pub struct App(pub Arc<AppInner>);
 pub struct AppInner {
     pub gatts: RwLock<Weak<GattsInner>>,
     pub interface: RwLock<Option<GattInterface>>,
-    pub services: Arc<RwLock<HashMap<ServiceId, Arc<ServiceInner>>>>,
-    pub connections: Arc<RwLock<HashMap<ConnectionId, ConnectionInner>>>,
+    pub services: Arc<OrderedRwLock<lock::Services, HashMap<ServiceId, Arc<ServiceInner>>>>,
+    pub connections: Arc<OrderedRwLock<lock::Connections, HashMap<ConnectionId, Connection>>>,
 
     pub id: AppId,
+    // Shown in logs and errors next to the app id
+    pub name: Option<&'static str>,
+    middlewares: RwLock<Vec<Arc<dyn Middleware>>>,
 }
 
 impl App {
     pub fn new(app_id: AppId) -> Self {
+        Self::build(app_id, None)
+    }
+
+    /// App with a name, which identifies it in logs and errors of servers
+    /// with several apps
+    pub fn named(app_id: AppId, name: &'static str) -> Self {
+        Self::build(app_id, Some(name))
+    }
+
+    fn build(app_id: AppId, name: Option<&'static str>) -> Self {
         let app = AppInner {
             gatts: Default::default(),
             id: app_id,
+            name,
             services: Default::default(),
             interface: RwLock::new(None),
             connections: Default::default(),
+            middlewares: RwLock::new(Vec::new()),
         };
 
         Self(Arc::new(app))
     }
 
+    /// Appends middleware to the chain run on every peer read and write of the
+    /// app's attributes, see `Middleware`
+    pub fn add_middleware(&self, middleware: impl Middleware) -> anyhow::Result<()> {
+        self.0
+            .middlewares
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write app middlewares"))?
+            .push(Arc::new(middleware));
+
+        Ok(())
+    }
+
     pub fn register_bluedroid(&self, gatts: &Arc<GattsInner>) -> anyhow::Result<()> {
         *self
             .0
@@ -49,29 +82,38 @@ impl App {
             .map_err(|_| anyhow::anyhow!("Failed to write Gatt interface"))? =
             Arc::downgrade(gatts);
 
-        let (tx, rx) = bounded(1);
+        trace::span!("gatts.register_app", app = %self.0.ident());
+
         let callback_key = discriminant(&GattsEvent::ServiceRegistered {
             status: GattStatus::Busy,
             app_id: 0,
         });
 
-        gatts
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events"))?
-            .insert(callback_key.clone(), tx.clone());
+        let rx = gatts.waiter(callback_key)?;
 
         gatts.gatts.register_app(self.0.id).map_err(|err| {
-            anyhow::anyhow!("Failed to register GATT app {:?}: {:?}", self.0.id, err)
+            anyhow::anyhow!("Failed to register GATT {}: {:?}", self.0.ident(), err)
         })?;
 
         match rx.recv_timeout(std::time::Duration::from_secs(5)) {
-            Ok(GattsEventMessage(interface, GattsEvent::ServiceRegistered { status, app_id })) => {
+            Ok(GattsEventMessage(
+                interface,
+                GattsEvent::ServiceRegistered { status, app_id },
+                _,
+            )) => {
                 if app_id != self.0.id {
-                    return Err(anyhow::anyhow!("Received unexpected GATT: {:?}", app_id));
+                    return Err(anyhow::anyhow!(
+                        "Received registration of app {:#06x} while registering {}",
+                        app_id,
+                        self.0.ident()
+                    ));
                 }
                 if status != GattStatus::Ok {
-                    return Err(anyhow::anyhow!("Failed to register: {:?}", status));
+                    return Err(anyhow::anyhow!(
+                        "Failed to register {}: {:?}",
+                        self.0.ident(),
+                        status
+                    ));
                 }
 
                 self.0
@@ -83,7 +125,10 @@ impl App {
                 Ok(())
             }
             Ok(_) => Err(anyhow::anyhow!("Received unexpected GATT event")),
-            Err(_) => Err(anyhow::anyhow!("Timed out waiting for GATT event")),
+            Err(_) => Err(anyhow::anyhow!(
+                "Timed out waiting for registration of {}",
+                self.0.ident()
+            )),
         }
     }
 
@@ -93,8 +138,7 @@ impl App {
         if self
             .0
             .services
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire write lock on Gatts services"))?
+            .write()?
             .insert(service.0.id.clone(), service.0.clone())
             .is_some()
         {
@@ -106,9 +150,41 @@ impl App {
 
         Ok(service.clone())
     }
+
+    /// Starts every registered service which is not started yet. Failure of one
+    /// service does not prevent starting the others, failures are reported together
+    pub fn start_all(&self) -> anyhow::Result<()> {
+        self.0.for_each_service("start", |service| {
+            if service.0.is_started()? {
+                return Ok(());
+            }
+
+            service.start()
+        })
+    }
+
+    /// Stops every started service, e.g. to take the server offline for maintenance
+    /// while peers stay connected. Failures are reported together
+    pub fn stop_all(&self) -> anyhow::Result<()> {
+        self.0.for_each_service("stop", |service| {
+            if !service.0.is_started()? {
+                return Ok(());
+            }
+
+            service.stop()
+        })
+    }
 }
 
 impl AppInner {
+    pub(crate) fn middlewares(&self) -> anyhow::Result<Vec<Arc<dyn Middleware>>> {
+        Ok(self
+            .middlewares
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read app middlewares"))?
+            .clone())
+    }
+
     pub fn get_gatts(&self) -> anyhow::Result<Arc<GattsInner>> {
         self.gatts
             .read()
@@ -117,11 +193,74 @@ impl AppInner {
             .ok_or(anyhow::anyhow!("Failed to upgrade Gatts"))
     }
 
+    pub fn schema(&self) -> anyhow::Result<Vec<ServiceSchema>> {
+        let mut services = self
+            .services
+            .read()?
+            .values()
+            .map(|service| Ok((service.get_handle()?, service.clone())))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        services.sort_by_key(|(handle, _)| *handle);
+
+        services
+            .into_iter()
+            .map(|(_, service)| service.schema())
+            .collect()
+    }
+
     pub fn interface(&self) -> anyhow::Result<GattInterface> {
         self.interface
             .read()
             .map_err(|_| anyhow::anyhow!("Failed to read Gatt interface"))?
             .clone()
-            .ok_or(anyhow::anyhow!("Gatt interface is not set"))
+            .ok_or(anyhow::anyhow!(
+                "Gatt interface of {} is not set",
+                self.ident()
+            ))
+    }
+
+    fn for_each_service(
+        &self,
+        operation: &str,
+        f: impl Fn(&Service) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        // Snapshot, starting and stopping waits for stack events
+        let services = self.services.read()?.values().cloned().collect::<Vec<_>>();
+
+        let errors = services
+            .into_iter()
+            .map(Service)
+            .filter_map(|service| {
+                f(&service)
+                    .err()
+                    .map(|err| format!("{:?}: {:?}", service.uuid(), err))
+            })
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Failed to {} some of services of {}: {:?}",
+                operation,
+                self.ident(),
+                errors
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn ident(&self) -> AppIdent {
+        AppIdent {
+            id: self.id,
+            name: self.name,
+        }
+    }
+
+    /// Interface of the registered app, labeled with the app for logs and errors
+    pub fn app_interface(&self) -> anyhow::Result<AppInterface> {
+        Ok(AppInterface {
+            interface: self.interface()?,
+            app: Some(self.ident()),
+        })
     }
 }
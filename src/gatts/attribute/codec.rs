@@ -0,0 +1,209 @@
+//! Codecs decoupling the wire format of a value from its type, so the same
+//! struct can be exposed with a different encoding on each characteristic, see
+//! `Characteristic::new_with_codec`. The value is held in `Coded`, which
+//! implements `Attribute` through the codec instead of the blanket serde one.
+
+use std::{ops::Deref, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Attribute,
+    encoding::{self, EncodingConfig},
+};
+use crate::gatts::schema::{ValueFormat, ValueSchema};
+
+/// Encodes values into the bytes peers read
+pub trait Encode<T>: Send + Sync + 'static {
+    fn encode(&self, value: &T) -> anyhow::Result<Vec<u8>>;
+
+    /// Wire format and shape of the value, exported with the GATT schema
+    fn value_schema(&self, _value: &T) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::Bytes)
+    }
+}
+
+/// Decodes bytes written by peers into values
+pub trait Decode<T>: Send + Sync + 'static {
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T>;
+}
+
+/// Value together with the codec of its characteristic
+pub struct Coded<T, C> {
+    value: T,
+    codec: Arc<C>,
+}
+
+impl<T, C> Coded<T, C> {
+    pub fn new(value: T, codec: C) -> Self {
+        Self {
+            value,
+            codec: Arc::new(codec),
+        }
+    }
+
+    /// New value sharing the codec of this one
+    pub fn with_value(&self, value: T) -> Self {
+        Self {
+            value,
+            codec: self.codec.clone(),
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+}
+
+impl<T, C> Deref for Coded<T, C> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T, C> Attribute for Coded<T, C>
+where
+    T: Send + Sync + 'static,
+    C: Encode<T> + Decode<T> + Default,
+{
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        self.codec.encode(&self.value)
+    }
+
+    // Decodes with the default codec, peer writes go through `decode_update` and
+    // the codec of the current value, e.g. a `BincodeCodec` with its own config
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let codec = C::default();
+        Ok(Self::new(codec.decode(bytes)?, codec))
+    }
+
+    fn decode_update(&self, bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(self.with_value(self.codec.decode(bytes)?))
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        self.codec.value_schema(&self.value)
+    }
+}
+
+/// Bincode codec of serde values, with the global `EncodingConfig` when
+/// `config` is None, same as the blanket serde `Attribute` implementation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BincodeCodec {
+    pub config: Option<EncodingConfig>,
+}
+
+impl<T: Serialize> Encode<T> for BincodeCodec {
+    fn encode(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        match self.config {
+            Some(config) => encoding::encode_limited(value, config),
+            None => encoding::encode(value),
+        }
+    }
+
+    // Schema describes values with the global config only
+    fn value_schema(&self, value: &T) -> (ValueFormat, ValueSchema) {
+        match self.config {
+            Some(_) => (ValueFormat::Raw, ValueSchema::Bytes),
+            None => ValueSchema::trace_or_bytes(ValueFormat::Bincode, value),
+        }
+    }
+}
+
+impl<T: for<'a> Deserialize<'a>> Decode<T> for BincodeCodec {
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        match self.config {
+            Some(config) => encoding::decode_limited(bytes, config),
+            None => encoding::decode(bytes),
+        }
+    }
+}
+
+/// JSON codec of serde values (`json` feature), readable by phone apps and Web
+/// Bluetooth without a bincode implementation
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl<T: Serialize> Encode<T> for JsonCodec {
+    fn encode(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        serde_json::to_vec(value)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize value to JSON: {:?}", err))
+    }
+
+    // JSON text takes the whole value, clients parse the string themselves
+    fn value_schema(&self, _value: &T) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::String)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: for<'a> Deserialize<'a>> Decode<T> for JsonCodec {
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        serde_json::from_slice(bytes)
+            .map_err(|err| anyhow::anyhow!("Failed to deserialize value from JSON: {:?}", err))
+    }
+}
+
+/// CBOR codec of serde values (`cbor` feature), about as compact as bincode
+/// and decoded by common libraries on every mobile platform
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl<T: Serialize> Encode<T> for CborCodec {
+    fn encode(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize value to CBOR: {:?}", err))?;
+
+        Ok(bytes)
+    }
+
+    fn value_schema(&self, value: &T) -> (ValueFormat, ValueSchema) {
+        ValueSchema::trace_or_bytes(ValueFormat::Cbor, value)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<T: for<'a> Deserialize<'a>> Decode<T> for CborCodec {
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        ciborium::from_reader(bytes)
+            .map_err(|err| anyhow::anyhow!("Failed to deserialize value from CBOR: {:?}", err))
+    }
+}
+
+/// Postcard codec of serde values (`postcard` feature), smaller than bincode with
+/// varint integers, and its wire format is stable across major versions, so apps
+/// keep decoding values of newer firmware
+#[cfg(feature = "postcard")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PostcardCodec;
+
+#[cfg(feature = "postcard")]
+impl<T: Serialize> Encode<T> for PostcardCodec {
+    fn encode(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        postcard::to_stdvec(value)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize value to postcard: {:?}", err))
+    }
+
+    fn value_schema(&self, value: &T) -> (ValueFormat, ValueSchema) {
+        ValueSchema::trace_or_bytes(ValueFormat::Postcard, value)
+    }
+}
+
+#[cfg(feature = "postcard")]
+impl<T: for<'a> Deserialize<'a>> Decode<T> for PostcardCodec {
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        postcard::from_bytes(bytes)
+            .map_err(|err| anyhow::anyhow!("Failed to deserialize value from postcard: {:?}", err))
+    }
+}
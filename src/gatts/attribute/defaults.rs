@@ -1,5 +1,11 @@
-use crate::gatts::attribute::Attribute;
-use std::fmt::Debug;
+use crate::gatts::{
+    attribute::Attribute,
+    schema::{ValueFormat, ValueSchema},
+};
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
 
 /// A wrapper for u8 values that implements the Attribute trait.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -19,6 +25,10 @@ impl Attribute for U8Attr {
         }
         Ok(U8Attr(bytes[0]))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::U8)
+    }
 }
 
 /// A wrapper for u16 values that implements the Attribute trait.
@@ -41,6 +51,10 @@ impl Attribute for U16Attr {
         let value = u16::from_le_bytes([bytes[0], bytes[1]]);
         Ok(U16Attr(value))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::U16)
+    }
 }
 
 /// A wrapper for u32 values that implements the Attribute trait.
@@ -63,6 +77,59 @@ impl Attribute for U32Attr {
         let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
         Ok(U32Attr(value))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::U32)
+    }
+}
+
+/// A wrapper for u64 values that implements the Attribute trait.
+/// Uses little-endian byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct U64Attr(pub u64);
+
+impl Attribute for U64Attr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 8 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for U64Attr: expected 8 bytes, got {}",
+                bytes.len()
+            ));
+        }
+        let value = u64::from_le_bytes(bytes.try_into()?);
+        Ok(U64Attr(value))
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::U64)
+    }
+}
+
+/// A wrapper for u128 values that implements the Attribute trait.
+/// Uses little-endian byte order.
+/// Exported as plain bytes, schemas have no 128-bit numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct U128Attr(pub u128);
+
+impl Attribute for U128Attr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 16 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for U128Attr: expected 16 bytes, got {}",
+                bytes.len()
+            ));
+        }
+        let value = u128::from_le_bytes(bytes.try_into()?);
+        Ok(U128Attr(value))
+    }
 }
 
 /// A wrapper for i8 values that implements the Attribute trait.
@@ -83,6 +150,10 @@ impl Attribute for I8Attr {
         }
         Ok(I8Attr(bytes[0] as i8))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::I8)
+    }
 }
 
 /// A wrapper for i16 values that implements the Attribute trait.
@@ -105,6 +176,10 @@ impl Attribute for I16Attr {
         let value = i16::from_le_bytes([bytes[0], bytes[1]]);
         Ok(I16Attr(value))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::I16)
+    }
 }
 
 /// A wrapper for i32 values that implements the Attribute trait.
@@ -127,6 +202,36 @@ impl Attribute for I32Attr {
         let value = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
         Ok(I32Attr(value))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::I32)
+    }
+}
+
+/// A wrapper for i64 values that implements the Attribute trait.
+/// Uses little-endian byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I64Attr(pub i64);
+
+impl Attribute for I64Attr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 8 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for I64Attr: expected 8 bytes, got {}",
+                bytes.len()
+            ));
+        }
+        let value = i64::from_le_bytes(bytes.try_into()?);
+        Ok(I64Attr(value))
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::I64)
+    }
 }
 
 /// A wrapper for boolean values that implements the Attribute trait.
@@ -148,6 +253,10 @@ impl Attribute for BoolAttr {
         }
         Ok(BoolAttr(bytes[0] != 0))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::Bool)
+    }
 }
 
 /// A wrapper for f32 values that implements the Attribute trait.
@@ -170,6 +279,36 @@ impl Attribute for F32Attr {
         let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
         Ok(F32Attr(value))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::F32)
+    }
+}
+
+/// A wrapper for f64 values that implements the Attribute trait.
+/// Uses little-endian byte order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct F64Attr(pub f64);
+
+impl Attribute for F64Attr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 8 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for F64Attr: expected 8 bytes, got {}",
+                bytes.len()
+            ));
+        }
+        let value = f64::from_le_bytes(bytes.try_into()?);
+        Ok(F64Attr(value))
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::F64)
+    }
 }
 
 /// A wrapper for string values that implements the Attribute trait.
@@ -187,6 +326,10 @@ impl Attribute for StringAttr {
             .map_err(|e| anyhow::anyhow!("Invalid UTF-8 string data: {}", e))?;
         Ok(StringAttr(string))
     }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::String)
+    }
 }
 
 /// A wrapper for byte array values that implements the Attribute trait.
@@ -202,3 +345,151 @@ impl Attribute for BytesAttr {
         Ok(BytesAttr(bytes.to_vec()))
     }
 }
+
+/// A wrapper for 128-bit UUID values that implements the Attribute trait.
+/// Uses little-endian byte order, same as UUIDs in ATT PDUs.
+///
+/// Converts from and to `BtUuid`, and with the `uuid` feature from and to
+/// `uuid::Uuid`, see `attribute::uuid`, so service ids can be declared from
+/// string literals:
+/// `BtUuid::from("6e400001-b5a3-f393-e0a9-e50e24dcca9e".parse::<UuidAttr>()?)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct UuidAttr(pub u128);
+
+impl UuidAttr {
+    // Bluetooth base UUID, 16 and 32-bit UUIDs are aliases of it
+    pub const BASE: u128 = 0x00000000_0000_1000_8000_00805f9b34fb;
+}
+
+impl Attribute for UuidAttr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 16 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for UuidAttr: expected 16 bytes, got {}",
+                bytes.len()
+            ));
+        }
+        let value = u128::from_le_bytes(bytes.try_into()?);
+        Ok(UuidAttr(value))
+    }
+}
+
+/// Canonical hyphenated form, e.g. `0000180f-0000-1000-8000-00805f9b34fb`
+impl Display for UuidAttr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.0;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            value >> 96,
+            (value >> 80) & 0xffff,
+            (value >> 64) & 0xffff,
+            (value >> 48) & 0xffff,
+            value & 0xffff_ffff_ffff
+        )
+    }
+}
+
+/// Parses the canonical hyphenated form, or 32 hex digits without hyphens
+impl FromStr for UuidAttr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let groups = s.split('-').collect::<Vec<_>>();
+        let lens = groups.iter().map(|group| group.len()).collect::<Vec<_>>();
+        let hex = match lens.as_slice() {
+            [8, 4, 4, 4, 12] | [32] => groups.concat(),
+            _ => return Err(anyhow::anyhow!("Invalid UUID: {:?}", s)),
+        };
+        if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!("Invalid UUID: {:?}", s));
+        }
+
+        u128::from_str_radix(&hex, 16)
+            .map(UuidAttr)
+            .map_err(|err| anyhow::anyhow!("Invalid UUID {:?}: {:?}", s, err))
+    }
+}
+
+/// A wrapper for fixed-size byte arrays that implements the Attribute trait,
+/// e.g. for keys, MAC addresses or fixed protocol frames.
+/// Peer writes of any other length fail with `InvalidAttrLen`, as every value
+/// which fails to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArrayAttr<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Default for ArrayAttr<N> {
+    fn default() -> Self {
+        ArrayAttr([0; N])
+    }
+}
+
+impl<const N: usize> Attribute for ArrayAttr<N> {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let array = bytes.try_into().map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid length for ArrayAttr: expected {} bytes, got {}",
+                N,
+                bytes.len()
+            )
+        })?;
+        Ok(ArrayAttr(array))
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (
+            ValueFormat::Raw,
+            ValueSchema::Tuple(vec![ValueSchema::U8; N]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_display_round_trip() {
+        let uuid = UuidAttr(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+        let string = uuid.to_string();
+
+        assert_eq!(string, "6e400001-b5a3-f393-e0a9-e50e24dcca9e");
+        assert_eq!(string.parse::<UuidAttr>().unwrap(), uuid);
+        assert_eq!(
+            "6E400001B5A3F393E0A9E50E24DCCA9E"
+                .parse::<UuidAttr>()
+                .unwrap(),
+            uuid
+        );
+        assert_eq!(
+            UuidAttr::from_bytes(&uuid.get_bytes().unwrap()).unwrap(),
+            uuid
+        );
+    }
+
+    #[test]
+    fn uuid_rejects_malformed() {
+        for input in [
+            "",
+            "0000180f-0000-1000-8000-00805f9b3-fb",
+            "0000180f00-00-1000-8000-00805f9b34fb",
+            "0000180f-0000-1000-8000-00805f9b34f",
+            "0000180f-0000-1000-8000-00805f9b34fb0",
+            "0000180f-0000-1000-8000-00805f9b34fg",
+            "0000180f-0000-1000-8000+00805f9b34fb",
+            "+000180f-0000-1000-8000-00805f9b34fb",
+            "0000180f0000100080000-0805f9b34fb",
+            "0000180f00001000800000805f9b34f",
+        ] {
+            assert!(input.parse::<UuidAttr>().is_err(), "{:?} parsed", input);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Support code of `#[derive(Attribute)]` (`derive` feature), referenced by the
+//! generated implementations. See `esp-bluedroid-derive` for the wire formats.
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::{Attribute, encoding};
+
+pub use super::encoding::{EncodingConfig, Endianness, IntEncoding};
+pub use crate::gatts::schema::{FieldSchema, ValueFormat, ValueSchema};
+pub use anyhow::Result;
+
+/// Field of a struct with the packed encoding. Implemented for numbers, `bool`,
+/// arrays of fields, and for `Vec<u8>` and `String` which take the rest of the
+/// value, so they must be the last field. Derived structs implement it as well,
+/// so they can be nested, always in their own byte order as `endianness` is
+/// ignored for them
+pub trait PackedField: Sized {
+    /// Whether the field takes the rest of the value, directly or through its
+    /// last field, checked by the derive for fields before the last one
+    const HAS_REST: bool = false;
+
+    fn encode_packed(&self, endianness: Endianness, out: &mut Vec<u8>) -> Result<()>;
+    /// Decodes the field from the start of `bytes` and advances past it
+    fn decode_packed(bytes: &mut &[u8], endianness: Endianness) -> Result<Self>;
+
+    /// Shape of the field in a `ValueFormat::Raw` schema, None when it has none,
+    /// e.g. for big endian fields
+    fn schema() -> Option<ValueSchema>;
+}
+
+pub fn get_bytes<T: PackedField>(value: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    value.encode_packed(Endianness::Little, &mut bytes)?;
+
+    Ok(bytes)
+}
+
+pub fn from_bytes<T: PackedField>(bytes: &[u8]) -> Result<T> {
+    let mut rest = bytes;
+    let value = T::decode_packed(&mut rest, Endianness::Little)?;
+
+    if !rest.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Value of {} bytes has {} trailing bytes",
+            bytes.len(),
+            rest.len()
+        ));
+    }
+
+    Ok(value)
+}
+
+pub fn value_schema<T: PackedField + Attribute>() -> (ValueFormat, ValueSchema) {
+    (ValueFormat::Raw, T::schema().unwrap_or(ValueSchema::Bytes))
+}
+
+pub fn encode_bincode<T: Serialize>(
+    value: &T,
+    int_encoding: IntEncoding,
+    endianness: Endianness,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let config = EncodingConfig {
+        int_encoding,
+        endianness,
+        limit: None,
+    };
+
+    out.extend(encoding::encode_with(value, config)?);
+
+    Ok(())
+}
+
+pub fn decode_bincode<T: DeserializeOwned>(
+    bytes: &mut &[u8],
+    int_encoding: IntEncoding,
+    endianness: Endianness,
+) -> Result<T> {
+    let config = EncodingConfig {
+        int_encoding,
+        endianness,
+        limit: None,
+    };
+
+    let (value, len) = encoding::decode_prefix_with(bytes, config)?;
+    *bytes = &bytes[len..];
+
+    Ok(value)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    let Some((field, rest)) = bytes.split_at_checked(len) else {
+        return Err(anyhow::anyhow!(
+            "Value is truncated, expected {} more bytes, got {}",
+            len,
+            bytes.len()
+        ));
+    };
+    *bytes = rest;
+
+    Ok(field)
+}
+
+macro_rules! packed_number {
+    ($($ty:ty => $schema:expr),* $(,)?) => {
+        $(
+            impl PackedField for $ty {
+                fn encode_packed(&self, endianness: Endianness, out: &mut Vec<u8>) -> Result<()> {
+                    match endianness {
+                        Endianness::Little => out.extend(self.to_le_bytes()),
+                        Endianness::Big => out.extend(self.to_be_bytes()),
+                    }
+
+                    Ok(())
+                }
+
+                fn decode_packed(bytes: &mut &[u8], endianness: Endianness) -> Result<Self> {
+                    let field = take(bytes, size_of::<$ty>())?;
+                    let array = field.try_into().map_err(|_| {
+                        anyhow::anyhow!("Failed to read {} field", stringify!($ty))
+                    })?;
+
+                    Ok(match endianness {
+                        Endianness::Little => <$ty>::from_le_bytes(array),
+                        Endianness::Big => <$ty>::from_be_bytes(array),
+                    })
+                }
+
+                fn schema() -> Option<ValueSchema> {
+                    $schema
+                }
+            }
+        )*
+    };
+}
+
+packed_number! {
+    u8 => Some(ValueSchema::U8),
+    u16 => Some(ValueSchema::U16),
+    u32 => Some(ValueSchema::U32),
+    u64 => Some(ValueSchema::U64),
+    u128 => None,
+    i8 => Some(ValueSchema::I8),
+    i16 => Some(ValueSchema::I16),
+    i32 => Some(ValueSchema::I32),
+    i64 => Some(ValueSchema::I64),
+    i128 => None,
+    f32 => Some(ValueSchema::F32),
+    f64 => Some(ValueSchema::F64),
+}
+
+impl PackedField for bool {
+    fn encode_packed(&self, _endianness: Endianness, out: &mut Vec<u8>) -> Result<()> {
+        out.push(*self as u8);
+
+        Ok(())
+    }
+
+    fn decode_packed(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self> {
+        match take(bytes, 1)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            value => Err(anyhow::anyhow!("Invalid bool field: {:?}", value)),
+        }
+    }
+
+    fn schema() -> Option<ValueSchema> {
+        Some(ValueSchema::Bool)
+    }
+}
+
+impl<T: PackedField, const N: usize> PackedField for [T; N] {
+    const HAS_REST: bool = T::HAS_REST;
+
+    fn encode_packed(&self, endianness: Endianness, out: &mut Vec<u8>) -> Result<()> {
+        self.iter()
+            .try_for_each(|item| item.encode_packed(endianness, out))
+    }
+
+    fn decode_packed(bytes: &mut &[u8], endianness: Endianness) -> Result<Self> {
+        let items = (0..N)
+            .map(|_| T::decode_packed(bytes, endianness))
+            .collect::<Result<Vec<_>>>()?;
+
+        items
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Failed to read array of {} fields", N))
+    }
+
+    fn schema() -> Option<ValueSchema> {
+        Some(ValueSchema::Tuple(vec![T::schema()?; N]))
+    }
+}
+
+impl PackedField for Vec<u8> {
+    const HAS_REST: bool = true;
+
+    fn encode_packed(&self, _endianness: Endianness, out: &mut Vec<u8>) -> Result<()> {
+        out.extend_from_slice(self);
+
+        Ok(())
+    }
+
+    fn decode_packed(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self> {
+        Ok(std::mem::take(bytes).to_vec())
+    }
+
+    fn schema() -> Option<ValueSchema> {
+        Some(ValueSchema::Bytes)
+    }
+}
+
+impl PackedField for String {
+    const HAS_REST: bool = true;
+
+    fn encode_packed(&self, _endianness: Endianness, out: &mut Vec<u8>) -> Result<()> {
+        out.extend_from_slice(self.as_bytes());
+
+        Ok(())
+    }
+
+    fn decode_packed(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self> {
+        String::from_utf8(std::mem::take(bytes).to_vec())
+            .map_err(|err| anyhow::anyhow!("Invalid UTF-8 string field: {:?}", err))
+    }
+
+    fn schema() -> Option<ValueSchema> {
+        Some(ValueSchema::String)
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::RwLock;
+
+use bincode::config::{BigEndian, Configuration, Fixint, LittleEndian, Varint};
+use serde::{Deserialize, Serialize};
+
+/// Integer encoding used for serde values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntEncoding {
+    // Integers take as many bytes as their type, matches C-like fixed-width layouts
+    Fixed,
+    // Small integers take less bytes, bincode default
+    Variable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Bincode options used by the blanket serde `Attribute` implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingConfig {
+    pub int_encoding: IntEncoding,
+    pub endianness: Endianness,
+
+    // Maximum number of bytes a value may be encoded to or decoded from
+    pub limit: Option<usize>,
+}
+
+impl EncodingConfig {
+    /// Same as `bincode::config::standard()`
+    pub const STANDARD: Self = Self {
+        int_encoding: IntEncoding::Variable,
+        endianness: Endianness::Little,
+        limit: None,
+    };
+
+    /// Same as `bincode::config::legacy()`, compatible with fixed-width struct layouts
+    pub const LEGACY: Self = Self {
+        int_encoding: IntEncoding::Fixed,
+        endianness: Endianness::Little,
+        limit: None,
+    };
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+static ENCODING_CONFIG: RwLock<EncodingConfig> = RwLock::new(EncodingConfig::STANDARD);
+
+/// Sets encoding of all serde attributes, should be called before any
+/// characteristic is registered, as already stored values are not re-encoded
+pub fn set_encoding_config(config: EncodingConfig) -> anyhow::Result<()> {
+    *ENCODING_CONFIG
+        .write()
+        .map_err(|_| anyhow::anyhow!("Failed to write encoding config"))? = config;
+
+    Ok(())
+}
+
+pub fn encoding_config() -> anyhow::Result<EncodingConfig> {
+    Ok(*ENCODING_CONFIG
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed to read encoding config"))?)
+}
+
+// Bincode configuration is selected at type level, so every combination is spelled out
+macro_rules! with_bincode_config {
+    ($config:expr, |$bincode:ident| $body:expr) => {
+        match ($config.int_encoding, $config.endianness) {
+            (IntEncoding::Variable, Endianness::Little) => {
+                let $bincode: Configuration<LittleEndian, Varint> = bincode::config::standard();
+                $body
+            }
+            (IntEncoding::Variable, Endianness::Big) => {
+                let $bincode: Configuration<BigEndian, Varint> =
+                    bincode::config::standard().with_big_endian();
+                $body
+            }
+            (IntEncoding::Fixed, Endianness::Little) => {
+                let $bincode: Configuration<LittleEndian, Fixint> =
+                    bincode::config::standard().with_fixed_int_encoding();
+                $body
+            }
+            (IntEncoding::Fixed, Endianness::Big) => {
+                let $bincode: Configuration<BigEndian, Fixint> = bincode::config::standard()
+                    .with_fixed_int_encoding()
+                    .with_big_endian();
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) fn encode<T: Serialize>(value: &T) -> anyhow::Result<Vec<u8>> {
+    encode_limited(value, encoding_config()?)
+}
+
+pub(crate) fn decode<T: for<'a> Deserialize<'a>>(bytes: &[u8]) -> anyhow::Result<T> {
+    decode_limited(bytes, encoding_config()?)
+}
+
+/// Encodes with the given config instead of the global one, including its limit
+pub(crate) fn encode_limited<T: Serialize>(
+    value: &T,
+    config: EncodingConfig,
+) -> anyhow::Result<Vec<u8>> {
+    let bytes = encode_with(value, config)?;
+
+    if let Some(limit) = config.limit.filter(|limit| bytes.len() > *limit) {
+        return Err(anyhow::anyhow!(
+            "Serialized characteristic value is {} bytes, over the limit of {} bytes",
+            bytes.len(),
+            limit
+        ));
+    }
+
+    Ok(bytes)
+}
+
+pub(crate) fn decode_limited<T: for<'a> Deserialize<'a>>(
+    bytes: &[u8],
+    config: EncodingConfig,
+) -> anyhow::Result<T> {
+    if let Some(limit) = config.limit.filter(|limit| bytes.len() > *limit) {
+        return Err(anyhow::anyhow!(
+            "Characteristic value is {} bytes, over the limit of {} bytes",
+            bytes.len(),
+            limit
+        ));
+    }
+
+    let (value, _) = decode_prefix_with(bytes, config)?;
+
+    Ok(value)
+}
+
+/// Encodes with the given config instead of the global one, ignoring its limit
+pub(crate) fn encode_with<T: Serialize>(
+    value: &T,
+    config: EncodingConfig,
+) -> anyhow::Result<Vec<u8>> {
+    with_bincode_config!(config, |bincode| {
+        bincode::serde::encode_to_vec(value, bincode)
+    })
+    .map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to serialize characteristic value to bytes: {:?}",
+            err
+        )
+    })
+}
+
+/// Decodes a value from the start of `bytes` with the given config, returns it
+/// with the number of bytes it took
+pub(crate) fn decode_prefix_with<T: for<'a> Deserialize<'a>>(
+    bytes: &[u8],
+    config: EncodingConfig,
+) -> anyhow::Result<(T, usize)> {
+    with_bincode_config!(config, |bincode| {
+        bincode::serde::decode_from_slice(bytes, bincode)
+    })
+    .map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to deserialize bytes to characteristic value: {:?}",
+            err
+        )
+    })
+}
@@ -0,0 +1,186 @@
+//! Fieldless enums exposed as a single byte, e.g. modes and states. Declare the
+//! enum with `wire_enum!` and register `EnumAttr<E>` in a characteristic:
+//!
+//! ```ignore
+//! wire_enum! {
+//!     #[derive(Debug)]
+//!     pub enum Mode: unknown = Fallback(Mode::Idle) {
+//!         Idle = 0,
+//!         Heating = 1,
+//!         Cooling = 2,
+//!     }
+//! }
+//!
+//! let mode = Characteristic::new(EnumAttr(Mode::Idle), config, None);
+//! ```
+//!
+//! Sets of flags, e.g. feature bits or status registers, are `EnumSetType` enums
+//! held in `FlagsAttr`, carried as the smallest integer with a bit per variant.
+
+use enumset::{EnumSet, EnumSetType};
+use esp_idf_svc::bt::ble::gatt::GattStatus;
+
+use super::Attribute;
+use crate::gatts::{
+    error::AttError,
+    schema::{ValueFormat, ValueSchema},
+};
+
+/// What a byte which is no variant of the enum decodes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownValue<E> {
+    /// Fails the decode, peer writes are answered with `OutOfRange`
+    Reject,
+    /// Decodes to the given variant, e.g. a catch-all `Unknown` one
+    Fallback(E),
+}
+
+/// Fieldless enum carried as a single byte, implemented by `wire_enum!`
+pub trait WireEnum: Copy + Send + Sync + 'static {
+    const UNKNOWN: UnknownValue<Self>;
+
+    fn to_wire(self) -> u8;
+    fn from_wire(value: u8) -> Option<Self>;
+}
+
+/// A wrapper for `WireEnum` values that implements the Attribute trait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EnumAttr<E>(pub E);
+
+impl<E: WireEnum> Attribute for EnumAttr<E> {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(vec![self.0.to_wire()])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let [value] = bytes else {
+            return Err(anyhow::anyhow!(
+                "Invalid length for EnumAttr: expected 1 byte, got {}",
+                bytes.len()
+            ));
+        };
+
+        match (E::from_wire(*value), E::UNKNOWN) {
+            (Some(variant), _) => Ok(EnumAttr(variant)),
+            (None, UnknownValue::Fallback(variant)) => Ok(EnumAttr(variant)),
+            // Status stays downcastable through the context, see `att_error`
+            (None, UnknownValue::Reject) => Err(anyhow::Error::from(AttError::Status(
+                GattStatus::OutOfRange,
+            ))
+            .context(format!("Unknown enum value: {:#04x}", value))),
+        }
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::U8)
+    }
+}
+
+/// A wrapper for sets of flags that implements the Attribute trait. Bit `n` of the
+/// little-endian integer is the variant with discriminant `n`, so positions are
+/// fixed with explicit discriminants. Values with bits of no variant are rejected
+/// with `OutOfRange`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlagsAttr<T: EnumSetType>(pub EnumSet<T>);
+
+impl<T: EnumSetType> FlagsAttr<T> {
+    // Width of the integer in bytes, rounded up to a whole number type
+    const WIDTH: usize = match EnumSet::<T>::bit_width().div_ceil(8) {
+        0 | 1 => 1,
+        2 => 2,
+        3 | 4 => 4,
+        5..=8 => 8,
+        _ => 16,
+    };
+
+    pub fn contains(&self, flag: T) -> bool {
+        self.0.contains(flag)
+    }
+
+    /// Sets or clears a single flag
+    pub fn set(&mut self, flag: T, enabled: bool) {
+        match enabled {
+            true => self.0.insert(flag),
+            false => self.0.remove(flag),
+        };
+    }
+}
+
+impl<T: EnumSetType> From<EnumSet<T>> for FlagsAttr<T> {
+    fn from(flags: EnumSet<T>) -> Self {
+        FlagsAttr(flags)
+    }
+}
+
+impl<T: EnumSetType + Send + Sync + 'static> Attribute for FlagsAttr<T> {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.as_u128().to_le_bytes()[..Self::WIDTH].to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != Self::WIDTH {
+            return Err(anyhow::anyhow!(
+                "Invalid length for FlagsAttr: expected {} bytes, got {}",
+                Self::WIDTH,
+                bytes.len()
+            ));
+        }
+
+        let mut value = [0; 16];
+        value[..Self::WIDTH].copy_from_slice(bytes);
+        let value = u128::from_le_bytes(value);
+
+        EnumSet::try_from_u128(value).map(FlagsAttr).ok_or_else(|| {
+            anyhow::Error::from(AttError::Status(GattStatus::OutOfRange))
+                .context(format!("Unknown flags: {:#x}", value))
+        })
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        let schema = match Self::WIDTH {
+            1 => ValueSchema::U8,
+            2 => ValueSchema::U16,
+            4 => ValueSchema::U32,
+            8 => ValueSchema::U64,
+            _ => ValueSchema::Bytes,
+        };
+
+        (ValueFormat::Raw, schema)
+    }
+}
+
+/// Declares a fieldless enum with explicit `u8` discriminants and implements
+/// `WireEnum` for it. `unknown` is the `UnknownValue` policy, `Reject` or
+/// `Fallback(variant)`
+#[macro_export]
+macro_rules! wire_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident: unknown = $unknown:ident $(($fallback:expr))? {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:literal),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        #[repr(u8)]
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant = $value),*
+        }
+
+        impl $crate::gatts::attribute::enums::WireEnum for $name {
+            const UNKNOWN: $crate::gatts::attribute::enums::UnknownValue<Self> =
+                $crate::gatts::attribute::enums::UnknownValue::$unknown $(($fallback))?;
+
+            fn to_wire(self) -> u8 {
+                self as u8
+            }
+
+            fn from_wire(value: u8) -> ::core::option::Option<Self> {
+                match value {
+                    $($value => ::core::option::Option::Some(Self::$variant),)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+    };
+}
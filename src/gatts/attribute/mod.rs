@@ -1,16 +1,84 @@
+pub mod codec;
 pub mod defaults;
+#[cfg(feature = "derive")]
+pub mod derive;
+pub mod encoding;
+pub mod enums;
+pub mod scaled;
+mod size;
+pub mod telemetry;
+pub mod uuid;
 
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use crossbeam_channel::{Receiver, Sender};
-use esp_idf_svc::bt::ble::gatt::Handle;
+use esp_idf_svc::bt::{
+    BtUuid,
+    ble::gatt::{GattStatus, Handle, server::ConnectionId},
+};
+use scaled::PresentationFormat;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "derive")]
+pub use esp_bluedroid_derive::Attribute;
+
+use super::{
+    diff,
+    error::AttError,
+    event::EventStamp,
+    pending::HeldResponse,
+    schema::{ValueFormat, ValueSchema},
+};
+
+/// Value codec of an attribute, converts values from and to the bytes peers read
+/// and write. Implemented for every serde value, registered attributes holding
+/// the value implement `AnyAttribute`
 pub trait Attribute: Send + Sync + 'static {
     fn get_bytes(&self) -> anyhow::Result<Vec<u8>>;
     fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self>
     where
         Self: Sized;
+
+    /// Decodes bytes written by a peer into a new value, keeping any metadata
+    /// from the current value which is not part of the wire format
+    fn decode_update(&self, bytes: &[u8]) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::from_bytes(bytes)
+    }
+
+    /// If Some, a Characteristic Presentation Format descriptor is registered
+    /// alongside the characteristic holding this value
+    fn presentation_format(&self) -> Option<PresentationFormat> {
+        None
+    }
+
+    /// Human-readable JSON form of the value, Some only for serde values
+    /// with the `json` feature enabled
+    fn to_json(&self) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Wire format and shape of the value, exported with the GATT schema
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        (ValueFormat::Raw, ValueSchema::Bytes)
+    }
+
+    /// Encoded fields compared by diff notifications, the whole value is one field
+    /// unless it is a serde struct
+    fn fields(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        Ok(vec![self.get_bytes()?])
+    }
+
+    /// Largest size values shaped like this one encode to, checked against
+    /// `value_max_len` at registration. Current size unless it is a serde value
+    fn max_encoded_len(&self) -> anyhow::Result<usize> {
+        Ok(self.get_bytes()?.len())
+    }
 }
 
 pub trait SerializableAttribute: Serialize + for<'a> Deserialize<'a> {}
@@ -19,41 +87,131 @@ where
     T: Serialize + for<'a> Deserialize<'a> + Send + Sync + 'static,
 {
     fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
-        bincode::serde::encode_to_vec(self, bincode::config::standard()).map_err(|err| {
-            anyhow::anyhow!(
-                "Failed to serialize characteristic value to bytes: {:?}",
-                err
-            )
-        })
+        encoding::encode(self)
     }
 
     fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
-        let (new_value, _): (T, usize) =
-            bincode::serde::decode_from_slice(bytes, bincode::config::standard()).map_err(
-                |err| {
-                    anyhow::anyhow!(
-                        "Failed to deserialize bytes to characteristic value: {:?}",
-                        err
-                    )
-                },
-            )?;
-
-        Ok(new_value)
+        encoding::decode(bytes)
+    }
+
+    #[cfg(feature = "json")]
+    fn to_json(&self) -> anyhow::Result<Option<String>> {
+        serde_json::to_string(self)
+            .map(Some)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize value to JSON: {:?}", err))
+    }
+
+    fn value_schema(&self) -> (ValueFormat, ValueSchema) {
+        ValueSchema::trace_or_bytes(ValueFormat::Bincode, self)
+    }
+
+    fn fields(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        match diff::split_fields(self)? {
+            Some(fields) => Ok(fields),
+            None => Ok(vec![self.get_bytes()?]),
+        }
+    }
+
+    fn max_encoded_len(&self) -> anyhow::Result<usize> {
+        size::max_encoded_len(self, &encoding::encoding_config()?)
     }
 }
 
+/// Attribute registered in the GATT table with its value type erased, the one
+/// definition of access to its bytes. `CharacteristicAttribute` and
+/// `DescriptorAttribute` add registration on top of it
 pub trait AnyAttribute: Send + Sync + 'static {
+    fn uuid(&self) -> BtUuid;
+    fn handle(&self) -> anyhow::Result<Handle>;
+
+    /// Applies bytes as an update made by the app, characteristics notify
+    /// subscribed peers as they do for `Characteristic::update_value`
     fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()>;
     fn get_bytes(&self) -> anyhow::Result<Vec<u8>>;
+
+    /// Applies bytes written by the peer of the given connection, characteristics
+    /// use the writer to decide which peers are indicated
+    fn write_from_peer(&self, bytes: &[u8], _writer: ConnectionId) -> anyhow::Result<()> {
+        self.update_from_bytes(bytes)
+    }
+
+    /// Time the application has to approve peer writes, None if they are applied
+    /// right away, see `CharacteristicConfig::write_approval`
+    fn write_approval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Stages a peer write for the approval of the application, the value is
+    /// applied and `response` answered once it decides
+    fn stage_write(
+        self: Arc<Self>,
+        _bytes: Vec<u8>,
+        _writer: ConnectionId,
+        _response: HeldResponse,
+    ) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Attribute does not stage writes"))
+    }
+
+    /// Bytes returned to a peer reading the attribute starting at given offset,
+    /// long reads continue with non zero offsets into the same value
+    fn read_bytes(&self, _offset: u16) -> anyhow::Result<Vec<u8>> {
+        self.get_bytes()
+    }
+
+    /// Value a peer write completes, None while more writes are needed. Only
+    /// chunked characteristics spread a value over several writes
+    fn reassemble_write(
+        &self,
+        bytes: Vec<u8>,
+        _writer: ConnectionId,
+    ) -> Result<Option<Vec<u8>>, AttError> {
+        Ok(Some(bytes))
+    }
+
+    /// Drops state kept for the peer of a closed connection, e.g. values it was
+    /// writing in chunks
+    fn peer_disconnected(&self, _conn_id: ConnectionId) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Reacts to a subscription restored for a reconnecting bonded peer, e.g.
+    /// characteristics with diff notifications send the full value first
+    fn peer_resubscribed(&self, _conn_id: ConnectionId) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Checks bytes written by a peer before they are applied,
+    /// returned error is reported to the peer in the write response
+    fn validate_write(&self, _bytes: &[u8]) -> Result<(), AttError> {
+        Ok(())
+    }
+
+    /// Decodes the current value and encodes it again, run by `Gatts::self_test`
+    fn round_trip(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Handle of the characteristic owning the attribute, None unless it is a descriptor
+    fn characteristic_handle(&self) -> anyhow::Result<Option<Handle>> {
+        Ok(None)
+    }
+
+    /// Size of the value a peer is expected to write in a long write, reported
+    /// in `WriteProgress`
+    fn expected_write_len(&self) -> anyhow::Result<Option<usize>> {
+        Ok(None)
+    }
 }
 
 #[derive(Clone)]
 pub struct AttributeUpdate<T> {
     pub old: T,
     pub new: T,
+    // Stamp of the peer write which caused the update, or of the local update
+    pub stamp: EventStamp,
 }
 
 pub struct AttributeInner<T: Attribute> {
@@ -103,6 +261,48 @@ impl<T: Attribute> AttributeInner<T> {
         self.get_value()?.get_bytes()
     }
 
+    /// Checks the current value decodes and encodes back to the same bytes,
+    /// otherwise peers could read a value they are unable to write back
+    pub fn round_trip(&self) -> anyhow::Result<()> {
+        let value = self.get_value()?;
+        let bytes = value.get_bytes()?;
+        let encoded = value.decode_update(&bytes)?.get_bytes()?;
+
+        if encoded != bytes {
+            return Err(anyhow::anyhow!(
+                "Value of {} bytes encodes to different {} bytes once decoded",
+                bytes.len(),
+                encoded.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn decode_update(&self, bytes: &[u8]) -> anyhow::Result<Arc<T>> {
+        Ok(Arc::new(self.get_value()?.decode_update(bytes)?))
+    }
+
+    /// Decodes bytes written by a peer without applying them, values which fail
+    /// to decode are reported as invalid length unless the decoder gave a status
+    pub fn decode_write(&self, bytes: &[u8]) -> Result<Arc<T>, AttError> {
+        self.decode_update(bytes).map_err(|err| {
+            err.downcast_ref::<AttError>()
+                .copied()
+                .unwrap_or(AttError::Status(GattStatus::InvalidAttrLen))
+        })
+    }
+
+    /// Replaces stored value without publishing an update
+    pub fn replace(&self, new_value: Arc<T>) -> anyhow::Result<()> {
+        *self
+            .value
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write attribute value"))? = new_value;
+
+        Ok(())
+    }
+
     pub fn update(&self, new_value: Arc<T>) -> anyhow::Result<()> {
         let old_value = self.get_value()?;
         *self
@@ -114,6 +314,7 @@ impl<T: Attribute> AttributeInner<T> {
             .send(AttributeUpdate {
                 old: old_value,
                 new: new_value,
+                stamp: EventStamp::current(),
             })
             .map_err(|_| anyhow::anyhow!("Failed to send attribute update"))?;
 
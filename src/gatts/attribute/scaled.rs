@@ -0,0 +1,178 @@
+use crate::gatts::attribute::Attribute;
+
+/// Commonly used unit UUIDs from the Bluetooth SIG assigned numbers.
+pub mod unit {
+    pub const UNITLESS: u16 = 0x2700;
+    pub const METRE: u16 = 0x2701;
+    pub const KILOGRAM: u16 = 0x2702;
+    pub const SECOND: u16 = 0x2703;
+    pub const AMPERE: u16 = 0x2704;
+    pub const KELVIN: u16 = 0x2705;
+    pub const HERTZ: u16 = 0x2722;
+    pub const PASCAL: u16 = 0x2724;
+    pub const WATT: u16 = 0x2726;
+    pub const VOLT: u16 = 0x2728;
+    pub const CELSIUS: u16 = 0x272F;
+    pub const LUX: u16 = 0x2731;
+    pub const PERCENTAGE: u16 = 0x27AD;
+}
+
+/// Content of the Characteristic Presentation Format descriptor (0x2904).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentationFormat {
+    pub format: u8,
+    pub exponent: i8,
+    pub unit: u16,
+    pub namespace: u8,
+    pub description: u16,
+}
+
+impl PresentationFormat {
+    pub const UUID: u16 = 0x2904;
+    /// Namespace of units and descriptions assigned by the Bluetooth SIG.
+    pub const NAMESPACE_BLUETOOTH_SIG: u8 = 0x01;
+}
+
+impl Attribute for PresentationFormat {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(7);
+        bytes.push(self.format);
+        bytes.push(self.exponent as u8);
+        bytes.extend_from_slice(&self.unit.to_le_bytes());
+        bytes.push(self.namespace);
+        bytes.extend_from_slice(&self.description.to_le_bytes());
+
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 7 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for PresentationFormat: expected 7 bytes, got {}",
+                bytes.len()
+            ));
+        }
+
+        Ok(PresentationFormat {
+            format: bytes[0],
+            exponent: bytes[1] as i8,
+            unit: u16::from_le_bytes([bytes[2], bytes[3]]),
+            namespace: bytes[4],
+            description: u16::from_le_bytes([bytes[5], bytes[6]]),
+        })
+    }
+}
+
+/// Integer types which can be carried by a [`ScaledAttr`].
+pub trait ScaledValue: Copy + Send + Sync + 'static {
+    /// Format code used in the Characteristic Presentation Format descriptor.
+    const FORMAT: u8;
+
+    fn to_le_vec(self) -> Vec<u8>;
+    fn from_le_slice(bytes: &[u8]) -> anyhow::Result<Self>;
+    fn as_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+}
+
+macro_rules! impl_scaled_value {
+    ($ty:ty, $format:expr) => {
+        impl ScaledValue for $ty {
+            const FORMAT: u8 = $format;
+
+            fn to_le_vec(self) -> Vec<u8> {
+                self.to_le_bytes().to_vec()
+            }
+
+            fn from_le_slice(bytes: &[u8]) -> anyhow::Result<Self> {
+                let bytes = bytes.try_into().map_err(|_| {
+                    anyhow::anyhow!(
+                        "Invalid length for ScaledAttr<{}>: expected {} bytes, got {}",
+                        stringify!($ty),
+                        size_of::<$ty>(),
+                        bytes.len()
+                    )
+                })?;
+
+                Ok(<$ty>::from_le_bytes(bytes))
+            }
+
+            fn as_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn from_f64(value: f64) -> Self {
+                value.round() as $ty
+            }
+        }
+    };
+}
+
+impl_scaled_value!(u8, 0x04);
+impl_scaled_value!(u16, 0x06);
+impl_scaled_value!(u32, 0x08);
+impl_scaled_value!(i8, 0x0C);
+impl_scaled_value!(i16, 0x0E);
+impl_scaled_value!(i32, 0x10);
+
+/// A raw integer with a decimal exponent and unit, serialized per Bluetooth SIG conventions:
+/// the represented value is `value * 10^exponent` expressed in `unit`.
+/// Registering it as a characteristic automatically adds the matching
+/// Characteristic Presentation Format descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaledAttr<T: ScaledValue> {
+    pub value: T,
+    pub exponent: i8,
+    pub unit: u16,
+}
+
+impl<T: ScaledValue> ScaledAttr<T> {
+    pub fn new(value: T, exponent: i8, unit: u16) -> Self {
+        Self {
+            value,
+            exponent,
+            unit,
+        }
+    }
+
+    /// Creates the raw value from a value expressed in human units.
+    pub fn from_real(real: f64, exponent: i8, unit: u16) -> Self {
+        Self::new(
+            T::from_f64(real / 10f64.powi(exponent.into())),
+            exponent,
+            unit,
+        )
+    }
+
+    /// Value expressed in human units.
+    pub fn real(&self) -> f64 {
+        self.value.as_f64() * 10f64.powi(self.exponent.into())
+    }
+}
+
+impl<T: ScaledValue> Attribute for ScaledAttr<T> {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.value.to_le_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::new(T::from_le_slice(bytes)?, 0, unit::UNITLESS))
+    }
+
+    fn decode_update(&self, bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            T::from_le_slice(bytes)?,
+            self.exponent,
+            self.unit,
+        ))
+    }
+
+    fn presentation_format(&self) -> Option<PresentationFormat> {
+        Some(PresentationFormat {
+            format: T::FORMAT,
+            exponent: self.exponent,
+            unit: self.unit,
+            namespace: PresentationFormat::NAMESPACE_BLUETOOTH_SIG,
+            description: 0,
+        })
+    }
+}
@@ -0,0 +1,359 @@
+//! Worst-case encoded size of serde values. Walks the value like the bincode
+//! encoder does, counting every integer at the largest size its type may take,
+//! so a value whose numbers grow later is still covered. Strings, sequences and
+//! maps keep their current length, None counts as its tag only.
+
+use serde::{Serialize, ser};
+
+use super::encoding::{EncodingConfig, IntEncoding};
+
+pub(crate) fn max_encoded_len<T: Serialize + ?Sized>(
+    value: &T,
+    config: &EncodingConfig,
+) -> anyhow::Result<usize> {
+    let mut counter = SizeCounter {
+        len: 0,
+        fixed: config.int_encoding == IntEncoding::Fixed,
+    };
+
+    value
+        .serialize(&mut counter)
+        .map_err(|err| anyhow::anyhow!("Failed to size characteristic value: {}", err))?;
+
+    Ok(counter.len)
+}
+
+#[derive(Debug)]
+struct SizeError(String);
+
+impl std::fmt::Display for SizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SizeError {}
+
+impl ser::Error for SizeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+struct SizeCounter {
+    len: usize,
+    fixed: bool,
+}
+
+impl SizeCounter {
+    // Largest encoding of an integer of `size` bytes, variable encoding adds
+    // a marker byte before the full width value
+    fn int(&mut self, size: usize) {
+        self.len += match (self.fixed, size) {
+            (_, 1) => 1,
+            (true, size) => size,
+            (false, size) => size + 1,
+        };
+    }
+
+    // Lengths are encoded as u64
+    fn length(&mut self, len: usize) {
+        self.int(8);
+        self.len += len;
+    }
+
+    // Variant indices are encoded as u32
+    fn variant(&mut self) {
+        self.int(4);
+    }
+}
+
+impl ser::Serializer for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, _v: bool) -> Result<(), SizeError> {
+        self.len += 1;
+        Ok(())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<(), SizeError> {
+        self.int(1);
+        Ok(())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<(), SizeError> {
+        self.int(2);
+        Ok(())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<(), SizeError> {
+        self.int(4);
+        Ok(())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<(), SizeError> {
+        self.int(8);
+        Ok(())
+    }
+
+    fn serialize_i128(self, _v: i128) -> Result<(), SizeError> {
+        self.int(16);
+        Ok(())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<(), SizeError> {
+        self.int(1);
+        Ok(())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<(), SizeError> {
+        self.int(2);
+        Ok(())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<(), SizeError> {
+        self.int(4);
+        Ok(())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<(), SizeError> {
+        self.int(8);
+        Ok(())
+    }
+
+    fn serialize_u128(self, _v: u128) -> Result<(), SizeError> {
+        self.int(16);
+        Ok(())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<(), SizeError> {
+        self.len += 4;
+        Ok(())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), SizeError> {
+        self.len += 8;
+        Ok(())
+    }
+
+    // Chars are encoded as UTF-8
+    fn serialize_char(self, _v: char) -> Result<(), SizeError> {
+        self.len += 4;
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), SizeError> {
+        self.length(v.len());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SizeError> {
+        self.length(v.len());
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), SizeError> {
+        self.len += 1;
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SizeError> {
+        self.len += 1;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SizeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), SizeError> {
+        self.variant();
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), SizeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), SizeError> {
+        self.variant();
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, SizeError> {
+        len.ok_or(SizeError(String::from("Sequence without length")))?;
+        self.length(0);
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, SizeError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, SizeError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, SizeError> {
+        self.variant();
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, SizeError> {
+        len.ok_or(SizeError(String::from("Map without length")))?;
+        self.length(0);
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, SizeError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, SizeError> {
+        self.variant();
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SizeError> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SizeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), SizeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut SizeCounter {
+    type Ok = ();
+    type Error = SizeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), SizeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SizeError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use crate::gatts::attribute::Attribute;
+
+/// Temperature (0x2A6E) encoded as a signed 16-bit value in units of 0.01 degrees Celsius.
+/// Uses little-endian byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TemperatureAttr(pub i16);
+
+impl TemperatureAttr {
+    pub const UUID: u16 = 0x2A6E;
+    /// Value reported when the temperature is not known.
+    pub const UNKNOWN: Self = Self(i16::MIN);
+
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self(((celsius * 100.0).round() as i16).max(i16::MIN + 1))
+    }
+
+    pub fn celsius(&self) -> Option<f32> {
+        (*self != Self::UNKNOWN).then(|| self.0 as f32 / 100.0)
+    }
+}
+
+impl Attribute for TemperatureAttr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 2 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for TemperatureAttr: expected 2 bytes, got {}",
+                bytes.len()
+            ));
+        }
+        Ok(TemperatureAttr(i16::from_le_bytes([bytes[0], bytes[1]])))
+    }
+}
+
+/// Humidity (0x2A6F) encoded as an unsigned 16-bit value in units of 0.01 percent.
+/// Uses little-endian byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HumidityAttr(pub u16);
+
+impl HumidityAttr {
+    pub const UUID: u16 = 0x2A6F;
+    /// Value reported when the humidity is not known.
+    pub const UNKNOWN: Self = Self(u16::MAX);
+
+    pub fn from_percent(percent: f32) -> Self {
+        Self((percent.clamp(0.0, 100.0) * 100.0).round() as u16)
+    }
+
+    pub fn percent(&self) -> Option<f32> {
+        (*self != Self::UNKNOWN).then(|| self.0 as f32 / 100.0)
+    }
+}
+
+impl Attribute for HumidityAttr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 2 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for HumidityAttr: expected 2 bytes, got {}",
+                bytes.len()
+            ));
+        }
+        let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+        if value > 10000 && value != u16::MAX {
+            return Err(anyhow::anyhow!(
+                "Invalid value for HumidityAttr: {} is out of range",
+                value
+            ));
+        }
+        Ok(HumidityAttr(value))
+    }
+}
+
+/// Battery Level (0x2A19) encoded as a single byte percentage in range 0..=100.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryLevelAttr(pub u8);
+
+impl BatteryLevelAttr {
+    pub const UUID: u16 = 0x2A19;
+}
+
+impl Attribute for BatteryLevelAttr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(vec![self.0.min(100)])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 1 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for BatteryLevelAttr: expected 1 byte, got {}",
+                bytes.len()
+            ));
+        }
+        if bytes[0] > 100 {
+            return Err(anyhow::anyhow!(
+                "Invalid value for BatteryLevelAttr: {} is out of range",
+                bytes[0]
+            ));
+        }
+        Ok(BatteryLevelAttr(bytes[0]))
+    }
+}
+
+/// Percentage 8 (0x2B04) encoded as a single byte in units of 0.5 percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentage8Attr(pub u8);
+
+impl Percentage8Attr {
+    pub const UUID: u16 = 0x2B04;
+    /// Value reported when the percentage is not known.
+    pub const UNKNOWN: Self = Self(u8::MAX);
+
+    pub fn from_percent(percent: f32) -> Self {
+        Self((percent.clamp(0.0, 100.0) * 2.0).round() as u8)
+    }
+
+    pub fn percent(&self) -> Option<f32> {
+        (*self != Self::UNKNOWN).then(|| self.0 as f32 / 2.0)
+    }
+}
+
+impl Attribute for Percentage8Attr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(vec![self.0])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 1 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for Percentage8Attr: expected 1 byte, got {}",
+                bytes.len()
+            ));
+        }
+        if bytes[0] > 200 && bytes[0] != u8::MAX {
+            return Err(anyhow::anyhow!(
+                "Invalid value for Percentage8Attr: {} is out of range",
+                bytes[0]
+            ));
+        }
+        Ok(Percentage8Attr(bytes[0]))
+    }
+}
+
+/// Date Time (0x2A08) encoded as year (u16, little-endian), month, day, hours, minutes and seconds.
+/// Zero in year, month or day means the field is not known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DateTimeAttr {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTimeAttr {
+    pub const UUID: u16 = 0x2A08;
+}
+
+impl Attribute for DateTimeAttr {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(7);
+        bytes.extend_from_slice(&self.year.to_le_bytes());
+        bytes.extend_from_slice(&[self.month, self.day, self.hours, self.minutes, self.seconds]);
+
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != 7 {
+            return Err(anyhow::anyhow!(
+                "Invalid length for DateTimeAttr: expected 7 bytes, got {}",
+                bytes.len()
+            ));
+        }
+
+        let value = DateTimeAttr {
+            year: u16::from_le_bytes([bytes[0], bytes[1]]),
+            month: bytes[2],
+            day: bytes[3],
+            hours: bytes[4],
+            minutes: bytes[5],
+            seconds: bytes[6],
+        };
+
+        if (value.year != 0 && !(1582..=9999).contains(&value.year))
+            || value.month > 12
+            || value.day > 31
+            || value.hours > 23
+            || value.minutes > 59
+            || value.seconds > 59
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid value for DateTimeAttr: {:?}",
+                value
+            ));
+        }
+
+        Ok(value)
+    }
+}
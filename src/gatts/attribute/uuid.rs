@@ -0,0 +1,44 @@
+//! Conversions of `UuidAttr` from and to `BtUuid`, and `uuid::Uuid` with the
+//! `uuid` feature. Kept out of `defaults`, which the host bench and fuzz crates
+//! include without esp-idf.
+
+use esp_idf_svc::bt::BtUuid;
+
+use super::defaults::UuidAttr;
+
+/// Short UUIDs are expanded with the Bluetooth base UUID
+impl From<&BtUuid> for UuidAttr {
+    fn from(uuid: &BtUuid) -> Self {
+        let bytes = uuid.as_bytes();
+        let value = match bytes.len() {
+            2 => (u128::from(u16::from_le_bytes([bytes[0], bytes[1]])) << 96) | Self::BASE,
+            4 => {
+                (u128::from(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])) << 96)
+                    | Self::BASE
+            }
+            _ => u128::from_le_bytes(bytes.try_into().unwrap_or([0; 16])),
+        };
+
+        UuidAttr(value)
+    }
+}
+
+impl From<UuidAttr> for BtUuid {
+    fn from(uuid: UuidAttr) -> Self {
+        BtUuid::uuid128(uuid.0)
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for UuidAttr {
+    fn from(uuid: uuid::Uuid) -> Self {
+        UuidAttr(uuid.as_u128())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<UuidAttr> for uuid::Uuid {
+    fn from(uuid: UuidAttr) -> Self {
+        uuid::Uuid::from_u128(uuid.0)
+    }
+}
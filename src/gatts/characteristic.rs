@@ -2,25 +2,47 @@ use std::{
     collections::HashMap,
     mem::discriminant,
     sync::{Arc, RwLock, Weak},
+    time::Duration,
 };
 
-use crossbeam_channel::bounded;
+use crossbeam_channel::{Receiver, Sender, bounded, unbounded};
 use enumset::EnumSet;
-use esp_idf_svc::bt::{
-    BtUuid,
-    ble::gatt::{AutoResponse, GattCharacteristic, GattStatus, Handle, Permission, Property},
+use esp_idf_svc::{
+    bt::{
+        BtUuid,
+        ble::gatt::{
+            AutoResponse, GattCharacteristic, GattStatus, Handle, Permission, Property,
+            server::ConnectionId,
+        },
+    },
+    sys::ESP_GATT_MAX_ATTR_LEN,
 };
 
 use super::{
-    GattsEvent,
+    CCCD_UUID, EXTENDED_PROPERTIES_UUID, GattsEvent, SCCD_UUID, USER_DESCRIPTION_UUID, att_error,
     attribute::{
         AnyAttribute, Attribute, AttributeInner,
-        defaults::{StringAttr, U16Attr},
+        codec::{Coded, Decode, Encode},
+        defaults::{StringAttr, U8Attr, U16Attr, U32Attr},
+        scaled::PresentationFormat,
     },
+    checksum,
+    chunked::{self, ChunkReassembler},
+    connection::Connection,
     descriptor::{Descriptor, DescriptorAttribute, DescriptorConfig, DescritporId},
+    diff,
+    error::{AttError, ValueSizeError},
     event::GattsEventMessage,
-    service::{self, ServiceInner},
+    ident::AppInterface,
+    loopback::{LOOPBACK_CONN_ID, Loopback},
+    pending::{HeldResponse, MAX_PENDING_WRITES, PendingWrite},
+    persistence::Persistence,
+    schema::CharacteristicSchema,
+    service::{self, Service, ServiceInner},
+    table::TableEntry,
+    uuid_string,
 };
+use crate::{gap::Gap, guard, trace};
 
 pub struct CharacteristicConfig {
     pub uuid: BtUuid,
@@ -28,6 +50,21 @@ pub struct CharacteristicConfig {
 
     pub readable: bool,
     pub writable: bool,
+    // If true, peers may also write without response (Write Without Response
+    // property), e.g. for streamed data where every acknowledgment costs a
+    // connection event. Needs one of the write options
+    pub write_without_response: bool,
+
+    // Require encrypted (paired) link, authenticated variants additionally
+    // require pairing with MITM protection. Each of them also enables
+    // corresponding Read/Write property
+    pub read_encrypted: bool,
+    pub write_encrypted: bool,
+    pub read_authenticated: bool,
+    pub write_authenticated: bool,
+
+    // How reads are answered when none of the read options above is set
+    pub write_only_reads: WriteOnlyRead,
 
     // If true, the characteristic will be broadcasted to all connected devices
     // this will automatically configure SCCD descriptor
@@ -38,6 +75,231 @@ pub struct CharacteristicConfig {
     pub enable_notify: bool,
 
     pub description: Option<String>,
+    // If true, peers can rename the characteristic by writing the User Description
+    // descriptor, see `Characteristic::set_on_descriptor_write`
+    pub description_writable: bool,
+
+    // If true, reads are answered directly by the Bluedroid stack (AutoResponse::ByGatt)
+    // from a copy of the value kept in the stack, bypassing the application round-trip.
+    // Useful for constant values like static strings
+    pub stack_managed: bool,
+
+    // If true, a read-only descriptor (JSON_MIRROR_UUID) exposes the value as JSON,
+    // so it can be inspected from generic tools like nRF Connect.
+    // Requires serde value and `json` feature
+    pub json_mirror: bool,
+
+    // If true, a read-only descriptor (CHECKSUM_UUID) holds the CRC-32 of the
+    // encoded value, computed on every read. Peers assembling a large value from
+    // long reads compare it to tell a consistent value from one changed midway
+    pub checksum: bool,
+
+    // If Some, value is loaded from NVS under this key (max 15 bytes) on registration
+    // and saved, debounced, after every accepted write or update
+    pub persistent: Option<&'static str>,
+
+    // Which peers are indicated with a value written by a peer, also applied to
+    // updates made by the write handler in response. Other updates from the
+    // application always go to every peer
+    pub write_echo: WriteEcho,
+
+    // If true, indications carry a `gatts::diff` frame with only the changed
+    // top-level fields of the value instead of the whole value, a peer enabling
+    // the CCCD first receives a full frame. Meant for large, mostly static
+    // serde structs
+    pub diff_notify: bool,
+
+    // How registration treats a value whose worst-case encoded size exceeds
+    // `value_max_len`, see `ValueSizeError`. Not checked when `value_max_len` is 0
+    pub size_check: SizeCheck,
+
+    // If Some, values are sent and received as `gatts::chunked` frames, so they
+    // may exceed the MTU and `ESP_GATT_MAX_ATTR_LEN`. `value_max_len` then
+    // bounds a single frame, values written by peers are bounded by this length
+    pub chunked: Option<usize>,
+
+    // If Some, the encoded value is mirrored into the advertising Service Data
+    // of this 16-bit UUID on registration and every update, so passive scanners
+    // read e.g. a door state without connecting. Replaces `GapConfig::service_data`,
+    // meant for values of a few bytes as the payload is limited to 31 bytes
+    pub advertised_service_data: Option<u16>,
+
+    // If Some, a descriptor (COMPRESSION_UUID) lets each peer opt in by writing 1,
+    // after which its indications and writes are `gatts::compression` frames and
+    // values of at least this many bytes are DEFLATE compressed. Reads return the
+    // plain value. Requires `compression` feature
+    pub compression: Option<usize>,
+
+    // If Some, peer writes which passed the write validator are staged and sent
+    // to `pending_writes_rx`, the value is applied and the write acknowledged once
+    // the application commits it. Writes not decided within this time are
+    // rejected, it should stay well below the ATT timeout of 30 seconds. Up to
+    // `pending::MAX_PENDING_WRITES` writes wait to be received, further ones are
+    // answered with `Busy`
+    pub write_approval: Option<Duration>,
+}
+
+/// Aspects of `CharacteristicConfig` which can change after registration, see
+/// `Characteristic::reconfigure`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    pub description: Option<String>,
+    pub enable_notify: bool,
+    // Up to ESP_GATT_MAX_ATTR_LEN
+    pub value_max_len: usize,
+}
+
+impl From<&CharacteristicConfig> for RuntimeConfig {
+    fn from(config: &CharacteristicConfig) -> Self {
+        Self {
+            description: config.description.clone(),
+            enable_notify: config.enable_notify,
+            value_max_len: config.value_max_len,
+        }
+    }
+}
+
+/// Notify-on-write policy, which subscribed peers learn of a value written by a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteEcho {
+    // Every connected peer, including the writer
+    #[default]
+    All,
+    // Peers other than the writer, which already knows the value it wrote
+    OthersOnly,
+    // Nobody, e.g. for commands whose value is not meaningful to other peers
+    None,
+}
+
+/// Answer to peers reading a characteristic which is not readable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteOnlyRead {
+    // Stack rejects the read with `ReadNotPermit`
+    #[default]
+    Deny,
+    // Read succeeds with a zero-length value, for mobile frameworks which handle
+    // read errors badly. The Read property stays unset, so peers honoring
+    // properties do not read at all
+    Empty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeCheck {
+    Off,
+    // Log a warning when a grown value may not fit, fail registration when the
+    // initial value already does not
+    #[default]
+    Warn,
+    // Fail registration in both cases
+    Deny,
+}
+
+// Value bytes fitting one ATT packet with the default MTU of 23
+const DEFAULT_MTU_PAYLOAD: usize = 20;
+// ATT notification header taken from the MTU
+const ATT_HEADER_LEN: usize = 3;
+// Extended Properties bit allowing writes of the User Description descriptor
+const WRITABLE_AUXILIARIES: u16 = 0x0002;
+
+#[cfg(feature = "compression")]
+fn compress(bytes: &[u8], threshold: usize) -> anyhow::Result<Vec<u8>> {
+    Ok(super::compression::compress(bytes, threshold))
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_bytes: &[u8], _threshold: usize) -> anyhow::Result<Vec<u8>> {
+    Err(anyhow::anyhow!(
+        "Compression requires `compression` feature"
+    ))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(frame: &[u8], max_len: usize) -> anyhow::Result<Vec<u8>> {
+    super::compression::decompress(frame, max_len)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_frame: &[u8], _max_len: usize) -> anyhow::Result<Vec<u8>> {
+    Err(anyhow::anyhow!(
+        "Compression requires `compression` feature"
+    ))
+}
+
+/// Peer write to one of the descriptors registered from `CharacteristicConfig`,
+/// see `Characteristic::set_on_descriptor_write`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorWrite {
+    // CCCD, the peer subscribed to or unsubscribed from the value
+    Subscription { notify: bool, indicate: bool },
+    // SCCD, the peer asks for the value to be broadcast in advertising
+    Broadcast { enabled: bool },
+    // Compression descriptor, the peer accepts compressed frames or stops to
+    Compression { enabled: bool },
+    // User Description, the peer renamed the characteristic
+    Description(String),
+}
+
+/// Notifications and indications a peer enabled in the CCCD of a characteristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Subscription {
+    pub notify: bool,
+    pub indicate: bool,
+}
+
+impl Subscription {
+    pub(crate) fn from_cccd(flags: u8) -> Self {
+        Self {
+            notify: flags & 0x01 != 0,
+            indicate: flags & 0x02 != 0,
+        }
+    }
+
+    pub(crate) fn cccd(&self) -> u16 {
+        u16::from(self.notify) | u16::from(self.indicate) << 1
+    }
+}
+
+impl CharacteristicConfig {
+    /// True when any of the read options is set
+    pub fn is_readable(&self) -> bool {
+        self.readable || self.read_encrypted || self.read_authenticated
+    }
+
+    pub const JSON_MIRROR_UUID: u128 = 0x6a1f0001_8d3c_4b6e_9f2a_3c5e7b9d1e0f;
+    pub const CHECKSUM_UUID: u128 = 0x6a1f0002_8d3c_4b6e_9f2a_3c5e7b9d1e0f;
+    pub const COMPRESSION_UUID: u128 = 0x6a1f0006_8d3c_4b6e_9f2a_3c5e7b9d1e0f;
+}
+
+impl Default for CharacteristicConfig {
+    fn default() -> Self {
+        Self {
+            uuid: BtUuid::uuid16(0),
+            value_max_len: 0,
+            readable: false,
+            writable: false,
+            write_without_response: false,
+            read_encrypted: false,
+            write_encrypted: false,
+            read_authenticated: false,
+            write_authenticated: false,
+            write_only_reads: WriteOnlyRead::Deny,
+            broadcasted: false,
+            enable_notify: false,
+            description: None,
+            description_writable: false,
+            stack_managed: false,
+            json_mirror: false,
+            checksum: false,
+            persistent: None,
+            write_echo: WriteEcho::All,
+            diff_notify: false,
+            size_check: SizeCheck::Warn,
+            chunked: None,
+            advertised_service_data: None,
+            compression: None,
+            write_approval: None,
+        }
+    }
 }
 
 impl Into<GattCharacteristic> for &CharacteristicConfig {
@@ -55,6 +317,35 @@ impl Into<GattCharacteristic> for &CharacteristicConfig {
             properties.insert(Property::Write);
         }
 
+        if self.write_without_response {
+            properties.insert(Property::WriteNoResponse);
+        }
+
+        if self.read_encrypted {
+            permissions.insert(Permission::ReadEncrypted);
+            properties.insert(Property::Read);
+        }
+
+        if self.write_encrypted {
+            permissions.insert(Permission::WriteEncrypted);
+            properties.insert(Property::Write);
+        }
+
+        if self.read_authenticated {
+            permissions.insert(Permission::ReadEncryptedMitm);
+            properties.insert(Property::Read);
+        }
+
+        if self.write_authenticated {
+            permissions.insert(Permission::WriteEncryptedMitm);
+            properties.insert(Property::Write);
+        }
+
+        // Stack rejects reads without permission before the application sees them
+        if !self.is_readable() && self.write_only_reads == WriteOnlyRead::Empty {
+            permissions.insert(Permission::Read);
+        }
+
         if self.broadcasted {
             properties.insert(Property::Broadcast);
         }
@@ -72,7 +363,11 @@ impl Into<GattCharacteristic> for &CharacteristicConfig {
             permissions,
             properties,
             max_len: self.value_max_len,
-            auto_rsp: AutoResponse::ByApp,
+            auto_rsp: if self.stack_managed {
+                AutoResponse::ByGatt
+            } else {
+                AutoResponse::ByApp
+            },
         }
     }
 }
@@ -85,9 +380,17 @@ impl std::hash::Hash for CharacteristicId {
     }
 }
 
-pub trait CharacteristicAttribute: Send + Sync + 'static {
-    fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()>;
-    fn get_bytes(&self) -> anyhow::Result<Vec<u8>>;
+/// Registration behavior of a characteristic on top of `AnyAttribute`, lets
+/// services hold characteristics of any value type
+pub trait CharacteristicAttribute: AnyAttribute {
+    /// Registers characteristic (with all its descriptors) again, used when
+    /// parent service is re-created
+    fn register_bluedroid(self: Arc<Self>, service: &Arc<ServiceInner>) -> anyhow::Result<()>;
+
+    fn schema(&self) -> anyhow::Result<CharacteristicSchema>;
+
+    /// Declaration, value and descriptor rows of the characteristic for `ServiceTable`
+    fn table_entries(self: Arc<Self>) -> anyhow::Result<Vec<TableEntry>>;
 }
 
 pub struct Characteristic<T: Attribute>(pub Arc<CharacteristicInner<T>>);
@@ -97,12 +400,37 @@ impl<T: Attribute> Clone for Characteristic<T> {
     }
 }
 
+pub type WriteValidator<T> = Arc<dyn Fn(&T) -> Result<(), AttError> + Send + Sync>;
+pub type ReadHandler<T> = Arc<dyn Fn() -> anyhow::Result<T> + Send + Sync>;
+pub type WriteHandler<T> = Arc<dyn Fn(ConnectionId, &T) + Send + Sync>;
+pub type DescriptorWriteHandler = Arc<dyn Fn(ConnectionId, &DescriptorWrite) + Send + Sync>;
+
 pub struct CharacteristicInner<T: Attribute> {
     pub service: RwLock<Weak<ServiceInner>>,
     pub config: CharacteristicConfig,
+    // Overrides matching fields of `config`, changed by `Characteristic::reconfigure`
+    runtime: RwLock<RuntimeConfig>,
     pub descriptors: HashMap<DescritporId, Arc<dyn DescriptorAttribute<T>>>,
 
     pub attribute: AttributeInner<T>,
+    write_validator: RwLock<Option<WriteValidator<T>>>,
+    read_handler: RwLock<Option<ReadHandler<T>>>,
+    write_handler: RwLock<Option<WriteHandler<T>>>,
+    descriptor_write_handler: RwLock<Option<DescriptorWriteHandler>>,
+    // Receives frames which would be indicated to peers, see `Characteristic::loopback`
+    pub(crate) loopback: RwLock<Option<Sender<Vec<u8>>>>,
+    // Peer whose write the write handler is running for, updates made meanwhile
+    // follow `write_echo` as the write itself does
+    handling_write: RwLock<Option<ConnectionId>>,
+    expected_write_len: RwLock<Option<usize>>,
+    // Identifier of the last value sent in chunked frames
+    chunk_transfer: RwLock<u8>,
+    // Values peers are writing in chunked frames
+    chunk_reassemblers: RwLock<HashMap<ConnectionId, ChunkReassembler>>,
+
+    // Staged peer writes waiting for a decision, see `CharacteristicConfig::write_approval`
+    pub pending_writes_rx: Receiver<PendingWrite<T>>,
+    pending_writes_tx: Sender<PendingWrite<T>>,
 }
 
 impl<T: Attribute> Characteristic<T> {
@@ -111,10 +439,23 @@ impl<T: Attribute> Characteristic<T> {
         config: CharacteristicConfig,
         descriptors: Option<Vec<Arc<dyn DescriptorAttribute<T>>>>,
     ) -> Self {
+        let (pending_writes_tx, pending_writes_rx) = bounded(MAX_PENDING_WRITES);
         let characterstic = CharacteristicInner {
             service: RwLock::new(Weak::new()),
+            runtime: RwLock::new((&config).into()),
             config,
             attribute: AttributeInner::new(value),
+            write_validator: RwLock::new(None),
+            read_handler: RwLock::new(None),
+            write_handler: RwLock::new(None),
+            descriptor_write_handler: RwLock::new(None),
+            loopback: RwLock::new(None),
+            handling_write: RwLock::new(None),
+            expected_write_len: RwLock::new(None),
+            chunk_transfer: RwLock::new(0),
+            chunk_reassemblers: RwLock::new(HashMap::new()),
+            pending_writes_rx,
+            pending_writes_tx,
             descriptors: match descriptors {
                 Some(descriptors) => descriptors
                     .into_iter()
@@ -141,24 +482,42 @@ impl<T: Attribute> Characteristic<T> {
             .write()
             .map_err(|_| anyhow::anyhow!("Failed to write Service"))? = Arc::downgrade(service);
 
+        trace::span!("gatts.register_characteristic", uuid = ?self.0.config.uuid);
+
+        self.load_persisted()?;
+        self.0.check_value_size()?;
         self.register_characteristic()?;
         self.register_in_global()?;
+        self.0.advertise_value()?;
 
+        for descriptor in self.descriptors()? {
+            descriptor.register(&self.0)?;
+        }
+
+        Ok(())
+    }
+
+    // Automatic descriptors derived from the config followed by user provided ones,
+    // a user descriptor replaces automatic one with the same UUID
+    fn descriptors(&self) -> anyhow::Result<Vec<Arc<dyn DescriptorAttribute<T>>>> {
         let mut descriptors_to_register: HashMap<DescritporId, Arc<dyn DescriptorAttribute<T>>> =
             HashMap::new();
 
+        let runtime = self.0.runtime()?;
+
         // Client Characteristic Configuration Descriptor (CCCD)
-        if self.0.config.enable_notify {
+        if runtime.enable_notify {
             let descriptor = Descriptor::<U16Attr, T>::new(
                 U16Attr(0),
                 DescriptorConfig {
-                    uuid: BtUuid::uuid16(0x2902),
+                    uuid: BtUuid::uuid16(CCCD_UUID),
                     readable: true,
                     writable: true,
+                    ..Default::default()
                 },
             );
 
-            descriptors_to_register.insert(DescritporId(descriptor.uuid()), Arc::new(descriptor));
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
         }
 
         // Server Characteristic Configuration Descriptor (SCCD)
@@ -166,40 +525,204 @@ impl<T: Attribute> Characteristic<T> {
             let descriptor = Descriptor::<U16Attr, T>::new(
                 U16Attr(0x0001),
                 DescriptorConfig {
-                    uuid: BtUuid::uuid16(0x2903),
+                    uuid: BtUuid::uuid16(SCCD_UUID),
                     readable: true,
                     writable: true,
+                    ..Default::default()
                 },
             );
 
-            descriptors_to_register.insert(DescritporId(descriptor.uuid()), Arc::new(descriptor));
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
         }
 
         // Characteristic User Description Descriptor
-        if let Some(description) = &self.0.config.description {
+        if let Some(description) = &runtime.description {
             let descriptor = Descriptor::<StringAttr, T>::new(
                 StringAttr(description.clone()),
                 DescriptorConfig {
-                    uuid: BtUuid::uuid16(0x2901),
+                    uuid: BtUuid::uuid16(USER_DESCRIPTION_UUID),
+                    readable: true,
+                    writable: self.0.config.description_writable,
+                    ..Default::default()
+                },
+            );
+
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
+        }
+
+        // Characteristic Extended Properties Descriptor, announces writable description
+        if self.0.has_extended_properties()? {
+            let descriptor = Descriptor::<U16Attr, T>::new(
+                U16Attr(WRITABLE_AUXILIARIES),
+                DescriptorConfig {
+                    uuid: BtUuid::uuid16(EXTENDED_PROPERTIES_UUID),
                     readable: true,
                     writable: false,
+                    ..Default::default()
+                },
+            );
+
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
+        }
+
+        // Characteristic Presentation Format Descriptor
+        if let Some(format) = self.0.attribute.get_value()?.presentation_format() {
+            let descriptor = Descriptor::<PresentationFormat, T>::new(
+                format,
+                DescriptorConfig {
+                    uuid: BtUuid::uuid16(PresentationFormat::UUID),
+                    readable: true,
+                    writable: false,
+                    ..Default::default()
+                },
+            );
+
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
+        }
+
+        // JSON mirror of the value, computed on every read
+        if self.0.config.json_mirror {
+            let value = self
+                .0
+                .attribute
+                .get_value()?
+                .to_json()?
+                .ok_or(anyhow::anyhow!(
+                    "JSON mirror of characteristic {:?} requires serde value and `json` feature",
+                    self.0.config.uuid
+                ))?;
+
+            let descriptor = Descriptor::<StringAttr, T>::new(
+                StringAttr(value),
+                DescriptorConfig {
+                    uuid: BtUuid::uuid128(CharacteristicConfig::JSON_MIRROR_UUID),
+                    readable: true,
+                    writable: false,
+                    ..Default::default()
+                },
+            );
+
+            let characteristic = Arc::downgrade(&self.0);
+            descriptor.set_on_read(move || {
+                let characteristic = characteristic
+                    .upgrade()
+                    .ok_or(anyhow::anyhow!("Failed to upgrade characteristic"))?;
+                let value = characteristic.attribute.get_value()?.to_json()?;
+
+                Ok(StringAttr(value.unwrap_or_default()))
+            })?;
+
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
+        }
+
+        // CRC-32 of the value, computed on every read
+        if self.0.config.checksum {
+            let value = checksum::crc32(&self.0.attribute.get_bytes()?);
+            let descriptor = Descriptor::<U32Attr, T>::new(
+                U32Attr(value),
+                DescriptorConfig {
+                    uuid: BtUuid::uuid128(CharacteristicConfig::CHECKSUM_UUID),
+                    readable: true,
+                    writable: false,
+                    ..Default::default()
+                },
+            );
+
+            let characteristic = Arc::downgrade(&self.0);
+            descriptor.set_on_read(move || {
+                let characteristic = characteristic
+                    .upgrade()
+                    .ok_or(anyhow::anyhow!("Failed to upgrade characteristic"))?;
+
+                Ok(U32Attr(checksum::crc32(
+                    &characteristic.attribute.get_bytes()?,
+                )))
+            })?;
+
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
+        }
+
+        // Compression opt-in of peers, kept per connection by `descriptor_written`
+        if self.0.config.compression.is_some() {
+            if !cfg!(feature = "compression") {
+                return Err(anyhow::anyhow!(
+                    "Compression of characteristic {:?} requires `compression` feature",
+                    self.0.config.uuid
+                ));
+            }
+
+            let descriptor = Descriptor::<U8Attr, T>::new(
+                U8Attr(0),
+                DescriptorConfig {
+                    uuid: BtUuid::uuid128(CharacteristicConfig::COMPRESSION_UUID),
+                    readable: true,
+                    writable: true,
+                    ..Default::default()
                 },
             );
 
-            descriptors_to_register.insert(DescritporId(descriptor.uuid()), Arc::new(descriptor));
+            descriptors_to_register.insert(DescritporId(descriptor.0.uuid()), descriptor.0);
         }
 
         self.0.descriptors.iter().for_each(|(_, descriptor)| {
             descriptors_to_register.insert(DescritporId(descriptor.uuid()), descriptor.clone());
         });
 
-        for descriptor in descriptors_to_register.values() {
-            descriptor.register(&self.0)?;
+        Ok(descriptors_to_register.into_values().collect())
+    }
+
+    // Counterpart of `register_bluedroid` for characteristics created with `ServiceTable`,
+    // the value is already in the stack under the given handle
+    fn bind_table(&self, service: &Arc<ServiceInner>, handle: Handle) -> anyhow::Result<()> {
+        *self
+            .0
+            .service
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write Service"))? = Arc::downgrade(service);
+
+        self.0.attribute.set_handle(handle)?;
+        self.load_persisted()?;
+        self.register_in_global()?;
+
+        // Table was created with the value from before loading the persisted one
+        if self.0.config.stack_managed && self.0.config.persistent.is_some() {
+            self.0.push_to_stack()?;
         }
 
         Ok(())
     }
 
+    fn load_persisted(&self) -> anyhow::Result<()> {
+        let Some(key) = self.0.config.persistent else {
+            return Ok(());
+        };
+        Persistence::validate_key(key)?;
+
+        let service = self.0.get_service()?;
+        let app = service.get_app()?;
+        let gatts = app.get_gatts()?;
+        let persistence = gatts.persistence.as_ref().ok_or(anyhow::anyhow!(
+            "Characteristic {:?} is persistent, but NVS is not available",
+            self.0.config.uuid
+        ))?;
+
+        let Some(bytes) = persistence.load(key)? else {
+            return Ok(());
+        };
+
+        match self.0.attribute.decode_update(&bytes) {
+            Ok(value) => self.0.attribute.replace(value),
+            Err(err) => {
+                log::warn!(
+                    "Failed to decode persisted value of {:?}, keeping initial value: {:?}",
+                    key,
+                    err
+                );
+                Ok(())
+            }
+        }
+    }
+
     fn register_in_global(&self) -> anyhow::Result<()> {
         let service = self.0.get_service()?;
         let app = service.get_app()?;
@@ -208,8 +731,7 @@ impl<T: Attribute> Characteristic<T> {
 
         if gatts
             .attributes
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatt attributes"))?
+            .write()?
             .insert(handle, self.0.clone())
             .is_some()
         {
@@ -220,7 +742,6 @@ impl<T: Attribute> Characteristic<T> {
     }
 
     fn register_characteristic(&self) -> anyhow::Result<()> {
-        let (tx, rx) = bounded(1);
         let callback_key = discriminant(&GattsEvent::CharacteristicAdded {
             status: GattStatus::Busy,
             attr_handle: 0,
@@ -234,15 +755,21 @@ impl<T: Attribute> Characteristic<T> {
         let gatts_interface = app.interface()?;
         let service_handle = service.get_handle()?;
 
-        gatts
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events"))?
-            .insert(callback_key, tx);
+        let rx = gatts.waiter(callback_key)?;
+
+        let initial_value = if self.0.config.stack_managed {
+            self.0.attribute.get_bytes()?
+        } else {
+            Vec::new()
+        };
 
         gatts
             .gatts
-            .add_characteristic(service_handle, &(&self.0.config).into(), &[])
+            .add_characteristic(
+                service_handle,
+                &self.0.gatt_characteristic()?,
+                &initial_value,
+            )
             .map_err(|err| {
                 anyhow::anyhow!(
                     "Failed to register GATT characteristic {:?}: {:?}",
@@ -260,11 +787,13 @@ impl<T: Attribute> Characteristic<T> {
                     service_handle,
                     char_uuid,
                 },
+                _,
             )) => {
                 if interface != gatts_interface {
                     return Err(anyhow::anyhow!(
-                        "Received unexpected GATT interface: {:?}",
-                        interface
+                        "Received characteristic of {} while registering on {}",
+                        AppInterface::unknown(interface),
+                        app.app_interface()?
                     ));
                 }
 
@@ -302,8 +831,217 @@ impl<T: Attribute> Characteristic<T> {
         self.0.attribute.get_value()
     }
 
+    /// Sets callback which is invoked with the decoded value of every peer write
+    /// before it is applied, returned error is sent back to the peer instead
+    pub fn set_write_validator(
+        &self,
+        validator: impl Fn(&T) -> Result<(), AttError> + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        *self
+            .0
+            .write_validator
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write characteristic write validator"))? =
+            Some(Arc::new(validator));
+
+        Ok(())
+    }
+
+    /// Sets callback which computes the value when a peer reads the characteristic,
+    /// e.g. ADC readings or uptime. Computed value is stored without notifying peers,
+    /// so continued long reads see the same value
+    pub fn set_on_read(
+        &self,
+        handler: impl Fn() -> anyhow::Result<T> + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        if self.0.config.stack_managed {
+            return Err(anyhow::anyhow!(
+                "Read callback is not supported for stack managed characteristic {:?}",
+                self.0.config.uuid
+            ));
+        }
+
+        *self
+            .0
+            .read_handler
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write characteristic read handler"))? =
+            Some(Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Sets callback which is invoked with the writing connection and the new value
+    /// after a peer write was applied, updates from the application are not reported
+    pub fn set_on_write(
+        &self,
+        handler: impl Fn(ConnectionId, &T) + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        *self
+            .0
+            .write_handler
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write characteristic write handler"))? =
+            Some(Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Sets the size of the value the next long writes are expected to carry, e.g.
+    /// announced by the peer before a firmware upload. Reported as the expected
+    /// total in `WriteProgress`, None when unknown
+    pub fn set_expected_write_len(&self, len: Option<usize>) -> anyhow::Result<()> {
+        *self
+            .0
+            .expected_write_len
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write expected write length"))? = len;
+
+        Ok(())
+    }
+
+    /// Sets callback which is invoked after a peer wrote the CCCD, SCCD or User
+    /// Description descriptor, e.g. to start broadcasting the value or store the
+    /// new name. A renamed description is kept when the service is rebuilt
+    pub fn set_on_descriptor_write(
+        &self,
+        handler: impl Fn(ConnectionId, &DescriptorWrite) + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        *self.0.descriptor_write_handler.write().map_err(|_| {
+            anyhow::anyhow!("Failed to write characteristic descriptor write handler")
+        })? = Some(Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Subscription of a connected peer, as last written to the CCCD by the peer
+    /// or set with `set_subscription`
+    pub fn subscription(&self, conn_id: ConnectionId) -> anyhow::Result<Subscription> {
+        let gatts = self.0.get_service()?.get_app()?.get_gatts()?;
+        gatts.subscription(conn_id, self.0.attribute.handle()?)
+    }
+
+    /// Sets the subscription of a connected peer as if it wrote the CCCD, e.g. to
+    /// re-subscribe a bonded peer to alerts on reconnect where the profile lets the
+    /// server restore subscriptions. Enabling sends the full value first with diff
+    /// notifications, the descriptor write handler is not invoked
+    pub fn set_subscription(
+        &self,
+        conn_id: ConnectionId,
+        subscription: Subscription,
+    ) -> anyhow::Result<()> {
+        if !self.0.runtime()?.enable_notify {
+            return Err(anyhow::anyhow!(
+                "Characteristic {:?} does not support notifications",
+                self.0.config.uuid
+            ));
+        }
+
+        let app = self.0.get_service()?.get_app()?;
+        if !app.connections.read()?.contains_key(&conn_id) {
+            return Err(anyhow::anyhow!(
+                "No found connection with given connection id: {:?}",
+                conn_id
+            ));
+        }
+
+        app.get_gatts()?
+            .set_subscription(conn_id, self.0.attribute.handle()?, subscription)?;
+
+        if subscription != Subscription::default() {
+            self.0.resync(conn_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Changes description, notify enablement and value_max_len at runtime, e.g.
+    /// behind feature flags. Bluedroid can not modify registered attributes, so
+    /// the service is rebuilt as by `Service::rebuild`: handles of its attributes
+    /// change and connected peers receive a Service Changed indication. Enabling
+    /// notify or a description adds a descriptor, which needs a spare handle in
+    /// the service
+    pub fn reconfigure(&self, runtime: RuntimeConfig) -> anyhow::Result<()> {
+        if runtime.value_max_len > ESP_GATT_MAX_ATTR_LEN as usize {
+            return Err(anyhow::anyhow!(
+                "value_max_len of {} bytes is above the stack limit of {} bytes",
+                runtime.value_max_len,
+                ESP_GATT_MAX_ATTR_LEN
+            ));
+        }
+
+        let previous = self.0.set_runtime(runtime.clone())?;
+        if previous == runtime {
+            return Ok(());
+        }
+
+        if let Err(err) = self.0.check_value_size() {
+            self.0.set_runtime(previous)?;
+            return Err(err);
+        }
+
+        let service = self
+            .0
+            .service
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read Service"))?
+            .upgrade();
+
+        // Not registered yet, the new config applies at registration
+        match service {
+            Some(service) => Service(service).rebuild(),
+            None => Ok(()),
+        }
+    }
+
+    /// Switches the characteristic to in-memory loopback, so attribute logic like
+    /// validators, handlers and diff notifications can be tested without
+    /// Bluetooth. Frames which would be indicated to peers are sent to the returned
+    /// `Loopback` instead. Meant for characteristics which are never registered
+    pub fn loopback(&self) -> anyhow::Result<Loopback<T>> {
+        let (frames_tx, frames_rx) = unbounded();
+
+        // Loopback subscribes right away, so it starts from the full value
+        if self.0.config.diff_notify {
+            let full = diff::full_frame(&self.0.attribute.get_value()?.fields()?)?;
+            for frame in self.0.frames(&full, DEFAULT_MTU_PAYLOAD)? {
+                frames_tx
+                    .send(frame)
+                    .map_err(|_| anyhow::anyhow!("Failed to send loopback frame"))?;
+            }
+        }
+
+        *self
+            .0
+            .loopback
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write characteristic loopback"))? =
+            Some(frames_tx);
+
+        Ok(Loopback::new(self.clone(), frames_rx))
+    }
+
     pub fn update_value(&self, value: T) -> anyhow::Result<()> {
-        AnyAttribute::update_from_bytes(&*self.0, &value.get_bytes()?)
+        self.0.update_from_bytes(&value.get_bytes()?)
+    }
+}
+
+impl<T, C> Characteristic<Coded<T, C>>
+where
+    T: Send + Sync + 'static,
+    C: Encode<T> + Decode<T> + Default,
+{
+    /// Characteristic whose value is encoded with `codec` instead of the
+    /// `Attribute` implementation of its type, e.g. a serde struct exposed with
+    /// `BincodeCodec` on one characteristic and another codec on the next
+    pub fn new_with_codec(value: T, codec: C, config: CharacteristicConfig) -> Self {
+        Self::new(Coded::new(value, codec), config, None)
+    }
+
+    /// Same as `update_value`, the value is encoded with the codec of the
+    /// characteristic
+    pub fn update_coded(&self, value: T) -> anyhow::Result<()> {
+        self.update_value(self.value()?.with_value(value))
     }
 }
 
@@ -319,23 +1057,372 @@ impl<T: Attribute> CharacteristicInner<T> {
     pub fn handle(&self) -> anyhow::Result<Handle> {
         self.attribute.handle()
     }
-}
 
-impl<T: Attribute> CharacteristicAttribute for CharacteristicInner<T> {
-    fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()> {
-        self.attribute.update(Arc::new(T::from_bytes(bytes)?))
+    pub fn runtime(&self) -> anyhow::Result<RuntimeConfig> {
+        Ok(self
+            .runtime
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read characteristic runtime config"))?
+            .clone())
     }
 
-    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
-        self.attribute.get_bytes()
+    // Returns the replaced config
+    fn set_runtime(&self, runtime: RuntimeConfig) -> anyhow::Result<RuntimeConfig> {
+        let mut current = self
+            .runtime
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write characteristic runtime config"))?;
+
+        Ok(std::mem::replace(&mut *current, runtime))
     }
-}
 
-impl<T: Attribute> AnyAttribute for CharacteristicInner<T> {
-    fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()> {
-        self.attribute.update(Arc::new(T::from_bytes(bytes)?))?;
+    // Stack definition of the characteristic, with runtime config applied
+    fn gatt_characteristic(&self) -> anyhow::Result<GattCharacteristic> {
+        let runtime = self.runtime()?;
+        let mut gatt_characteristic: GattCharacteristic = (&self.config).into();
+
+        gatt_characteristic.max_len = runtime.value_max_len;
+        if runtime.enable_notify {
+            gatt_characteristic.properties |= Property::Notify | Property::Indicate;
+        } else {
+            gatt_characteristic.properties -= Property::Notify | Property::Indicate;
+        }
+
+        if self.has_extended_properties()? {
+            gatt_characteristic.properties.insert(Property::ExtProps);
+        }
+
+        Ok(gatt_characteristic)
+    }
+
+    // Compares worst-case encoded size of the value against `value_max_len`, so a
+    // struct grown past the declared size is caught before writes start failing
+    fn check_value_size(&self) -> anyhow::Result<()> {
+        let value_max_len = self.runtime()?.value_max_len;
+        if self.config.size_check == SizeCheck::Off
+            || value_max_len == 0
+            || self.config.chunked.is_some()
+        {
+            return Ok(());
+        }
+
+        let value = self.attribute.get_value()?;
+        let encoded_len = value.get_bytes()?.len();
+        let max_encoded_len = value.max_encoded_len()?.max(encoded_len);
+
+        if max_encoded_len > DEFAULT_MTU_PAYLOAD {
+            log::debug!(
+                "Value of characteristic {:?} may take {} bytes, peers keeping the default MTU need long reads and writes",
+                self.config.uuid,
+                max_encoded_len
+            );
+        }
+
+        if max_encoded_len <= value_max_len {
+            return Ok(());
+        }
+
+        let err = ValueSizeError {
+            uuid: self.config.uuid.clone(),
+            value_max_len,
+            encoded_len,
+            max_encoded_len,
+        };
+
+        if err.initial_fits() && self.config.size_check == SizeCheck::Warn {
+            log::warn!("{}", err);
+            return Ok(());
+        }
+
+        Err(err.into())
+    }
+
+    // Applies a new value and indicates it to subscribed peers, `writer` is the
+    // connection which wrote the value, None for updates from the application
+    fn apply_update(&self, bytes: &[u8], writer: Option<ConnectionId>) -> anyhow::Result<()> {
+        let old_fields = if self.config.diff_notify {
+            Some(self.attribute.get_value()?.fields()?)
+        } else {
+            None
+        };
+
+        self.attribute
+            .update(self.attribute.decode_update(bytes)?)?;
+
+        let loopback = self
+            .loopback
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read characteristic loopback"))?
+            .clone();
+
+        if let Some(loopback) = loopback {
+            // Loopback stands for another subscribed peer, not the writer
+            if writer.is_some() && self.config.write_echo == WriteEcho::None {
+                return Ok(());
+            }
+
+            let notify_data = self.notify_data(old_fields)?;
+            for frame in self.frames(&notify_data, DEFAULT_MTU_PAYLOAD)? {
+                loopback
+                    .send(frame)
+                    .map_err(|_| anyhow::anyhow!("Failed to send loopback frame"))?;
+            }
+
+            return Ok(());
+        }
+
+        if self.config.stack_managed {
+            self.push_to_stack()?;
+        }
+
+        self.persist()?;
+        self.advertise_value()?;
+
+        let app = self.get_service()?.get_app()?;
+        let gatts = app.get_gatts()?;
+        let handle = self.attribute.handle()?;
+
+        // Snapshot, so connection events are not blocked while waiting for confirms
+        let connections = app
+            .connections
+            .read()?
+            .values()
+            .filter(|connection| match (self.config.write_echo, writer) {
+                (_, None) | (WriteEcho::All, _) => true,
+                (WriteEcho::OthersOnly, Some(writer)) => connection.id() != writer,
+                (WriteEcho::None, Some(_)) => false,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Only peers which enabled notifications or indications in their CCCD
+        let mut subscribed = Vec::with_capacity(connections.len());
+        for connection in connections {
+            if gatts.subscription(connection.id(), handle)? != Subscription::default() {
+                subscribed.push(connection);
+            }
+        }
+
+        let notify_data = self.notify_data(old_fields)?;
+        self.indicate(&subscribed, &notify_data)
+    }
+
+    // Mirrors the value into advertising Service Data, see
+    // `CharacteristicConfig::advertised_service_data`
+    fn advertise_value(&self) -> anyhow::Result<()> {
+        let Some(uuid) = self.config.advertised_service_data else {
+            return Ok(());
+        };
+
+        let gap = self.get_service()?.get_app()?.get_gatts()?.get_gap()?;
+        let service_data = [&uuid.to_le_bytes()[..], &self.attribute.get_bytes()?].concat();
+
+        Gap(gap).set_service_data(Some(service_data))
+    }
+
+    fn set_handling_write(&self, writer: Option<ConnectionId>) -> anyhow::Result<()> {
+        *self
+            .handling_write
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write characteristic write state"))? = writer;
+
+        Ok(())
+    }
+
+    // Indicated bytes of the current value, a diff frame against `old_fields`
+    // with diff notifications
+    fn notify_data(&self, old_fields: Option<Vec<Vec<u8>>>) -> anyhow::Result<Vec<u8>> {
+        match old_fields {
+            Some(old_fields) => {
+                let new_fields = self.attribute.get_value()?.fields()?;
+                match diff::patch_frame(&old_fields, &new_fields)? {
+                    Some(patch) => Ok(patch),
+                    None => diff::full_frame(&new_fields),
+                }
+            }
+            None => self.attribute.get_bytes(),
+        }
+    }
+
+    fn has_extended_properties(&self) -> anyhow::Result<bool> {
+        Ok(self.config.description_writable && self.runtime()?.description.is_some())
+    }
+
+    /// Reacts to a peer write of an automatic descriptor, `bytes` were already
+    /// applied to the descriptor
+    pub(crate) fn descriptor_written(
+        &self,
+        uuid: &BtUuid,
+        bytes: &[u8],
+        writer: ConnectionId,
+    ) -> anyhow::Result<()> {
+        let flags = bytes.first().copied().unwrap_or(0);
+
+        let event = if *uuid == BtUuid::uuid16(CCCD_UUID) {
+            let subscription = Subscription::from_cccd(flags);
+            self.get_service()?
+                .get_app()?
+                .get_gatts()?
+                .set_subscription(writer, self.attribute.handle()?, subscription)?;
+
+            // Peer enabling notifications or indications of a characteristic with
+            // diff notifications needs the full value before any patch
+            if subscription != Subscription::default() {
+                self.resync(writer)?;
+            }
+
+            DescriptorWrite::Subscription {
+                notify: subscription.notify,
+                indicate: subscription.indicate,
+            }
+        } else if *uuid == BtUuid::uuid16(SCCD_UUID) {
+            DescriptorWrite::Broadcast {
+                enabled: flags & 0x01 != 0,
+            }
+        } else if *uuid == BtUuid::uuid128(CharacteristicConfig::COMPRESSION_UUID) {
+            let enabled = flags & 0x01 != 0;
+            self.get_service()?
+                .get_app()?
+                .get_gatts()?
+                .set_compression(writer, self.attribute.handle()?, enabled)?;
+
+            DescriptorWrite::Compression { enabled }
+        } else if *uuid == BtUuid::uuid16(USER_DESCRIPTION_UUID) {
+            let description = String::from_utf8(bytes.to_vec())
+                .map_err(|err| anyhow::anyhow!("Invalid UTF-8 description: {:?}", err))?;
+
+            self.runtime
+                .write()
+                .map_err(|_| anyhow::anyhow!("Failed to write characteristic runtime config"))?
+                .description = Some(description.clone());
+
+            DescriptorWrite::Description(description)
+        } else {
+            return Ok(());
+        };
+
+        let handler = self
+            .descriptor_write_handler
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read characteristic descriptor write handler"))?
+            .clone();
+
+        if let Some(handler) = handler {
+            guard::run_hook("descriptor write", move || handler(writer, &event))?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the full value to a peer which just subscribed, so later patches of
+    /// diff notifications apply to a known value
+    pub(crate) fn resync(&self, conn_id: ConnectionId) -> anyhow::Result<()> {
+        if !self.config.diff_notify {
+            return Ok(());
+        }
+
+        let Some(connection) = self
+            .get_service()?
+            .get_app()?
+            .connections
+            .read()?
+            .get(&conn_id)
+            .cloned()
+        else {
+            return Ok(());
+        };
+
+        let full = diff::full_frame(&self.attribute.get_value()?.fields()?)?;
+        self.indicate(&[connection], &full)
+    }
+
+    // Frames carrying the data, `gatts::chunked` frames of at most `frame_len`
+    // bytes for chunked characteristics, the data as is otherwise
+    fn frames(&self, data: &[u8], frame_len: usize) -> anyhow::Result<Vec<Vec<u8>>> {
+        if self.config.chunked.is_none() {
+            return Ok(vec![data.to_vec()]);
+        }
+
+        let mut transfer = self
+            .chunk_transfer
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write characteristic chunk transfer"))?;
+        *transfer = transfer.wrapping_add(1);
+
+        chunked::fragments(data, *transfer, frame_len)
+            .map_err(|err| anyhow::anyhow!("Failed to split value into chunks: {:?}", err))
+    }
+
+    // Adds a chunked frame written by the peer, see `CharacteristicConfig::chunked`
+    fn reassemble_chunk(
+        &self,
+        bytes: Vec<u8>,
+        writer: ConnectionId,
+        max_len: usize,
+    ) -> Result<Option<Vec<u8>>, AttError> {
+        let mut reassemblers = self
+            .chunk_reassemblers
+            .write()
+            .map_err(|_| AttError::Status(GattStatus::Error))?;
+
+        let result = reassemblers
+            .entry(writer)
+            .or_insert_with(|| ChunkReassembler::new(max_len))
+            .push(&bytes);
+
+        if let Err(err) = result {
+            log::warn!(
+                "Chunked write of {:?} to characteristic {:?} failed: {:?}",
+                writer,
+                self.config.uuid,
+                err
+            );
+        }
+
+        Ok(result?)
+    }
+
+    // Whether the peer opted in to compression, see `CharacteristicConfig::compression`.
+    // Loopback never does
+    fn peer_compresses(&self, conn_id: ConnectionId) -> anyhow::Result<bool> {
+        if self.config.compression.is_none() || conn_id == LOOPBACK_CONN_ID {
+            return Ok(false);
+        }
+
+        self.get_service()?
+            .get_app()?
+            .get_gatts()?
+            .compresses(conn_id, self.attribute.handle()?)
+    }
+
+    fn indicate(&self, connections: &[Connection], notify_data: &[u8]) -> anyhow::Result<()> {
+        if self.config.chunked.is_none() && self.config.compression.is_none() {
+            return self.indicate_frame(connections, notify_data);
+        }
+
+        // Each peer receives data in its own format, in frames fitting its own MTU
+        for connection in connections {
+            let data = match self.config.compression {
+                Some(threshold) if self.peer_compresses(connection.id())? => {
+                    compress(notify_data, threshold)?
+                }
+                _ => notify_data.to_vec(),
+            };
+
+            let frame_len = match connection.mtu()? {
+                Some(mtu) => usize::from(mtu).saturating_sub(ATT_HEADER_LEN),
+                None => DEFAULT_MTU_PAYLOAD,
+            };
+
+            for frame in self.frames(&data, frame_len)? {
+                self.indicate_frame(std::slice::from_ref(connection), &frame)?;
+            }
+        }
 
-        let (tx, rx) = bounded(1);
+        Ok(())
+    }
+
+    fn indicate_frame(&self, connections: &[Connection], notify_data: &[u8]) -> anyhow::Result<()> {
         let callback_key = discriminant(&GattsEvent::Confirm {
             status: GattStatus::Busy,
             conn_id: 0,
@@ -349,50 +1436,70 @@ impl<T: Attribute> AnyAttribute for CharacteristicInner<T> {
         let gatts_interface = app.interface()?;
         let characteristic_handle = self.attribute.handle()?;
 
-        let connections = app
-            .connections
-            .read()
-            .map_err(|_| anyhow::anyhow!("Failed to read connections in App: {:?}", app.id))?;
-        let notify_data = self.attribute.get_bytes()?;
-
-        gatts
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events in App: {:?}", app.id))?
-            .insert(callback_key, tx);
+        let rx = gatts.waiter(callback_key)?;
 
         let send_results = connections
-            .values()
+            .iter()
             .map(|connection| {
-                let mtu = connection.mtu.ok_or(anyhow::anyhow!(
+                trace::span!(
+                    "gatts.indicate",
+                    conn_id = connection.id(),
+                    handle = characteristic_handle,
+                    len = notify_data.len()
+                );
+
+                // Peer may have unsubscribed since the connections were collected
+                let subscription = gatts.subscription(connection.id(), characteristic_handle)?;
+                if subscription == Subscription::default() {
+                    return Ok(());
+                }
+
+                let mtu = connection.mtu()?.ok_or(anyhow::anyhow!(
                     "Failed to read MTU for connection: {:?}",
-                    connection.id
+                    connection.id()
                 ))?;
-                let data_end_index = notify_data.len().min(mtu.into());
-
-                if data_end_index != notify_data.len() {
-                    log::warn!(
-                        "Data is too long to be sent, MTU is too small, cutting data: {:?}",
-                        mtu
-                    );
-                    // return Err(anyhow::anyhow!(
-                    //     "Data is too long to be sent, MTU is too small: {:?}",
-                    //     mtu
-                    // ));
+                let max_len = usize::from(mtu).saturating_sub(ATT_HEADER_LEN);
+
+                if notify_data.len() > max_len {
+                    return Err(anyhow::anyhow!(
+                        "Data of {} bytes does not fit MTU {:?} of {:?}, use chunked notifications",
+                        notify_data.len(),
+                        mtu,
+                        connection.id()
+                    ));
+                }
+
+                // Indications are preferred by peers which enabled both
+                if !subscription.indicate {
+                    return gatts
+                        .gatts
+                        .notify(
+                            gatts_interface,
+                            connection.id(),
+                            characteristic_handle,
+                            notify_data,
+                        )
+                        .map_err(|err| {
+                            anyhow::anyhow!(
+                                "Failed to send GATT notification to {:?}: {:?}",
+                                connection.peer_addr(),
+                                err
+                            )
+                        });
                 }
 
                 gatts
                     .gatts
                     .indicate(
                         gatts_interface,
-                        connection.id,
+                        connection.id(),
                         characteristic_handle,
-                        &notify_data[..data_end_index],
+                        notify_data,
                     )
                     .map_err(|err| {
                         anyhow::anyhow!(
                             "Failed to send GATT indication to {:?}: {:?}",
-                            connection.address,
+                            connection.peer_addr(),
                             err
                         )
                     })?;
@@ -406,8 +1513,9 @@ impl<T: Attribute> AnyAttribute for CharacteristicInner<T> {
                             handle,
                             ..
                         },
+                        _,
                     )) => {
-                        if conn_id != connection.id {
+                        if conn_id != connection.id() {
                             return Err(anyhow::anyhow!(
                                 "Received unexpected GATT confirm: {:?}",
                                 conn_id
@@ -451,7 +1559,263 @@ impl<T: Attribute> AnyAttribute for CharacteristicInner<T> {
         Ok(())
     }
 
+    fn persist(&self) -> anyhow::Result<()> {
+        let Some(key) = self.config.persistent else {
+            return Ok(());
+        };
+
+        let service = self.get_service()?;
+        let app = service.get_app()?;
+        let gatts = app.get_gatts()?;
+
+        match gatts.persistence.as_ref() {
+            Some(persistence) => persistence.save(key, self.attribute.get_bytes()?),
+            None => Err(anyhow::anyhow!("NVS is not available to persist {:?}", key)),
+        }
+    }
+
+    fn push_to_stack(&self) -> anyhow::Result<()> {
+        let service = self.get_service()?;
+        let app = service.get_app()?;
+        let gatts = app.get_gatts()?;
+        let handle = self.handle()?;
+
+        gatts
+            .gatts
+            .set_attr(handle, &self.attribute.get_bytes()?)
+            .map_err(|err| {
+                anyhow::anyhow!(
+                    "Failed to push value of GATT characteristic {:?} to stack: {:?}",
+                    self.config.uuid,
+                    err
+                )
+            })
+    }
+}
+
+impl<T: Attribute> CharacteristicAttribute for CharacteristicInner<T> {
+    fn register_bluedroid(self: Arc<Self>, service: &Arc<ServiceInner>) -> anyhow::Result<()> {
+        Characteristic(self).register_bluedroid(service)
+    }
+
+    fn table_entries(self: Arc<Self>) -> anyhow::Result<Vec<TableEntry>> {
+        self.check_value_size()?;
+
+        let gatt_characteristic = self.gatt_characteristic()?;
+        let value = self.attribute.get_bytes()?;
+        let characteristic = Characteristic(self.clone());
+
+        let mut entries = vec![
+            TableEntry::declaration(
+                TableEntry::CHARACTERISTIC_UUID,
+                vec![gatt_characteristic.properties.as_repr()],
+            ),
+            TableEntry {
+                uuid: self.config.uuid.clone(),
+                permissions: gatt_characteristic.permissions,
+                max_len: gatt_characteristic.max_len.max(value.len()) as u16,
+                value,
+                auto_response: self.config.stack_managed,
+                bind: Some(Box::new(move |service, handle| {
+                    characteristic.bind_table(service, handle)
+                })),
+            },
+        ];
+
+        for descriptor in Characteristic(self.clone()).descriptors()? {
+            entries.push(descriptor.table_entry(&self)?);
+        }
+
+        Ok(entries)
+    }
+
+    fn schema(&self) -> anyhow::Result<CharacteristicSchema> {
+        let (format, value) = self.attribute.get_value()?.value_schema();
+        let runtime = self.runtime()?;
+
+        Ok(CharacteristicSchema {
+            uuid: uuid_string(&self.config.uuid),
+            name: runtime.description,
+            readable: self.config.is_readable(),
+            writable: self.config.writable
+                || self.config.write_encrypted
+                || self.config.write_authenticated,
+            notify: runtime.enable_notify,
+            format,
+            value,
+        })
+    }
+}
+
+impl<T: Attribute> AnyAttribute for CharacteristicInner<T> {
+    fn uuid(&self) -> BtUuid {
+        self.config.uuid.clone()
+    }
+
+    fn handle(&self) -> anyhow::Result<Handle> {
+        self.attribute.handle()
+    }
+
+    fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        let writer = *self
+            .handling_write
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read characteristic write state"))?;
+
+        self.apply_update(bytes, writer)
+    }
+
+    fn write_from_peer(&self, bytes: &[u8], writer: ConnectionId) -> anyhow::Result<()> {
+        self.apply_update(bytes, Some(writer))?;
+
+        let handler = self
+            .write_handler
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read characteristic write handler"))?
+            .clone();
+
+        if let Some(handler) = handler {
+            let value = self.attribute.get_value()?;
+            self.set_handling_write(Some(writer))?;
+            let result = guard::run_hook("characteristic write", move || handler(writer, &value));
+            self.set_handling_write(None)?;
+            result?;
+        }
+
+        Ok(())
+    }
+
     fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
         self.attribute.get_bytes()
     }
+
+    fn peer_disconnected(&self, conn_id: ConnectionId) -> anyhow::Result<()> {
+        self.chunk_reassemblers
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write chunk reassemblers"))?
+            .remove(&conn_id);
+
+        Ok(())
+    }
+
+    fn peer_resubscribed(&self, conn_id: ConnectionId) -> anyhow::Result<()> {
+        self.resync(conn_id)
+    }
+
+    fn write_approval(&self) -> Option<Duration> {
+        self.config.write_approval
+    }
+
+    fn stage_write(
+        self: Arc<Self>,
+        bytes: Vec<u8>,
+        writer: ConnectionId,
+        response: HeldResponse,
+    ) -> anyhow::Result<()> {
+        let value = self.attribute.decode_write(&bytes)?;
+        let characteristic = self.clone();
+        let apply = Box::new(move || {
+            characteristic
+                .write_from_peer(&bytes, writer)
+                .map_err(|err| att_error(&err))
+        });
+
+        // Nobody receiving the staged writes, the caller answers with Busy
+        self.pending_writes_tx
+            .try_send(PendingWrite::new(writer, value, response, apply))
+            .map_err(|err| {
+                err.into_inner().cancel();
+                anyhow::Error::from(AttError::Status(GattStatus::Busy))
+                    .context("Too many pending writes, is `pending_writes_rx` received?")
+            })
+    }
+
+    fn read_bytes(&self, offset: u16) -> anyhow::Result<Vec<u8>> {
+        if !self.config.is_readable() {
+            return match self.config.write_only_reads {
+                WriteOnlyRead::Empty => Ok(Vec::new()),
+                WriteOnlyRead::Deny => Err(AttError::Status(GattStatus::ReadNotPermit).into()),
+            };
+        }
+
+        if offset == 0 {
+            let handler = self
+                .read_handler
+                .read()
+                .map_err(|_| anyhow::anyhow!("Failed to read characteristic read handler"))?
+                .clone();
+
+            if let Some(handler) = handler {
+                let value = guard::run_hook("characteristic read", move || handler())??;
+                self.attribute.replace(Arc::new(value))?;
+            }
+        }
+
+        self.attribute.get_bytes()
+    }
+
+    fn reassemble_write(
+        &self,
+        bytes: Vec<u8>,
+        writer: ConnectionId,
+    ) -> Result<Option<Vec<u8>>, AttError> {
+        let bytes = match self.config.chunked {
+            Some(max_len) => match self.reassemble_chunk(bytes, writer, max_len)? {
+                Some(bytes) => bytes,
+                None => return Ok(None),
+            },
+            None => bytes,
+        };
+
+        if !self
+            .peer_compresses(writer)
+            .map_err(|_| AttError::Status(GattStatus::Error))?
+        {
+            return Ok(Some(bytes));
+        }
+
+        let max_len = self
+            .config
+            .chunked
+            .unwrap_or(ESP_GATT_MAX_ATTR_LEN as usize);
+        match decompress(&bytes, max_len) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) => {
+                log::warn!(
+                    "Compressed write of {:?} to characteristic {:?} failed: {:?}",
+                    writer,
+                    self.config.uuid,
+                    err
+                );
+
+                Err(AttError::Status(GattStatus::InvalidPdu))
+            }
+        }
+    }
+
+    fn validate_write(&self, bytes: &[u8]) -> Result<(), AttError> {
+        let value = self.attribute.decode_write(bytes)?;
+        let validator = self
+            .write_validator
+            .read()
+            .map_err(|_| AttError::Status(GattStatus::Error))?
+            .clone();
+
+        match validator {
+            Some(validator) => guard::run_hook("write validator", move || validator(&value))
+                .unwrap_or(Err(AttError::Status(GattStatus::Error))),
+            None => Ok(()),
+        }
+    }
+
+    fn round_trip(&self) -> anyhow::Result<()> {
+        self.attribute.round_trip()
+    }
+
+    fn expected_write_len(&self) -> anyhow::Result<Option<usize>> {
+        Ok(*self
+            .expected_write_len
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read expected write length"))?)
+    }
 }
@@ -0,0 +1,15 @@
+//! CRC-32 of characteristic values, exposed by the checksum descriptor of
+//! `CharacteristicConfig::checksum`. Same parameters as zlib and Ethernet
+//! (reflected polynomial 0xEDB88320, initial value and final XOR 0xFFFFFFFF),
+//! so clients can verify it with any standard implementation.
+
+const POLYNOMIAL: u32 = 0xEDB88320;
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ POLYNOMIAL,
+            _ => crc >> 1,
+        })
+    })
+}
@@ -0,0 +1,296 @@
+//! Transfer framing of characteristics with `CharacteristicConfig::chunked`, for
+//! values larger than the MTU or `ESP_GATT_MAX_ATTR_LEN`. A value is sent as a
+//! start frame followed by continuation frames, each one notification or write:
+//!
+//! ```text
+//! start:        0x00, transfer: u8, total_len: u32 LE, crc32: u32 LE, bytes
+//! continuation: 0x01, transfer: u8, index: u16 LE, bytes
+//! ```
+//!
+//! `transfer` is the same for all frames of a value and changes with every value,
+//! `index` counts continuation frames from 1. The value is complete once
+//! `total_len` bytes arrived and their CRC-32 (see `gatts::checksum`) matches.
+//! A start frame abandons the transfer in progress, a frame out of order fails
+//! it, so the receiver waits for the next start frame.
+//!
+//! Reference decoder, also implemented by `esp-bluedroid-client`:
+//!
+//! 1. On a start frame remember transfer, total_len and crc32, keep its bytes
+//! 2. On a continuation frame of the same transfer with the next index append
+//!    its bytes, any other continuation frame drops the transfer
+//! 3. Once total_len bytes are kept, compare their CRC-32 and pass the value on
+//!
+//! Kept free of esp-idf dependencies, like `reassembly`.
+
+use super::checksum::crc32;
+
+/// Tag of the first frame of a value
+pub const START: u8 = 0x00;
+/// Tag of the following frames of a value
+pub const CONTINUATION: u8 = 0x01;
+
+pub const START_HEADER_LEN: usize = 10;
+pub const CONTINUATION_HEADER_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkError {
+    // Frame is shorter than its header or has an unknown tag
+    Malformed,
+    // Continuation frame of another transfer or out of order
+    UnexpectedFrame,
+    // Value exceeds the length the receiver accepts, or its frame count does
+    // not fit the index
+    TooLarge,
+    // Frame length leaves no room for value bytes
+    FrameTooShort,
+    // Reassembled value does not match its CRC-32
+    Checksum,
+}
+
+/// Splits a value into frames of at most `frame_len` bytes
+pub fn fragments(value: &[u8], transfer: u8, frame_len: usize) -> Result<Vec<Vec<u8>>, ChunkError> {
+    if frame_len <= START_HEADER_LEN {
+        return Err(ChunkError::FrameTooShort);
+    }
+
+    let total_len = u32::try_from(value.len()).map_err(|_| ChunkError::TooLarge)?;
+    let (first, rest) = value.split_at(value.len().min(frame_len - START_HEADER_LEN));
+
+    let mut start = Vec::with_capacity(START_HEADER_LEN + first.len());
+    start.extend_from_slice(&[START, transfer]);
+    start.extend_from_slice(&total_len.to_le_bytes());
+    start.extend_from_slice(&crc32(value).to_le_bytes());
+    start.extend_from_slice(first);
+
+    let mut frames = vec![start];
+    for (index, chunk) in rest.chunks(frame_len - CONTINUATION_HEADER_LEN).enumerate() {
+        let index = u16::try_from(index + 1).map_err(|_| ChunkError::TooLarge)?;
+
+        let mut frame = Vec::with_capacity(CONTINUATION_HEADER_LEN + chunk.len());
+        frame.extend_from_slice(&[CONTINUATION, transfer]);
+        frame.extend_from_slice(&index.to_le_bytes());
+        frame.extend_from_slice(chunk);
+        frames.push(frame);
+    }
+
+    Ok(frames)
+}
+
+#[derive(Debug)]
+struct Transfer {
+    id: u8,
+    total_len: usize,
+    crc: u32,
+    next_index: u16,
+    value: Vec<u8>,
+}
+
+/// Reassembles values from received frames
+#[derive(Debug)]
+pub struct ChunkReassembler {
+    max_len: usize,
+    transfer: Option<Transfer>,
+}
+
+impl ChunkReassembler {
+    /// Values longer than `max_len` are refused at their start frame
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            transfer: None,
+        }
+    }
+
+    /// Adds a received frame, returns the value once its last frame arrived
+    pub fn push(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>, ChunkError> {
+        match frame {
+            [START, id, ..] if frame.len() >= START_HEADER_LEN => {
+                let total_len = u32::from_le_bytes([frame[2], frame[3], frame[4], frame[5]]);
+                let crc = u32::from_le_bytes([frame[6], frame[7], frame[8], frame[9]]);
+
+                self.transfer = None;
+                let total_len = total_len as usize;
+                if total_len > self.max_len {
+                    return Err(ChunkError::TooLarge);
+                }
+
+                self.transfer = Some(Transfer {
+                    id: *id,
+                    total_len,
+                    crc,
+                    next_index: 1,
+                    // Grows as frames arrive, the peer sets total_len
+                    value: Vec::new(),
+                });
+
+                self.append(&frame[START_HEADER_LEN..])
+            }
+            [CONTINUATION, id, low, high, bytes @ ..] => {
+                let index = u16::from_le_bytes([*low, *high]);
+                let expected = self
+                    .transfer
+                    .as_ref()
+                    .is_some_and(|transfer| transfer.id == *id && transfer.next_index == index);
+
+                if !expected {
+                    self.transfer = None;
+                    return Err(ChunkError::UnexpectedFrame);
+                }
+
+                if let Some(transfer) = self.transfer.as_mut() {
+                    transfer.next_index = transfer.next_index.wrapping_add(1);
+                }
+
+                self.append(bytes)
+            }
+            _ => Err(ChunkError::Malformed),
+        }
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> Result<Option<Vec<u8>>, ChunkError> {
+        let Some(transfer) = self.transfer.as_mut() else {
+            return Err(ChunkError::UnexpectedFrame);
+        };
+
+        if transfer.value.len() + bytes.len() > transfer.total_len {
+            self.transfer = None;
+            return Err(ChunkError::TooLarge);
+        }
+
+        transfer.value.extend_from_slice(bytes);
+        if transfer.value.len() < transfer.total_len {
+            return Ok(None);
+        }
+
+        let transfer = self.transfer.take().ok_or(ChunkError::UnexpectedFrame)?;
+        if crc32(&transfer.value) != transfer.crc {
+            return Err(ChunkError::Checksum);
+        }
+
+        Ok(Some(transfer.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    fn reassemble(frames: &[Vec<u8>], max_len: usize) -> Result<Option<Vec<u8>>, ChunkError> {
+        let mut reassembler = ChunkReassembler::new(max_len);
+        let mut result = Ok(None);
+        for frame in frames {
+            result = reassembler.push(frame);
+        }
+
+        result
+    }
+
+    #[test]
+    fn round_trip() {
+        for len in [0, 1, 9, 10, 11, 50, 512, 1000] {
+            for frame_len in [START_HEADER_LEN + 1, 20, 23, 185, 517] {
+                let value = value(len);
+                let frames = fragments(&value, 3, frame_len).unwrap();
+
+                assert!(frames.iter().all(|frame| frame.len() <= frame_len));
+                assert_eq!(reassemble(&frames, len), Ok(Some(value)));
+            }
+        }
+    }
+
+    #[test]
+    fn lost_frame_fails_transfer() {
+        let value = value(100);
+        let mut frames = fragments(&value, 1, 20).unwrap();
+        frames.remove(2);
+
+        let mut reassembler = ChunkReassembler::new(100);
+        assert_eq!(reassembler.push(&frames[0]), Ok(None));
+        assert_eq!(reassembler.push(&frames[1]), Ok(None));
+        assert_eq!(
+            reassembler.push(&frames[2]),
+            Err(ChunkError::UnexpectedFrame)
+        );
+        // Later frames of the failed transfer are dropped as well
+        assert_eq!(
+            reassembler.push(&frames[3]),
+            Err(ChunkError::UnexpectedFrame)
+        );
+
+        // The next value is received in full
+        let next = fragments(&value, 2, 20).unwrap();
+        let mut result = Ok(None);
+        for frame in &next {
+            result = reassembler.push(frame);
+        }
+        assert_eq!(result, Ok(Some(value)));
+    }
+
+    #[test]
+    fn reordered_frames_fail_transfer() {
+        let value = value(100);
+        let mut frames = fragments(&value, 1, 20).unwrap();
+        frames.swap(2, 3);
+
+        assert_eq!(
+            reassemble(&frames[..3], 100),
+            Err(ChunkError::UnexpectedFrame)
+        );
+    }
+
+    #[test]
+    fn start_frame_abandons_transfer() {
+        let first = value(100);
+        let second = value(60);
+        let first_frames = fragments(&first, 1, 20).unwrap();
+        let second_frames = fragments(&second, 2, 20).unwrap();
+
+        let mut reassembler = ChunkReassembler::new(100);
+        assert_eq!(reassembler.push(&first_frames[0]), Ok(None));
+        assert_eq!(reassembler.push(&first_frames[1]), Ok(None));
+
+        let mut result = Ok(None);
+        for frame in &second_frames {
+            result = reassembler.push(frame);
+        }
+        assert_eq!(result, Ok(Some(second)));
+    }
+
+    #[test]
+    fn corrupted_value_fails_checksum() {
+        let mut frames = fragments(&value(100), 1, 20).unwrap();
+        let last = frames.last_mut().unwrap();
+        *last.last_mut().unwrap() ^= 0xff;
+
+        assert_eq!(reassemble(&frames, 100), Err(ChunkError::Checksum));
+    }
+
+    #[test]
+    fn oversized_value_is_refused_at_start() {
+        let frames = fragments(&value(101), 1, 20).unwrap();
+
+        assert_eq!(
+            ChunkReassembler::new(100).push(&frames[0]),
+            Err(ChunkError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn short_frames_are_malformed() {
+        let mut reassembler = ChunkReassembler::new(100);
+
+        assert_eq!(reassembler.push(&[]), Err(ChunkError::Malformed));
+        assert_eq!(
+            reassembler.push(&[START, 1, 0, 0]),
+            Err(ChunkError::Malformed)
+        );
+        assert_eq!(
+            fragments(&value(10), 1, START_HEADER_LEN),
+            Err(ChunkError::FrameTooShort)
+        );
+    }
+}
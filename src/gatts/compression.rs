@@ -0,0 +1,49 @@
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+/// Header byte of a frame holding uncompressed payload.
+pub const RAW: u8 = 0x00;
+/// Header byte of a frame holding raw DEFLATE compressed payload.
+pub const DEFLATE: u8 = 0x01;
+
+/// Payloads shorter than this are sent uncompressed by default,
+/// below it the compression overhead usually outweighs the savings
+pub const DEFAULT_THRESHOLD: usize = 64;
+
+// Favours speed and memory over ratio, which matters more on the device
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Wraps payload into a frame, compressing it when it is at least `threshold`
+/// bytes long and compression actually makes it smaller
+pub fn compress(bytes: &[u8], threshold: usize) -> Vec<u8> {
+    if bytes.len() >= threshold {
+        let compressed = compress_to_vec(bytes, COMPRESSION_LEVEL);
+
+        if compressed.len() < bytes.len() {
+            let mut frame = Vec::with_capacity(compressed.len() + 1);
+            frame.push(DEFLATE);
+            frame.extend_from_slice(&compressed);
+
+            return frame;
+        }
+    }
+
+    let mut frame = Vec::with_capacity(bytes.len() + 1);
+    frame.push(RAW);
+    frame.extend_from_slice(bytes);
+
+    frame
+}
+
+/// Unwraps frame produced by `compress`, `max_len` limits size of decompressed payload
+pub fn decompress(frame: &[u8], max_len: usize) -> anyhow::Result<Vec<u8>> {
+    match frame.split_first() {
+        Some((&RAW, payload)) => Ok(payload.to_vec()),
+        Some((&DEFLATE, payload)) => decompress_to_vec_with_limit(payload, max_len)
+            .map_err(|err| anyhow::anyhow!("Failed to decompress frame: {:?}", err)),
+        Some((header, _)) => Err(anyhow::anyhow!(
+            "Unknown compression frame header: {:#04x}",
+            header
+        )),
+        None => Err(anyhow::anyhow!("Empty compression frame")),
+    }
+}
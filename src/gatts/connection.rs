@@ -1,19 +1,395 @@
-use esp_idf_svc::bt::{
-    ble::gatt::{server::ConnectionId, GattConnParams},
-    BdAddr,
+use std::{
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, Instant},
 };
 
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, bounded};
+
+use esp_idf_svc::{
+    bt::{
+        BdAddr,
+        ble::gatt::{GattConnParams, GattConnReason, Handle, server::ConnectionId},
+    },
+    sys::{esp, esp_ble_gap_disconnect},
+};
+
+use super::{GattsInner, attribute::Attribute, characteristic::Characteristic, event::EventStamp};
+use crate::gap::phy::{Phy, PhyOptions};
+
+/// Largest MTU the stack supports, see `BleConfig::local_mtu`
+pub const MAX_MTU: u16 = 517;
+// Largest LE data length the controller supports
+const MAX_DATA_LEN: u16 = 251;
+// ATT notification header taken from the MTU
+const ATT_HEADER_LEN: u16 = 3;
+// How long a notification waits for a congested link to drain
+const CONGESTION_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Clone)]
 pub enum ConnectionStatus {
-    Connected(ConnectionInner),
-    Disconnected(ConnectionInner),
+    Connected(Connection),
+    // Reason tells apart supervision timeouts (`Timeout`), disconnects requested
+    // by the peer (`PeerUser`) and by this device (`LocalHost`)
+    Disconnected(Connection, GattConnReason),
+    // Peer refused by the connection filter, it is disconnected right away
+    // and never reported as connected
+    Rejected(Connection),
+}
+
+/// Connection status with the stamp of the stack event which caused it
+#[derive(Debug, Clone)]
+pub struct ConnectionEvent {
+    pub stamp: EventStamp,
+    pub status: ConnectionStatus,
 }
 
+/// Handle of a single link, stays valid after the peer disconnects, but operations
+/// on the link then fail
 #[derive(Debug, Clone)]
+pub struct Connection(pub Arc<ConnectionInner>);
+
+#[derive(Debug)]
 pub struct ConnectionInner {
     pub id: ConnectionId,
     pub link_role: u8,
-    pub mtu: Option<u16>,
     pub address: BdAddr,
-    pub conn_params: GattConnParams,
+    pub(crate) mtu: RwLock<Option<u16>>,
+    pub(crate) conn_params: RwLock<GattConnParams>,
+    pub(crate) congested: RwLock<bool>,
+    // Signalled whenever `congested` changes, wakes senders waiting for the link
+    pub(crate) congestion_tx: Sender<()>,
+    congestion_rx: Receiver<()>,
+    // Transmit and receive PHY
+    pub(crate) phy: RwLock<(Phy, Phy)>,
+
+    gatts: Weak<GattsInner>,
+}
+
+impl Connection {
+    pub(crate) fn new(
+        gatts: Weak<GattsInner>,
+        id: ConnectionId,
+        link_role: u8,
+        address: BdAddr,
+        conn_params: GattConnParams,
+    ) -> Self {
+        let (congestion_tx, congestion_rx) = bounded(1);
+
+        Self(Arc::new(ConnectionInner {
+            id,
+            link_role,
+            address,
+            mtu: RwLock::new(None),
+            conn_params: RwLock::new(conn_params),
+            congested: RwLock::new(false),
+            congestion_tx,
+            congestion_rx,
+            phy: RwLock::new((Phy::Le1M, Phy::Le1M)),
+            gatts,
+        }))
+    }
+
+    pub fn id(&self) -> ConnectionId {
+        self.0.id
+    }
+
+    pub fn peer_addr(&self) -> BdAddr {
+        self.0.address
+    }
+
+    /// MTU negotiated with the peer, None until the peer starts MTU exchange
+    pub fn mtu(&self) -> anyhow::Result<Option<u16>> {
+        Ok(*self
+            .0
+            .mtu
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read connection MTU"))?)
+    }
+
+    pub fn conn_params(&self) -> anyhow::Result<GattConnParams> {
+        Ok(self
+            .0
+            .conn_params
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read connection params"))?
+            .clone())
+    }
+
+    /// Whether the controller currently reports the link as congested,
+    /// see `Gatts::congestion_rx` to follow changes
+    pub fn is_congested(&self) -> anyhow::Result<bool> {
+        Ok(*self
+            .0
+            .congested
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read connection congestion"))?)
+    }
+
+    /// Reads signal strength of the link, requires `Gap` to be created
+    pub fn rssi(&self) -> anyhow::Result<i8> {
+        self.gatts()?.get_gap()?.read_rssi(self.0.address)
+    }
+
+    /// Requests new connection parameters and waits until the peer accepts them,
+    /// intervals are in milliseconds as in `GapConfig`
+    pub fn update_params(
+        &self,
+        min_interval_ms: u32,
+        max_interval_ms: u32,
+        latency_ms: u32,
+        timeout_ms: u32,
+    ) -> anyhow::Result<()> {
+        let conn_params = self.gatts()?.get_gap()?.update_conn_params(
+            self.0.address,
+            min_interval_ms,
+            max_interval_ms,
+            latency_ms,
+            timeout_ms,
+        )?;
+
+        *self
+            .0
+            .conn_params
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write connection params"))? = conn_params;
+
+        Ok(())
+    }
+
+    /// Transmit and receive PHY of the link, updated once a PHY update completes
+    pub fn phy(&self) -> anyhow::Result<(Phy, Phy)> {
+        Ok(*self
+            .0
+            .phy
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read connection PHY"))?)
+    }
+
+    /// Asks the controller to switch the link to one of the given PHYs, e.g. `Le2M`
+    /// for throughput or `Coded` for range. An empty slice leaves that direction
+    /// to the peer. Returns once the request is accepted, the outcome arrives
+    /// later on `Gap::phy_updates_rx` and is reflected by `phy`
+    pub fn set_preferred_phy(
+        &self,
+        tx: &[Phy],
+        rx: &[Phy],
+        options: PhyOptions,
+    ) -> anyhow::Result<()> {
+        self.gatts()?
+            .get_gap()?
+            .set_preferred_phy(self.0.address, tx, rx, options)
+    }
+
+    pub(crate) fn set_phy(&self, tx: Phy, rx: Phy) -> anyhow::Result<()> {
+        *self
+            .0
+            .phy
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write connection PHY"))? = (tx, rx);
+
+        Ok(())
+    }
+
+    /// Tunes the link for bulk transfers: enables Data Length Extension with 251
+    /// byte packets, asks for the 2M PHY and an 8-15 ms connection interval. Steps
+    /// the peer or controller refuse are logged and skipped, the report tells which
+    /// ones took effect. The MTU offered to peers is stack-wide and not changed
+    /// here, set `BleConfig::local_mtu` to `MAX_MTU` for larger packets
+    pub fn enter_high_throughput(&self) -> anyhow::Result<HighThroughputReport> {
+        let gap = self.gatts()?.get_gap()?;
+
+        let data_len = match gap.set_data_len(self.0.address, MAX_DATA_LEN) {
+            Ok((_, tx_len)) => Some(tx_len),
+            Err(err) => {
+                log::warn!(
+                    "Failed to extend data length of {:?}: {:?}",
+                    self.0.address,
+                    err
+                );
+                None
+            }
+        };
+
+        let phy_requested =
+            match self.set_preferred_phy(&[Phy::Le2M], &[Phy::Le2M], PhyOptions::default()) {
+                Ok(()) => true,
+                Err(err) => {
+                    log::warn!(
+                        "Failed to request 2M PHY of {:?}: {:?}",
+                        self.0.address,
+                        err
+                    );
+                    false
+                }
+            };
+
+        let conn_params = match self.update_params(8, 15, 0, 4000) {
+            Ok(()) => Some(self.conn_params()?),
+            Err(err) => {
+                log::warn!(
+                    "Failed to shorten connection interval of {:?}: {:?}",
+                    self.0.address,
+                    err
+                );
+                None
+            }
+        };
+
+        Ok(HighThroughputReport {
+            mtu: self.mtu()?,
+            data_len,
+            phy_requested,
+            conn_params,
+        })
+    }
+
+    /// Sends `data` to this peer as notifications of the characteristic, split to
+    /// the MTU, without changing its value. Fails unless the peer enabled
+    /// notifications in the CCCD. Notifications are not confirmed, so sending only
+    /// pauses while the link is congested. Returns the measured throughput, e.g. to
+    /// check the effect of `enter_high_throughput`
+    pub fn send_notifications<T: Attribute>(
+        &self,
+        characteristic: &Characteristic<T>,
+        data: &[u8],
+    ) -> anyhow::Result<Throughput> {
+        let gatts = self.gatts()?;
+        let interface = characteristic.0.get_service()?.get_app()?.interface()?;
+        let handle = characteristic.0.attribute.handle()?;
+
+        if !gatts.subscription(self.0.id, handle)?.notify {
+            return Err(anyhow::anyhow!(
+                "Peer {:?} did not enable notifications of {:?}",
+                self.0.address,
+                handle
+            ));
+        }
+
+        let packet_len = self.mtu()?.unwrap_or(23).saturating_sub(ATT_HEADER_LEN);
+        let started = Instant::now();
+        let mut packets = 0;
+
+        for packet in data.chunks(usize::from(packet_len.max(1))) {
+            self.wait_uncongested()?;
+
+            gatts
+                .gatts
+                .notify(interface, self.0.id, handle, packet)
+                .map_err(|err| {
+                    anyhow::anyhow!(
+                        "Failed to send GATT notification to {:?}: {:?}",
+                        self.0.address,
+                        err
+                    )
+                })?;
+            packets += 1;
+        }
+
+        Ok(Throughput {
+            bytes: data.len(),
+            packets,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    fn wait_uncongested(&self) -> anyhow::Result<()> {
+        let deadline = Instant::now() + CONGESTION_TIMEOUT;
+        while self.is_congested()? {
+            // A signal left from an earlier change only repeats the check
+            match self.0.congestion_rx.recv_deadline(deadline) {
+                Ok(()) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(anyhow::anyhow!(
+                        "Timed out waiting for congested link to {:?}",
+                        self.0.address
+                    ));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow::anyhow!("Connection congestion channel closed"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Terminates the link, `ConnectionStatus::Disconnected` follows once the stack
+    /// reports it closed
+    pub fn disconnect(&self) -> anyhow::Result<()> {
+        let mut raw_addr = self.0.address.raw();
+
+        esp!(unsafe { esp_ble_gap_disconnect(raw_addr.as_mut_ptr()) })
+            .map_err(|err| anyhow::anyhow!("Failed to disconnect {:?}: {:?}", self.0.address, err))
+    }
+
+    fn gatts(&self) -> anyhow::Result<Arc<GattsInner>> {
+        self.0
+            .gatts
+            .upgrade()
+            .ok_or(anyhow::anyhow!("Failed to upgrade Gatts"))
+    }
+}
+
+/// Outcome of `Connection::enter_high_throughput`
+#[derive(Debug, Clone)]
+pub struct HighThroughputReport {
+    // MTU negotiated so far, None until the peer exchanges MTU
+    pub mtu: Option<u16>,
+    // Transmit payload length of the link, None when Data Length Extension failed
+    pub data_len: Option<u16>,
+    // Whether the controller accepted the 2M PHY request, the switch itself is
+    // reported on `Gap::phy_updates_rx`
+    pub phy_requested: bool,
+    // Parameters the peer accepted, None when it refused the shorter interval
+    pub conn_params: Option<GattConnParams>,
+}
+
+/// Measured transfer of `Connection::send_notifications`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Throughput {
+    pub bytes: usize,
+    pub packets: usize,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    pub fn bytes_per_sec(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Reported when the controller buffers of a connection fill up or drain again.
+/// While congested, notifications and indications fail, senders should pause
+/// until the link is reported uncongested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CongestionStatus {
+    pub conn_id: ConnectionId,
+    pub congested: bool,
+}
+
+/// Progress of a prepared (long) write, e.g. a firmware or file upload. Sent
+/// for every fragment the peer queues and once more when it executes or
+/// cancels the write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteProgress {
+    pub conn_id: ConnectionId,
+    pub handle: Handle,
+    // Bytes of the value reassembled so far
+    pub received: usize,
+    // Size of the whole value when known, see `Characteristic::set_expected_write_len`
+    pub expected: Option<usize>,
+    pub state: WriteProgressState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteProgressState {
+    Receiving,
+    // Value was applied to the attribute
+    Executed,
+    // Peer canceled the write or applying the value failed
+    Canceled,
 }
@@ -3,24 +3,49 @@ use std::{
     sync::{Arc, RwLock, Weak},
 };
 
-use crossbeam_channel::bounded;
 use enumset::EnumSet;
 use esp_idf_svc::bt::{
-    ble::gatt::{GattDescriptor, GattStatus, Handle, Permission},
+    ble::gatt::{server::ConnectionId, GattDescriptor, GattStatus, Handle, Permission},
     BtUuid,
 };
 
 use super::{
+    CCCD_UUID,
     attribute::{AnyAttribute, Attribute, AttributeInner},
-    characteristic::CharacteristicInner,
+    characteristic::{CharacteristicInner, ReadHandler},
+    error::AttError,
     event::{GattsEvent, GattsEventMessage},
+    ident::AppInterface,
+    table::TableEntry,
 };
+use crate::{guard, trace};
 
 pub struct DescriptorConfig {
     pub uuid: BtUuid,
 
     pub readable: bool,
     pub writable: bool,
+
+    // Require encrypted (paired) link, authenticated variants additionally
+    // require pairing with MITM protection
+    pub read_encrypted: bool,
+    pub write_encrypted: bool,
+    pub read_authenticated: bool,
+    pub write_authenticated: bool,
+}
+
+impl Default for DescriptorConfig {
+    fn default() -> Self {
+        Self {
+            uuid: BtUuid::uuid16(0),
+            readable: false,
+            writable: false,
+            read_encrypted: false,
+            write_encrypted: false,
+            read_authenticated: false,
+            write_authenticated: false,
+        }
+    }
 }
 
 impl Into<GattDescriptor> for &DescriptorConfig {
@@ -35,6 +60,22 @@ impl Into<GattDescriptor> for &DescriptorConfig {
             permissions.insert(Permission::Write);
         }
 
+        if self.read_encrypted {
+            permissions.insert(Permission::ReadEncrypted);
+        }
+
+        if self.write_encrypted {
+            permissions.insert(Permission::WriteEncrypted);
+        }
+
+        if self.read_authenticated {
+            permissions.insert(Permission::ReadEncryptedMitm);
+        }
+
+        if self.write_authenticated {
+            permissions.insert(Permission::WriteEncryptedMitm);
+        }
+
         GattDescriptor {
             uuid: self.uuid.clone(),
             permissions,
@@ -51,12 +92,21 @@ impl std::hash::Hash for DescritporId {
     }
 }
 
-pub trait DescriptorAttribute<T: Attribute>: Send + Sync + 'static {
-    fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()>;
-    fn get_bytes(&self) -> anyhow::Result<Vec<u8>>;
-    fn register(&self, service: &Arc<CharacteristicInner<T>>) -> anyhow::Result<()>;
-    fn uuid(&self) -> BtUuid;
-    fn handle(&self) -> anyhow::Result<Handle>;
+/// Registration behavior of a descriptor on top of `AnyAttribute`, lets
+/// characteristics of value type `T` hold descriptors of any value type.
+/// `Descriptor` converts into `Arc<dyn DescriptorAttribute<T>>` with `into()`
+pub trait DescriptorAttribute<T: Attribute>: AnyAttribute {
+    fn register(
+        self: Arc<Self>,
+        characteristic: &Arc<CharacteristicInner<T>>,
+    ) -> anyhow::Result<()>;
+
+    /// Row of the descriptor for `ServiceTable`, bound to the characteristic once
+    /// the table is created
+    fn table_entry(
+        self: Arc<Self>,
+        characteristic: &Arc<CharacteristicInner<T>>,
+    ) -> anyhow::Result<TableEntry>;
 }
 
 #[derive(Clone)]
@@ -67,6 +117,7 @@ pub struct DescriptorInner<T: Attribute, A: Attribute> {
     pub config: DescriptorConfig,
 
     pub attribute: AttributeInner<T>,
+    read_handler: RwLock<Option<ReadHandler<T>>>,
 }
 
 impl<T: Attribute, A: Attribute> Descriptor<T, A> {
@@ -75,67 +126,115 @@ impl<T: Attribute, A: Attribute> Descriptor<T, A> {
             characteristic: RwLock::new(Weak::new()),
             config,
             attribute: AttributeInner::new(value),
+            read_handler: RwLock::new(None),
         };
 
         Self(Arc::new(descriptor))
     }
-}
 
-impl<T: Attribute, A: Attribute> DescriptorInner<T, A> {
-    fn get_characteristic(&self) -> anyhow::Result<Arc<CharacteristicInner<A>>> {
-        self.characteristic
-            .read()
-            .map_err(|_| anyhow::anyhow!("Failed to read characteristic"))?
-            .upgrade()
-            .ok_or(anyhow::anyhow!("Failed to upgrade characteristic"))
+    /// Sets callback which computes the value when a peer reads the descriptor
+    pub fn set_on_read(
+        &self,
+        handler: impl Fn() -> anyhow::Result<T> + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        *self
+            .0
+            .read_handler
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write descriptor read handler"))? =
+            Some(Arc::new(handler));
+
+        Ok(())
     }
+}
 
-    fn handle(&self) -> anyhow::Result<Handle> {
-        self.attribute
-            .handle
-            .read()
-            .map_err(|_| anyhow::anyhow!("Failed to read attribute"))?
-            .ok_or_else(|| anyhow::anyhow!("Attribute handle not set"))
+impl<T: Attribute, A: Attribute> From<Descriptor<T, A>> for Arc<dyn DescriptorAttribute<A>> {
+    fn from(descriptor: Descriptor<T, A>) -> Self {
+        descriptor.0
     }
 }
 
 impl<T: Attribute, A: Attribute> AnyAttribute for DescriptorInner<T, A> {
+    fn uuid(&self) -> BtUuid {
+        self.config.uuid.clone()
+    }
+
+    fn handle(&self) -> anyhow::Result<Handle> {
+        self.attribute.handle()
+    }
+
     fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()> {
-        self.attribute.update(Arc::new(T::from_bytes(bytes)?))
+        self.attribute.update(self.attribute.decode_update(bytes)?)
+    }
+
+    fn write_from_peer(&self, bytes: &[u8], writer: ConnectionId) -> anyhow::Result<()> {
+        // CCCD values are kept per peer by the characteristic, see `GattsInner::subscription`
+        if self.config.uuid != BtUuid::uuid16(CCCD_UUID) {
+            self.update_from_bytes(bytes)?;
+        }
+
+        let characteristic = self
+            .characteristic
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read descriptor characteristic"))?
+            .upgrade();
+
+        match characteristic {
+            Some(characteristic) => {
+                characteristic.descriptor_written(&self.config.uuid, bytes, writer)
+            }
+            None => Ok(()),
+        }
     }
 
     fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
         self.attribute.get_bytes()
     }
-}
 
-impl<T: Attribute, A: Attribute> DescriptorAttribute<A> for Descriptor<T, A> {
-    fn update_from_bytes(&self, bytes: &[u8]) -> anyhow::Result<()> {
-        self.0.attribute.update(Arc::new(T::from_bytes(bytes)?))
+    fn read_bytes(&self, offset: u16) -> anyhow::Result<Vec<u8>> {
+        if offset == 0 {
+            let handler = self
+                .read_handler
+                .read()
+                .map_err(|_| anyhow::anyhow!("Failed to read descriptor read handler"))?
+                .clone();
+
+            if let Some(handler) = handler {
+                let value = guard::run_hook("descriptor read", move || handler())??;
+                self.attribute.replace(Arc::new(value))?;
+            }
+        }
+
+        self.attribute.get_bytes()
     }
 
-    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
-        self.0.attribute.get_bytes()
+    fn validate_write(&self, bytes: &[u8]) -> Result<(), AttError> {
+        self.attribute.decode_write(bytes).map(|_| ())
     }
 
-    fn handle(&self) -> anyhow::Result<Handle> {
-        self.0
-            .attribute
-            .handle
-            .read()
-            .map_err(|_| anyhow::anyhow!("Failed to read attribute"))?
-            .ok_or_else(|| anyhow::anyhow!("Attribute handle not set"))
+    fn round_trip(&self) -> anyhow::Result<()> {
+        self.attribute.round_trip()
     }
 
-    fn register(&self, characteristic: &Arc<CharacteristicInner<A>>) -> anyhow::Result<()> {
-        *self
-            .0
+    fn characteristic_handle(&self) -> anyhow::Result<Option<Handle>> {
+        let characteristic = self
             .characteristic
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Service"))? =
-            Arc::downgrade(characteristic);
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read descriptor characteristic"))?
+            .upgrade()
+            .ok_or(anyhow::anyhow!("Characteristic of descriptor was dropped"))?;
+
+        characteristic.attribute.handle().map(Some)
+    }
+}
+
+impl<T: Attribute, A: Attribute> DescriptorAttribute<A> for DescriptorInner<T, A> {
+    fn register(
+        self: Arc<Self>,
+        characteristic: &Arc<CharacteristicInner<A>>,
+    ) -> anyhow::Result<()> {
+        trace::span!("gatts.register_descriptor", uuid = ?self.config.uuid);
 
-        let (tx, rx) = bounded(1);
         let callback_key = discriminant(&GattsEvent::DescriptorAdded {
             status: GattStatus::Busy,
             attr_handle: 0,
@@ -148,19 +247,15 @@ impl<T: Attribute, A: Attribute> DescriptorAttribute<A> for Descriptor<T, A> {
         let gatts = app.get_gatts()?;
         let parent_service_handle = service.get_handle()?;
 
-        gatts
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events"))?
-            .insert(callback_key.clone(), tx.clone());
+        let rx = gatts.waiter(callback_key)?;
 
         gatts
             .gatts
-            .add_descriptor(parent_service_handle, &(&self.0.config).into())
+            .add_descriptor(parent_service_handle, &(&self.config).into())
             .map_err(|err| {
                 anyhow::anyhow!(
                     "Failed to register GATT descriptor {:?}: {:?}",
-                    self.0.config.uuid,
+                    self.config.uuid,
                     err
                 )
             })?;
@@ -174,11 +269,13 @@ impl<T: Attribute, A: Attribute> DescriptorAttribute<A> for Descriptor<T, A> {
                     service_handle,
                     descr_uuid,
                 },
+                _,
             )) => {
                 if interface != app.interface()? {
                     return Err(anyhow::anyhow!(
-                        "Received unexpected GATT interface: {:?}",
-                        interface
+                        "Received descriptor of {} while registering on {}",
+                        AppInterface::unknown(interface),
+                        app.app_interface()?
                     ));
                 }
 
@@ -189,7 +286,7 @@ impl<T: Attribute, A: Attribute> DescriptorAttribute<A> for Descriptor<T, A> {
                     ));
                 }
 
-                if self.0.config.uuid != descr_uuid {
+                if self.config.uuid != descr_uuid {
                     return Err(anyhow::anyhow!(
                         "Received unexpected GATT descriptor uuid: {:?}",
                         descr_uuid
@@ -200,22 +297,60 @@ impl<T: Attribute, A: Attribute> DescriptorAttribute<A> for Descriptor<T, A> {
                     return Err(anyhow::anyhow!("Failed to register: {:?}", status));
                 }
 
-                self.0.attribute.set_handle(attr_handle)?;
+                Descriptor(self).bind(characteristic, attr_handle)
             }
-            Ok(_) => return Err(anyhow::anyhow!("Received unexpected GATT event")),
-            Err(_) => return Err(anyhow::anyhow!("Timed out waiting for GATT event")),
+            Ok(_) => Err(anyhow::anyhow!("Received unexpected GATT event")),
+            Err(_) => Err(anyhow::anyhow!("Timed out waiting for GATT event")),
         }
+    }
+
+    fn table_entry(
+        self: Arc<Self>,
+        characteristic: &Arc<CharacteristicInner<A>>,
+    ) -> anyhow::Result<TableEntry> {
+        let gatt_descriptor: GattDescriptor = (&self.config).into();
+        let value = self.attribute.get_bytes()?;
+        let descriptor = Descriptor(self);
+        let characteristic = characteristic.clone();
+
+        Ok(TableEntry {
+            uuid: gatt_descriptor.uuid,
+            permissions: gatt_descriptor.permissions,
+            max_len: value.len() as u16,
+            value,
+            auto_response: false,
+            bind: Some(Box::new(move |_, handle| {
+                descriptor.bind(&characteristic, handle)
+            })),
+        })
+    }
+}
+
+impl<T: Attribute, A: Attribute> Descriptor<T, A> {
+    // Attaches registered descriptor to its characteristic and makes it reachable
+    // from read/write events
+    fn bind(
+        &self,
+        characteristic: &Arc<CharacteristicInner<A>>,
+        handle: Handle,
+    ) -> anyhow::Result<()> {
+        *self
+            .0
+            .characteristic
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write Service"))? =
+            Arc::downgrade(characteristic);
+
+        self.0.attribute.set_handle(handle)?;
 
-        let characteristic = self.0.get_characteristic()?;
         let service = characteristic.get_service()?;
         let app = service.get_app()?;
         let gatts = app.get_gatts()?;
 
         if gatts
             .attributes
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write GATT attributes"))?
-            .insert(self.handle()?, self.0.clone())
+            .write()?
+            .insert(self.0.handle()?, self.0.clone())
             .is_some()
         {
             return Err(anyhow::anyhow!(
@@ -226,8 +361,4 @@ impl<T: Attribute, A: Attribute> DescriptorAttribute<A> for Descriptor<T, A> {
 
         Ok(())
     }
-
-    fn uuid(&self) -> BtUuid {
-        self.0.config.uuid.clone()
-    }
 }
@@ -0,0 +1,235 @@
+//! Framing of diff notifications, see `CharacteristicConfig::diff_notify`.
+//!
+//! Bincode encodes a struct as its fields one after another, so a value is handled
+//! as a list of encoded top-level fields. A full frame carries all of them, a patch
+//! frame only those which changed, addressed by their index in the struct (same
+//! order as fields of the exported `ValueSchema`). Values which are not structs
+//! are a single field.
+//!
+//! ```text
+//! full:  0x00, count: u8, count * (len: u16 LE, bytes)
+//! patch: 0x01, n * (index: u8, len: u16 LE, bytes)
+//! ```
+
+use serde::{Serialize, ser};
+
+use super::attribute::encoding;
+
+/// Tag of a frame holding every field of the value
+pub const FULL: u8 = 0x00;
+/// Tag of a frame holding changed fields only
+pub const PATCH: u8 = 0x01;
+
+/// Encoded top-level fields of a serde struct, None for other values
+pub(crate) fn split_fields<T: Serialize + ?Sized>(
+    value: &T,
+) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+    match value.serialize(FieldSplitter) {
+        Ok(fields) => Ok(Some(fields)),
+        Err(FieldsError::NotStruct) => Ok(None),
+        Err(FieldsError::Encode(err)) => Err(err),
+    }
+}
+
+pub(crate) fn full_frame(fields: &[Vec<u8>]) -> anyhow::Result<Vec<u8>> {
+    let count = u8::try_from(fields.len())
+        .map_err(|_| anyhow::anyhow!("Diff notifications support up to 255 fields"))?;
+
+    let mut frame = vec![FULL, count];
+    for field in fields {
+        push_field(&mut frame, field)?;
+    }
+
+    Ok(frame)
+}
+
+/// Patch of the fields changed between two values, None when the fields do not
+/// line up or the patch would not be shorter than the full frame
+pub(crate) fn patch_frame(old: &[Vec<u8>], new: &[Vec<u8>]) -> anyhow::Result<Option<Vec<u8>>> {
+    if old.len() != new.len() {
+        return Ok(None);
+    }
+
+    let mut frame = vec![PATCH];
+    for (index, (old, new)) in old.iter().zip(new).enumerate() {
+        if old != new {
+            frame.push(index as u8);
+            push_field(&mut frame, new)?;
+        }
+    }
+
+    let full_len = 2 + new.iter().map(|field| 2 + field.len()).sum::<usize>();
+    Ok((frame.len() < full_len).then_some(frame))
+}
+
+fn push_field(frame: &mut Vec<u8>, field: &[u8]) -> anyhow::Result<()> {
+    let len = u16::try_from(field.len())
+        .map_err(|_| anyhow::anyhow!("Field of {} bytes is too long to frame", field.len()))?;
+
+    frame.extend(len.to_le_bytes());
+    frame.extend(field);
+
+    Ok(())
+}
+
+#[derive(Debug)]
+enum FieldsError {
+    NotStruct,
+    Encode(anyhow::Error),
+}
+
+impl std::fmt::Display for FieldsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotStruct => f.write_str("Value is not a struct"),
+            Self::Encode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for FieldsError {}
+
+impl ser::Error for FieldsError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Encode(anyhow::anyhow!("{}", msg))
+    }
+}
+
+// Accepts a struct only, encoding each of its fields separately
+struct FieldSplitter;
+
+macro_rules! not_struct {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Vec<Vec<u8>>, FieldsError> {
+                Err(FieldsError::NotStruct)
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for FieldSplitter {
+    type Ok = Vec<Vec<u8>>;
+    type Error = FieldsError;
+
+    type SerializeSeq = ser::Impossible<Vec<Vec<u8>>, FieldsError>;
+    type SerializeTuple = ser::Impossible<Vec<Vec<u8>>, FieldsError>;
+    type SerializeTupleStruct = ser::Impossible<Vec<Vec<u8>>, FieldsError>;
+    type SerializeTupleVariant = ser::Impossible<Vec<Vec<u8>>, FieldsError>;
+    type SerializeMap = ser::Impossible<Vec<Vec<u8>>, FieldsError>;
+    type SerializeStruct = FieldCollector;
+    type SerializeStructVariant = ser::Impossible<Vec<Vec<u8>>, FieldsError>;
+
+    not_struct! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<Self::Ok, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, FieldsError> {
+        Ok(FieldCollector(Vec::with_capacity(len)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, FieldsError> {
+        Err(FieldsError::NotStruct)
+    }
+}
+
+struct FieldCollector(Vec<Vec<u8>>);
+
+impl ser::SerializeStruct for FieldCollector {
+    type Ok = Vec<Vec<u8>>;
+    type Error = FieldsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), FieldsError> {
+        self.0
+            .push(encoding::encode(&value).map_err(FieldsError::Encode)?);
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Vec<Vec<u8>>, FieldsError> {
+        Ok(self.0)
+    }
+}
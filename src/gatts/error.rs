@@ -0,0 +1,100 @@
+use std::fmt::Display;
+
+use esp_idf_svc::bt::{BtUuid, ble::gatt::GattStatus};
+
+use super::{chunked::ChunkError, reassembly::ReassemblyError};
+
+/// Error returned to a peer in an ATT response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttError {
+    /// One of the statuses defined by the stack, e.g. `WriteNotPermit` or `InvalidAttrLen`.
+    Status(GattStatus),
+    /// Application defined error code, must be in range 0x80..=0x9F.
+    Application(u8),
+}
+
+impl AttError {
+    pub const APPLICATION_ERROR_RANGE: std::ops::RangeInclusive<u8> = 0x80..=0x9F;
+
+    pub fn application(code: u8) -> anyhow::Result<Self> {
+        if !Self::APPLICATION_ERROR_RANGE.contains(&code) {
+            return Err(anyhow::anyhow!(
+                "Application error code {:#04x} is outside of range 0x80..=0x9F",
+                code
+            ));
+        }
+
+        Ok(Self::Application(code))
+    }
+}
+
+impl From<GattStatus> for AttError {
+    fn from(status: GattStatus) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl From<ReassemblyError> for AttError {
+    fn from(err: ReassemblyError) -> Self {
+        match err {
+            ReassemblyError::InvalidOffset => Self::Status(GattStatus::InvalidOffset),
+            ReassemblyError::InvalidLength => Self::Status(GattStatus::InvalidAttrLen),
+            ReassemblyError::OtherAttribute => Self::Status(GattStatus::PrepareQFull),
+        }
+    }
+}
+
+impl From<ChunkError> for AttError {
+    fn from(err: ChunkError) -> Self {
+        match err {
+            ChunkError::TooLarge => Self::Status(GattStatus::InvalidAttrLen),
+            ChunkError::Malformed
+            | ChunkError::UnexpectedFrame
+            | ChunkError::FrameTooShort
+            | ChunkError::Checksum => Self::Status(GattStatus::InvalidPdu),
+        }
+    }
+}
+
+impl Display for AttError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttError::Status(status) => write!(f, "ATT error: {:?}", status),
+            AttError::Application(code) => write!(f, "ATT application error: {:#04x}", code),
+        }
+    }
+}
+
+impl std::error::Error for AttError {}
+
+/// Characteristic value which may not fit its declared `value_max_len`, found at
+/// registration. Returned as the error, or logged as a warning, depending on
+/// `CharacteristicConfig::size_check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueSizeError {
+    pub uuid: BtUuid,
+    pub value_max_len: usize,
+    // Size of the initial value
+    pub encoded_len: usize,
+    // Largest size of values shaped like the initial one
+    pub max_encoded_len: usize,
+}
+
+impl ValueSizeError {
+    /// Whether the initial value fits, so only grown values would be rejected
+    pub fn initial_fits(&self) -> bool {
+        self.encoded_len <= self.value_max_len
+    }
+}
+
+impl Display for ValueSizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Value of characteristic {:?} encodes to {} bytes and up to {} bytes, over value_max_len of {} bytes",
+            self.uuid, self.encoded_len, self.max_encoded_len, self.value_max_len
+        )
+    }
+}
+
+impl std::error::Error for ValueSizeError {}
@@ -1,3 +1,9 @@
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
 use esp_idf_svc::bt::{
     ble::gatt::{
         self,
@@ -135,6 +141,25 @@ pub enum GattsEvent {
     Other,
 }
 
+impl GattsEvent {
+    // Connection the event belongs to
+    pub(crate) fn conn_id(&self) -> Option<ConnectionId> {
+        match self {
+            GattsEvent::Read { conn_id, .. }
+            | GattsEvent::Write { conn_id, .. }
+            | GattsEvent::ExecWrite { conn_id, .. }
+            | GattsEvent::Mtu { conn_id, .. }
+            | GattsEvent::Confirm { conn_id, .. }
+            | GattsEvent::PeerConnected { conn_id, .. }
+            | GattsEvent::PeerDisconnected { conn_id, .. }
+            | GattsEvent::Close { conn_id, .. }
+            | GattsEvent::Listen { conn_id, .. }
+            | GattsEvent::Congest { conn_id, .. } => Some(*conn_id),
+            _ => None,
+        }
+    }
+}
+
 impl<'d> From<gatt::server::GattsEvent<'d>> for GattsEvent {
     fn from(event: gatt::server::GattsEvent<'d>) -> Self {
         match event {
@@ -331,5 +356,50 @@ impl<'d> From<gatt::server::GattsEvent<'d>> for GattsEvent {
     }
 }
 
+/// Stack event with the app interface it was received on and its stamp
 #[derive(Debug, Clone)]
-pub struct GattsEventMessage(pub GattInterface, pub GattsEvent);
+pub struct GattsEventMessage(pub GattInterface, pub GattsEvent, pub EventStamp);
+
+// Sequence number of the next stamp, shared by all events
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // Stamp of the event the dispatcher is handling on this thread
+    static HANDLING: Cell<Option<EventStamp>> = const { Cell::new(None) };
+}
+
+/// Monotonic time of an event, taken in the stack callback before the event is
+/// queued, so it does not include dispatch lag. `sequence` increases with every
+/// stamp and orders events stamped at the same instant, stamps compare by it.
+///
+/// Connection and attribute events all pass one dispatcher thread in the order
+/// the stack reported them, so events of a connection are never reordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventStamp {
+    pub sequence: u64,
+    pub at: Instant,
+}
+
+impl EventStamp {
+    pub fn now() -> Self {
+        Self {
+            sequence: SEQUENCE.fetch_add(1, Ordering::Relaxed),
+            at: Instant::now(),
+        }
+    }
+
+    /// Stamp of the event handled on this thread, so updates caused by a peer
+    /// write carry the time of the write. A new stamp outside of event handlers
+    pub(crate) fn current() -> Self {
+        HANDLING.get().unwrap_or_else(Self::now)
+    }
+
+    // Runs the handler of the event stamped with this stamp
+    pub(crate) fn handle<R>(self, handler: impl FnOnce() -> R) -> R {
+        let previous = HANDLING.replace(Some(self));
+        let result = handler();
+        HANDLING.set(previous);
+
+        result
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use esp_idf_svc::bt::BdAddr;
+
+use super::connection::Connection;
+use crate::guard;
+
+pub type ConnectionFilterFn = Arc<dyn Fn(&Connection) -> bool + Send + Sync>;
+
+/// Decides which peers may stay connected. Evaluated on every `PeerConnected` before the
+/// connection is visible to the application, rejected peers are disconnected and any
+/// of their requests until then are answered with an error.
+///
+/// Addresses are compared as reported by the stack, peers using resolvable private
+/// addresses are reported with their identity address only once bonded.
+#[derive(Clone, Default)]
+pub enum ConnectionFilter {
+    #[default]
+    AcceptAll,
+    // Only listed peers are accepted
+    Allow(Vec<BdAddr>),
+    // Listed peers are rejected
+    Deny(Vec<BdAddr>),
+    // Callback returning true for accepted peers, failing callback rejects the peer
+    Custom(ConnectionFilterFn),
+}
+
+impl ConnectionFilter {
+    pub fn custom(filter: impl Fn(&Connection) -> bool + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(filter))
+    }
+
+    pub fn accepts(&self, connection: &Connection) -> bool {
+        match self {
+            Self::AcceptAll => true,
+            Self::Allow(addresses) => addresses.contains(&connection.peer_addr()),
+            Self::Deny(addresses) => !addresses.contains(&connection.peer_addr()),
+            Self::Custom(filter) => {
+                let filter = filter.clone();
+                let connection = connection.clone();
+
+                guard::run_hook("connection filter", move || filter(&connection)).unwrap_or(false)
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+
+use esp_idf_svc::bt::ble::gatt::{GattInterface, server::AppId};
+
+/// Application id of an app together with its optional name, so logs and errors
+/// of multi-app servers tell the apps apart. Compared by id only
+#[derive(Clone, Copy)]
+pub struct AppIdent {
+    pub id: AppId,
+    pub name: Option<&'static str>,
+}
+
+impl PartialEq for AppIdent {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for AppIdent {}
+
+impl std::hash::Hash for AppIdent {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl fmt::Display for AppIdent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "app {:#06x}", self.id)?;
+
+        match self.name {
+            Some(name) => write!(f, " \"{}\"", name),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for AppIdent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// GATT interface assigned by the stack, together with the app registered on it
+/// when known. Compared by interface only
+#[derive(Clone, Copy)]
+pub struct AppInterface {
+    pub interface: GattInterface,
+    pub app: Option<AppIdent>,
+}
+
+impl AppInterface {
+    /// Interface reported by the stack, before it is matched to an app
+    pub fn unknown(interface: GattInterface) -> Self {
+        Self {
+            interface,
+            app: None,
+        }
+    }
+}
+
+impl PartialEq for AppInterface {
+    fn eq(&self, other: &Self) -> bool {
+        self.interface == other.interface
+    }
+}
+
+impl Eq for AppInterface {}
+
+impl std::hash::Hash for AppInterface {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.interface.hash(state);
+    }
+}
+
+impl fmt::Display for AppInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interface {}", self.interface)?;
+
+        match self.app {
+            Some(app) => write!(f, " ({})", app),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for AppInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
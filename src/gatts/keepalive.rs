@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use esp_idf_svc::bt::{BdAddr, BtUuid, ble::gatt::server::ConnectionId};
+
+use super::{
+    attribute::defaults::BytesAttr,
+    characteristic::{Characteristic, CharacteristicConfig, WriteEcho},
+    service::{Service, ServiceInner},
+};
+
+pub const KEEPALIVE_UUID: u128 = 0x6a1f0003_8d3c_4b6e_9f2a_3c5e7b9d1e0f;
+
+pub struct KeepaliveConfig {
+    pub uuid: BtUuid,
+
+    // Period in which the client is expected to write the characteristic,
+    // any value is accepted
+    pub interval: Duration,
+    // Number of intervals without a write after which the peer is reported lost
+    pub max_missed: u32,
+    // Disconnect lost peers, e.g. so a safety interlock does not stay
+    // connected to an app which stopped responding
+    pub disconnect: bool,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            uuid: BtUuid::uuid128(KEEPALIVE_UUID),
+            interval: Duration::from_secs(1),
+            max_missed: 3,
+            disconnect: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveEvent {
+    // Peer did not write for `max_missed` intervals
+    Lost {
+        conn_id: ConnectionId,
+        peer_addr: BdAddr,
+        missed: u32,
+    },
+    // Lost peer, which was not disconnected, wrote again
+    Restored {
+        conn_id: ConnectionId,
+        peer_addr: BdAddr,
+    },
+}
+
+struct PeerState {
+    peer_addr: BdAddr,
+    // Latest write, or the time the connection was first seen
+    last_seen: Instant,
+    lost: bool,
+}
+
+/// Watchdog detecting connected clients which went silent, e.g. an app killed
+/// in the background while the link is kept alive by the phone. The client
+/// writes the keepalive characteristic every `interval`, connections which miss
+/// `max_missed` intervals in a row are reported through `events_rx`
+#[derive(Clone)]
+pub struct Keepalive(pub Arc<KeepaliveInner>);
+
+pub struct KeepaliveInner {
+    pub config: KeepaliveConfig,
+    pub characteristic: Characteristic<BytesAttr>,
+    peers: RwLock<HashMap<ConnectionId, PeerState>>,
+
+    pub events_rx: Receiver<KeepaliveEvent>,
+    events_tx: Sender<KeepaliveEvent>,
+}
+
+impl Keepalive {
+    pub fn new(config: KeepaliveConfig) -> anyhow::Result<Self> {
+        if config.interval.is_zero() || config.max_missed == 0 {
+            return Err(anyhow::anyhow!(
+                "Keepalive interval and max missed intervals must be non zero"
+            ));
+        }
+
+        let characteristic = Characteristic::new(
+            BytesAttr(Vec::new()),
+            CharacteristicConfig {
+                uuid: config.uuid.clone(),
+                value_max_len: 20,
+                writable: true,
+                description: Some(String::from("Keepalive")),
+                write_echo: WriteEcho::None,
+                ..Default::default()
+            },
+            None,
+        );
+        let (events_tx, events_rx) = unbounded();
+
+        let keepalive = Self(Arc::new(KeepaliveInner {
+            config,
+            characteristic,
+            peers: RwLock::new(HashMap::new()),
+            events_rx,
+            events_tx,
+        }));
+
+        let inner = Arc::downgrade(&keepalive.0);
+        keepalive.0.characteristic.set_on_write(move |conn_id, _| {
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+
+            if let Err(err) = inner.seen(conn_id) {
+                log::error!("Failed to record keepalive write: {:?}", err);
+            }
+        })?;
+
+        Ok(keepalive)
+    }
+
+    /// Registers the keepalive characteristic in the service and starts watching
+    /// connections of its app
+    pub fn register(&self, service: &Service) -> anyhow::Result<()> {
+        service.register_characteristic(&self.0.characteristic)?;
+
+        let keepalive = Arc::downgrade(&self.0);
+        let service = Arc::downgrade(&service.0);
+        let interval = self.0.config.interval;
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(interval);
+
+                let (Some(keepalive), Some(service)) = (keepalive.upgrade(), service.upgrade())
+                else {
+                    log::warn!("Keepalive or its service dropped, exiting keepalive thread");
+                    return;
+                };
+
+                if let Err(err) = keepalive.check(&service) {
+                    log::error!("Failed to check keepalive: {:?}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl KeepaliveInner {
+    fn seen(&self, conn_id: ConnectionId) -> anyhow::Result<()> {
+        let mut peers = self
+            .peers
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write keepalive peers"))?;
+
+        let Some(peer) = peers.get_mut(&conn_id) else {
+            // Connection not seen by the watchdog yet, it starts counting from now anyway
+            return Ok(());
+        };
+
+        peer.last_seen = Instant::now();
+        if peer.lost {
+            peer.lost = false;
+            self.send(KeepaliveEvent::Restored {
+                conn_id,
+                peer_addr: peer.peer_addr,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn check(&self, service: &Arc<ServiceInner>) -> anyhow::Result<()> {
+        // Snapshot, so connection events are not blocked while disconnecting
+        let connections = service
+            .get_app()?
+            .connections
+            .read()?
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut lost = Vec::new();
+        {
+            let mut peers = self
+                .peers
+                .write()
+                .map_err(|_| anyhow::anyhow!("Failed to write keepalive peers"))?;
+
+            peers.retain(|conn_id, _| connections.iter().any(|c| c.id() == *conn_id));
+
+            for connection in &connections {
+                let peer = peers.entry(connection.id()).or_insert_with(|| PeerState {
+                    peer_addr: connection.peer_addr(),
+                    last_seen: Instant::now(),
+                    lost: false,
+                });
+
+                let missed = (peer.last_seen.elapsed().as_millis()
+                    / self.config.interval.as_millis()) as u32;
+                if peer.lost || missed < self.config.max_missed {
+                    continue;
+                }
+
+                log::warn!(
+                    "Peer {:?} missed {} keepalive intervals",
+                    peer.peer_addr,
+                    missed
+                );
+                peer.lost = true;
+                lost.push((connection.clone(), missed));
+            }
+        }
+
+        for (connection, missed) in lost {
+            self.send(KeepaliveEvent::Lost {
+                conn_id: connection.id(),
+                peer_addr: connection.peer_addr(),
+                missed,
+            })?;
+
+            if self.config.disconnect {
+                connection.disconnect()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&self, event: KeepaliveEvent) -> anyhow::Result<()> {
+        self.events_tx
+            .send(event)
+            .map_err(|err| anyhow::anyhow!("Failed to send keepalive event: {:?}", err))
+    }
+}
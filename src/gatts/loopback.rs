@@ -0,0 +1,91 @@
+use std::{sync::Arc, time::Duration};
+
+use crossbeam_channel::{Receiver, bounded};
+use esp_idf_svc::bt::ble::gatt::server::ConnectionId;
+
+use super::{
+    att_error,
+    attribute::{AnyAttribute, Attribute, AttributeUpdate},
+    characteristic::Characteristic,
+    error::AttError,
+    pending::{self, HeldResponse},
+};
+
+/// Connection reported to write handlers for writes through `Loopback::write`
+pub const LOOPBACK_CONN_ID: ConnectionId = ConnectionId::MAX;
+
+/// In-memory peer of a characteristic, returned by `Characteristic::loopback`.
+/// Writes take the same path as writes of a connected peer, frames which would be
+/// indicated to peers are received from `frames_rx`.
+///
+/// Attribute updates are published to a channel of capacity 1, so tests need
+/// to take each update with `next_update` before applying the next one
+pub struct Loopback<T: Attribute> {
+    pub characteristic: Characteristic<T>,
+    pub frames_rx: Receiver<Vec<u8>>,
+}
+
+impl<T: Attribute> Loopback<T> {
+    pub(crate) fn new(characteristic: Characteristic<T>, frames_rx: Receiver<Vec<u8>>) -> Self {
+        Self {
+            characteristic,
+            frames_rx,
+        }
+    }
+
+    /// Writes bytes as a peer would, errors are those reported in the write response.
+    /// Writes needing approval wait for the decision on `pending_writes_rx`
+    pub fn write(&self, bytes: &[u8]) -> Result<(), AttError> {
+        let characteristic = &self.characteristic.0;
+
+        let Some(bytes) = characteristic.reassemble_write(bytes.to_vec(), LOOPBACK_CONN_ID)? else {
+            return Ok(());
+        };
+
+        characteristic.validate_write(&bytes)?;
+
+        let Some(timeout) = characteristic.write_approval() else {
+            return characteristic
+                .write_from_peer(&bytes, LOOPBACK_CONN_ID)
+                .map_err(|err| att_error(&err));
+        };
+
+        let (decision_tx, decision_rx) = bounded(1);
+        let response = HeldResponse::new(
+            timeout,
+            Box::new(move |decision| {
+                let _ = decision_tx.send(decision);
+            }),
+        );
+
+        characteristic
+            .clone()
+            .stage_write(bytes, LOOPBACK_CONN_ID, response.clone())
+            .map_err(|err| att_error(&err))?;
+        pending::await_decision(&decision_rx, &response)
+    }
+
+    /// Reads bytes as a peer would, starting at the given offset
+    pub fn read(&self, offset: u16) -> anyhow::Result<Vec<u8>> {
+        let bytes = self.characteristic.0.read_bytes(offset)?;
+
+        bytes
+            .get(offset as usize..)
+            .map(|bytes| bytes.to_vec())
+            .ok_or(anyhow::anyhow!("Read offset {} is out of value", offset))
+    }
+
+    /// Next frame which would be indicated to peers, None if there is none
+    pub fn next_frame(&self) -> Option<Vec<u8>> {
+        self.frames_rx.try_recv().ok()
+    }
+
+    pub fn next_update(&self, timeout: Duration) -> Option<AttributeUpdate<Arc<T>>> {
+        self.characteristic
+            .0
+            .attribute
+            .updates_rx
+            .recv_timeout(timeout)
+            .ok()
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use esp_idf_svc::bt::{
+    BdAddr,
+    ble::gatt::{GattConnReason, server::ConnectionId},
+};
+
+use crate::lock::{self, OrderedRwLock};
+
+/// Connection history of a single peer, kept in memory since boot
+#[derive(Debug, Clone)]
+pub struct PeerMetrics {
+    pub address: BdAddr,
+    pub connection_count: u32,
+
+    // Time the current or last connection was established
+    pub last_connected: Option<Instant>,
+    pub last_disconnected: Option<Instant>,
+    pub last_disconnect_reason: Option<GattConnReason>,
+
+    // Summed duration of finished connections, see `PeerMetrics::connected_duration`
+    // for the total including the current connection
+    pub total_connected: Duration,
+
+    pub connected: bool,
+}
+
+impl PeerMetrics {
+    fn new(address: BdAddr) -> Self {
+        Self {
+            address,
+            connection_count: 0,
+            last_connected: None,
+            last_disconnected: None,
+            last_disconnect_reason: None,
+            total_connected: Duration::ZERO,
+            connected: false,
+        }
+    }
+
+    /// Total time connected, including the ongoing connection
+    pub fn connected_duration(&self) -> Duration {
+        match (self.connected, self.last_connected) {
+            (true, Some(connected)) => self.total_connected + connected.elapsed(),
+            _ => self.total_connected,
+        }
+    }
+}
+
+pub struct MetricsConfig {
+    // Maximum number of tracked peers, peer disconnected for the longest time
+    // is dropped when a new peer connects over the limit
+    pub max_peers: usize,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { max_peers: 16 }
+    }
+}
+
+#[derive(Default)]
+struct MetricsState {
+    peers: HashMap<BdAddr, PeerMetrics>,
+    // Links currently connected, every app reports the same link
+    active: HashMap<ConnectionId, BdAddr>,
+}
+
+pub(crate) struct ConnectionMetrics {
+    config: RwLock<MetricsConfig>,
+    state: OrderedRwLock<lock::Metrics, MetricsState>,
+}
+
+impl ConnectionMetrics {
+    pub fn new() -> Self {
+        Self {
+            config: RwLock::new(MetricsConfig::default()),
+            state: Default::default(),
+        }
+    }
+
+    pub fn set_config(&self, config: MetricsConfig) -> anyhow::Result<()> {
+        *self
+            .config
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write metrics config"))? = config;
+
+        Ok(())
+    }
+
+    pub fn get(&self, address: BdAddr) -> anyhow::Result<Option<PeerMetrics>> {
+        Ok(self.state.read()?.peers.get(&address).cloned())
+    }
+
+    pub fn all(&self) -> anyhow::Result<Vec<PeerMetrics>> {
+        Ok(self.state.read()?.peers.values().cloned().collect())
+    }
+
+    pub fn connected(&self, address: BdAddr, connection: ConnectionId) -> anyhow::Result<()> {
+        let max_peers = self
+            .config
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read metrics config"))?
+            .max_peers;
+
+        let mut state = self.state.write()?;
+        if state.active.insert(connection, address).is_some() {
+            return Ok(());
+        }
+
+        if !state.peers.contains_key(&address) && state.peers.len() >= max_peers.max(1) {
+            let oldest = state
+                .peers
+                .values()
+                .filter(|peer| !peer.connected)
+                .min_by_key(|peer| peer.last_disconnected)
+                .map(|peer| peer.address);
+
+            if let Some(oldest) = oldest {
+                state.peers.remove(&oldest);
+            }
+        }
+
+        let peer = state
+            .peers
+            .entry(address)
+            .or_insert_with(|| PeerMetrics::new(address));
+        peer.connection_count += 1;
+        peer.last_connected = Some(Instant::now());
+        peer.connected = true;
+
+        Ok(())
+    }
+
+    pub fn disconnected(
+        &self,
+        connection: ConnectionId,
+        reason: GattConnReason,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.write()?;
+        let Some(address) = state.active.remove(&connection) else {
+            return Ok(());
+        };
+
+        let Some(peer) = state.peers.get_mut(&address) else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        if let Some(connected) = peer.last_connected {
+            peer.total_connected += now - connected;
+        }
+        peer.last_disconnected = Some(now);
+        peer.last_disconnect_reason = Some(reason);
+        peer.connected = false;
+
+        Ok(())
+    }
+}
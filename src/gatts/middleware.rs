@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use esp_idf_svc::bt::{
+    BtUuid,
+    ble::gatt::{GattStatus, Handle},
+};
+
+use super::{connection::Connection, error::AttError};
+use crate::guard;
+
+/// Peer read passing through the middleware chain of an app. `value` holds the
+/// whole attribute value, the response is cut from it at `offset`
+#[derive(Debug, Clone)]
+pub struct ReadRequest {
+    pub connection: Connection,
+    pub handle: Handle,
+    pub uuid: BtUuid,
+    pub offset: u16,
+    pub value: Vec<u8>,
+}
+
+/// Peer write passing through the middleware chain of an app, after long writes
+/// were reassembled and before the value is validated and applied
+#[derive(Debug, Clone)]
+pub struct WriteRequest {
+    pub connection: Connection,
+    pub handle: Handle,
+    pub uuid: BtUuid,
+    pub value: Vec<u8>,
+}
+
+/// Interceptor of every read and write of an app's attributes, added with
+/// `App::add_middleware`. Middlewares run in the order they were added, each
+/// can change the request or deny it with the error reported to the peer,
+/// which skips the rest of the chain
+pub trait Middleware: Send + Sync + 'static {
+    fn on_read(&self, _request: &mut ReadRequest) -> Result<(), AttError> {
+        Ok(())
+    }
+
+    fn on_write(&self, _request: &mut WriteRequest) -> Result<(), AttError> {
+        Ok(())
+    }
+}
+
+pub(crate) fn run_read(
+    chain: &[Arc<dyn Middleware>],
+    mut request: ReadRequest,
+) -> Result<ReadRequest, AttError> {
+    for middleware in chain {
+        let middleware = middleware.clone();
+        let result;
+        (request, result) = guard::run_hook("middleware read", move || {
+            let result = middleware.on_read(&mut request);
+            (request, result)
+        })
+        .map_err(|_| AttError::Status(GattStatus::Error))?;
+
+        result?;
+    }
+
+    Ok(request)
+}
+
+pub(crate) fn run_write(
+    chain: &[Arc<dyn Middleware>],
+    mut request: WriteRequest,
+) -> Result<WriteRequest, AttError> {
+    for middleware in chain {
+        let middleware = middleware.clone();
+        let result;
+        (request, result) = guard::run_hook("middleware write", move || {
+            let result = middleware.on_write(&mut request);
+            (request, result)
+        })
+        .map_err(|_| AttError::Status(GattStatus::Error))?;
+
+        result?;
+    }
+
+    Ok(request)
+}
+
+/// Logs every read and write at debug level
+pub struct Logging;
+
+impl Middleware for Logging {
+    fn on_read(&self, request: &mut ReadRequest) -> Result<(), AttError> {
+        log::debug!(
+            "Peer {:?} reads {:?} (handle {}) at offset {}",
+            request.connection.peer_addr(),
+            request.uuid,
+            request.handle,
+            request.offset
+        );
+
+        Ok(())
+    }
+
+    fn on_write(&self, request: &mut WriteRequest) -> Result<(), AttError> {
+        log::debug!(
+            "Peer {:?} writes {} bytes to {:?} (handle {})",
+            request.connection.peer_addr(),
+            request.value.len(),
+            request.uuid,
+            request.handle
+        );
+
+        Ok(())
+    }
+}
@@ -1,75 +1,236 @@
 pub mod app;
 pub mod attribute;
 pub mod characteristic;
+pub mod checksum;
+pub mod chunked;
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod connection;
 pub mod descriptor;
+pub mod diff;
+pub mod error;
 pub mod event;
+pub mod filter;
+pub mod ident;
+pub mod keepalive;
+pub mod loopback;
+pub mod metrics;
+pub mod middleware;
+pub mod pending;
+pub mod persistence;
+pub mod protocol;
+pub mod reassembly;
+mod routing;
+pub mod schema;
+pub mod self_test;
 pub mod service;
+pub mod session;
+pub mod standard;
+pub mod stream;
+pub mod table;
+mod tree;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     mem::{Discriminant, discriminant},
-    sync::{Arc, RwLock},
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, Instant},
 };
 
 use app::{App, AppInner};
+use characteristic::Subscription;
 
-use attribute::AnyAttribute;
-use connection::ConnectionStatus;
-use crossbeam_channel::{Receiver, Sender, unbounded};
+use attribute::{
+    AnyAttribute,
+    defaults::UuidAttr,
+    encoding::{self, Endianness, IntEncoding},
+};
+use connection::{
+    CongestionStatus, Connection, ConnectionEvent, ConnectionStatus, WriteProgress,
+    WriteProgressState,
+};
+use crossbeam_channel::{Receiver, Select, Sender, unbounded};
+use error::AttError;
 use esp_idf_svc::{
     bt::{
-        BdAddr,
+        BdAddr, BtUuid,
         ble::gatt::{
             GattConnParams, GattConnReason, GattInterface, GattResponse, GattStatus, Handle,
             server::{ConnectionId, EspGatts, TransferId},
         },
     },
-    sys::ESP_GATT_MAX_ATTR_LEN,
+    nvs::EspDefaultNvsPartition,
+    sys::{
+        ESP_GATT_MAX_ATTR_LEN, esp, esp_ble_gap_disconnect, esp_ble_gatt_set_local_mtu,
+        esp_ble_gatts_send_response, esp_gatt_status_t,
+    },
+};
+use event::{EventStamp, GattsEvent, GattsEventMessage};
+use filter::ConnectionFilter;
+use ident::AppInterface;
+use metrics::{ConnectionMetrics, MetricsConfig, PeerMetrics};
+use middleware::{ReadRequest, WriteRequest};
+use pending::HeldResponse;
+use persistence::Persistence;
+use reassembly::{PrepareQueues, WriteReassembler};
+use routing::{ParkedConnection, Route, Router};
+use schema::{EncodingSchema, GattSchema, SCHEMA_VERSION};
+use self_test::SelfTestReport;
+use session::{Session, SessionConfig, SessionEvent, Sessions};
+
+use crate::{
+    ble::ExtBtDriver,
+    gap::{GapInner, peers::bonded_peers},
+    guard,
+    health::DispatcherHealth,
+    lock::{self, OrderedRwLock},
+    suspend::PeerSubscription,
+    trace,
+    waiters::{EventWaiters, Waiter},
 };
-use event::{GattsEvent, GattsEventMessage};
-
-use crate::ble::ExtBtDriver;
 use esp_idf_svc as svc;
 
-struct PrepareWriteBuffer {
-    value: Vec<u8>,
-    handle: Handle,
+// Maps an error of a failed write to the status reported to the peer
+fn att_error(err: &anyhow::Error) -> AttError {
+    err.downcast_ref::<AttError>()
+        .copied()
+        .unwrap_or(AttError::Status(GattStatus::Error))
+}
+
+// Canonical 128-bit form of the UUID, short UUIDs are expanded with the Bluetooth base UUID
+fn uuid_string(uuid: &BtUuid) -> String {
+    UuidAttr::from(uuid).to_string()
+}
+
+const EXTENDED_PROPERTIES_UUID: u16 = 0x2900;
+const USER_DESCRIPTION_UUID: u16 = 0x2901;
+const CCCD_UUID: u16 = 0x2902;
+const SCCD_UUID: u16 = 0x2903;
+
+type AttributeMap = HashMap<Handle, Arc<dyn AnyAttribute>>;
+type GattsEventWaiters = EventWaiters<Discriminant<GattsEvent>, GattsEventMessage>;
+pub(crate) type GattsWaiter<'a> =
+    Waiter<'a, lock::Events, Discriminant<GattsEvent>, GattsEventMessage>;
+
+// App a connection was routed to by its advertising identity, `owner` is None
+// when the connection is visible to every app
+#[derive(Clone, Copy)]
+struct ConnectionRoute {
+    owner: Option<GattInterface>,
+    // Cleared on disconnect, so a new connection reusing the id is routed again
+    connected: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Filtered {
+    accepted: bool,
+    // Apps which saw the connection and not yet its disconnect
+    apps: usize,
+}
+
+enum FilterDecision {
+    Accepted,
+    Rejected,
+    // Rejected when another app saw the connection
+    AlreadyRejected,
 }
 
 pub struct Gatts(pub Arc<GattsInner>);
 
 pub struct GattsInner {
     gatts: EspGatts<'static, svc::bt::Ble, ExtBtDriver>,
-    pub apps: Arc<RwLock<HashMap<GattInterface, Arc<AppInner>>>>,
-    write_buffer: Arc<RwLock<HashMap<TransferId, PrepareWriteBuffer>>>,
-    attributes: Arc<RwLock<HashMap<Handle, Arc<dyn AnyAttribute>>>>,
-
-    pub connections_rx: Receiver<ConnectionStatus>,
-    connections_tx: Sender<ConnectionStatus>,
+    // Set once Gap is created, used for link operations of connections
+    pub(crate) gap: RwLock<Weak<GapInner>>,
+    pub apps: Arc<OrderedRwLock<lock::Apps, HashMap<GattInterface, Arc<AppInner>>>>,
+    // Prepare queue of each connection, see `PrepareQueues`
+    write_buffer: Arc<OrderedRwLock<lock::WriteBuffer, PrepareQueues<ConnectionId, Handle>>>,
+    // Responses of writes staged for approval, rejected by the dispatcher
+    // once their deadline passes
+    held_responses: OrderedRwLock<lock::HeldResponses, Vec<HeldResponse>>,
+    attributes: Arc<OrderedRwLock<lock::Attributes, AttributeMap>>,
+    persistence: Option<Persistence>,
+
+    connection_filter: RwLock<ConnectionFilter>,
+    // Filter decision of each connection, taken once however many apps see it,
+    // until every app which saw the connection saw its disconnect
+    filtered_connections: OrderedRwLock<lock::FilteredConnections, HashMap<ConnectionId, Filtered>>,
+    sessions: Sessions,
+    metrics: ConnectionMetrics,
+    congested_connections: OrderedRwLock<lock::CongestedConnections, HashSet<ConnectionId>>,
+    // CCCD value of each peer by characteristic handle, CCCD reads of a peer
+    // are answered from it
+    subscriptions:
+        OrderedRwLock<lock::Subscriptions, HashMap<(ConnectionId, Handle), Subscription>>,
+    // Subscriptions of bonded peers which are not connected, CCCD values of
+    // bonded clients persist across connections
+    saved_subscriptions: RwLock<HashMap<BdAddr, Vec<(Handle, Subscription)>>>,
+    // Characteristics each peer opted in to compression of, by handle
+    compressing_peers: OrderedRwLock<lock::CompressingPeers, HashSet<(ConnectionId, Handle)>>,
+    connection_routes:
+        OrderedRwLock<lock::ConnectionRoutes, HashMap<ConnectionId, ConnectionRoute>>,
+    // Advertising identities peers connected through, reported by Gap
+    routes_rx: Receiver<Route>,
+    routes_tx: Sender<Route>,
+
+    // Connection events of all apps, each connection in the order the stack reported them
+    pub connections_rx: Receiver<ConnectionEvent>,
+    connections_tx: Sender<ConnectionEvent>,
 
     pub gap_connections_rx: Receiver<ConnectionStatus>,
     gap_connections_tx: Sender<ConnectionStatus>,
 
-    gatts_events: Arc<RwLock<HashMap<Discriminant<GattsEvent>, Sender<GattsEventMessage>>>>,
+    pub congestion_rx: Receiver<CongestionStatus>,
+    congestion_tx: Sender<CongestionStatus>,
+
+    // Progress of long writes, each queued fragment is reported before the peer
+    // executes the write
+    pub write_progress_rx: Receiver<WriteProgress>,
+    write_progress_tx: Sender<WriteProgress>,
+
+    gatts_events: Arc<OrderedRwLock<lock::Events, GattsEventWaiters>>,
+    pub(crate) health: Arc<DispatcherHealth>,
 }
 
 impl Gatts {
-    pub fn new(bt: ExtBtDriver) -> anyhow::Result<Self> {
+    pub fn new(bt: ExtBtDriver, nvs: Option<EspDefaultNvsPartition>) -> anyhow::Result<Self> {
         let (connections_tx, connections_rx) = unbounded();
         let (gap_connections_tx, gap_connections_rx) = unbounded();
+        let (congestion_tx, congestion_rx) = unbounded();
+        let (write_progress_tx, write_progress_rx) = unbounded();
+        let (routes_tx, routes_rx) = unbounded();
+
+        guard::warn_panic_abort();
 
         let gatts = EspGatts::new(bt)?;
         let gatts_inner = GattsInner {
             gatts,
+            gap: RwLock::new(Weak::new()),
             apps: Default::default(),
             gatts_events: Default::default(),
             write_buffer: Default::default(),
+            held_responses: Default::default(),
             attributes: Default::default(),
+            persistence: nvs.map(Persistence::new).transpose()?,
+            connection_filter: Default::default(),
+            filtered_connections: Default::default(),
+            sessions: Sessions::new(),
+            metrics: ConnectionMetrics::new(),
+            congested_connections: Default::default(),
+            subscriptions: Default::default(),
+            saved_subscriptions: Default::default(),
+            compressing_peers: Default::default(),
+            connection_routes: Default::default(),
+            routes_rx,
+            routes_tx,
             connections_rx,
             connections_tx,
             gap_connections_rx,
             gap_connections_tx,
+            congestion_rx,
+            congestion_tx,
+            write_progress_rx,
+            write_progress_tx,
+            health: Arc::new(DispatcherHealth::new()),
         };
 
         let gatts = Self(Arc::new(gatts_inner));
@@ -80,14 +241,22 @@ impl Gatts {
         Ok(gatts)
     }
 
+    /// Underlying esp-idf-svc GATT server, for stack features this crate does not wrap.
+    ///
+    /// Events are routed by the callback this crate subscribed, calling `subscribe`
+    /// or `unsubscribe` on it stops all apps. Apps, services and attributes created
+    /// through it are unknown to `Gatts`, their read and write requests are answered
+    /// with an error unless their handles are auto responded by the stack. Responses
+    /// to requests of attributes registered here are sent by this crate, sending
+    /// them again fails the transfer
+    pub fn raw(&self) -> &EspGatts<'static, svc::bt::Ble, ExtBtDriver> {
+        &self.0.gatts
+    }
+
     fn configure_global_events(&self) -> anyhow::Result<()> {
         let (tx, rx) = unbounded();
 
-        let mut gatt_events = self
-            .0
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events map"))?;
+        let mut gatt_events = self.0.gatts_events.write()?;
 
         gatt_events.insert(
             discriminant(&GattsEvent::Read {
@@ -148,19 +317,77 @@ impl Gatts {
             discriminant(&GattsEvent::Mtu { conn_id: 0, mtu: 0 }),
             tx.clone(),
         );
+        gatt_events.insert(
+            discriminant(&GattsEvent::Congest {
+                conn_id: 0,
+                congested: false,
+            }),
+            tx.clone(),
+        );
 
         let gatts = Arc::downgrade(&self.0);
+        let routes_rx = self.0.routes_rx.clone();
         std::thread::Builder::new()
             .stack_size(8 * 1024)
             .spawn(move || {
-                for event in rx.iter() {
+                // Single thread handles events one at a time in callback order, which
+                // keeps events of each connection ordered for every consumer. Events
+                // of connections waiting for their route are held back, never blocking
+                // the thread, see `routing`
+                let mut router = Router::default();
+                loop {
+                    let mut select = Select::new();
+                    let events = select.recv(&rx);
+                    select.recv(&routes_rx);
+                    let deadline = [
+                        router.next_deadline(),
+                        gatts.upgrade().and_then(|gatts| gatts.next_held_deadline()),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .min();
+                    let operation = match deadline {
+                        Some(deadline) => select.select_deadline(deadline).ok(),
+                        None => Some(select.select()),
+                    };
+
                     let Some(gatts) = gatts.upgrade() else {
                         log::warn!("Failed to upgrade Gatts, exiting write events thread");
                         return;
                     };
 
-                    if let Err(err) = gatts.handle_gatts_global_event(event) {
-                        log::error!("Failed to handle global event: {:?}", err);
+                    let ready = match operation {
+                        Some(operation) if operation.index() == events => {
+                            let Ok(event) = operation.recv(&rx) else {
+                                return;
+                            };
+                            gatts.route_event(&mut router, event)
+                        }
+                        Some(operation) => {
+                            let Ok(route) = operation.recv(&routes_rx) else {
+                                return;
+                            };
+                            gatts.add_route(&mut router, route)
+                        }
+                        None => Ok(Vec::new()),
+                    };
+                    let ready = ready.unwrap_or_else(|err| {
+                        log::error!("Failed to route global event: {:?}", err);
+                        Vec::new()
+                    });
+
+                    for event in ready.into_iter().chain(gatts.expire_routes(&mut router)) {
+                        let stamp = event.2;
+                        let result = stamp.handle(|| {
+                            gatts.health.time(|| gatts.handle_gatts_global_event(event))
+                        });
+                        if let Err(err) = result {
+                            log::error!("Failed to handle global event: {:?}", err);
+                        }
+                    }
+
+                    if let Err(err) = gatts.expire_held_responses() {
+                        log::error!("Failed to expire staged writes: {:?}", err);
                     }
                 }
             })?;
@@ -170,9 +397,12 @@ impl Gatts {
 
     fn init_callback(&self) -> anyhow::Result<()> {
         let callback_inner_ref = Arc::downgrade(&self.0.gatts_events);
+        let health = self.0.health.clone();
         self.0
             .gatts
             .subscribe(move |(interface, e)| {
+                // Stamped first, so the time does not include locking or logging
+                let stamp = EventStamp::now();
                 log::info!("Received event {:?}", (interface, &e));
 
                 let Some(callback_map) = callback_inner_ref.upgrade() else {
@@ -180,22 +410,30 @@ impl Gatts {
                     return;
                 };
 
-                let Ok(callback_map) = callback_map.read() else {
-                    log::error!("Failed to acquire read lock on Gatts events map");
-                    return;
-                };
-
                 let event = GattsEvent::from(e);
-                let Some(sender) = callback_map.get(&discriminant(&event)) else {
-                    log::warn!("No callback found for event {:?}", event);
-                    return;
+                let key = discriminant(&event);
+
+                let undelivered = {
+                    let Ok(callback_map) = callback_map.read() else {
+                        log::error!("Failed to acquire read lock on Gatts events map");
+                        return;
+                    };
+
+                    let sender = callback_map.get(&key);
+                    if sender.is_none() {
+                        log::warn!("No callback found for event {:?}", event);
+                    }
+
+                    health.dispatch(sender, GattsEventMessage(interface, event, stamp))
                 };
 
-                sender
-                    .send(GattsEventMessage(interface, event))
-                    .unwrap_or_else(|err| {
-                        log::error!("Failed to send event: {:?}", err);
-                    });
+                // Waiter may register right after the event arrived, keep it for replay
+                if let Some(message) = undelivered {
+                    match callback_map.write() {
+                        Ok(mut callback_map) => callback_map.buffer(key, message),
+                        Err(err) => log::error!("Failed to buffer event: {:?}", err),
+                    }
+                }
             })
             .map_err(|err| anyhow::anyhow!("Failed to subscribe to GATT events: {:?}", err))?;
 
@@ -209,22 +447,192 @@ impl Gatts {
         if self
             .0
             .apps
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire write lock on Gatts apps"))?
+            .write()?
             .insert(interface, app.0.clone())
             .is_some()
         {
             return Err(anyhow::anyhow!(
-                "App with interface {:?} already exists",
-                interface
+                "App with {} already exists",
+                app.0.app_interface()?
             ));
         }
 
         Ok(app.clone())
     }
+
+    /// Starts every service of every registered app, see `App::start_all`
+    pub fn start_all(&self) -> anyhow::Result<()> {
+        self.for_each_app("start", App::start_all)
+    }
+
+    /// Stops every service of every registered app, e.g. while an OTA update is
+    /// applied, see `App::stop_all`
+    pub fn stop_all(&self) -> anyhow::Result<()> {
+        self.for_each_app("stop", App::stop_all)
+    }
+
+    fn for_each_app(
+        &self,
+        operation: &str,
+        f: impl Fn(&App) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let apps = self.0.apps.read()?.values().cloned().collect::<Vec<_>>();
+
+        let errors = apps
+            .into_iter()
+            .map(App)
+            .filter_map(|app| f(&app).err())
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Failed to {} services of some apps: {:?}",
+                operation,
+                errors
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Sets filter deciding which peers may connect, applies to new connections only
+    pub fn set_connection_filter(&self, filter: ConnectionFilter) -> anyhow::Result<()> {
+        *self
+            .0
+            .connection_filter
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write connection filter"))? = filter;
+
+        Ok(())
+    }
+
+    pub fn set_session_config(&self, config: SessionConfig) -> anyhow::Result<()> {
+        self.0.sessions.set_config(config)
+    }
+
+    /// Sets callback invoked when a session starts, resumes on reconnect, is suspended
+    /// on disconnect or ends
+    pub fn on_session(
+        &self,
+        handler: impl Fn(SessionEvent) + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        self.0.sessions.set_handler(Arc::new(handler))
+    }
+
+    pub fn session(&self, identity: BdAddr) -> anyhow::Result<Option<Session>> {
+        self.0.sessions.get(identity)
+    }
+
+    pub fn session_by_connection(&self, conn_id: ConnectionId) -> anyhow::Result<Option<Session>> {
+        self.0.sessions.by_connection(conn_id)
+    }
+
+    /// Ends session of the peer and drops its state, e.g. after the bond is removed
+    pub fn remove_session(&self, identity: BdAddr) -> anyhow::Result<Option<Session>> {
+        self.0.sessions.remove(identity)
+    }
+
+    /// Whether the controller currently reports the connection as congested, sending
+    /// notifications or indications to it fails until the congestion clears
+    pub fn is_congested(&self, conn_id: ConnectionId) -> anyhow::Result<bool> {
+        Ok(self.0.congested_connections.read()?.contains(&conn_id))
+    }
+
+    pub fn set_metrics_config(&self, config: MetricsConfig) -> anyhow::Result<()> {
+        self.0.metrics.set_config(config)
+    }
+
+    /// Connection history of the peer, None if it did not connect since boot
+    /// or was dropped to stay within `MetricsConfig::max_peers`
+    pub fn peer_metrics(&self, address: BdAddr) -> anyhow::Result<Option<PeerMetrics>> {
+        self.0.metrics.get(address)
+    }
+
+    pub fn all_peer_metrics(&self) -> anyhow::Result<Vec<PeerMetrics>> {
+        self.0.metrics.all()
+    }
+
+    /// Sets MTU offered to peers during MTU exchange, valid range is 23..=517.
+    /// Should be called before peers connect, already negotiated MTUs are not changed
+    pub fn set_local_mtu(&self, mtu: u16) -> anyhow::Result<()> {
+        const MTU_RANGE: std::ops::RangeInclusive<u16> = 23..=517;
+
+        if !MTU_RANGE.contains(&mtu) {
+            return Err(anyhow::anyhow!(
+                "Local MTU {} is outside of range {:?}",
+                mtu,
+                MTU_RANGE
+            ));
+        }
+
+        esp!(unsafe { esp_ble_gatt_set_local_mtu(mtu) })
+            .map_err(|err| anyhow::anyhow!("Failed to set local MTU {}: {:?}", mtu, err))
+    }
+
+    /// Describes all registered services, characteristics and their value types,
+    /// see `esp-bluedroid-cli codegen` for generating client code from it
+    pub fn schema(&self) -> anyhow::Result<GattSchema> {
+        let encoding = encoding::encoding_config()?;
+
+        let mut apps = self
+            .0
+            .apps
+            .read()?
+            .iter()
+            .map(|(interface, app)| (*interface, app.clone()))
+            .collect::<Vec<_>>();
+        apps.sort_by_key(|(interface, _)| *interface);
+
+        let mut services = Vec::new();
+        for (_, app) in apps {
+            services.extend(app.schema()?);
+        }
+
+        Ok(GattSchema {
+            schema_version: SCHEMA_VERSION,
+            encoding: EncodingSchema {
+                fixed_int: encoding.int_encoding == IntEncoding::Fixed,
+                big_endian: encoding.endianness == Endianness::Big,
+            },
+            services,
+        })
+    }
+
+    /// Schema as pretty printed JSON, ready to be saved and passed to the CLI
+    #[cfg(feature = "json")]
+    pub fn export_schema(&self) -> anyhow::Result<String> {
+        serde_json::to_string_pretty(&self.schema()?)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize GATT schema: {:?}", err))
+    }
+
+    /// Checks the registered GATT table: values round-trip through their encoding,
+    /// characteristics are registered within their service and descriptors belong
+    /// to registered characteristics. Meant to run at boot once all apps are
+    /// registered, so misconfigurations show up before a peer hits them
+    pub fn self_test(&self) -> anyhow::Result<SelfTestReport> {
+        self_test::run(&self.0)
+    }
+
+    /// Logs the registered GATT tree at info level with names of well-known
+    /// UUIDs, e.g. once at boot after all apps are registered and started
+    pub fn log_table(&self) -> anyhow::Result<()> {
+        for line in tree::render(&self.0)?.lines() {
+            log::info!("{}", line);
+        }
+
+        Ok(())
+    }
 }
 
 impl GattsInner {
+    pub(crate) fn get_gap(&self) -> anyhow::Result<Arc<GapInner>> {
+        self.gap
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read Gap"))?
+            .upgrade()
+            .ok_or(anyhow::anyhow!("Failed to upgrade Gap, is it created?"))
+    }
+
     fn send_response(
         &self,
         attribute_handle: Handle,
@@ -234,24 +642,68 @@ impl GattsInner {
         status: GattStatus,
         response: Option<&GattResponse>,
     ) -> anyhow::Result<()> {
-        let (tx, rx) = unbounded();
+        self.await_response_complete(response.map(|_| attribute_handle), || {
+            self.gatts
+                .send_response(gatts_if, conn_id, trans_id, status, response)
+                .map_err(|err| anyhow::anyhow!("Failed to send GATT response: {:?}", err))
+        })
+    }
+
+    fn send_error_response(
+        &self,
+        attribute_handle: Handle,
+        gatts_if: GattInterface,
+        conn_id: ConnectionId,
+        trans_id: TransferId,
+        error: AttError,
+    ) -> anyhow::Result<()> {
+        match error {
+            AttError::Status(status) => {
+                self.send_response(attribute_handle, gatts_if, conn_id, trans_id, status, None)
+            }
+            // Application error codes are not representable as GattStatus,
+            // so the response is sent through the raw API
+            AttError::Application(code) => self.await_response_complete(None, || {
+                esp!(unsafe {
+                    esp_ble_gatts_send_response(
+                        gatts_if,
+                        conn_id,
+                        trans_id,
+                        code as esp_gatt_status_t,
+                        std::ptr::null_mut(),
+                    )
+                })
+                .map_err(|err| anyhow::anyhow!("Failed to send GATT error response: {:?}", err))
+            }),
+        }
+    }
+
+    /// Registers a one-shot waiter for the next event of the given kind, it is
+    /// deregistered once dropped
+    pub(crate) fn waiter(
+        &self,
+        callback_key: Discriminant<GattsEvent>,
+    ) -> anyhow::Result<GattsWaiter<'_>> {
+        Waiter::register(&self.gatts_events, callback_key)
+    }
+
+    fn await_response_complete(
+        &self,
+        attribute_handle: Option<Handle>,
+        send: impl FnOnce() -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
         let callback_key = discriminant(&GattsEvent::ResponseComplete {
             status: GattStatus::Busy,
             handle: 0,
         });
 
-        self.gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events"))?
-            .insert(callback_key.clone(), tx.clone());
+        let rx = self.waiter(callback_key)?;
 
-        self.gatts
-            .send_response(gatts_if, conn_id, trans_id, status, response)
-            .map_err(|err| anyhow::anyhow!("Failed to send GATT response: {:?}", err))?;
+        send()?;
 
         match rx.recv_timeout(std::time::Duration::from_secs(5)) {
-            Ok(GattsEventMessage(_, GattsEvent::ResponseComplete { status, handle })) => {
-                if attribute_handle != handle {
+            Ok(GattsEventMessage(_, GattsEvent::ResponseComplete { status, handle }, _)) => {
+                if attribute_handle.is_some_and(|attribute_handle| attribute_handle != handle) {
                     return Err(anyhow::anyhow!(
                         "Received unexpected GATT attribute handle: {:?}",
                         attribute_handle
@@ -269,11 +721,487 @@ impl GattsInner {
         }
     }
 
+    // Peer may send requests before the disconnect of a rejected connection completes
+    fn send_write_progress(
+        &self,
+        conn_id: ConnectionId,
+        handle: Handle,
+        received: usize,
+        state: WriteProgressState,
+    ) -> anyhow::Result<()> {
+        // Progress is informative, an attribute removed meanwhile has no expected size
+        let expected = match self.get_attribute(handle) {
+            Ok(attribute) => attribute.expected_write_len()?,
+            Err(_) => None,
+        };
+
+        self.write_progress_tx
+            .send(WriteProgress {
+                conn_id,
+                handle,
+                received,
+                expected,
+                state,
+            })
+            .map_err(|err| anyhow::anyhow!("Failed to send write progress: {:?}", err))
+    }
+
+    fn check_not_rejected(&self, conn_id: ConnectionId) -> anyhow::Result<()> {
+        if self.is_rejected(conn_id)? {
+            return Err(AttError::Status(GattStatus::InsufAuthorization).into());
+        }
+
+        Ok(())
+    }
+
+    fn is_rejected(&self, conn_id: ConnectionId) -> anyhow::Result<bool> {
+        Ok(self
+            .filtered_connections
+            .read()?
+            .get(&conn_id)
+            .is_some_and(|filtered| !filtered.accepted))
+    }
+
+    // Runs the connection filter for the first app which sees a connection,
+    // the other apps get the same decision
+    fn filter_connection(&self, connection: &Connection) -> anyhow::Result<FilterDecision> {
+        let conn_id = connection.id();
+        if let Some(filtered) = self.filtered_connections.write()?.get_mut(&conn_id) {
+            filtered.apps += 1;
+
+            return Ok(match filtered.accepted {
+                true => FilterDecision::Accepted,
+                false => FilterDecision::AlreadyRejected,
+            });
+        }
+
+        let filter = self
+            .connection_filter
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read connection filter"))?
+            .clone();
+        // Custom filters run without holding the decisions
+        let accepted = filter.accepts(connection);
+
+        self.filtered_connections
+            .write()?
+            .insert(conn_id, Filtered { accepted, apps: 1 });
+
+        Ok(match accepted {
+            true => FilterDecision::Accepted,
+            false => FilterDecision::Rejected,
+        })
+    }
+
+    // Whether a disconnected connection was rejected, its decision is dropped
+    // once the last app which saw the connection saw the disconnect
+    fn filter_disconnected(&self, conn_id: ConnectionId) -> anyhow::Result<bool> {
+        let mut filtered_connections = self.filtered_connections.write()?;
+        let Some(filtered) = filtered_connections.get_mut(&conn_id) else {
+            return Ok(false);
+        };
+
+        let rejected = !filtered.accepted;
+        filtered.apps = filtered.apps.saturating_sub(1);
+        if filtered.apps == 0 {
+            filtered_connections.remove(&conn_id);
+        }
+
+        Ok(rejected)
+    }
+
+    /// Routes a new connection to the app of the advertising identity a peer
+    /// connected through, reported by Gap with the HCI handle of the link
+    pub(crate) fn route_connection(
+        &self,
+        interface: GattInterface,
+        conn_handle: u16,
+    ) -> anyhow::Result<()> {
+        self.routes_tx
+            .send(Route {
+                interface,
+                conn_handle,
+                at: Instant::now(),
+            })
+            .map_err(|err| anyhow::anyhow!("Failed to send connection route: {:?}", err))
+    }
+
+    // Holds back events of a new connection until its route is known, returns
+    // the events ready to be handled. Without advertising identities every app
+    // sees every connection
+    fn route_event(
+        &self,
+        router: &mut Router,
+        event: GattsEventMessage,
+    ) -> anyhow::Result<Vec<GattsEventMessage>> {
+        let Some(conn_id) = event.1.conn_id() else {
+            return Ok(vec![event]);
+        };
+
+        if router.is_parked(conn_id) {
+            router.hold(conn_id, event);
+            return Ok(Vec::new());
+        }
+
+        let routed = self
+            .connection_routes
+            .read()?
+            .get(&conn_id)
+            .is_some_and(|route| route.connected);
+        if routed || !matches!(event.1, GattsEvent::PeerConnected { .. }) {
+            return Ok(vec![event]);
+        }
+
+        let routing = match self.get_gap() {
+            Ok(gap) => gap.has_identities()?,
+            Err(_) => false,
+        };
+        if !routing {
+            self.set_route(conn_id, None)?;
+            return Ok(vec![event]);
+        }
+
+        match router.take_route(event.2.at) {
+            Some(route) => {
+                self.set_route(conn_id, Some(route.interface))?;
+                Ok(vec![event])
+            }
+            None => {
+                router.park(conn_id, event);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    // Route reported by Gap, releases the parked connection it belongs to
+    fn add_route(
+        &self,
+        router: &mut Router,
+        route: Route,
+    ) -> anyhow::Result<Vec<GattsEventMessage>> {
+        match router.add_route(route) {
+            Some(parked) => self.release_parked(parked, Some(route.interface)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Releases parked connections which got no route in time to every app
+    fn expire_routes(&self, router: &mut Router) -> Vec<GattsEventMessage> {
+        router
+            .expire(Instant::now())
+            .into_iter()
+            .flat_map(|parked| {
+                self.release_parked(parked, None).unwrap_or_else(|err| {
+                    log::error!("Failed to release parked connection: {:?}", err);
+                    Vec::new()
+                })
+            })
+            .collect()
+    }
+
+    // Stages a peer write for the approval of the application, `respond` answers
+    // the peer once it decides or the deadline passes, the dispatcher goes on
+    // with other events meanwhile
+    fn stage_write(
+        self: &Arc<Self>,
+        attribute: Arc<dyn AnyAttribute>,
+        timeout: Duration,
+        value: Vec<u8>,
+        conn_id: ConnectionId,
+        respond: impl FnOnce(&GattsInner, Result<(), AttError>) -> anyhow::Result<()> + Send + 'static,
+    ) -> anyhow::Result<()> {
+        let gatts = Arc::downgrade(self);
+        let response = HeldResponse::new(
+            timeout,
+            Box::new(move |decision| {
+                let Some(gatts) = gatts.upgrade() else {
+                    return;
+                };
+
+                if let Err(err) = respond(&gatts, decision) {
+                    log::error!("Failed to answer staged write: {:?}", err);
+                }
+            }),
+        );
+
+        attribute.stage_write(value, conn_id, response.clone())?;
+        self.held_responses.write()?.push(response);
+
+        Ok(())
+    }
+
+    // Earliest deadline of a held response, the dispatcher wakes up for it
+    fn next_held_deadline(&self) -> Option<Instant> {
+        self.held_responses
+            .read()
+            .ok()?
+            .iter()
+            .map(HeldResponse::deadline)
+            .min()
+    }
+
+    // Rejects staged writes the application did not decide on in time
+    fn expire_held_responses(&self) -> anyhow::Result<()> {
+        let now = Instant::now();
+        let expired = std::mem::take(&mut *self.held_responses.write()?);
+
+        // Answered outside the lock, responses wait for the stack
+        let held = expired
+            .into_iter()
+            .filter(|response| !response.expire(now))
+            .collect::<Vec<_>>();
+        self.held_responses.write()?.extend(held);
+
+        Ok(())
+    }
+
+    fn release_parked(
+        &self,
+        parked: ParkedConnection,
+        owner: Option<GattInterface>,
+    ) -> anyhow::Result<Vec<GattsEventMessage>> {
+        self.set_route(parked.conn_id, owner)?;
+
+        Ok(parked.events)
+    }
+
+    fn set_route(&self, conn_id: ConnectionId, owner: Option<GattInterface>) -> anyhow::Result<()> {
+        self.connection_routes.write()?.insert(
+            conn_id,
+            ConnectionRoute {
+                owner,
+                connected: true,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn routed_elsewhere(
+        &self,
+        interface: GattInterface,
+        conn_id: ConnectionId,
+    ) -> anyhow::Result<bool> {
+        Ok(matches!(
+            self.connection_routes.read()?.get(&conn_id),
+            Some(ConnectionRoute { owner: Some(owner), .. }) if *owner != interface
+        ))
+    }
+
+    // Apps do not serve peers connected through the advertising identity of another app
+    fn check_routed(&self, interface: GattInterface, conn_id: ConnectionId) -> anyhow::Result<()> {
+        if self.routed_elsewhere(interface, conn_id)? {
+            return Err(AttError::Status(GattStatus::InsufAuthorization).into());
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn persistence(&self) -> anyhow::Result<&Persistence> {
+        self.persistence
+            .as_ref()
+            .ok_or(anyhow::anyhow!("NVS is not available"))
+    }
+
+    /// Subscriptions of bonded peers, connected ones and those saved when they
+    /// disconnected, to keep them over deep sleep with `Ble::suspend`
+    pub(crate) fn bonded_subscriptions(&self) -> anyhow::Result<Vec<PeerSubscription>> {
+        let bonded = bonded_peers()?
+            .into_iter()
+            .map(|peer| peer.address)
+            .collect::<HashSet<_>>();
+
+        let mut peers = HashMap::new();
+        for app in self.apps.read()?.values() {
+            for connection in app.connections.read()?.values() {
+                if bonded.contains(&connection.peer_addr()) {
+                    peers.insert(connection.id(), connection.peer_addr());
+                }
+            }
+        }
+
+        let mut saved = self
+            .saved_subscriptions
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read saved subscriptions"))?
+            .clone();
+        for ((conn_id, handle), subscription) in self.subscriptions.read()?.iter() {
+            if let Some(address) = peers.get(conn_id) {
+                saved
+                    .entry(*address)
+                    .or_default()
+                    .push((*handle, *subscription));
+            }
+        }
+
+        Ok(saved
+            .into_iter()
+            .filter(|(address, _)| bonded.contains(address))
+            .flat_map(|(address, subscriptions)| {
+                subscriptions
+                    .into_iter()
+                    .map(move |(handle, subscription)| PeerSubscription {
+                        address: address.raw(),
+                        handle,
+                        cccd: subscription.cccd(),
+                    })
+            })
+            .collect())
+    }
+
+    /// Keeps subscriptions saved by `Ble::suspend` until their peers reconnect
+    pub(crate) fn restore_subscriptions(
+        &self,
+        subscriptions: &[PeerSubscription],
+    ) -> anyhow::Result<()> {
+        let mut saved = self
+            .saved_subscriptions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write saved subscriptions"))?;
+
+        for subscription in subscriptions {
+            saved
+                .entry(BdAddr::from_bytes(subscription.address))
+                .or_default()
+                .push((
+                    subscription.handle,
+                    Subscription::from_cccd(subscription.cccd as u8),
+                ));
+        }
+
+        Ok(())
+    }
+
+    // Saves subscriptions of a bonded peer which disconnects, until it reconnects
+    fn save_subscriptions(&self, conn_id: ConnectionId, addr: BdAddr) -> anyhow::Result<()> {
+        if !bonded_peers()?.iter().any(|peer| peer.address == addr) {
+            return Ok(());
+        }
+
+        let subscriptions = self
+            .subscriptions
+            .read()?
+            .iter()
+            .filter(|((subscriber, _), _)| *subscriber == conn_id)
+            .map(|((_, handle), subscription)| (*handle, *subscription))
+            .collect::<Vec<_>>();
+
+        let mut saved = self
+            .saved_subscriptions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write saved subscriptions"))?;
+        if subscriptions.is_empty() {
+            saved.remove(&addr);
+        } else {
+            saved.insert(addr, subscriptions);
+        }
+
+        Ok(())
+    }
+
+    // Applies subscriptions saved for a bonded peer which reconnected
+    fn resubscribe(&self, conn_id: ConnectionId, addr: BdAddr) -> anyhow::Result<()> {
+        let saved = self
+            .saved_subscriptions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write saved subscriptions"))?
+            .remove(&addr);
+        let Some(saved) = saved else {
+            return Ok(());
+        };
+
+        // Bond may have been removed while the peer was away
+        if !bonded_peers()?.iter().any(|peer| peer.address == addr) {
+            return Ok(());
+        }
+
+        for (handle, subscription) in saved {
+            let attribute = match self.get_attribute(handle) {
+                Ok(attribute) if attribute.characteristic_handle()?.is_none() => attribute,
+                _ => {
+                    log::warn!(
+                        "Saved subscription of {:?} to {:?} does not match a characteristic, were services registered in a different order?",
+                        addr,
+                        handle
+                    );
+                    continue;
+                }
+            };
+
+            self.set_subscription(conn_id, handle, subscription)?;
+            if let Err(err) = attribute.peer_resubscribed(conn_id) {
+                log::warn!(
+                    "Failed to resync {:?} after restoring subscription of {:?}: {:?}",
+                    handle,
+                    addr,
+                    err
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn subscription(
+        &self,
+        conn_id: ConnectionId,
+        characteristic: Handle,
+    ) -> anyhow::Result<Subscription> {
+        Ok(self
+            .subscriptions
+            .read()?
+            .get(&(conn_id, characteristic))
+            .copied()
+            .unwrap_or_default())
+    }
+
+    pub(crate) fn set_subscription(
+        &self,
+        conn_id: ConnectionId,
+        characteristic: Handle,
+        subscription: Subscription,
+    ) -> anyhow::Result<()> {
+        let mut subscriptions = self.subscriptions.write()?;
+        if subscription == Subscription::default() {
+            subscriptions.remove(&(conn_id, characteristic));
+        } else {
+            subscriptions.insert((conn_id, characteristic), subscription);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn compresses(
+        &self,
+        conn_id: ConnectionId,
+        characteristic: Handle,
+    ) -> anyhow::Result<bool> {
+        Ok(self
+            .compressing_peers
+            .read()?
+            .contains(&(conn_id, characteristic)))
+    }
+
+    pub(crate) fn set_compression(
+        &self,
+        conn_id: ConnectionId,
+        characteristic: Handle,
+        enabled: bool,
+    ) -> anyhow::Result<()> {
+        let mut compressing_peers = self.compressing_peers.write()?;
+        if enabled {
+            compressing_peers.insert((conn_id, characteristic));
+        } else {
+            compressing_peers.remove(&(conn_id, characteristic));
+        }
+
+        Ok(())
+    }
+
     fn get_attribute(&self, handle: Handle) -> anyhow::Result<Arc<dyn AnyAttribute>> {
         let attribute = self
             .attributes
-            .read()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire read lock on Gatts attributes"))?
+            .read()?
             .get(&handle)
             .ok_or(anyhow::anyhow!(
                 "No found attribute with given handle: {:?}",
@@ -284,7 +1212,53 @@ impl GattsInner {
         Ok(attribute)
     }
 
-    fn handle_gatts_global_event(&self, event: GattsEventMessage) -> anyhow::Result<()> {
+    // Passes a complete peer write through the middleware chain of the app
+    fn run_write_middlewares(
+        &self,
+        interface: GattInterface,
+        conn_id: ConnectionId,
+        handle: Handle,
+        attribute: &Arc<dyn AnyAttribute>,
+        value: Vec<u8>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let app = self.app(interface)?;
+        let middlewares = app.middlewares()?;
+        if middlewares.is_empty() {
+            return Ok(value);
+        }
+
+        let connection = app
+            .connections
+            .read()?
+            .get(&conn_id)
+            .cloned()
+            .ok_or(anyhow::anyhow!(
+                "No found connection with given connection id: {:?}",
+                conn_id
+            ))?;
+
+        let request = WriteRequest {
+            connection,
+            handle,
+            uuid: attribute.uuid(),
+            value,
+        };
+
+        Ok(middleware::run_write(&middlewares, request)?.value)
+    }
+
+    fn app(&self, interface: GattInterface) -> anyhow::Result<Arc<AppInner>> {
+        self.apps
+            .read()?
+            .get(&interface)
+            .cloned()
+            .ok_or(anyhow::anyhow!(
+                "No found app registered on {}",
+                AppInterface::unknown(interface)
+            ))
+    }
+
+    fn handle_gatts_global_event(self: &Arc<Self>, event: GattsEventMessage) -> anyhow::Result<()> {
         match event {
             GattsEventMessage(
                 interface,
@@ -296,35 +1270,57 @@ impl GattsInner {
                     need_rsp,
                     ..
                 },
+                _,
             ) => {
                 if !need_rsp {
                     log::warn!("Read event without response, ignoring");
                     return Ok(());
                 }
 
+                trace::span!("gatts.read", conn_id, handle, offset);
+
                 let response = (|| {
+                    self.check_routed(interface, conn_id)?;
+
                     let attribute = self.get_attribute(handle)?;
-                    let bytes = attribute.get_bytes()?;
-
-                    let app = self.apps.read().map_err(|_| {
-                        anyhow::anyhow!("Failed to acquire read lock on Gatts connections")
-                    })?.get(&interface).ok_or(anyhow::anyhow!(
-                        "No found app with given gatts interface: {:?}",
-                        interface
-                    ))?.clone();
-
-                    let connections = app.connections.read().map_err(|_| {
-                        anyhow::anyhow!("Failed to acquire read lock on Gatts connections")
-                    })?;
-                    let connection = connections.get(&conn_id).ok_or(anyhow::anyhow!(
-                        "No found connection with given connection id: {:?}",
-                        conn_id
-                    ))?;
-                    let mtu = connection.mtu.ok_or(anyhow::anyhow!(
+                    let bytes = match attribute.characteristic_handle()? {
+                        // Each peer reads its own subscription, not a shared value
+                        Some(characteristic) if attribute.uuid() == BtUuid::uuid16(CCCD_UUID) => self
+                            .subscription(conn_id, characteristic)?
+                            .cccd()
+                            .to_le_bytes()
+                            .to_vec(),
+                        _ => attribute.read_bytes(offset)?,
+                    };
+
+                    let app = self.app(interface)?;
+
+                    let connection = app
+                        .connections
+                        .read()?
+                        .get(&conn_id)
+                        .cloned()
+                        .ok_or(anyhow::anyhow!(
+                            "No found connection with given connection id: {:?}",
+                            conn_id
+                        ))?;
+                    let mtu = connection.mtu()?.ok_or(anyhow::anyhow!(
                         "No found MTU for connection with given connection id: {:?}",
                         conn_id
                     ))?;
 
+                    let bytes = middleware::run_read(
+                        &app.middlewares()?,
+                        ReadRequest {
+                            connection,
+                            handle,
+                            uuid: attribute.uuid(),
+                            offset,
+                            value: bytes,
+                        },
+                    )?
+                    .value;
+
                     let effective_mtu_for_data = mtu.saturating_sub(1);
                     let end_index =  (offset + effective_mtu_for_data).min(bytes.len() as u16).min(ESP_GATT_MAX_ATTR_LEN as u16) as usize;
 
@@ -334,7 +1330,7 @@ impl GattsInner {
                     Ok(response)
                 })()
                 .map_err(|err: anyhow::Error| {
-                    match self.send_response(handle,interface, conn_id, trans_id, GattStatus::Error, None) {
+                    match self.send_error_response(handle, interface, conn_id, trans_id, att_error(&err)) {
                         Ok(_) => anyhow::anyhow!("Failed to prepare attribute bytes: {:?}", err),
                         Err(send_err) => {
                             anyhow::anyhow!("Failed to prepare attribute bytes ({:?}) and send error response ({:?})", err, send_err)
@@ -365,27 +1361,98 @@ impl GattsInner {
                     value,
                     ..
                 },
+                _,
             ) => {
-                let result: anyhow::Result<()> = (|| {
-                    let mut temp_storage = self.write_buffer.write().map_err(|_| {
-                        anyhow::anyhow!("Failed to acquire write lock on temporary write buffer")
-                    })?;
-                    let temp_buffer = temp_storage.entry(trans_id).or_insert(PrepareWriteBuffer {
-                        value: Vec::new(),
-                        handle,
-                    });
+                trace::span!(
+                    "gatts.write",
+                    conn_id,
+                    handle,
+                    offset,
+                    is_prep,
+                    len = value.len()
+                );
 
-                    if temp_buffer.value.len() < offset as usize + value.len() {
-                        temp_buffer.value.resize(offset as usize + value.len(), 0);
-                    }
-                    temp_buffer.value[offset as usize..offset as usize + value.len()]
-                        .copy_from_slice(&value);
+                // Set once the write is staged for approval, its response is held back
+                let mut held = false;
+                let result: anyhow::Result<()> = (|| {
+                    self.check_not_rejected(conn_id)?;
+                    self.check_routed(interface, conn_id)?;
+
+                    let written = &value;
+
+                    if is_prep {
+                        let received = self
+                            .write_buffer
+                            .write()?
+                            .prepare(
+                                conn_id,
+                                handle,
+                                offset,
+                                &value,
+                                ESP_GATT_MAX_ATTR_LEN as usize,
+                            )
+                            .map_err(AttError::from)?;
+
+                        self.send_write_progress(
+                            conn_id,
+                            handle,
+                            received,
+                            WriteProgressState::Receiving,
+                        )?;
+                    } else {
+                        let mut buffer = WriteReassembler::new();
+                        buffer
+                            .write(offset, &value, ESP_GATT_MAX_ATTR_LEN as usize)
+                            .map_err(AttError::from)?;
+                        let value = buffer.take();
 
-                    if !is_prep {
                         let attribute = self.get_attribute(handle)?;
-                        attribute.update_from_bytes(&temp_buffer.value)?;
+                        let Some(value) = attribute.reassemble_write(value, conn_id)? else {
+                            return Ok(());
+                        };
+                        let value = self
+                            .run_write_middlewares(interface, conn_id, handle, &attribute, value)?;
+                        attribute.validate_write(&value)?;
+
+                        if let Some(timeout) = attribute.write_approval() {
+                            let written = written.clone();
+                            self.stage_write(
+                                attribute,
+                                timeout,
+                                value,
+                                conn_id,
+                                move |gatts, decision| {
+                                    if !need_rsp {
+                                        return Ok(());
+                                    }
+
+                                    match decision {
+                                        Ok(()) => gatts.send_response(
+                                            handle,
+                                            interface,
+                                            conn_id,
+                                            trans_id,
+                                            GattStatus::Ok,
+                                            Some(
+                                                GattResponse::new()
+                                                    .attr_handle(handle)
+                                                    .auth_req(0)
+                                                    .offset(offset)
+                                                    .value(&written)?,
+                                            ),
+                                        ),
+                                        Err(error) => gatts.send_error_response(
+                                            handle, interface, conn_id, trans_id, error,
+                                        ),
+                                    }
+                                },
+                            )?;
+                            held = true;
+
+                            return Ok(());
+                        }
 
-                        temp_storage.remove(&trans_id);
+                        attribute.write_from_peer(&value, conn_id)?;
                     }
 
                     Ok(())
@@ -396,24 +1463,29 @@ impl GattsInner {
                     return result;
                 }
 
-                self.send_response(
-                    handle,
-                    interface,
-                    conn_id,
-                    trans_id,
-                    if result.is_ok() {
-                        GattStatus::Ok
-                    } else {
-                        GattStatus::Error
-                    },
-                    Some(
-                        GattResponse::new()
-                            .attr_handle(handle)
-                            .auth_req(0)
-                            .offset(offset)
-                            .value(&value)?,
-                    ),
-                )?;
+                if held {
+                    return result;
+                }
+
+                match result.as_ref().err().map(att_error) {
+                    None => self.send_response(
+                        handle,
+                        interface,
+                        conn_id,
+                        trans_id,
+                        GattStatus::Ok,
+                        Some(
+                            GattResponse::new()
+                                .attr_handle(handle)
+                                .auth_req(0)
+                                .offset(offset)
+                                .value(&value)?,
+                        ),
+                    )?,
+                    Some(error) => {
+                        self.send_error_response(handle, interface, conn_id, trans_id, error)?
+                    }
+                }
 
                 result
             }
@@ -425,41 +1497,119 @@ impl GattsInner {
                     canceled,
                     ..
                 },
+                _,
             ) => {
+                trace::span!("gatts.exec_write", conn_id, canceled);
+
                 let mut handle = None;
+                // Set once the write is staged for approval, its progress and
+                // response are sent once the application decides
+                let mut held = false;
                 let result = (|| {
-                    let mut temp_storage = self.write_buffer.write().map_err(|_| {
-                        anyhow::anyhow!("Failed to acquire write lock on temporary write buffer")
-                    })?;
-                    let temp_buffer = temp_storage.get(&trans_id).ok_or(anyhow::anyhow!(
-                        "Not found temporary write buffer with given transfer id: {:?}",
-                        trans_id
-                    ))?;
+                    self.check_not_rejected(conn_id)?;
+                    self.check_routed(interface, conn_id)?;
+
+                    // Queue is released before the update, which may wait for indication
+                    // confirms, canceled or failed writes are discarded as well
+                    let mut temp_buffer =
+                        self.write_buffer
+                            .write()?
+                            .take(&conn_id)
+                            .ok_or(anyhow::anyhow!(
+                                "Not found prepared writes of connection: {:?}",
+                                conn_id
+                            ))?;
                     handle.replace(temp_buffer.handle);
+                    let received = temp_buffer.value.value().len();
+
+                    let executed = match canceled {
+                        true => Ok(WriteProgressState::Canceled),
+                        false => (|| {
+                            let attribute = self.get_attribute(temp_buffer.handle)?;
+                            let Some(value) =
+                                attribute.reassemble_write(temp_buffer.value.take(), conn_id)?
+                            else {
+                                return Ok(WriteProgressState::Executed);
+                            };
+                            let value = self.run_write_middlewares(
+                                interface,
+                                conn_id,
+                                temp_buffer.handle,
+                                &attribute,
+                                value,
+                            )?;
+                            attribute.validate_write(&value)?;
+
+                            if let Some(timeout) = attribute.write_approval() {
+                                let handle = temp_buffer.handle;
+                                self.stage_write(
+                                    attribute,
+                                    timeout,
+                                    value,
+                                    conn_id,
+                                    move |gatts, decision| {
+                                        let state = match decision {
+                                            Ok(()) => WriteProgressState::Executed,
+                                            Err(_) => WriteProgressState::Canceled,
+                                        };
+                                        gatts.send_write_progress(
+                                            conn_id, handle, received, state,
+                                        )?;
+
+                                        match decision {
+                                            Ok(()) => gatts.send_response(
+                                                handle,
+                                                interface,
+                                                conn_id,
+                                                trans_id,
+                                                GattStatus::Ok,
+                                                None,
+                                            ),
+                                            Err(error) => gatts.send_error_response(
+                                                handle, interface, conn_id, trans_id, error,
+                                            ),
+                                        }
+                                    },
+                                )?;
+                                held = true;
+
+                                return Ok(WriteProgressState::Executed);
+                            }
+
+                            attribute.write_from_peer(&value, conn_id)?;
+
+                            Ok(WriteProgressState::Executed)
+                        })(),
+                    };
 
-                    if !canceled {
-                        let attribute = self.get_attribute(temp_buffer.handle)?;
-                        attribute.update_from_bytes(&temp_buffer.value)?;
-
-                        temp_storage.remove(&trans_id);
+                    if held {
+                        return Ok(());
                     }
 
-                    Ok(())
-                })();
-
-                if let Some(handle) = handle {
-                    self.send_response(
-                        handle,
-                        interface,
+                    self.send_write_progress(
                         conn_id,
-                        trans_id,
-                        if result.is_ok() {
-                            GattStatus::Ok
-                        } else {
-                            GattStatus::Error
-                        },
-                        None,
+                        temp_buffer.handle,
+                        received,
+                        *executed.as_ref().unwrap_or(&WriteProgressState::Canceled),
                     )?;
+
+                    executed.map(|_| ())
+                })();
+
+                if let Some(handle) = handle.filter(|_| !held) {
+                    match result.as_ref().err().map(att_error) {
+                        None => self.send_response(
+                            handle,
+                            interface,
+                            conn_id,
+                            trans_id,
+                            GattStatus::Ok,
+                            None,
+                        )?,
+                        Some(error) => {
+                            self.send_error_response(handle, interface, conn_id, trans_id, error)?
+                        }
+                    }
                 }
 
                 result
@@ -472,98 +1622,185 @@ impl GattsInner {
                     addr,
                     conn_params,
                 },
+                _,
             ) => {
-                let app = self
-                    .apps
-                    .read()
-                    .map_err(|_| anyhow::anyhow!("Failed to acquire read lock on Gatts apps"))?
-                    .get(&interface)
-                    .ok_or(anyhow::anyhow!(
-                        "No found app with given gatts interface: {:?}",
-                        interface
-                    ))?
-                    .clone();
+                trace::span!("gatts.connected", conn_id, peer = ?addr);
 
-                let connection = connection::ConnectionInner {
-                    id: conn_id,
-                    link_role,
-                    mtu: None,
-                    conn_params,
-                    address: addr,
-                };
-                app.connections
-                    .write()
-                    .map_err(|_| {
-                        anyhow::anyhow!("Failed to acquire write lock on Gatts connections")
-                    })?
-                    .insert(conn_id, connection.clone());
+                let app = self.app(interface)?;
+
+                // Connections made through the identity of another app are not its own
+                if self.routed_elsewhere(interface, conn_id)? {
+                    return Ok(());
+                }
+
+                let connection =
+                    Connection::new(Arc::downgrade(self), conn_id, link_role, addr, conn_params);
+
+                match self.filter_connection(&connection)? {
+                    FilterDecision::Accepted => {}
+                    FilterDecision::AlreadyRejected => return Ok(()),
+                    FilterDecision::Rejected => {
+                        log::warn!("Peer {:?} rejected by connection filter", addr);
+
+                        let connection_status = ConnectionStatus::Rejected(connection);
+                        self.gap_connections_tx.send(connection_status.clone())?;
+                        self.connections_tx.send(ConnectionEvent {
+                            stamp: EventStamp::current(),
+                            status: connection_status,
+                        })?;
+
+                        return esp!(unsafe { esp_ble_gap_disconnect(addr.raw().as_mut_ptr()) })
+                            .map_err(|err| {
+                                anyhow::anyhow!("Failed to disconnect rejected peer: {:?}", err)
+                            });
+                    }
+                }
+
+                app.connections.write()?.insert(conn_id, connection.clone());
+                self.resubscribe(conn_id, addr)?;
 
                 let connection_status = ConnectionStatus::Connected(connection);
 
                 self.gap_connections_tx.send(connection_status.clone())?;
-                self.connections_tx.send(connection_status)?;
+                self.connections_tx.send(ConnectionEvent {
+                    stamp: EventStamp::current(),
+                    status: connection_status,
+                })?;
 
-                Ok(())
+                self.metrics.connected(addr, conn_id)?;
+                self.sessions.connected(addr, conn_id)
             }
-            GattsEventMessage(interface, GattsEvent::PeerDisconnected { conn_id, .. }) => {
-                let app = self
-                    .apps
-                    .read()
-                    .map_err(|_| anyhow::anyhow!("Failed to acquire read lock on Gatts apps"))?
-                    .get(&interface)
-                    .ok_or(anyhow::anyhow!(
-                        "No found app with given gatts interface: {:?}",
-                        interface
-                    ))?
-                    .clone();
+            GattsEventMessage(
+                interface,
+                GattsEvent::PeerDisconnected {
+                    conn_id, reason, ..
+                },
+                _,
+            ) => {
+                trace::span!("gatts.disconnected", conn_id, reason = ?reason);
 
-                let connection = app
-                    .connections
-                    .write()
-                    .map_err(|_| {
-                        anyhow::anyhow!("Failed to acquire write lock on Gatts connections")
-                    })?
-                    .remove(&conn_id)
-                    .ok_or(anyhow::anyhow!(
-                        "No found connection with given connection id: {:?}",
-                        conn_id
-                    ))?;
+                if let Some(route) = self.connection_routes.write()?.get_mut(&conn_id) {
+                    route.connected = false;
+                }
 
-                let connection_status = ConnectionStatus::Disconnected(connection);
+                if self.routed_elsewhere(interface, conn_id)? {
+                    return Ok(());
+                }
+
+                // Rejected connections were never handed to the apps
+                if self.filter_disconnected(conn_id)? {
+                    return Ok(());
+                }
+
+                let app = self.app(interface)?;
+
+                let connection =
+                    app.connections
+                        .write()?
+                        .remove(&conn_id)
+                        .ok_or(anyhow::anyhow!(
+                            "No found connection with given connection id: {:?}",
+                            conn_id
+                        ))?;
+
+                self.write_buffer.write()?.take(&conn_id);
+                self.save_subscriptions(conn_id, connection.peer_addr())?;
+                self.congested_connections.write()?.remove(&conn_id);
+                self.subscriptions
+                    .write()?
+                    .retain(|(subscriber, _), _| *subscriber != conn_id);
+                self.compressing_peers
+                    .write()?
+                    .retain(|(peer, _)| *peer != conn_id);
+                // Attributes are notified without holding the map, so their
+                // cleanup may take locks of any level
+                let attributes = self
+                    .attributes
+                    .read()?
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for attribute in attributes {
+                    attribute.peer_disconnected(conn_id)?;
+                }
+                self.metrics.disconnected(conn_id, reason)?;
+
+                let connection_status = ConnectionStatus::Disconnected(connection, reason);
 
                 log::info!("Sending disconnect event: {:?}", connection_status);
                 self.gap_connections_tx.send(connection_status.clone())?;
-                self.connections_tx.send(connection_status)?;
+                self.connections_tx.send(ConnectionEvent {
+                    stamp: EventStamp::current(),
+                    status: connection_status,
+                })?;
 
-                Ok(())
+                self.sessions.disconnected(conn_id)
             }
-            GattsEventMessage(interface, GattsEvent::Mtu { conn_id, mtu }) => {
-                let app = self
-                    .apps
-                    .read()
-                    .map_err(|_| anyhow::anyhow!("Failed to acquire read lock on Gatts apps"))?
-                    .get(&interface)
-                    .ok_or(anyhow::anyhow!(
-                        "No found app with given gatts interface: {:?}",
-                        interface
-                    ))?
-                    .clone();
+            GattsEventMessage(interface, GattsEvent::Mtu { conn_id, mtu }, _) => {
+                if self.is_rejected(conn_id)? || self.routed_elsewhere(interface, conn_id)? {
+                    return Ok(());
+                }
+
+                let app = self.app(interface)?;
 
                 app.connections
-                    .write()
-                    .map_err(|_| {
-                        anyhow::anyhow!("Failed to acquire write lock on Gatts connections")
-                    })?
-                    .get_mut(&conn_id)
+                    .read()?
+                    .get(&conn_id)
                     .ok_or(anyhow::anyhow!(
                         "No found connection with given connection id: {:?}",
                         conn_id
                     ))?
+                    .0
                     .mtu
+                    .write()
+                    .map_err(|_| anyhow::anyhow!("Failed to write connection MTU"))?
                     .replace(mtu);
 
                 Ok(())
             }
+            GattsEventMessage(interface, GattsEvent::Congest { conn_id, congested }, _) => {
+                if self.is_rejected(conn_id)? || self.routed_elsewhere(interface, conn_id)? {
+                    return Ok(());
+                }
+
+                let app = self.app(interface)?;
+
+                let connection =
+                    app.connections
+                        .read()?
+                        .get(&conn_id)
+                        .cloned()
+                        .ok_or(anyhow::anyhow!(
+                            "No found connection with given connection id: {:?}",
+                            conn_id
+                        ))?;
+                *connection
+                    .0
+                    .congested
+                    .write()
+                    .map_err(|_| anyhow::anyhow!("Failed to write connection congestion"))? =
+                    congested;
+                // Full when a signal is already pending, which wakes the sender as well
+                let _ = connection.0.congestion_tx.try_send(());
+
+                // Every app reports the same link, the change is forwarded only once
+                let changed = {
+                    let mut congested_connections = self.congested_connections.write()?;
+                    if congested {
+                        congested_connections.insert(conn_id)
+                    } else {
+                        congested_connections.remove(&conn_id)
+                    }
+                };
+
+                if changed {
+                    log::debug!("Connection {} congested: {}", conn_id, congested);
+                    self.congestion_tx
+                        .send(CongestionStatus { conn_id, congested })?;
+                }
+
+                Ok(())
+            }
             _ => Err(anyhow::anyhow!("Unexpected GATT event: {:?}", event)),
         }
     }
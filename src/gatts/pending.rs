@@ -0,0 +1,177 @@
+//! Two-phase peer writes of characteristics with `CharacteristicConfig::write_approval`.
+//! A write which passed the validator is staged and sent to the application as a
+//! `PendingWrite`, the ATT response is held back until the application commits or
+//! rejects it, e.g. after checking motor limits on the hardware.
+//!
+//! The GATTS dispatcher does not wait for the decision: it keeps the transaction
+//! in a `HeldResponse`, answered from the thread which decides, or by the
+//! dispatcher once the deadline passes.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use esp_idf_svc::bt::ble::gatt::{GattStatus, server::ConnectionId};
+
+use super::error::AttError;
+
+/// Staged writes a characteristic holds while the application does not receive
+/// them from `pending_writes_rx`, further writes are answered with `Busy`
+pub const MAX_PENDING_WRITES: usize = 4;
+
+// Sends the held back response with the outcome of the write
+pub(crate) type Respond = Box<dyn FnOnce(Result<(), AttError>) + Send>;
+
+// Applies a committed value and runs the write handler
+pub(crate) type Apply = Box<dyn FnOnce() -> Result<(), AttError> + Send>;
+
+/// Response of a staged write, answered once by whichever of the decision of the
+/// application and the deadline comes first
+#[derive(Clone)]
+pub struct HeldResponse {
+    deadline: Instant,
+    respond: Arc<Mutex<Option<Respond>>>,
+}
+
+impl HeldResponse {
+    pub(crate) fn new(timeout: Duration, respond: Respond) -> Self {
+        Self {
+            deadline: Instant::now() + timeout,
+            respond: Arc::new(Mutex::new(Some(respond))),
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Whether the write was committed, rejected or expired
+    pub fn is_answered(&self) -> bool {
+        self.respond
+            .lock()
+            .map(|respond| respond.is_none())
+            .unwrap_or(true)
+    }
+
+    // Takes the response to answer it, None once it was taken
+    fn take(&self) -> Option<Respond> {
+        self.respond.lock().ok()?.take()
+    }
+
+    // Rejects the write once its deadline passed, true if it is answered by now
+    pub(crate) fn expire(&self, now: Instant) -> bool {
+        if now < self.deadline {
+            return self.is_answered();
+        }
+
+        if let Some(respond) = self.take() {
+            log::warn!("Pending write was not committed before its deadline, rejecting");
+            respond(Err(AttError::Status(GattStatus::Error)));
+        }
+
+        true
+    }
+}
+
+/// Peer write waiting for a decision of the application. Dropping it without
+/// a decision rejects the write, same as missing the deadline
+pub struct PendingWrite<T> {
+    conn_id: ConnectionId,
+    value: Arc<T>,
+    response: HeldResponse,
+    apply: Option<Apply>,
+}
+
+impl<T> PendingWrite<T> {
+    pub(crate) fn new(
+        conn_id: ConnectionId,
+        value: Arc<T>,
+        response: HeldResponse,
+        apply: Apply,
+    ) -> Self {
+        Self {
+            conn_id,
+            value,
+            response,
+            apply: Some(apply),
+        }
+    }
+
+    /// Connection of the writing peer
+    pub fn conn_id(&self) -> ConnectionId {
+        self.conn_id
+    }
+
+    /// Decoded value the peer wrote, not applied yet
+    pub fn value(&self) -> &Arc<T> {
+        &self.value
+    }
+
+    /// Time after which the write is rejected, decisions made later are ignored
+    pub fn deadline(&self) -> Instant {
+        self.response.deadline()
+    }
+
+    /// Applies the value and acknowledges the write to the peer, fails when the
+    /// deadline already passed and the write was rejected. The write handler runs
+    /// on the calling thread
+    pub fn commit(mut self) -> anyhow::Result<()> {
+        let respond = self.take_response()?;
+        let result = self.apply.take().map_or(Ok(()), |apply| apply());
+        respond(result);
+
+        Ok(result?)
+    }
+
+    /// Discards the value and sends the error in the write response
+    pub fn reject(self, error: AttError) -> anyhow::Result<()> {
+        let respond = self.take_response()?;
+        respond(Err(error));
+
+        Ok(())
+    }
+
+    // Drops the write without answering it, its response is sent by the caller
+    pub(crate) fn cancel(self) {
+        self.response.take();
+    }
+
+    fn take_response(&self) -> anyhow::Result<Respond> {
+        self.response.take().ok_or(anyhow::anyhow!(
+            "Pending write of {:?} expired before the decision",
+            self.conn_id
+        ))
+    }
+}
+
+impl<T> Drop for PendingWrite<T> {
+    fn drop(&mut self) {
+        if let Some(respond) = self.response.take() {
+            respond(Err(AttError::Status(GattStatus::Error)));
+        }
+    }
+}
+
+// Waits for the decision on a staged write, for callers which may block,
+// e.g. `Loopback`
+pub(crate) fn await_decision(
+    decision_rx: &Receiver<Result<(), AttError>>,
+    response: &HeldResponse,
+) -> Result<(), AttError> {
+    let timeout = response
+        .deadline()
+        .saturating_duration_since(Instant::now());
+    match decision_rx.recv_timeout(timeout) {
+        Ok(decision) => decision,
+        Err(RecvTimeoutError::Timeout) => {
+            // A commit which took the response just before still answers it
+            response.expire(Instant::now());
+            decision_rx
+                .recv()
+                .unwrap_or(Err(AttError::Status(GattStatus::Error)))
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(AttError::Status(GattStatus::Error)),
+    }
+}
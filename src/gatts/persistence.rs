@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crossbeam_channel::{RecvTimeoutError, Sender, unbounded};
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition};
+
+/// NVS namespace holding persisted characteristic values
+pub const NVS_NAMESPACE: &str = "esp_bluedroid";
+
+/// Maximum length of NVS key
+pub const MAX_KEY_LEN: usize = 15;
+
+/// Values are written after no other value was saved for this long,
+/// so bursts of writes end up as a single flash write
+pub const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub struct Persistence {
+    nvs: Arc<Mutex<EspDefaultNvs>>,
+    save_tx: Sender<(String, Vec<u8>)>,
+}
+
+impl Persistence {
+    pub fn new(partition: EspDefaultNvsPartition) -> anyhow::Result<Self> {
+        let nvs = EspDefaultNvs::new(partition, NVS_NAMESPACE, true)
+            .map_err(|err| anyhow::anyhow!("Failed to open NVS namespace: {:?}", err))?;
+        let nvs = Arc::new(Mutex::new(nvs));
+        let (save_tx, save_rx) = unbounded::<(String, Vec<u8>)>();
+
+        let writer_nvs = nvs.clone();
+        std::thread::Builder::new()
+            .stack_size(4 * 1024)
+            .spawn(move || {
+                let mut pending = HashMap::new();
+
+                loop {
+                    if pending.is_empty() {
+                        let Ok((key, value)) = save_rx.recv() else {
+                            return;
+                        };
+                        pending.insert(key, value);
+                        continue;
+                    }
+
+                    match save_rx.recv_timeout(SAVE_DEBOUNCE) {
+                        Ok((key, value)) => {
+                            pending.insert(key, value);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            Self::flush(&writer_nvs, &mut pending);
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            Self::flush(&writer_nvs, &mut pending);
+                            return;
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self { nvs, save_tx })
+    }
+
+    pub fn validate_key(key: &str) -> anyhow::Result<()> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(anyhow::anyhow!(
+                "Invalid NVS key {:?}: must be 1..={} bytes long",
+                key,
+                MAX_KEY_LEN
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn load(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let nvs = self
+            .nvs
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock NVS"))?;
+
+        let Some(len) = nvs
+            .blob_len(key)
+            .map_err(|err| anyhow::anyhow!("Failed to read NVS key {:?}: {:?}", key, err))?
+        else {
+            return Ok(None);
+        };
+
+        let mut buffer = vec![0; len];
+        let value = nvs
+            .get_blob(key, &mut buffer)
+            .map_err(|err| anyhow::anyhow!("Failed to read NVS key {:?}: {:?}", key, err))?
+            .map(|value| value.to_vec());
+
+        Ok(value)
+    }
+
+    /// Queues value to be saved, actual write is debounced
+    pub fn save(&self, key: &str, value: Vec<u8>) -> anyhow::Result<()> {
+        self.save_tx
+            .send((key.to_string(), value))
+            .map_err(|_| anyhow::anyhow!("Failed to queue NVS write of key {:?}", key))
+    }
+
+    /// Writes value right away, bypassing the debounce, e.g. right before sleep
+    pub fn store(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        self.nvs
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock NVS"))?
+            .set_blob(key, value)
+            .map_err(|err| anyhow::anyhow!("Failed to write NVS key {:?}: {:?}", key, err))
+    }
+
+    pub fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.nvs
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock NVS"))?
+            .remove(key)
+            .map_err(|err| anyhow::anyhow!("Failed to remove NVS key {:?}: {:?}", key, err))?;
+
+        Ok(())
+    }
+
+    fn flush(nvs: &Mutex<EspDefaultNvs>, pending: &mut HashMap<String, Vec<u8>>) {
+        let Ok(mut nvs) = nvs.lock() else {
+            log::error!(
+                "Failed to lock NVS, dropping {} pending values",
+                pending.len()
+            );
+            pending.clear();
+            return;
+        };
+
+        for (key, value) in pending.drain() {
+            if let Err(err) = nvs.set_blob(&key, &value) {
+                log::error!("Failed to write NVS key {:?}: {:?}", key, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use esp_idf_svc::bt::BtUuid;
+
+use super::{
+    attribute::{
+        Attribute,
+        encoding::{self, EncodingConfig, Endianness, IntEncoding},
+    },
+    characteristic::{Characteristic, CharacteristicConfig},
+};
+
+/// Version of the wire format described by `ProtocolInfo`, bumped on incompatible changes.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+pub const PROTOCOL_INFO_UUID: u128 = 0x6a1f0002_8d3c_4b6e_9f2a_3c5e7b9d1e0f;
+
+/// Optional protocol features enabled in the firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProtocolFeatures(pub u32);
+
+impl ProtocolFeatures {
+    pub const JSON: Self = Self(1 << 0);
+    pub const CHUNKING: Self = Self(1 << 1);
+    pub const RELIABLE: Self = Self(1 << 2);
+    pub const COMPRESSION: Self = Self(1 << 3);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Features compiled into this build
+    pub fn enabled() -> Self {
+        // Chunked characteristics need no feature, see `gatts::chunked`
+        let mut features = Self::CHUNKING;
+
+        if cfg!(feature = "json") {
+            features.0 |= Self::JSON.0;
+        }
+
+        if cfg!(feature = "compression") {
+            features.0 |= Self::COMPRESSION.0;
+        }
+
+        features
+    }
+}
+
+/// Describes framing/encoding of the firmware, so clients can adapt to it.
+///
+/// Uses fixed layout independent of the configured serde encoding:
+/// protocol version (u8), crate version (3 x u8), encoding flags (u8, bit 0 - fixed
+/// int encoding, bit 1 - big endian), encoding limit (u32 LE, 0 - no limit),
+/// features (u32 LE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolInfo {
+    pub protocol_version: u8,
+    pub crate_version: (u8, u8, u8),
+    pub encoding: EncodingConfig,
+    pub features: ProtocolFeatures,
+}
+
+impl ProtocolInfo {
+    pub const LEN: usize = 13;
+
+    pub fn current() -> anyhow::Result<Self> {
+        let version = |part: &str| {
+            part.parse::<u8>()
+                .map_err(|err| anyhow::anyhow!("Failed to parse crate version: {:?}", err))
+        };
+
+        Ok(Self {
+            protocol_version: PROTOCOL_VERSION,
+            crate_version: (
+                version(env!("CARGO_PKG_VERSION_MAJOR"))?,
+                version(env!("CARGO_PKG_VERSION_MINOR"))?,
+                version(env!("CARGO_PKG_VERSION_PATCH"))?,
+            ),
+            encoding: encoding::encoding_config()?,
+            features: ProtocolFeatures::enabled(),
+        })
+    }
+
+    /// Read-only characteristic always reporting current protocol info
+    pub fn characteristic() -> anyhow::Result<Characteristic<ProtocolInfo>> {
+        let characteristic = Characteristic::new(
+            Self::current()?,
+            CharacteristicConfig {
+                uuid: BtUuid::uuid128(PROTOCOL_INFO_UUID),
+                value_max_len: Self::LEN,
+                readable: true,
+                description: Some("Protocol Info".to_string()),
+                ..Default::default()
+            },
+            None,
+        );
+        characteristic.set_on_read(Self::current)?;
+
+        Ok(characteristic)
+    }
+}
+
+impl Attribute for ProtocolInfo {
+    fn get_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut flags = 0;
+        if self.encoding.int_encoding == IntEncoding::Fixed {
+            flags |= 1 << 0;
+        }
+        if self.encoding.endianness == Endianness::Big {
+            flags |= 1 << 1;
+        }
+
+        let limit = u32::try_from(self.encoding.limit.unwrap_or(0))
+            .map_err(|_| anyhow::anyhow!("Encoding limit does not fit into u32"))?;
+
+        let mut bytes = Vec::with_capacity(Self::LEN);
+        bytes.push(self.protocol_version);
+        bytes.extend_from_slice(&[
+            self.crate_version.0,
+            self.crate_version.1,
+            self.crate_version.2,
+        ]);
+        bytes.push(flags);
+        bytes.extend_from_slice(&limit.to_le_bytes());
+        bytes.extend_from_slice(&self.features.0.to_le_bytes());
+
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() != Self::LEN {
+            return Err(anyhow::anyhow!(
+                "Invalid length for ProtocolInfo: expected {} bytes, got {}",
+                Self::LEN,
+                bytes.len()
+            ));
+        }
+
+        let limit = u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]);
+
+        Ok(Self {
+            protocol_version: bytes[0],
+            crate_version: (bytes[1], bytes[2], bytes[3]),
+            encoding: EncodingConfig {
+                int_encoding: if bytes[4] & (1 << 0) != 0 {
+                    IntEncoding::Fixed
+                } else {
+                    IntEncoding::Variable
+                },
+                endianness: if bytes[4] & (1 << 1) != 0 {
+                    Endianness::Big
+                } else {
+                    Endianness::Little
+                },
+                limit: (limit != 0).then_some(limit as usize),
+            },
+            features: ProtocolFeatures(u32::from_le_bytes([
+                bytes[9], bytes[10], bytes[11], bytes[12],
+            ])),
+        })
+    }
+}
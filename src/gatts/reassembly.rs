@@ -0,0 +1,121 @@
+// Kept free of esp-idf dependencies, so host-side fuzz targets can include it directly
+
+use std::{collections::HashMap, hash::Hash};
+
+/// Error of a write fragment which cannot be applied to the reassembled value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReassemblyError {
+    // Fragment starts past the end of already written data
+    InvalidOffset,
+    // Fragment would grow the value past the maximum attribute length
+    InvalidLength,
+    // Fragment is for another attribute than the ones already queued
+    OtherAttribute,
+}
+
+/// Reassembles value of a prepared (long) write from its fragments.
+#[derive(Debug, Default)]
+pub struct WriteReassembler {
+    value: Vec<u8>,
+}
+
+impl WriteReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes fragment at given offset, fragments may overwrite each other
+    /// but must not leave gaps in the value
+    pub fn write(
+        &mut self,
+        offset: u16,
+        fragment: &[u8],
+        max_len: usize,
+    ) -> Result<(), ReassemblyError> {
+        let offset = offset as usize;
+        if offset > self.value.len() {
+            return Err(ReassemblyError::InvalidOffset);
+        }
+
+        let end = offset
+            .checked_add(fragment.len())
+            .filter(|end| *end <= max_len)
+            .ok_or(ReassemblyError::InvalidLength)?;
+
+        if self.value.len() < end {
+            self.value.resize(end, 0);
+        }
+        self.value[offset..end].copy_from_slice(fragment);
+
+        Ok(())
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.value)
+    }
+}
+
+/// Prepared write queued by a connection, fragments of a single attribute
+#[derive(Debug)]
+pub struct PreparedWrite<H> {
+    pub handle: H,
+    pub value: WriteReassembler,
+}
+
+/// Prepare queue of each connection. The peer sends every Prepare Write and
+/// the Execute Write in its own transaction, so the queue lives until the
+/// connection executes or cancels it, or disconnects
+#[derive(Debug)]
+pub struct PrepareQueues<C, H> {
+    queues: HashMap<C, PreparedWrite<H>>,
+}
+
+impl<C, H> Default for PrepareQueues<C, H> {
+    fn default() -> Self {
+        Self {
+            queues: HashMap::new(),
+        }
+    }
+}
+
+impl<C: Copy + Eq + Hash, H: Copy + PartialEq> PrepareQueues<C, H> {
+    /// Queues a fragment written by the connection, returns the length of the
+    /// queued value. A fragment which can not be applied discards the queue
+    pub fn prepare(
+        &mut self,
+        conn_id: C,
+        handle: H,
+        offset: u16,
+        fragment: &[u8],
+        max_len: usize,
+    ) -> Result<usize, ReassemblyError> {
+        let prepared = self.queues.entry(conn_id).or_insert(PreparedWrite {
+            handle,
+            value: WriteReassembler::new(),
+        });
+
+        // Long writes of several attributes in one queue are not supported
+        let result = if prepared.handle != handle {
+            Err(ReassemblyError::OtherAttribute)
+        } else {
+            prepared.value.write(offset, fragment, max_len)
+        };
+
+        match result {
+            Ok(()) => Ok(prepared.value.value().len()),
+            Err(err) => {
+                self.queues.remove(&conn_id);
+                Err(err)
+            }
+        }
+    }
+
+    /// Takes the queue of the connection to execute or cancel it
+    pub fn take(&mut self, conn_id: &C) -> Option<PreparedWrite<H>> {
+        self.queues.remove(conn_id)
+    }
+}
@@ -0,0 +1,170 @@
+//! Routing of connections made through advertising identities to their apps.
+//!
+//! The stack reports a connection to GATTS and the advertising set it came through
+//! to Gap separately, in no fixed order, and neither event carries a key of the
+//! other: GATTS knows the connection id and peer address, Gap only the HCI handle.
+//! Routes are therefore matched to connections by time. A new connection without
+//! a route is parked with all its events until Gap reports one, and released to
+//! every app once `ROUTE_TIMEOUT` passes without one. Routes which match no
+//! connection in time, or come after their connection was released, are dropped,
+//! so they are never applied to a later, unrelated connection.
+//!
+//! The router is owned by the GATTS dispatcher thread, which never blocks on it.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use esp_idf_svc::bt::ble::gatt::{GattInterface, server::ConnectionId};
+
+use super::event::GattsEventMessage;
+
+// How long a new connection and the route Gap reports for it may be apart
+pub(crate) const ROUTE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Advertising identity a peer connected through, reported by Gap
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Route {
+    pub interface: GattInterface,
+    // HCI handle of the link, for logging only, GATTS does not know it
+    pub conn_handle: u16,
+    pub at: Instant,
+}
+
+// Connection waiting for its route, with its events held back in order
+pub(crate) struct ParkedConnection {
+    pub conn_id: ConnectionId,
+    since: Instant,
+    pub events: Vec<GattsEventMessage>,
+}
+
+#[derive(Default)]
+pub(crate) struct Router {
+    routes: VecDeque<Route>,
+    parked: VecDeque<ParkedConnection>,
+    // When connections were released without a route, a route coming right
+    // after one of them is late for it
+    released: VecDeque<Instant>,
+}
+
+impl Router {
+    pub fn is_parked(&self, conn_id: ConnectionId) -> bool {
+        self.parked.iter().any(|parked| parked.conn_id == conn_id)
+    }
+
+    /// Holds back an event of a parked connection
+    pub fn hold(&mut self, conn_id: ConnectionId, event: GattsEventMessage) {
+        if let Some(parked) = self
+            .parked
+            .iter_mut()
+            .find(|parked| parked.conn_id == conn_id)
+        {
+            parked.events.push(event);
+        }
+    }
+
+    /// Route reported before the connection made at `at`
+    pub fn take_route(&mut self, at: Instant) -> Option<Route> {
+        let index = self
+            .routes
+            .iter()
+            .position(|route| at.saturating_duration_since(route.at) <= ROUTE_TIMEOUT)?;
+
+        self.routes.remove(index)
+    }
+
+    /// Parks a new connection until its route is reported
+    pub fn park(&mut self, conn_id: ConnectionId, event: GattsEventMessage) {
+        self.parked.push_back(ParkedConnection {
+            conn_id,
+            since: event.2.at,
+            events: vec![event],
+        });
+    }
+
+    /// Takes a route reported by Gap, returns the oldest parked connection
+    /// it belongs to
+    pub fn add_route(&mut self, route: Route) -> Option<ParkedConnection> {
+        self.expire_routes(route.at);
+
+        if let Some(parked) = self.parked.pop_front() {
+            return Some(parked);
+        }
+
+        if self.released.pop_front().is_some() {
+            log::warn!(
+                "Route of link {:#x} to app {} came after its connection was released, dropped",
+                route.conn_handle,
+                route.interface
+            );
+            return None;
+        }
+
+        self.routes.push_back(route);
+        None
+    }
+
+    /// Releases parked connections without a route after `ROUTE_TIMEOUT`, they
+    /// are visible to every app
+    pub fn expire(&mut self, now: Instant) -> Vec<ParkedConnection> {
+        self.expire_routes(now);
+
+        let mut expired = Vec::new();
+        while self
+            .parked
+            .front()
+            .is_some_and(|parked| now.saturating_duration_since(parked.since) >= ROUTE_TIMEOUT)
+        {
+            let Some(parked) = self.parked.pop_front() else {
+                break;
+            };
+
+            log::warn!(
+                "Connection {} did not come through an advertising identity, visible to every app",
+                parked.conn_id
+            );
+            self.released.push_back(now);
+            expired.push(parked);
+        }
+
+        expired
+    }
+
+    /// Time of the next `expire` which has something to do
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let parked = self.parked.front().map(|parked| parked.since);
+        let route = self.routes.front().map(|route| route.at);
+        let released = self.released.front().copied();
+
+        [parked, route, released]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(|at| at + ROUTE_TIMEOUT)
+    }
+
+    fn expire_routes(&mut self, now: Instant) {
+        while let Some(route) = self
+            .routes
+            .front()
+            .filter(|route| now.saturating_duration_since(route.at) >= ROUTE_TIMEOUT)
+            .copied()
+        {
+            log::warn!(
+                "Route of link {:#x} to app {} matched no connection, dropped",
+                route.conn_handle,
+                route.interface
+            );
+            self.routes.pop_front();
+        }
+
+        while self
+            .released
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= ROUTE_TIMEOUT)
+        {
+            self.released.pop_front();
+        }
+    }
+}
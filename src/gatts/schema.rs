@@ -0,0 +1,487 @@
+use serde::{Deserialize, Serialize, ser};
+
+/// Version of the schema format, bumped on incompatible changes
+pub const SCHEMA_VERSION: u8 = 1;
+
+/// Description of the whole GATT table, exported by the firmware and consumed
+/// by `esp-bluedroid-cli codegen` to generate client side accessors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GattSchema {
+    pub schema_version: u8,
+    pub encoding: EncodingSchema,
+    pub services: Vec<ServiceSchema>,
+}
+
+/// Bincode configuration used for `ValueFormat::Bincode` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncodingSchema {
+    pub fixed_int: bool,
+    pub big_endian: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceSchema {
+    pub uuid: String,
+    pub characteristics: Vec<CharacteristicSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharacteristicSchema {
+    pub uuid: String,
+    pub name: Option<String>,
+    pub readable: bool,
+    pub writable: bool,
+    pub notify: bool,
+    pub format: ValueFormat,
+    pub value: ValueSchema,
+}
+
+/// How the value is laid out on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValueFormat {
+    /// Serde value encoded with bincode, see `EncodingSchema`
+    Bincode,
+    /// Fixed width little endian numbers, strings and bytes take the whole value
+    Raw,
+    /// Serde value encoded as CBOR (RFC 8949), see `CborCodec`
+    Cbor,
+    /// Serde value encoded with postcard, see `PostcardCodec`
+    Postcard,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ValueSchema {
+    Unit,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    String,
+    Bytes,
+    Option(Box<ValueSchema>),
+    Seq(Box<ValueSchema>),
+    Map(Box<ValueSchema>, Box<ValueSchema>),
+    Tuple(Vec<ValueSchema>),
+    Struct {
+        name: String,
+        fields: Vec<FieldSchema>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<VariantSchema>,
+    },
+    /// Type without a schema, e.g. `char`
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    pub name: String,
+    pub value: ValueSchema,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariantSchema {
+    pub index: u32,
+    pub name: String,
+    pub value: ValueSchema,
+}
+
+impl ValueSchema {
+    /// Traces schema of a serde value by serializing it.
+    ///
+    /// Only the shape of the given value is visible, so values whose shape depends
+    /// on them are refused with None: enums, which show only the held variant,
+    /// `None` options and empty sequences or maps. Such types need a schema of
+    /// their type, e.g. from `#[derive(Attribute)]`
+    pub fn trace<T: Serialize + ?Sized>(value: &T) -> Option<Self> {
+        value.serialize(Tracer).ok()
+    }
+
+    /// Format and traced schema of a serde value, raw bytes when it can not be
+    /// traced, see `trace`
+    pub fn trace_or_bytes<T: Serialize + ?Sized>(
+        format: ValueFormat,
+        value: &T,
+    ) -> (ValueFormat, Self) {
+        match Self::trace(value) {
+            Some(schema) => (format, schema),
+            None => (ValueFormat::Raw, Self::Bytes),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TraceError(String);
+
+impl std::fmt::Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl ser::Error for TraceError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+// Schema of a nested value, a refused shape refuses the whole value
+fn traced<T: Serialize + ?Sized>(value: &T) -> Result<ValueSchema, TraceError> {
+    value.serialize(Tracer)
+}
+
+// Error of a value whose shape can not be told from the value alone
+fn value_dependent(what: &str) -> TraceError {
+    TraceError(format!("Schema of {} depends on the value", what))
+}
+
+struct Tracer;
+
+impl ser::Serializer for Tracer {
+    type Ok = ValueSchema;
+    type Error = TraceError;
+
+    type SerializeSeq = SeqTracer;
+    type SerializeTuple = TupleTracer;
+    type SerializeTupleStruct = TupleTracer;
+    type SerializeTupleVariant = ser::Impossible<ValueSchema, TraceError>;
+    type SerializeMap = MapTracer;
+    type SerializeStruct = StructTracer;
+    type SerializeStructVariant = ser::Impossible<ValueSchema, TraceError>;
+
+    fn serialize_bool(self, _v: bool) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Bool)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::I8)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::I16)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::I32)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::I64)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::U8)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::U16)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::U32)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::U64)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::F32)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::F64)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Unknown)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::String)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Bytes)
+    }
+
+    fn serialize_none(self) -> Result<ValueSchema, TraceError> {
+        Err(value_dependent("None option"))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Option(Box::new(traced(value)?)))
+    }
+
+    fn serialize_unit(self) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Unit)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Unit)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<ValueSchema, TraceError> {
+        Err(value_dependent(name))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<ValueSchema, TraceError> {
+        traced(value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<ValueSchema, TraceError> {
+        Err(value_dependent(name))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqTracer, TraceError> {
+        Ok(SeqTracer(None))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<TupleTracer, TraceError> {
+        Ok(TupleTracer(Vec::with_capacity(len)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<TupleTracer, TraceError> {
+        Ok(TupleTracer(Vec::with_capacity(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, TraceError> {
+        Err(value_dependent(name))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapTracer, TraceError> {
+        Ok(MapTracer {
+            key: None,
+            value: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<StructTracer, TraceError> {
+        Ok(StructTracer {
+            name: name.to_string(),
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, TraceError> {
+        Err(value_dependent(name))
+    }
+}
+
+struct SeqTracer(Option<ValueSchema>);
+
+impl ser::SerializeSeq for SeqTracer {
+    type Ok = ValueSchema;
+    type Error = TraceError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TraceError> {
+        // Elements share a type, the first one tells it
+        if self.0.is_none() {
+            self.0 = Some(traced(value)?);
+        }
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<ValueSchema, TraceError> {
+        let element = self.0.ok_or_else(|| value_dependent("empty sequence"))?;
+        Ok(ValueSchema::Seq(Box::new(element)))
+    }
+}
+
+struct TupleTracer(Vec<ValueSchema>);
+
+impl ser::SerializeTuple for TupleTracer {
+    type Ok = ValueSchema;
+    type Error = TraceError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TraceError> {
+        self.0.push(traced(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Tuple(self.0))
+    }
+}
+
+impl ser::SerializeTupleStruct for TupleTracer {
+    type Ok = ValueSchema;
+    type Error = TraceError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TraceError> {
+        ser::SerializeTuple::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<ValueSchema, TraceError> {
+        ser::SerializeTuple::end(self)
+    }
+}
+
+struct MapTracer {
+    key: Option<ValueSchema>,
+    value: Option<ValueSchema>,
+}
+
+impl ser::SerializeMap for MapTracer {
+    type Ok = ValueSchema;
+    type Error = TraceError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), TraceError> {
+        if self.key.is_none() {
+            self.key = Some(traced(key)?);
+        }
+
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TraceError> {
+        if self.value.is_none() {
+            self.value = Some(traced(value)?);
+        }
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<ValueSchema, TraceError> {
+        match (self.key, self.value) {
+            (Some(key), Some(value)) => Ok(ValueSchema::Map(Box::new(key), Box::new(value))),
+            _ => Err(value_dependent("empty map")),
+        }
+    }
+}
+
+struct StructTracer {
+    name: String,
+    fields: Vec<FieldSchema>,
+}
+
+impl ser::SerializeStruct for StructTracer {
+    type Ok = ValueSchema;
+    type Error = TraceError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), TraceError> {
+        self.fields.push(FieldSchema {
+            name: key.to_string(),
+            value: traced(value)?,
+        });
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<ValueSchema, TraceError> {
+        Ok(ValueSchema::Struct {
+            name: self.name,
+            fields: self.fields,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Reading {
+        id: u16,
+        samples: Vec<i8>,
+    }
+
+    #[derive(Serialize)]
+    enum Mode {
+        Idle,
+        Run(u8),
+    }
+
+    #[test]
+    fn traces_fixed_shapes() {
+        let reading = Reading {
+            id: 1,
+            samples: vec![2],
+        };
+
+        assert_eq!(
+            ValueSchema::trace(&reading),
+            Some(ValueSchema::Struct {
+                name: "Reading".to_string(),
+                fields: vec![
+                    FieldSchema {
+                        name: "id".to_string(),
+                        value: ValueSchema::U16,
+                    },
+                    FieldSchema {
+                        name: "samples".to_string(),
+                        value: ValueSchema::Seq(Box::new(ValueSchema::I8)),
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn refuses_value_dependent_shapes() {
+        assert_eq!(ValueSchema::trace(&Mode::Idle), None);
+        assert_eq!(ValueSchema::trace(&Mode::Run(1)), None);
+        assert_eq!(ValueSchema::trace(&None::<u8>), None);
+        assert_eq!(ValueSchema::trace(&Vec::<u8>::new()), None);
+        assert_eq!(ValueSchema::trace(&HashMap::<u8, u8>::new()), None);
+        // A refused field refuses the whole value
+        let reading = Reading {
+            id: 1,
+            samples: Vec::new(),
+        };
+        assert_eq!(ValueSchema::trace(&reading), None);
+
+        assert_eq!(
+            ValueSchema::trace_or_bytes(ValueFormat::Bincode, &Mode::Idle),
+            (ValueFormat::Raw, ValueSchema::Bytes)
+        );
+    }
+}
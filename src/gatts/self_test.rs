@@ -0,0 +1,184 @@
+use std::collections::{HashMap, HashSet};
+
+use esp_idf_svc::bt::{BtUuid, ble::gatt::Handle};
+
+use super::{CCCD_UUID, GattsInner, service::ServiceId};
+
+/// Result of `Gatts::self_test`, empty `issues` means the table is consistent
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    // Number of registered attributes which were checked
+    pub attributes: usize,
+    pub issues: Vec<SelfTestIssue>,
+}
+
+impl SelfTestReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelfTestIssue {
+    /// Service was added to an app but the stack never assigned its handle
+    UnregisteredService { id: ServiceId, error: String },
+    /// Characteristic is missing from the attribute map or its handle differs
+    /// from the one the service knows it by
+    UnregisteredCharacteristic { handle: Handle, error: String },
+    /// Characteristic handle lies outside of the handles reserved by its service
+    OutOfServiceRange {
+        handle: Handle,
+        service: Handle,
+        num_handles: u16,
+    },
+    /// Current value does not survive decoding and encoding again
+    RoundTrip {
+        handle: Handle,
+        uuid: BtUuid,
+        error: String,
+    },
+    /// Descriptor whose characteristic was dropped or is not registered
+    OrphanDescriptor {
+        handle: Handle,
+        uuid: BtUuid,
+        error: String,
+    },
+    /// Descriptor placed before its characteristic, peers discover descriptors
+    /// after the characteristic value
+    MisplacedDescriptor {
+        handle: Handle,
+        uuid: BtUuid,
+        characteristic: Handle,
+    },
+    /// Characteristic supports notifications but has no CCCD to enable them
+    MissingCccd { handle: Handle },
+}
+
+pub(crate) fn run(gatts: &GattsInner) -> anyhow::Result<SelfTestReport> {
+    let attributes = gatts
+        .attributes
+        .read()?
+        .iter()
+        .map(|(handle, attribute)| (*handle, attribute.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut report = SelfTestReport {
+        attributes: attributes.len(),
+        issues: Vec::new(),
+    };
+
+    let apps = gatts.apps.read()?.values().cloned().collect::<Vec<_>>();
+    let mut notifying = Vec::new();
+
+    for app in apps {
+        let services = app.services.read()?.values().cloned().collect::<Vec<_>>();
+
+        for service in services {
+            let service_handle = match service.get_handle() {
+                Ok(handle) => handle,
+                Err(err) => {
+                    report.issues.push(SelfTestIssue::UnregisteredService {
+                        id: service.id.clone(),
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let characteristics = service
+                .characteristics
+                .read()?
+                .iter()
+                .map(|(handle, characteristic)| (*handle, characteristic.clone()))
+                .collect::<Vec<_>>();
+
+            for (handle, characteristic) in characteristics {
+                let registered = match characteristic.handle() {
+                    Ok(own) if own != handle => Err(anyhow::anyhow!(
+                        "Characteristic reports handle {} instead",
+                        own
+                    )),
+                    Ok(_) if !attributes.contains_key(&handle) => {
+                        Err(anyhow::anyhow!("Handle is missing from the attribute map"))
+                    }
+                    other => other.map(|_| ()),
+                };
+
+                if let Err(err) = registered {
+                    report
+                        .issues
+                        .push(SelfTestIssue::UnregisteredCharacteristic {
+                            handle,
+                            error: err.to_string(),
+                        });
+                }
+
+                if handle <= service_handle || handle - service_handle >= service.num_handles {
+                    report.issues.push(SelfTestIssue::OutOfServiceRange {
+                        handle,
+                        service: service_handle,
+                        num_handles: service.num_handles,
+                    });
+                }
+
+                if characteristic.schema()?.notify {
+                    notifying.push(handle);
+                }
+            }
+        }
+    }
+
+    let mut with_cccd = HashSet::new();
+    let mut handles = attributes.keys().copied().collect::<Vec<_>>();
+    handles.sort();
+
+    for handle in handles {
+        let attribute = &attributes[&handle];
+
+        if let Err(err) = attribute.round_trip() {
+            report.issues.push(SelfTestIssue::RoundTrip {
+                handle,
+                uuid: attribute.uuid(),
+                error: err.to_string(),
+            });
+        }
+
+        let characteristic = match attribute.characteristic_handle() {
+            Ok(Some(characteristic)) if !attributes.contains_key(&characteristic) => Err(
+                anyhow::anyhow!("Characteristic {} is not registered", characteristic),
+            ),
+            other => other,
+        };
+
+        match characteristic {
+            Ok(None) => {}
+            Ok(Some(characteristic)) => {
+                if handle <= characteristic {
+                    report.issues.push(SelfTestIssue::MisplacedDescriptor {
+                        handle,
+                        uuid: attribute.uuid(),
+                        characteristic,
+                    });
+                }
+
+                if attribute.uuid() == BtUuid::uuid16(CCCD_UUID) {
+                    with_cccd.insert(characteristic);
+                }
+            }
+            Err(err) => report.issues.push(SelfTestIssue::OrphanDescriptor {
+                handle,
+                uuid: attribute.uuid(),
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    report.issues.extend(
+        notifying
+            .into_iter()
+            .filter(|handle| !with_cccd.contains(handle))
+            .map(|handle| SelfTestIssue::MissingCccd { handle }),
+    );
+
+    Ok(report)
+}
@@ -5,17 +5,26 @@ use std::{
     sync::{Arc, RwLock, Weak},
 };
 
-use crossbeam_channel::unbounded;
-use esp_idf_svc::bt::{
-    ble::gatt::{GattId, GattServiceId, GattStatus, Handle},
-    BtUuid,
+use esp_idf_svc::{
+    bt::{
+        ble::gatt::{GattId, GattServiceId, GattStatus, Handle},
+        BtUuid,
+    },
+    sys::{esp, esp_ble_gatts_send_service_change_indication},
 };
 
 use super::{
     app::AppInner,
     attribute::Attribute,
     characteristic::{Characteristic, CharacteristicAttribute},
-    GattsEvent, GattsEventMessage,
+    ident::AppInterface,
+    protocol::ProtocolInfo,
+    schema::ServiceSchema,
+    uuid_string, GattsEvent, GattsEventMessage,
+};
+use crate::{
+    lock::{self, OrderedRwLock},
+    trace,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -28,6 +37,8 @@ impl std::hash::Hash for ServiceId {
     }
 }
 
+type CharacteristicMap = HashMap<Handle, Arc<dyn CharacteristicAttribute>>;
+
 #[derive(Clone)]
 pub struct Service(pub Arc<ServiceInner>);
 
@@ -36,8 +47,9 @@ pub struct ServiceInner {
     pub id: ServiceId,
     pub num_handles: u16,
 
-    pub characteristics: Arc<RwLock<HashMap<Handle, Arc<dyn CharacteristicAttribute>>>>,
+    pub characteristics: Arc<OrderedRwLock<lock::Characteristics, CharacteristicMap>>,
     pub handle: RwLock<Option<Handle>>,
+    pub started: RwLock<bool>,
 }
 
 impl Service {
@@ -46,6 +58,7 @@ impl Service {
             app: Default::default(),
             id: ServiceId(service_id),
             handle: RwLock::new(None),
+            started: RwLock::new(false),
             num_handles,
             characteristics: Default::default(),
         };
@@ -64,7 +77,8 @@ impl Service {
             .write()
             .map_err(|_| anyhow::anyhow!("Failed to write Gatt interface"))? = Arc::downgrade(app);
 
-        let (tx, rx) = unbounded();
+        trace::span!("gatts.register_service", uuid = ?self.uuid());
+
         let callback_key = discriminant(&GattsEvent::ServiceCreated {
             status: GattStatus::Busy,
             service_handle: 0,
@@ -80,11 +94,7 @@ impl Service {
         let gatt_interface = app.interface()?;
         let gatts = app.get_gatts()?;
 
-        gatts
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events"))?
-            .insert(callback_key.clone(), tx.clone());
+        let rx = gatts.waiter(callback_key)?;
 
         gatts
             .gatts
@@ -101,11 +111,13 @@ impl Service {
                     service_handle,
                     service_id,
                 },
+                _,
             )) => {
                 if interface != gatt_interface {
                     return Err(anyhow::anyhow!(
-                        "Received unexpected GATT interface: {:?}",
-                        interface
+                        "Received service of {} while registering on {}",
+                        AppInterface::unknown(interface),
+                        app.app_interface()?
                     ));
                 }
 
@@ -146,8 +158,7 @@ impl Service {
         if self
             .0
             .characteristics
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire write lock on Gatts services"))?
+            .write()?
             .insert(characteristic_handle, characteristic.0.clone())
             .is_some()
         {
@@ -160,8 +171,13 @@ impl Service {
         Ok(characteristic.clone())
     }
 
+    /// Registers read-only characteristic describing framing/encoding and enabled
+    /// features of the firmware, see `ProtocolInfo`
+    pub fn register_protocol_info(&self) -> anyhow::Result<Characteristic<ProtocolInfo>> {
+        self.register_characteristic(&ProtocolInfo::characteristic()?)
+    }
+
     pub fn start(&self) -> anyhow::Result<()> {
-        let (tx, rx) = unbounded();
         let callback_key = discriminant(&GattsEvent::ServiceStarted {
             status: GattStatus::Busy,
             service_handle: 0,
@@ -171,11 +187,7 @@ impl Service {
         let gatts = app.get_gatts()?;
         let handle = self.0.get_handle()?;
 
-        gatts
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events"))?
-            .insert(callback_key, tx);
+        let rx = gatts.waiter(callback_key)?;
 
         gatts.gatts.start_service(handle.clone()).map_err(|err| {
             anyhow::anyhow!("Failed to start GATT service {:?}: {:?}", handle, err)
@@ -188,6 +200,7 @@ impl Service {
                     status,
                     service_handle,
                 },
+                _,
             )) => {
                 if service_handle != handle {
                     return Err(anyhow::anyhow!(
@@ -200,7 +213,7 @@ impl Service {
                     return Err(anyhow::anyhow!("Failed to start service: {:?}", status));
                 }
 
-                Ok(())
+                self.0.set_started(true)
             }
             Ok(_) => Err(anyhow::anyhow!("Received unexpected GATT")),
             Err(_) => Err(anyhow::anyhow!("Timed out waiting for GATT")),
@@ -208,7 +221,6 @@ impl Service {
     }
 
     pub fn stop(&self) -> anyhow::Result<()> {
-        let (tx, rx) = unbounded();
         let callback_key = discriminant(&GattsEvent::ServiceStopped {
             status: GattStatus::Busy,
             service_handle: 0,
@@ -217,11 +229,7 @@ impl Service {
         let gatts = app.get_gatts()?;
         let handle = self.0.get_handle()?;
 
-        gatts
-            .gatts_events
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to write Gatts events"))?
-            .insert(callback_key, tx);
+        let rx = gatts.waiter(callback_key)?;
 
         gatts.gatts.stop_service(handle.clone()).map_err(|err| {
             anyhow::anyhow!("Failed to stop GATT service {:?}: {:?}", handle, err)
@@ -234,6 +242,7 @@ impl Service {
                     status,
                     service_handle,
                 },
+                _,
             )) => {
                 if service_handle != handle {
                     return Err(anyhow::anyhow!(
@@ -246,6 +255,112 @@ impl Service {
                     return Err(anyhow::anyhow!("Failed to stop service: {:?}", status));
                 }
 
+                self.0.set_started(false)
+            }
+            Ok(_) => Err(anyhow::anyhow!("Received unexpected GATT")),
+            Err(_) => Err(anyhow::anyhow!("Timed out waiting for GATT")),
+        }
+    }
+
+    /// Removes characteristic from the service at runtime.
+    ///
+    /// Bluedroid can not delete single attributes, so the whole service is deleted
+    /// and created again with the remaining characteristics (their handles change),
+    /// then connected peers are notified with Service Changed indication.
+    pub fn remove_characteristic(&self, handle: Handle) -> anyhow::Result<()> {
+        if self.0.characteristics.write()?.remove(&handle).is_none() {
+            return Err(anyhow::anyhow!(
+                "Characteristic with handle {:?} does not exist",
+                handle
+            ));
+        }
+
+        self.rebuild()
+    }
+
+    /// Re-creates service in the stack with its current characteristics, restoring
+    /// started state and notifying connected peers about changed attribute table
+    pub fn rebuild(&self) -> anyhow::Result<()> {
+        let app = self.0.get_app()?;
+        let gatts = app.get_gatts()?;
+        let was_started = self.0.is_started()?;
+
+        if was_started {
+            self.stop()?;
+        }
+
+        let old_handle = self.0.get_handle()?;
+        self.delete()?;
+
+        // Every attribute of the service lives in its handle range
+        let handles = old_handle..old_handle.saturating_add(self.0.num_handles);
+        gatts
+            .attributes
+            .write()?
+            .retain(|handle, _| !handles.contains(handle));
+
+        let mut characteristics = self.0.characteristics.write()?.drain().collect::<Vec<_>>();
+        // Keep original order of characteristics in the table
+        characteristics.sort_by_key(|(handle, _)| *handle);
+
+        self.register_bluedroid(&app)?;
+
+        for (_, characteristic) in characteristics {
+            characteristic.register_bluedroid(&self.0)?;
+        }
+
+        if was_started {
+            self.start()?;
+        }
+
+        let interface = app.interface()?;
+        esp!(unsafe {
+            esp_ble_gatts_send_service_change_indication(interface, std::ptr::null_mut())
+        })
+        .map_err(|err| anyhow::anyhow!("Failed to send Service Changed indication: {:?}", err))
+    }
+
+    fn delete(&self) -> anyhow::Result<()> {
+        let callback_key = discriminant(&GattsEvent::ServiceDeleted {
+            status: GattStatus::Busy,
+            service_handle: 0,
+        });
+        let app = self.0.get_app()?;
+        let gatts = app.get_gatts()?;
+        let handle = self.0.get_handle()?;
+
+        let rx = gatts.waiter(callback_key)?;
+
+        gatts.gatts.delete_service(handle).map_err(|err| {
+            anyhow::anyhow!("Failed to delete GATT service {:?}: {:?}", handle, err)
+        })?;
+
+        match rx.recv_timeout(std::time::Duration::from_secs(5)) {
+            Ok(GattsEventMessage(
+                _,
+                GattsEvent::ServiceDeleted {
+                    status,
+                    service_handle,
+                },
+                _,
+            )) => {
+                if service_handle != handle {
+                    return Err(anyhow::anyhow!(
+                        "Received unexpected GATT service handle: {:?}",
+                        service_handle
+                    ));
+                }
+
+                if status != GattStatus::Ok {
+                    return Err(anyhow::anyhow!("Failed to delete service: {:?}", status));
+                }
+
+                self.0
+                    .handle
+                    .write()
+                    .map_err(|_| anyhow::anyhow!("Failed to write Service handle"))?
+                    .take();
+
                 Ok(())
             }
             Ok(_) => Err(anyhow::anyhow!("Received unexpected GATT")),
@@ -269,4 +384,38 @@ impl ServiceInner {
             .map_err(|_| anyhow::anyhow!("Failed to read Service handle"))?
             .ok_or(anyhow::anyhow!("Service handle is not set"))
     }
+
+    pub fn schema(&self) -> anyhow::Result<ServiceSchema> {
+        let mut characteristics = self
+            .characteristics
+            .read()?
+            .iter()
+            .map(|(handle, characteristic)| (*handle, characteristic.clone()))
+            .collect::<Vec<_>>();
+        characteristics.sort_by_key(|(handle, _)| *handle);
+
+        Ok(ServiceSchema {
+            uuid: uuid_string(&self.id.0.id.uuid),
+            characteristics: characteristics
+                .into_iter()
+                .map(|(_, characteristic)| characteristic.schema())
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    pub fn is_started(&self) -> anyhow::Result<bool> {
+        self.started
+            .read()
+            .map(|started| *started)
+            .map_err(|_| anyhow::anyhow!("Failed to read Service state"))
+    }
+
+    fn set_started(&self, started: bool) -> anyhow::Result<()> {
+        *self
+            .started
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write Service state"))? = started;
+
+        Ok(())
+    }
 }
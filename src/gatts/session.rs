@@ -0,0 +1,307 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use esp_idf_svc::bt::{BdAddr, ble::gatt::server::ConnectionId};
+
+use crate::{
+    guard,
+    lock::{self, OrderedRwLock},
+};
+
+/// Application state of a single peer, kept across its reconnects, so state like
+/// last-read positions or authorization does not have to be keyed by `ConnectionId`,
+/// which changes on every connection.
+///
+/// Sessions are keyed by peer address as reported by the stack, which is the identity
+/// address for bonded peers. State lives in memory only and is lost on restart.
+#[derive(Clone)]
+pub struct Session(pub Arc<SessionInner>);
+
+pub struct SessionInner {
+    pub identity: BdAddr,
+    connection: RwLock<Option<ConnectionId>>,
+    state: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+}
+
+impl Session {
+    fn new(identity: BdAddr) -> Self {
+        Self(Arc::new(SessionInner {
+            identity,
+            connection: RwLock::new(None),
+            state: RwLock::new(HashMap::new()),
+        }))
+    }
+
+    pub fn identity(&self) -> BdAddr {
+        self.0.identity
+    }
+
+    /// Current connection of the peer, None while it is disconnected
+    pub fn connection_id(&self) -> anyhow::Result<Option<ConnectionId>> {
+        Ok(*self
+            .0
+            .connection
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read session connection"))?)
+    }
+
+    /// Stores value of type S, replacing and returning previous one
+    pub fn insert<S: Any + Send + Sync>(&self, value: S) -> anyhow::Result<Option<S>> {
+        let previous = self
+            .0
+            .state
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write session state"))?
+            .insert(TypeId::of::<S>(), Box::new(value));
+
+        Ok(previous.and_then(|value| value.downcast().ok().map(|value| *value)))
+    }
+
+    pub fn get<S: Any + Send + Sync + Clone>(&self) -> anyhow::Result<Option<S>> {
+        Ok(self
+            .0
+            .state
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read session state"))?
+            .get(&TypeId::of::<S>())
+            .and_then(|value| value.downcast_ref::<S>())
+            .cloned())
+    }
+
+    /// Updates value of type S in place, starting from `S::default()` if not set yet
+    pub fn update<S: Any + Send + Sync + Default, R>(
+        &self,
+        update: impl FnOnce(&mut S) -> R,
+    ) -> anyhow::Result<R> {
+        let mut state = self
+            .0
+            .state
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write session state"))?;
+
+        let value = state
+            .entry(TypeId::of::<S>())
+            .or_insert_with(|| Box::new(S::default()))
+            .downcast_mut::<S>()
+            .ok_or(anyhow::anyhow!("Failed to downcast session state"))?;
+
+        Ok(update(value))
+    }
+
+    pub fn remove<S: Any + Send + Sync>(&self) -> anyhow::Result<Option<S>> {
+        let previous = self
+            .0
+            .state
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write session state"))?
+            .remove(&TypeId::of::<S>());
+
+        Ok(previous.and_then(|value| value.downcast().ok().map(|value| *value)))
+    }
+
+    fn set_connection(&self, connection: Option<ConnectionId>) -> anyhow::Result<()> {
+        *self
+            .0
+            .connection
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write session connection"))? = connection;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub enum SessionEvent {
+    // First connection of the peer
+    Started(Session),
+    // Peer with existing session connected again, state is kept
+    Resumed(Session),
+    // Peer disconnected, session is kept until removed or evicted
+    Suspended(Session),
+    // Session is dropped together with its state
+    Ended(Session),
+}
+
+pub type SessionHandler = Arc<dyn Fn(SessionEvent) + Send + Sync>;
+
+pub struct SessionConfig {
+    // Maximum number of kept sessions, least recently disconnected session
+    // is ended when a new peer connects over the limit
+    pub max_sessions: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self { max_sessions: 8 }
+    }
+}
+
+pub(crate) struct Sessions {
+    config: RwLock<SessionConfig>,
+    handler: RwLock<Option<SessionHandler>>,
+    // Sessions in order of their last disconnect, connected ones at the end
+    sessions: OrderedRwLock<lock::Sessions, Vec<Session>>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self {
+            config: RwLock::new(SessionConfig::default()),
+            handler: RwLock::new(None),
+            sessions: Default::default(),
+        }
+    }
+
+    pub fn set_config(&self, config: SessionConfig) -> anyhow::Result<()> {
+        *self
+            .config
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write session config"))? = config;
+
+        Ok(())
+    }
+
+    pub fn set_handler(&self, handler: SessionHandler) -> anyhow::Result<()> {
+        *self
+            .handler
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write session handler"))? = Some(handler);
+
+        Ok(())
+    }
+
+    pub fn get(&self, identity: BdAddr) -> anyhow::Result<Option<Session>> {
+        Ok(self
+            .sessions
+            .read()?
+            .iter()
+            .find(|session| session.identity() == identity)
+            .cloned())
+    }
+
+    pub fn by_connection(&self, connection: ConnectionId) -> anyhow::Result<Option<Session>> {
+        for session in self.sessions.read()?.iter() {
+            if session.connection_id()? == Some(connection) {
+                return Ok(Some(session.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn connected(&self, identity: BdAddr, connection: ConnectionId) -> anyhow::Result<()> {
+        let max_sessions = self
+            .config
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read session config"))?
+            .max_sessions;
+
+        let mut events = Vec::new();
+        {
+            let mut sessions = self.sessions.write()?;
+
+            let event = match sessions
+                .iter()
+                .position(|session| session.identity() == identity)
+            {
+                Some(index) => {
+                    let session = sessions.remove(index);
+                    // Every app reports the same link, only the first one resumes
+                    let resumed = session.connection_id()? != Some(connection);
+                    session.set_connection(Some(connection))?;
+                    sessions.push(session.clone());
+
+                    resumed.then_some(SessionEvent::Resumed(session))
+                }
+                None => {
+                    let session = Session::new(identity);
+                    session.set_connection(Some(connection))?;
+                    sessions.push(session.clone());
+
+                    Some(SessionEvent::Started(session))
+                }
+            };
+
+            while sessions.len() > max_sessions.max(1) {
+                if sessions[0].connection_id()?.is_some() {
+                    break;
+                }
+                events.push(SessionEvent::Ended(sessions.remove(0)));
+            }
+            events.extend(event);
+        }
+
+        self.emit(events)
+    }
+
+    pub fn disconnected(&self, connection: ConnectionId) -> anyhow::Result<()> {
+        let mut events = Vec::new();
+        {
+            let mut sessions = self.sessions.write()?;
+
+            let mut index = None;
+            for (i, session) in sessions.iter().enumerate() {
+                if session.connection_id()? == Some(connection) {
+                    index = Some(i);
+                }
+            }
+
+            if let Some(index) = index {
+                let session = sessions.remove(index);
+                session.set_connection(None)?;
+
+                // Disconnected sessions are kept before the connected ones
+                let position = sessions
+                    .iter()
+                    .map(|session| session.connection_id())
+                    .collect::<anyhow::Result<Vec<_>>>()?
+                    .iter()
+                    .position(Option::is_some)
+                    .unwrap_or(sessions.len());
+                sessions.insert(position, session.clone());
+
+                events.push(SessionEvent::Suspended(session));
+            }
+        }
+
+        self.emit(events)
+    }
+
+    pub fn remove(&self, identity: BdAddr) -> anyhow::Result<Option<Session>> {
+        let session = {
+            let mut sessions = self.sessions.write()?;
+            sessions
+                .iter()
+                .position(|session| session.identity() == identity)
+                .map(|index| sessions.remove(index))
+        };
+
+        if let Some(session) = &session {
+            self.emit(vec![SessionEvent::Ended(session.clone())])?;
+        }
+
+        Ok(session)
+    }
+
+    fn emit(&self, events: Vec<SessionEvent>) -> anyhow::Result<()> {
+        let handler = self
+            .handler
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to read session handler"))?
+            .clone();
+
+        let Some(handler) = handler else {
+            return Ok(());
+        };
+
+        for event in events {
+            let handler = handler.clone();
+            guard::run_hook("session handler", move || handler(event))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+//! Well-known services built on the characteristic API, ready to register in an app.
+
+pub mod uart;
+
+pub use uart::{UartConfig, UartService};
@@ -0,0 +1,171 @@
+//! Nordic UART Service (NUS), the de facto standard serial port over BLE which
+//! terminal apps like nRF Connect or Serial Bluetooth Terminal talk to. Directions
+//! are named from the point of view of this device: peers write the RX
+//! characteristic and receive data through notifications of the TX characteristic.
+//! Unlike `GattStream` data is sent as is, without sequence numbers.
+
+use std::sync::Arc;
+
+use crossbeam_channel::{Receiver, unbounded};
+use esp_idf_svc::{
+    bt::{
+        BtUuid,
+        ble::gatt::{GattId, GattServiceId, server::ConnectionId},
+    },
+    sys::ESP_GATT_MAX_ATTR_LEN,
+};
+
+use crate::gatts::{
+    attribute::defaults::BytesAttr,
+    characteristic::{Characteristic, CharacteristicConfig, WriteEcho},
+    service::Service,
+};
+
+pub const UART_SERVICE_UUID: u128 = 0x6e400001_b5a3_f393_e0a9_e50e24dcca9e;
+// Written by peers
+pub const UART_RX_UUID: u128 = 0x6e400002_b5a3_f393_e0a9_e50e24dcca9e;
+// Notified to peers
+pub const UART_TX_UUID: u128 = 0x6e400003_b5a3_f393_e0a9_e50e24dcca9e;
+
+// Service, RX declaration and value, TX declaration, value, CCCD and description,
+// with room for descriptors added by the characteristic config
+const NUM_HANDLES: u16 = 10;
+
+pub struct UartConfig {
+    // User Description of the TX characteristic, shown by generic tools
+    pub description: Option<String>,
+    // If true, peers need an encrypted link to write RX
+    pub write_encrypted: bool,
+}
+
+impl Default for UartConfig {
+    fn default() -> Self {
+        Self {
+            description: Some(String::from("UART TX")),
+            write_encrypted: false,
+        }
+    }
+}
+
+/// Nordic UART Service with its RX and TX characteristics. Register `service` in
+/// an app, then the characteristics with `register`
+#[derive(Clone)]
+pub struct UartService(pub Arc<UartServiceInner>);
+
+pub struct UartServiceInner {
+    pub service: Service,
+    pub rx: Characteristic<BytesAttr>,
+    pub tx: Characteristic<BytesAttr>,
+
+    // Data written by peers to RX, with the writing connection, in order of arrival
+    pub received_rx: Receiver<(ConnectionId, Vec<u8>)>,
+}
+
+impl UartService {
+    pub fn new(config: UartConfig) -> Self {
+        let service = Service::new(
+            GattServiceId {
+                id: GattId {
+                    uuid: BtUuid::uuid128(UART_SERVICE_UUID),
+                    inst_id: 0,
+                },
+                is_primary: true,
+            },
+            NUM_HANDLES,
+        );
+
+        let rx = Characteristic::new(
+            BytesAttr(Vec::new()),
+            CharacteristicConfig {
+                uuid: BtUuid::uuid128(UART_RX_UUID),
+                value_max_len: ESP_GATT_MAX_ATTR_LEN as usize,
+                writable: !config.write_encrypted,
+                write_encrypted: config.write_encrypted,
+                write_without_response: true,
+                write_echo: WriteEcho::None,
+                ..Default::default()
+            },
+            None,
+        );
+        let tx = Characteristic::new(
+            BytesAttr(Vec::new()),
+            CharacteristicConfig {
+                uuid: BtUuid::uuid128(UART_TX_UUID),
+                value_max_len: ESP_GATT_MAX_ATTR_LEN as usize,
+                enable_notify: true,
+                description: config.description,
+                ..Default::default()
+            },
+            None,
+        );
+
+        let (received_tx, received_rx) = unbounded();
+        // RX is not shared yet, its handler lock can not be poisoned
+        rx.set_on_write(move |conn_id, data| {
+            if received_tx.send((conn_id, data.0.clone())).is_err() {
+                log::warn!("UART data of {:?} dropped, receiver is gone", conn_id);
+            }
+        })
+        .expect("Failed to set UART RX write handler");
+
+        Self(Arc::new(UartServiceInner {
+            service,
+            rx,
+            tx,
+            received_rx,
+        }))
+    }
+
+    /// Registers both characteristics, the service must be registered in an app
+    pub fn register(&self) -> anyhow::Result<()> {
+        self.0.service.register_characteristic(&self.0.rx)?;
+        self.0.service.register_characteristic(&self.0.tx)?;
+
+        Ok(())
+    }
+
+    /// Sends data to every peer subscribed to TX, split to the MTU of each peer.
+    /// Peers which did not enable notifications are skipped, as terminal apps
+    /// expect nothing before subscribing. Returns how many peers were sent to,
+    /// a peer failing does not stop the others, the errors are returned together
+    /// once every peer was tried
+    pub fn send(&self, data: &[u8]) -> anyhow::Result<usize> {
+        // Snapshot, so connection events are not blocked while sending
+        let connections = self
+            .0
+            .tx
+            .0
+            .get_service()?
+            .get_app()?
+            .connections
+            .read()?
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut sent = 0;
+        let mut errors = Vec::new();
+        for connection in connections {
+            let subscription = self.0.tx.subscription(connection.id())?;
+            if !subscription.notify && !subscription.indicate {
+                continue;
+            }
+
+            match connection.send_notifications(&self.0.tx, data) {
+                Ok(_) => sent += 1,
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Failed to send to {} of {} peers: {:?}",
+                errors.len(),
+                sent + errors.len(),
+                errors
+            ));
+        }
+
+        Ok(sent)
+    }
+}
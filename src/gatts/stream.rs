@@ -0,0 +1,371 @@
+//! Byte stream over a characteristic pair, peers write the RX characteristic
+//! and receive the TX characteristic through its CCCD. Every packet starts with
+//! a sequence number (u8, wrapping) followed by a chunk of the stream, so
+//! serial-style protocols run over BLE without chunking their messages to the
+//! MTU, and either side notices a lost packet.
+//!
+//! With the `embedded-io` feature the stream also implements `embedded_io::Read`
+//! and `Write`, the `async` feature adds the `embedded_io_async` variants.
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    task::Waker,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+use esp_idf_svc::{bt::BtUuid, sys::ESP_GATT_MAX_ATTR_LEN};
+
+use super::{
+    attribute::defaults::BytesAttr,
+    characteristic::{Characteristic, CharacteristicConfig, WriteEcho},
+    service::Service,
+};
+
+pub const STREAM_RX_UUID: u128 = 0x6a1f0004_8d3c_4b6e_9f2a_3c5e7b9d1e0f;
+pub const STREAM_TX_UUID: u128 = 0x6a1f0005_8d3c_4b6e_9f2a_3c5e7b9d1e0f;
+
+// Sequence number before the chunk of every packet
+const HEADER_LEN: usize = 1;
+// ATT notification header taken from the MTU
+const ATT_HEADER_LEN: usize = 3;
+// Payload of the default MTU of 23
+const DEFAULT_PACKET_LEN: usize = 20;
+
+pub struct GattStreamConfig {
+    // Characteristic written by peers
+    pub rx_uuid: BtUuid,
+    // Characteristic notified to peers
+    pub tx_uuid: BtUuid,
+    // How long `read` waits for data, None blocks until a peer writes
+    pub read_timeout: Option<Duration>,
+}
+
+impl Default for GattStreamConfig {
+    fn default() -> Self {
+        Self {
+            rx_uuid: BtUuid::uuid128(STREAM_RX_UUID),
+            tx_uuid: BtUuid::uuid128(STREAM_TX_UUID),
+            read_timeout: None,
+        }
+    }
+}
+
+/// `std::io::Read` and `std::io::Write` over an RX and TX characteristic pair.
+/// Meant for a single peer: writes of every peer end up in the same stream and
+/// written data is sent to every connection of the app
+#[derive(Clone)]
+pub struct GattStream(pub Arc<GattStreamInner>);
+
+pub struct GattStreamInner {
+    pub config: GattStreamConfig,
+    pub rx: Characteristic<BytesAttr>,
+    pub tx: Characteristic<BytesAttr>,
+
+    // Chunks of peer writes, in order of arrival
+    chunks_rx: Receiver<Vec<u8>>,
+    // Rest of the chunk partially returned by the last read
+    pending: Mutex<Vec<u8>>,
+    // Async read waiting for the next chunk
+    #[cfg(feature = "async")]
+    reader: Arc<Mutex<Option<Waker>>>,
+    tx_sequence: Mutex<u8>,
+}
+
+impl GattStream {
+    pub fn new(config: GattStreamConfig) -> anyhow::Result<Self> {
+        let rx = Characteristic::new(
+            BytesAttr(Vec::new()),
+            CharacteristicConfig {
+                uuid: config.rx_uuid.clone(),
+                value_max_len: ESP_GATT_MAX_ATTR_LEN as usize,
+                writable: true,
+                description: Some(String::from("Stream RX")),
+                write_echo: WriteEcho::None,
+                ..Default::default()
+            },
+            None,
+        );
+        let tx = Characteristic::new(
+            BytesAttr(Vec::new()),
+            CharacteristicConfig {
+                uuid: config.tx_uuid.clone(),
+                value_max_len: ESP_GATT_MAX_ATTR_LEN as usize,
+                enable_notify: true,
+                description: Some(String::from("Stream TX")),
+                ..Default::default()
+            },
+            None,
+        );
+
+        let (chunks_tx, chunks_rx) = unbounded();
+        let rx_sequence = Mutex::new(None);
+        let reader = Arc::new(Mutex::new(None));
+        let waiting_reader = reader.clone();
+        rx.set_on_write(move |conn_id, packet| {
+            if let Err(err) = receive(&chunks_tx, &rx_sequence, &waiting_reader, &packet.0) {
+                log::error!(
+                    "Failed to receive stream packet of {:?}: {:?}",
+                    conn_id,
+                    err
+                );
+            }
+        })?;
+
+        Ok(Self(Arc::new(GattStreamInner {
+            config,
+            rx,
+            tx,
+            chunks_rx,
+            pending: Mutex::new(Vec::new()),
+            #[cfg(feature = "async")]
+            reader,
+            tx_sequence: Mutex::new(0),
+        })))
+    }
+
+    /// Registers both characteristics in the service
+    pub fn register(&self, service: &Service) -> anyhow::Result<()> {
+        service.register_characteristic(&self.0.rx)?;
+        service.register_characteristic(&self.0.tx)?;
+
+        Ok(())
+    }
+}
+
+// Queues the chunk of a packet written by a peer and wakes a waiting async read
+fn receive(
+    chunks_tx: &Sender<Vec<u8>>,
+    rx_sequence: &Mutex<Option<u8>>,
+    reader: &Mutex<Option<Waker>>,
+    packet: &[u8],
+) -> anyhow::Result<()> {
+    let (&sequence, chunk) = packet
+        .split_first()
+        .ok_or(anyhow::anyhow!("Stream packet without header"))?;
+
+    let mut expected = rx_sequence
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Failed to lock stream sequence"))?;
+
+    // First packet sets the sequence, peers may start from any number
+    match *expected {
+        Some(expected) if expected != sequence => log::warn!(
+            "Stream packet {} received instead of {}, data was lost",
+            sequence,
+            expected
+        ),
+        _ => {}
+    }
+    *expected = Some(sequence.wrapping_add(1));
+
+    if chunk.is_empty() {
+        return Ok(());
+    }
+
+    chunks_tx
+        .send(chunk.to_vec())
+        .map_err(|err| anyhow::anyhow!("Failed to queue stream chunk: {:?}", err))?;
+
+    let reader = reader
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Failed to lock stream reader"))?
+        .take();
+    if let Some(reader) = reader {
+        reader.wake();
+    }
+
+    Ok(())
+}
+
+// Moves the start of `pending` into `buf`
+fn take_pending(pending: &mut Vec<u8>, buf: &mut [u8]) -> usize {
+    let len = buf.len().min(pending.len());
+    buf[..len].copy_from_slice(&pending[..len]);
+    pending.drain(..len);
+
+    len
+}
+
+impl GattStreamInner {
+    // Largest chunk every connected peer receives in one notification
+    fn chunk_len(&self) -> anyhow::Result<usize> {
+        let connections = self
+            .tx
+            .0
+            .get_service()?
+            .get_app()?
+            .connections
+            .read()?
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut packet_len = ESP_GATT_MAX_ATTR_LEN as usize;
+        for connection in connections {
+            let payload = match connection.mtu()? {
+                Some(mtu) => usize::from(mtu).saturating_sub(ATT_HEADER_LEN),
+                None => DEFAULT_PACKET_LEN,
+            };
+            packet_len = packet_len.min(payload);
+        }
+
+        Ok(packet_len.saturating_sub(HEADER_LEN).max(1))
+    }
+
+    fn send(&self, data: &[u8]) -> anyhow::Result<()> {
+        let mut sequence = self
+            .tx_sequence
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock stream sequence"))?;
+
+        for chunk in data.chunks(self.chunk_len()?) {
+            let mut packet = Vec::with_capacity(HEADER_LEN + chunk.len());
+            packet.push(*sequence);
+            packet.extend_from_slice(chunk);
+
+            self.tx.update_value(BytesAttr(packet))?;
+            *sequence = sequence.wrapping_add(1);
+        }
+
+        Ok(())
+    }
+
+    fn next_chunk(&self) -> io::Result<Option<Vec<u8>>> {
+        let chunk = match self.config.read_timeout {
+            Some(timeout) => self.chunks_rx.recv_timeout(timeout),
+            None => self
+                .chunks_rx
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match chunk {
+            Ok(chunk) => Ok(Some(chunk)),
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Timed out waiting for stream data",
+            )),
+            // Characteristic and its write handler were dropped
+            Err(RecvTimeoutError::Disconnected) => Ok(None),
+        }
+    }
+
+    // Non-blocking read for the async stream, `read_timeout` does not apply
+    #[cfg(feature = "async")]
+    fn poll_read(
+        &self,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        use crossbeam_channel::TryRecvError;
+        use std::task::Poll;
+
+        let mut pending = self
+            .pending
+            .lock()
+            .map_err(|_| io::Error::other("Failed to lock stream buffer"))?;
+
+        if pending.is_empty() {
+            let mut reader = self
+                .reader
+                .lock()
+                .map_err(|_| io::Error::other("Failed to lock stream reader"))?;
+
+            // Checked while holding the reader, so a chunk queued meanwhile wakes it
+            match self.chunks_rx.try_recv() {
+                Ok(chunk) => *pending = chunk,
+                Err(TryRecvError::Empty) => {
+                    *reader = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                Err(TryRecvError::Disconnected) => return Poll::Ready(Ok(0)),
+            }
+        }
+
+        Poll::Ready(Ok(take_pending(&mut pending, buf)))
+    }
+}
+
+impl io::Read for GattStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pending = self
+            .0
+            .pending
+            .lock()
+            .map_err(|_| io::Error::other("Failed to lock stream buffer"))?;
+
+        if pending.is_empty() {
+            match self.0.next_chunk()? {
+                Some(chunk) => *pending = chunk,
+                None => return Ok(0),
+            }
+        }
+
+        Ok(take_pending(&mut pending, buf))
+    }
+}
+
+impl io::Write for GattStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.send(buf).map_err(io::Error::other)?;
+
+        Ok(buf.len())
+    }
+
+    // Every write is sent right away
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::ErrorType for GattStream {
+    type Error = io::Error;
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Read for GattStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        io::Read::read(self, buf)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Write for GattStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        io::Write::write(self, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        io::Write::flush(self)
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_io_async::Read for GattStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        std::future::poll_fn(|cx| self.0.poll_read(cx, buf)).await
+    }
+}
+
+// Sending still waits for peers to confirm each packet, as `update_value` does
+#[cfg(feature = "async")]
+impl embedded_io_async::Write for GattStream {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        io::Write::write(self, buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        io::Write::flush(self)
+    }
+}
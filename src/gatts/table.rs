@@ -0,0 +1,272 @@
+use std::{mem::discriminant, sync::Arc};
+
+use esp_idf_svc::{
+    bt::{
+        BtUuid,
+        ble::gatt::{GattServiceId, GattStatus, Handle, Permission},
+    },
+    sys::{
+        ESP_GATT_ATTR_HANDLE_MAX, ESP_GATT_AUTO_RSP, ESP_GATT_RSP_BY_APP, esp, esp_attr_control_t,
+        esp_attr_desc_t, esp_ble_gatts_create_attr_tab, esp_gatts_attr_db_t,
+    },
+};
+
+use super::{
+    app::App,
+    attribute::Attribute,
+    characteristic::{Characteristic, CharacteristicAttribute},
+    event::{GattsEvent, GattsEventMessage},
+    ident::AppInterface,
+    service::{Service, ServiceInner},
+};
+
+pub(crate) type BindHandle = Box<dyn FnOnce(&Arc<ServiceInner>, Handle) -> anyhow::Result<()>>;
+
+/// Single row of the attribute table passed to `esp_ble_gatts_create_attr_tab`
+pub struct TableEntry {
+    pub uuid: BtUuid,
+    pub permissions: enumset::EnumSet<Permission>,
+    pub max_len: u16,
+    pub value: Vec<u8>,
+
+    // Stack answers reads and stores writes itself, value must be set
+    pub auto_response: bool,
+
+    // Called with the handle assigned by the stack, declarations have nothing to bind
+    pub(crate) bind: Option<BindHandle>,
+}
+
+impl TableEntry {
+    pub const PRIMARY_SERVICE_UUID: u16 = 0x2800;
+    pub const SECONDARY_SERVICE_UUID: u16 = 0x2801;
+    pub const CHARACTERISTIC_UUID: u16 = 0x2803;
+
+    /// Read-only declaration with constant value answered by the stack
+    pub fn declaration(uuid: u16, value: Vec<u8>) -> Self {
+        Self {
+            uuid: BtUuid::uuid16(uuid),
+            permissions: Permission::Read.into(),
+            max_len: value.len() as u16,
+            value,
+            auto_response: true,
+            bind: None,
+        }
+    }
+}
+
+/// Alternative to `Service::new` + `Service::register_characteristic`, which registers
+/// the service with all its characteristics and descriptors in a single stack call
+/// instead of waiting for an event after every attribute.
+///
+/// Table is limited to `ESP_GATT_ATTR_HANDLE_MAX` (100) attributes, UUIDs must be
+/// 16 or 128 bit.
+pub struct ServiceTable {
+    service_id: GattServiceId,
+    characteristics: Vec<Arc<dyn CharacteristicAttribute>>,
+}
+
+impl ServiceTable {
+    pub fn new(service_id: GattServiceId) -> Self {
+        Self {
+            service_id,
+            characteristics: Vec::new(),
+        }
+    }
+
+    /// Adds characteristic, characteristics appear in the table in the order they are added
+    pub fn characteristic<T: Attribute>(mut self, characteristic: &Characteristic<T>) -> Self {
+        self.characteristics.push(characteristic.0.clone());
+        self
+    }
+
+    pub fn register(self, app: &App) -> anyhow::Result<Service> {
+        let declaration_uuid = if self.service_id.is_primary {
+            TableEntry::PRIMARY_SERVICE_UUID
+        } else {
+            TableEntry::SECONDARY_SERVICE_UUID
+        };
+
+        let mut entries = vec![TableEntry::declaration(
+            declaration_uuid,
+            self.service_id.id.uuid.as_bytes().to_vec(),
+        )];
+        for characteristic in &self.characteristics {
+            entries.extend(characteristic.clone().table_entries()?);
+        }
+
+        if entries.len() > ESP_GATT_ATTR_HANDLE_MAX as usize {
+            return Err(anyhow::anyhow!(
+                "Attribute table of service {:?} has {} attributes, maximum is {}",
+                self.service_id,
+                entries.len(),
+                ESP_GATT_ATTR_HANDLE_MAX
+            ));
+        }
+
+        let service = Service::new(self.service_id.clone(), entries.len() as u16);
+        *service
+            .0
+            .app
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write Gatt interface"))? =
+            Arc::downgrade(&app.0);
+
+        let handles = create_attr_tab(&service.0, &self.service_id, &mut entries)?;
+
+        service
+            .0
+            .handle
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to write Service handle"))?
+            .replace(handles[0]);
+
+        for (entry, handle) in entries.into_iter().zip(handles) {
+            if let Some(bind) = entry.bind {
+                bind(&service.0, handle)?;
+            }
+        }
+
+        let mut characteristics = service.0.characteristics.write()?;
+        for characteristic in self.characteristics {
+            characteristics.insert(characteristic.handle()?, characteristic);
+        }
+        drop(characteristics);
+
+        if app
+            .0
+            .services
+            .write()?
+            .insert(service.0.id.clone(), service.0.clone())
+            .is_some()
+        {
+            return Err(anyhow::anyhow!(
+                "Service with handle {:?} already exists",
+                service.0.id
+            ));
+        }
+
+        Ok(service)
+    }
+}
+
+// Registers the table and returns handles assigned to its entries, in the same order
+fn create_attr_tab(
+    service: &Arc<ServiceInner>,
+    service_id: &GattServiceId,
+    entries: &mut [TableEntry],
+) -> anyhow::Result<Vec<Handle>> {
+    let app = service.get_app()?;
+    let gatts = app.get_gatts()?;
+    let gatts_interface = app.interface()?;
+
+    // Pointers in the table reference entries, which are kept alive until the stack
+    // reports created table, as it copies only the table itself
+    let mut uuids = entries
+        .iter()
+        .map(|entry| match entry.uuid.as_bytes().len() {
+            2 | 16 => Ok(entry.uuid.as_bytes().to_vec()),
+            _ => Err(anyhow::anyhow!(
+                "Attribute table supports only 16 and 128 bit UUIDs: {:?}",
+                entry.uuid
+            )),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let table = entries
+        .iter_mut()
+        .zip(uuids.iter_mut())
+        .map(|(entry, uuid)| esp_gatts_attr_db_t {
+            attr_control: esp_attr_control_t {
+                auto_rsp: if entry.auto_response {
+                    ESP_GATT_AUTO_RSP
+                } else {
+                    ESP_GATT_RSP_BY_APP
+                } as u8,
+            },
+            att_desc: esp_attr_desc_t {
+                uuid_length: uuid.len() as u16,
+                uuid_p: uuid.as_mut_ptr(),
+                perm: entry.permissions.as_repr(),
+                max_length: entry.max_len,
+                length: entry.value.len() as u16,
+                value: if entry.value.is_empty() {
+                    std::ptr::null_mut()
+                } else {
+                    entry.value.as_mut_ptr()
+                },
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let callback_key = discriminant(&GattsEvent::AttributeTableCreated {
+        status: GattStatus::Busy,
+        svc_uuid: BtUuid::uuid16(0),
+        svc_inst_id: 0,
+        handles: Vec::new(),
+    });
+
+    let rx = gatts.waiter(callback_key)?;
+
+    esp!(unsafe {
+        esp_ble_gatts_create_attr_tab(
+            table.as_ptr(),
+            gatts_interface,
+            table.len() as _,
+            service_id.id.inst_id,
+        )
+    })
+    .map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to create GATT attribute table {:?}: {:?}",
+            service_id,
+            err
+        )
+    })?;
+
+    match rx.recv_timeout(std::time::Duration::from_secs(5)) {
+        Ok(GattsEventMessage(
+            interface,
+            GattsEvent::AttributeTableCreated {
+                status,
+                svc_uuid,
+                handles,
+                ..
+            },
+            _,
+        )) => {
+            if interface != gatts_interface {
+                return Err(anyhow::anyhow!(
+                    "Received attribute table of {} while registering on {}",
+                    AppInterface::unknown(interface),
+                    app.app_interface()?
+                ));
+            }
+
+            if svc_uuid != service_id.id.uuid {
+                return Err(anyhow::anyhow!(
+                    "Received unexpected GATT service uuid: {:?}",
+                    svc_uuid
+                ));
+            }
+
+            if status != GattStatus::Ok {
+                return Err(anyhow::anyhow!(
+                    "Failed to create GATT attribute table: {:?}",
+                    status
+                ));
+            }
+
+            if handles.len() != table.len() {
+                return Err(anyhow::anyhow!(
+                    "Received {} handles for attribute table of {} entries",
+                    handles.len(),
+                    table.len()
+                ));
+            }
+
+            Ok(handles)
+        }
+        Ok(_) => Err(anyhow::anyhow!("Received unexpected GATT event")),
+        Err(_) => Err(anyhow::anyhow!("Timed out waiting for GATT event")),
+    }
+}
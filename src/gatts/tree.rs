@@ -0,0 +1,117 @@
+use std::{collections::HashMap, fmt::Write};
+
+use esp_idf_svc::bt::{BtUuid, ble::gatt::Handle};
+
+use super::{
+    GattsInner, characteristic::CharacteristicConfig, keepalive::KEEPALIVE_UUID, service::Service,
+    stream, uuid_string,
+};
+use crate::assigned_numbers;
+
+/// Registered GATT tree of every app, one attribute per line indented under its
+/// service and characteristic. Well-known UUIDs are followed by their name
+pub(crate) fn render(gatts: &GattsInner) -> anyhow::Result<String> {
+    let mut descriptors: HashMap<Handle, Vec<(Handle, BtUuid)>> = HashMap::new();
+    for (handle, attribute) in gatts.attributes.read()?.iter() {
+        if let Some(characteristic) = attribute.characteristic_handle()? {
+            descriptors
+                .entry(characteristic)
+                .or_default()
+                .push((*handle, attribute.uuid()));
+        }
+    }
+
+    let mut apps = gatts
+        .apps
+        .read()?
+        .iter()
+        .map(|(interface, app)| (*interface, app.clone()))
+        .collect::<Vec<_>>();
+    apps.sort_by_key(|(interface, _)| *interface);
+
+    let mut tree = String::new();
+    for (interface, app) in apps {
+        writeln!(tree, "{} (interface {})", app.ident(), interface)?;
+
+        let mut services = app
+            .services
+            .read()?
+            .values()
+            .map(|service| (service.get_handle().ok(), Service(service.clone())))
+            .collect::<Vec<_>>();
+        services.sort_by_key(|(handle, _)| *handle);
+
+        for (handle, service) in services {
+            let handle = match handle {
+                Some(handle) => format!("{:#06x}", handle),
+                None => String::from("------"),
+            };
+            writeln!(tree, "  {} Service {}", handle, name(&service.uuid()))?;
+
+            let mut characteristics = service
+                .0
+                .characteristics
+                .read()?
+                .iter()
+                .map(|(handle, characteristic)| (*handle, characteristic.clone()))
+                .collect::<Vec<_>>();
+            characteristics.sort_by_key(|(handle, _)| *handle);
+
+            for (handle, characteristic) in characteristics {
+                let schema = characteristic.schema()?;
+                let flags = [
+                    (schema.readable, 'R'),
+                    (schema.writable, 'W'),
+                    (schema.notify, 'N'),
+                ]
+                .iter()
+                .map(|(set, flag)| if *set { *flag } else { '-' })
+                .collect::<String>();
+
+                write!(
+                    tree,
+                    "    {:#06x} Characteristic {} [{}]",
+                    handle,
+                    name(&characteristic.uuid()),
+                    flags
+                )?;
+                match schema.name {
+                    Some(description) => writeln!(tree, " \"{}\"", description)?,
+                    None => writeln!(tree)?,
+                }
+
+                let mut characteristic_descriptors =
+                    descriptors.remove(&handle).unwrap_or_default();
+                characteristic_descriptors.sort_by_key(|(handle, _)| *handle);
+
+                for (handle, uuid) in characteristic_descriptors {
+                    writeln!(tree, "      {:#06x} Descriptor {}", handle, name(&uuid))?;
+                }
+            }
+        }
+    }
+
+    Ok(tree)
+}
+
+// UUID followed by its name when known
+fn name(uuid: &BtUuid) -> String {
+    match sig_name(uuid) {
+        Some(name) => format!("{} ({})", uuid_string(uuid), name),
+        None => uuid_string(uuid),
+    }
+}
+
+/// Name of a UUID assigned by the Bluetooth SIG or used by this crate
+fn sig_name(uuid: &BtUuid) -> Option<&'static str> {
+    let crate_uuid = <[u8; 16]>::try_from(uuid.as_bytes()).map(u128::from_le_bytes);
+
+    match crate_uuid {
+        Ok(CharacteristicConfig::JSON_MIRROR_UUID) => Some("JSON Mirror"),
+        Ok(CharacteristicConfig::COMPRESSION_UUID) => Some("Compression"),
+        Ok(KEEPALIVE_UUID) => Some("Keepalive"),
+        Ok(stream::STREAM_RX_UUID) => Some("Stream RX"),
+        Ok(stream::STREAM_TX_UUID) => Some("Stream TX"),
+        _ => assigned_numbers::uuid_name(uuid),
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    any::Any,
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{
+        Mutex, Once, OnceLock, RwLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{RecvTimeoutError, Sender, bounded, unbounded};
+
+/// How hooks are executed relative to the event dispatch thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookMode {
+    // Hook runs on the dispatch thread, overruns are only detected after it returns
+    Inline,
+    // Soft real-time mode, hook runs on a worker thread of its own and the dispatch
+    // thread waits at most `budget` from the start of the hook. Overrunning hook
+    // is cancelled: peer gets an error
+    // response and the result is discarded once the hook eventually returns.
+    // Threads can not be interrupted, so side effects of the hook still happen and
+    // are not rolled back, e.g. a write handler may update state after the peer
+    // was told the write failed. Each cancelled hook keeps its worker until it
+    // returns, hooks are refused while `MAX_CANCELLED_WORKERS` are still running
+    Bounded,
+}
+
+/// Limits applied to user hooks (read handlers, write validators, passkey callbacks).
+/// Hooks run on the event dispatch threads, so while a hook runs no other GATT or GAP
+/// event is handled and the peer waits for the ATT response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookConfig {
+    // Hooks running longer are reported, in `HookMode::Inline` hooks can not be
+    // interrupted, so the whole duration still delays following events
+    pub budget: Duration,
+
+    // Discard result of a hook which exceeded the budget and answer the peer
+    // with an error, otherwise the result is used and only a warning is logged.
+    // Always the case in `HookMode::Bounded`
+    pub reject_overrun: bool,
+
+    pub mode: HookMode,
+}
+
+impl HookConfig {
+    pub const DEFAULT: Self = Self {
+        budget: Duration::from_millis(100),
+        reject_overrun: false,
+        mode: HookMode::Inline,
+    };
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static HOOK_CONFIG: RwLock<HookConfig> = RwLock::new(HookConfig::DEFAULT);
+
+pub fn set_hook_config(config: HookConfig) -> anyhow::Result<()> {
+    *HOOK_CONFIG
+        .write()
+        .map_err(|_| anyhow::anyhow!("Failed to write hook config"))? = config;
+
+    Ok(())
+}
+
+pub fn hook_config() -> anyhow::Result<HookConfig> {
+    Ok(*HOOK_CONFIG
+        .read()
+        .map_err(|_| anyhow::anyhow!("Failed to read hook config"))?)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+// Logs once at startup that panicking hooks are not caught in this build
+pub(crate) fn warn_panic_abort() {
+    static WARNED: Once = Once::new();
+
+    if cfg!(panic = "abort") {
+        WARNED.call_once(|| {
+            log::warn!(
+                "Built with panic = \"abort\", a panicking hook aborts instead of failing its event"
+            );
+        });
+    }
+}
+
+static CANCELLED_HOOKS: AtomicU32 = AtomicU32::new(0);
+
+/// Workers of cancelled bounded hooks which may still be running, each holds
+/// its own stack, further bounded hooks are refused until one of them returns
+pub const MAX_CANCELLED_WORKERS: u32 = 2;
+
+// Workers of cancelled hooks which did not return yet
+static CANCELLED_WORKERS: AtomicU32 = AtomicU32::new(0);
+
+/// Number of hooks cancelled for exceeding the budget in `HookMode::Bounded`
+pub fn cancelled_hooks() -> u32 {
+    CANCELLED_HOOKS.load(Ordering::Relaxed)
+}
+
+/// Runs user hook, checking it against the configured budget. With `panic = "unwind"`
+/// a panicking hook is turned into an error, with `panic = "abort"`, as in the release
+/// profile of this crate, it aborts the whole firmware, see `warn_panic_abort`
+pub fn run_hook<R: Send + 'static>(
+    name: &'static str,
+    hook: impl FnOnce() -> R + Send + 'static,
+) -> anyhow::Result<R> {
+    let config = hook_config()?;
+    if config.mode == HookMode::Bounded {
+        return run_bounded(name, config.budget, hook);
+    }
+
+    let started = Instant::now();
+
+    let result = catch_unwind(AssertUnwindSafe(hook)).map_err(|payload| {
+        log::error!("Hook {} panicked: {}", name, panic_message(&*payload));
+        anyhow::anyhow!("Hook {} panicked: {}", name, panic_message(&*payload))
+    })?;
+
+    let elapsed = started.elapsed();
+    if elapsed > config.budget {
+        log::warn!(
+            "Hook {} took {:?}, exceeding budget of {:?}, move heavy work to guard::defer",
+            name,
+            elapsed,
+            config.budget
+        );
+
+        if config.reject_overrun {
+            return Err(anyhow::anyhow!(
+                "Hook {} exceeded budget of {:?}: {:?}",
+                name,
+                config.budget,
+                elapsed
+            ));
+        }
+    }
+
+    Ok(result)
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Idle workers of bounded hooks. Each hook takes a worker for itself, so it never
+// waits behind another one, workers of cancelled hooks are not returned
+static HOOK_WORKERS: Mutex<Vec<Sender<Job>>> = Mutex::new(Vec::new());
+
+// Worker thread running jobs until its sender is dropped, `on_exit` runs once
+// the last job returned
+fn spawn_worker(name: &'static str, on_exit: fn()) -> anyhow::Result<Sender<Job>> {
+    let (tx, rx) = unbounded::<Job>();
+    std::thread::Builder::new()
+        .name(name.into())
+        .stack_size(8 * 1024)
+        .spawn(move || {
+            for job in rx.iter() {
+                if let Err(payload) = catch_unwind(AssertUnwindSafe(job)) {
+                    log::error!("Job on {} panicked: {}", name, panic_message(&*payload));
+                }
+            }
+
+            on_exit();
+        })?;
+
+    Ok(tx)
+}
+
+// Takes an idle hook worker, or spawns one if all of them are busy
+fn take_worker(name: &'static str) -> anyhow::Result<Sender<Job>> {
+    let cancelled = CANCELLED_WORKERS.load(Ordering::Relaxed);
+    if cancelled >= MAX_CANCELLED_WORKERS {
+        log::error!(
+            "Hook {} refused, {} cancelled hooks are still running",
+            name,
+            cancelled
+        );
+        return Err(anyhow::anyhow!(
+            "Hook {} refused, {} cancelled hooks are still running",
+            name,
+            cancelled
+        ));
+    }
+
+    let idle = HOOK_WORKERS
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Failed to lock hook workers"))?
+        .pop();
+
+    match idle {
+        Some(worker) => Ok(worker),
+        // Hook workers only exit once their hook was cancelled
+        None => spawn_worker("ble-hooks", || {
+            CANCELLED_WORKERS.fetch_sub(1, Ordering::Relaxed);
+        }),
+    }
+}
+
+fn run_bounded<R: Send + 'static>(
+    name: &'static str,
+    budget: Duration,
+    hook: impl FnOnce() -> R + Send + 'static,
+) -> anyhow::Result<R> {
+    let (started_tx, started_rx) = bounded(1);
+    let (tx, rx) = bounded(1);
+    let job: Job = Box::new(move || {
+        let _ = started_tx.send(());
+        // Receiver is gone if the hook was cancelled
+        let _ = tx.send(catch_unwind(AssertUnwindSafe(hook)));
+    });
+
+    let worker = take_worker(name)?;
+    worker
+        .send(job)
+        .map_err(|err| anyhow::anyhow!("Failed to queue hook {}: {:?}", name, err))?;
+
+    // Budget counts from the start of the hook, not from spawning its worker
+    started_rx
+        .recv()
+        .map_err(|_| anyhow::anyhow!("Hook worker exited before running {}", name))?;
+
+    match rx.recv_timeout(budget) {
+        Ok(result) => {
+            HOOK_WORKERS
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Failed to lock hook workers"))?
+                .push(worker);
+
+            result.map_err(|payload| {
+                log::error!("Hook {} panicked: {}", name, panic_message(&*payload));
+                anyhow::anyhow!("Hook {} panicked: {}", name, panic_message(&*payload))
+            })
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            CANCELLED_HOOKS.fetch_add(1, Ordering::Relaxed);
+
+            // Worker is still busy with the cancelled hook and exits once the hook
+            // returns, as its sender is dropped here. It is counted before, so its
+            // exit never comes first
+            CANCELLED_WORKERS.fetch_add(1, Ordering::Relaxed);
+            drop(worker);
+
+            log::error!("Hook {} cancelled, exceeded budget of {:?}", name, budget);
+            Err(anyhow::anyhow!(
+                "Hook {} cancelled, exceeded budget of {:?}",
+                name,
+                budget
+            ))
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            Err(anyhow::anyhow!("Hook worker exited while running {}", name))
+        }
+    }
+}
+
+static DEFERRED: OnceLock<Sender<Job>> = OnceLock::new();
+
+fn deferred_sender() -> anyhow::Result<&'static Sender<Job>> {
+    if let Some(sender) = DEFERRED.get() {
+        return Ok(sender);
+    }
+
+    let tx = spawn_worker("ble-deferred", || {})?;
+
+    // Another thread may have won the race, its worker is used and ours exits
+    // together with the dropped sender
+    Ok(DEFERRED.get_or_init(|| tx))
+}
+
+/// Queues work to run on a separate worker thread, so a hook can return immediately
+/// and the ATT response is not delayed by e.g. flash writes or network requests
+pub fn defer(job: impl FnOnce() + Send + 'static) -> anyhow::Result<()> {
+    deferred_sender()?
+        .send(Box::new(job))
+        .map_err(|err| anyhow::anyhow!("Failed to queue deferred job: {:?}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_hook_does_not_wait_behind_slow_one() {
+        let slow = std::thread::spawn(|| {
+            run_bounded("slow", Duration::from_secs(5), || {
+                std::thread::sleep(Duration::from_millis(300))
+            })
+        });
+        std::thread::sleep(Duration::from_millis(50));
+
+        // Would time out if queued behind the slow hook
+        let fast = run_bounded("fast", Duration::from_millis(100), || 42);
+
+        assert_eq!(fast.unwrap(), 42);
+        slow.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn cancelled_hook_keeps_its_worker() {
+        let cancelled = run_bounded("cancelled", Duration::from_millis(20), || {
+            std::thread::sleep(Duration::from_millis(200))
+        });
+        assert!(cancelled.is_err());
+        assert!(cancelled_hooks() >= 1);
+
+        // Following hooks get another worker while the cancelled one still runs
+        assert_eq!(
+            run_bounded("next", Duration::from_millis(100), || 7).unwrap(),
+            7
+        );
+    }
+}
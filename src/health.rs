@@ -0,0 +1,148 @@
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+
+use crate::lock::{self, OrderedRwLock};
+
+// Handler durations kept for percentiles, oldest are dropped first
+const HANDLER_SAMPLES: usize = 128;
+
+/// Counters of one event dispatcher, i.e. the stack callback forwarding events
+/// to waiting calls and handler threads
+pub(crate) struct DispatcherHealth {
+    dispatched: AtomicU64,
+    // Channel of the receiver was full or its receiver gone, e.g. a waiter which
+    // already got its event or timed out. Such events are buffered briefly for
+    // the next waiter of their kind
+    dropped: AtomicU64,
+    // No receiver registered for the event, buffered as well
+    unhandled: AtomicU64,
+    last_queue_depth: AtomicUsize,
+    max_queue_depth: AtomicUsize,
+    handler_times: OrderedRwLock<lock::Health, VecDeque<Duration>>,
+}
+
+impl DispatcherHealth {
+    pub fn new() -> Self {
+        Self {
+            dispatched: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            unhandled: AtomicU64::new(0),
+            last_queue_depth: AtomicUsize::new(0),
+            max_queue_depth: AtomicUsize::new(0),
+            handler_times: OrderedRwLock::new(VecDeque::with_capacity(HANDLER_SAMPLES)),
+        }
+    }
+
+    /// Forwards the event without blocking the stack callback, so a full channel
+    /// does not stall every later event. Returns the event if it was not delivered
+    pub fn dispatch<T>(&self, sender: Option<&Sender<T>>, event: T) -> Option<T> {
+        let Some(sender) = sender else {
+            self.unhandled.fetch_add(1, Ordering::Relaxed);
+            return Some(event);
+        };
+
+        match sender.try_send(event) {
+            Ok(()) => {
+                let depth = sender.len();
+                self.dispatched.fetch_add(1, Ordering::Relaxed);
+                self.last_queue_depth.store(depth, Ordering::Relaxed);
+                self.max_queue_depth.fetch_max(depth, Ordering::Relaxed);
+                None
+            }
+            Err(err) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Some(err.into_inner())
+            }
+        }
+    }
+
+    /// Runs a handler of dispatched events and records how long it took
+    pub fn time<R>(&self, handler: impl FnOnce() -> R) -> R {
+        let started = Instant::now();
+        let result = handler();
+        let elapsed = started.elapsed();
+
+        match self.handler_times.write() {
+            Ok(mut times) => {
+                if times.len() == HANDLER_SAMPLES {
+                    times.pop_front();
+                }
+                times.push_back(elapsed);
+            }
+            Err(err) => log::error!("Failed to record handler time: {:?}", err),
+        }
+
+        result
+    }
+
+    pub fn report(&self) -> anyhow::Result<DispatcherReport> {
+        let mut times = self
+            .handler_times
+            .read()?
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        times.sort_unstable();
+
+        Ok(DispatcherReport {
+            dispatched: self.dispatched.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            unhandled: self.unhandled.load(Ordering::Relaxed),
+            last_queue_depth: self.last_queue_depth.load(Ordering::Relaxed),
+            max_queue_depth: self.max_queue_depth.load(Ordering::Relaxed),
+            handler_times: HandlerTimes::from_sorted(&times),
+        })
+    }
+}
+
+/// Percentiles of the latest handler durations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerTimes {
+    pub samples: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl HandlerTimes {
+    fn from_sorted(times: &[Duration]) -> Option<Self> {
+        let max = *times.last()?;
+        let percentile = |percent: usize| times[(times.len() - 1) * percent / 100];
+
+        Some(Self {
+            samples: times.len(),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DispatcherReport {
+    pub dispatched: u64,
+    pub dropped: u64,
+    pub unhandled: u64,
+
+    // Events queued in the receiving channel right after the latest dispatch,
+    // a growing depth means the handler can not keep up
+    pub last_queue_depth: usize,
+    pub max_queue_depth: usize,
+
+    // None until a handler ran
+    pub handler_times: Option<HandlerTimes>,
+}
+
+/// Snapshot returned by `Ble::health`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Health {
+    pub gap: DispatcherReport,
+    pub gatts: DispatcherReport,
+}
@@ -1,6 +1,15 @@
+pub mod assigned_numbers;
 pub mod ble;
+pub mod controller;
 pub mod gap;
 pub mod gatts;
+pub mod guard;
+pub mod health;
+pub mod lock;
+pub mod power;
+pub mod suspend;
+mod trace;
+mod waiters;
 
 pub use esp_idf_svc as svc;
 
@@ -0,0 +1,304 @@
+use std::{
+    cell::Cell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Position of a shared map in the global lock order. Locks must be taken in
+/// increasing `LEVEL`, e.g. connections may be locked while holding apps, never
+/// the other way around
+pub trait LockLevel: Send + Sync + 'static {
+    const LEVEL: u8;
+    const NAME: &'static str;
+}
+
+macro_rules! lock_levels {
+    ($($(#[$meta:meta])* $name:ident = $level:expr),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            pub struct $name;
+
+            impl LockLevel for $name {
+                const LEVEL: u8 = $level;
+                const NAME: &'static str = stringify!($name);
+            }
+        )*
+
+        fn level_name(level: u8) -> &'static str {
+            match level {
+                $($level => stringify!($name),)*
+                _ => "Unknown",
+            }
+        }
+    };
+}
+
+lock_levels! {
+    /// `GattsInner::apps`
+    Apps = 0,
+    /// `AppInner::services`
+    Services = 1,
+    /// `ServiceInner::characteristics`
+    Characteristics = 2,
+    /// `GattsInner::write_buffer`
+    WriteBuffer = 3,
+    /// `GattsInner::held_responses`
+    HeldResponses = 4,
+    /// `GattsInner::attributes`
+    Attributes = 5,
+    /// `AppInner::connections`
+    Connections = 6,
+    /// `GattsInner::connection_routes`
+    ConnectionRoutes = 7,
+    /// `GattsInner::filtered_connections`
+    FilteredConnections = 8,
+    /// `GattsInner::congested_connections`
+    CongestedConnections = 9,
+    /// `GattsInner::subscriptions`
+    Subscriptions = 10,
+    /// `GattsInner::compressing_peers`
+    CompressingPeers = 11,
+    /// `Sessions::sessions`
+    Sessions = 12,
+    /// `ConnectionMetrics::state`
+    Metrics = 13,
+    /// `GattsInner::gatts_events`, taken last as waiters register themselves
+    /// right before calling into the stack
+    Events = 14,
+    /// `DispatcherHealth::handler_times`, taken by handler threads after
+    /// an event is handled
+    Health = 15,
+}
+
+thread_local! {
+    // Bit per level currently held by this thread
+    static HELD: Cell<u32> = const { Cell::new(0) };
+}
+
+fn acquire<L: LockLevel>() -> anyhow::Result<()> {
+    HELD.with(|held| {
+        let later = held.get() >> L::LEVEL;
+        if later != 0 {
+            let holding = (0..u32::BITS as u8)
+                .filter(|level| held.get() & (1 << level) != 0)
+                .map(level_name)
+                .collect::<Vec<_>>();
+
+            return Err(anyhow::anyhow!(
+                "Lock order violation: acquiring {} while holding {:?}",
+                L::NAME,
+                holding
+            ));
+        }
+
+        held.set(held.get() | (1 << L::LEVEL));
+        Ok(())
+    })
+}
+
+fn release<L: LockLevel>() {
+    HELD.with(|held| held.set(held.get() & !(1 << L::LEVEL)));
+}
+
+/// `RwLock` with its level in the lock order encoded in the type. Taking it out of
+/// order (or twice on the same thread) returns an error instead of risking a deadlock
+pub struct OrderedRwLock<L: LockLevel, T> {
+    lock: RwLock<T>,
+    _level: PhantomData<L>,
+}
+
+impl<L: LockLevel, T> OrderedRwLock<L, T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            lock: RwLock::new(value),
+            _level: PhantomData,
+        }
+    }
+
+    pub fn read(&self) -> anyhow::Result<OrderedReadGuard<'_, L, T>> {
+        acquire::<L>()?;
+
+        match self.lock.read() {
+            Ok(guard) => Ok(OrderedReadGuard {
+                guard,
+                _level: PhantomData,
+            }),
+            Err(_) => {
+                release::<L>();
+                Err(anyhow::anyhow!(
+                    "Failed to acquire read lock on {}",
+                    L::NAME
+                ))
+            }
+        }
+    }
+
+    pub fn write(&self) -> anyhow::Result<OrderedWriteGuard<'_, L, T>> {
+        acquire::<L>()?;
+
+        match self.lock.write() {
+            Ok(guard) => Ok(OrderedWriteGuard {
+                guard,
+                _level: PhantomData,
+            }),
+            Err(_) => {
+                release::<L>();
+                Err(anyhow::anyhow!(
+                    "Failed to acquire write lock on {}",
+                    L::NAME
+                ))
+            }
+        }
+    }
+}
+
+impl<L: LockLevel, T: Default> Default for OrderedRwLock<L, T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+pub struct OrderedReadGuard<'a, L: LockLevel, T> {
+    guard: RwLockReadGuard<'a, T>,
+    _level: PhantomData<L>,
+}
+
+impl<L: LockLevel, T> Deref for OrderedReadGuard<'_, L, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<L: LockLevel, T> Drop for OrderedReadGuard<'_, L, T> {
+    fn drop(&mut self) {
+        release::<L>();
+    }
+}
+
+pub struct OrderedWriteGuard<'a, L: LockLevel, T> {
+    guard: RwLockWriteGuard<'a, T>,
+    _level: PhantomData<L>,
+}
+
+impl<L: LockLevel, T> Deref for OrderedWriteGuard<'_, L, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<L: LockLevel, T> DerefMut for OrderedWriteGuard<'_, L, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<L: LockLevel, T> Drop for OrderedWriteGuard<'_, L, T> {
+    fn drop(&mut self) {
+        release::<L>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn nested_in_order() {
+        let apps = OrderedRwLock::<Apps, _>::new(1);
+        let connections = OrderedRwLock::<Connections, _>::new(2);
+        let events = OrderedRwLock::<Events, _>::new(3);
+
+        // Connections of an app while holding apps, then a waiter registered last
+        let apps = apps.read().unwrap();
+        let mut connections = connections.write().unwrap();
+        *connections += *apps;
+        let events = events.read().unwrap();
+
+        assert_eq!((*apps, *connections, *events), (1, 3, 3));
+    }
+
+    #[test]
+    fn out_of_order_fails() {
+        let write_buffer = OrderedRwLock::<WriteBuffer, _>::new(());
+        let attributes = OrderedRwLock::<Attributes, _>::new(());
+
+        let _attributes = attributes.read().unwrap();
+        let err = write_buffer.write().err().unwrap();
+
+        assert!(err.to_string().contains("acquiring WriteBuffer"));
+        assert!(err.to_string().contains("Attributes"));
+    }
+
+    #[test]
+    fn same_level_twice_fails() {
+        let sessions = OrderedRwLock::<Sessions, _>::new(());
+        let other = OrderedRwLock::<Sessions, _>::new(());
+
+        let _sessions = sessions.read().unwrap();
+        assert!(sessions.read().is_err());
+        assert!(other.write().is_err());
+    }
+
+    #[test]
+    fn connection_maps_nest() {
+        let connections = OrderedRwLock::<Connections, _>::new(());
+        let subscriptions = OrderedRwLock::<Subscriptions, _>::new(());
+        let compressing_peers = OrderedRwLock::<CompressingPeers, _>::new(());
+
+        // Per-peer maps of a connection may be taken while the connection is held
+        let _connections = connections.read().unwrap();
+        let _subscriptions = subscriptions.write().unwrap();
+        let _compressing_peers = compressing_peers.write().unwrap();
+    }
+
+    #[test]
+    fn released_on_drop() {
+        let services = OrderedRwLock::<Services, _>::new(());
+        let metrics = OrderedRwLock::<Metrics, _>::new(());
+
+        drop(metrics.write().unwrap());
+        // Lower level is fine once the higher one is released
+        let _services = services.write().unwrap();
+        let _metrics = metrics.read().unwrap();
+    }
+
+    #[test]
+    fn failed_acquire_is_released() {
+        let characteristics = OrderedRwLock::<Characteristics, _>::new(());
+        let health = Arc::new(OrderedRwLock::<Health, _>::new(()));
+
+        let poisoned = health.clone();
+        std::thread::spawn(move || {
+            let _health = poisoned.write().unwrap();
+            panic!("poisoning the lock");
+        })
+        .join()
+        .unwrap_err();
+
+        assert!(health.read().is_err());
+        // The failed acquisition does not count as held
+        let _characteristics = characteristics.read().unwrap();
+    }
+
+    #[test]
+    fn levels_are_per_thread() {
+        let apps = Arc::new(OrderedRwLock::<Apps, _>::new(()));
+        let events = OrderedRwLock::<Events, _>::new(());
+
+        let _events = events.read().unwrap();
+        let other = apps.clone();
+        std::thread::spawn(move || other.read().map(|_| ()))
+            .join()
+            .unwrap()
+            .unwrap();
+
+        assert!(apps.read().is_err());
+    }
+}
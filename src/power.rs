@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use esp_idf_svc::sys::{
+    ESP_ERR_NOT_SUPPORTED, esp, esp_bt_sleep_disable, esp_bt_sleep_enable, esp_light_sleep_start,
+    esp_pm_config_t, esp_pm_configure, esp_sleep_disable_wakeup_source,
+    esp_sleep_enable_gpio_wakeup, esp_sleep_enable_timer_wakeup, esp_sleep_get_wakeup_cause,
+    esp_sleep_source_t_ESP_SLEEP_WAKEUP_ALL, esp_sleep_source_t_ESP_SLEEP_WAKEUP_BT,
+    esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO, esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER,
+    gpio_int_type_t, gpio_int_type_t_GPIO_INTR_HIGH_LEVEL, gpio_int_type_t_GPIO_INTR_LOW_LEVEL,
+    gpio_wakeup_disable, gpio_wakeup_enable,
+};
+
+/// Sleep behaviour of the controller and the CPU while BLE is running.
+///
+/// Sleep modes need the controller to keep time while the main clock is off, which
+/// has to be enabled in sdkconfig:
+///
+/// ```text
+/// CONFIG_BT_CTRL_MODEM_SLEEP=y
+/// CONFIG_BT_CTRL_MODEM_SLEEP_MODE_1=y
+/// # External 32 kHz crystal keeps the best timing accuracy
+/// CONFIG_BT_CTRL_LPCLK_SEL_EXT_32K_XTAL=y
+/// # or main XTAL, which then stays powered during light sleep
+/// # CONFIG_BT_CTRL_LPCLK_SEL_MAIN_XTAL=y
+/// # CONFIG_BT_CTRL_MAIN_XTAL_PU_DURING_LIGHT_SLEEP=y
+/// ```
+///
+/// `LightSleep` additionally needs power management and tickless idle, so FreeRTOS
+/// can sleep whenever all tasks are blocked:
+///
+/// ```text
+/// CONFIG_PM_ENABLE=y
+/// CONFIG_FREERTOS_USE_TICKLESS_IDLE=y
+/// ```
+///
+/// While asleep, timers and tasks run only on the next wake up, so advertising
+/// and connection intervals bound the latency of everything else
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerMode {
+    // Controller and CPU never sleep, lowest latency and highest consumption
+    #[default]
+    Performance,
+    // Controller sleeps between advertising and connection events
+    ModemSleep,
+    // Modem sleep and automatic light sleep of the CPU whenever it is idle,
+    // CPU frequency scales between the given bounds while awake
+    LightSleep {
+        max_freq_mhz: i32,
+        min_freq_mhz: i32,
+    },
+}
+
+impl PowerMode {
+    /// Applies the mode, called by `Ble::with_config` once the controller is enabled
+    pub fn apply(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Performance => esp!(unsafe { esp_bt_sleep_disable() })
+                .map_err(|err| anyhow::anyhow!("Failed to disable modem sleep: {:?}", err)),
+            Self::ModemSleep => Self::enable_modem_sleep(),
+            Self::LightSleep {
+                max_freq_mhz,
+                min_freq_mhz,
+            } => {
+                Self::enable_modem_sleep()?;
+
+                let config = esp_pm_config_t {
+                    max_freq_mhz: *max_freq_mhz,
+                    min_freq_mhz: *min_freq_mhz,
+                    light_sleep_enable: true,
+                };
+
+                esp!(unsafe { esp_pm_configure(&config as *const _ as *const _) }).map_err(|err| {
+                    match err.code() {
+                        ESP_ERR_NOT_SUPPORTED => anyhow::anyhow!(
+                            "Light sleep is not supported, enable CONFIG_PM_ENABLE \
+                             and CONFIG_FREERTOS_USE_TICKLESS_IDLE"
+                        ),
+                        _ => anyhow::anyhow!("Failed to configure light sleep: {:?}", err),
+                    }
+                })
+            }
+        }
+    }
+
+    fn enable_modem_sleep() -> anyhow::Result<()> {
+        esp!(unsafe { esp_bt_sleep_enable() }).map_err(|err| match err.code() {
+            ESP_ERR_NOT_SUPPORTED => {
+                anyhow::anyhow!("Modem sleep is not supported, enable CONFIG_BT_CTRL_MODEM_SLEEP")
+            }
+            _ => anyhow::anyhow!("Failed to enable modem sleep: {:?}", err),
+        })
+    }
+}
+
+/// Level of the wake up GPIO of `WakeConfig` which wakes the chip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeLevel {
+    Low,
+    High,
+}
+
+impl WakeLevel {
+    fn raw(self) -> gpio_int_type_t {
+        match self {
+            Self::Low => gpio_int_type_t_GPIO_INTR_LOW_LEVEL,
+            Self::High => gpio_int_type_t_GPIO_INTR_HIGH_LEVEL,
+        }
+    }
+}
+
+/// Wake up sources of `Ble::light_sleep`, at least one has to be set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WakeConfig {
+    // Keeps BLE running, the controller wakes the chip for connection requests,
+    // scan requests and connection events, so peers stay connected. Without it
+    // advertising and CCCDs are suspended as for deep sleep and connections time out.
+    // Needs modem sleep, see `PowerMode`
+    pub bluetooth: bool,
+    // GPIO which wakes the chip while at the given level, e.g. a button or the
+    // interrupt line of a sensor
+    pub gpio: Option<(i32, WakeLevel)>,
+    // Longest time to sleep
+    pub timeout: Option<Duration>,
+}
+
+/// What ended light sleep, returned by `Ble::light_sleep`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeCause {
+    Bluetooth,
+    Gpio,
+    Timer,
+    // Any other source of `esp_sleep_source_t`, e.g. one enabled by the application
+    Other(u32),
+}
+
+/// Enters light sleep until one of the sources wakes the chip. RAM is retained,
+/// so the GATT server continues where it stopped. Sources are disabled again
+/// before returning
+pub(crate) fn light_sleep(config: &WakeConfig) -> anyhow::Result<WakeCause> {
+    if !config.bluetooth && config.gpio.is_none() && config.timeout.is_none() {
+        return Err(anyhow::anyhow!(
+            "Light sleep needs at least one wake up source"
+        ));
+    }
+
+    let result = enable_wakeup(config).and_then(|_| {
+        esp!(unsafe { esp_light_sleep_start() })
+            .map_err(|err| anyhow::anyhow!("Failed to enter light sleep: {:?}", err))
+    });
+
+    if let Some((gpio, _)) = config.gpio {
+        esp!(unsafe { gpio_wakeup_disable(gpio) })
+            .map_err(|err| anyhow::anyhow!("Failed to disable GPIO wake up: {:?}", err))?;
+    }
+    esp!(unsafe { esp_sleep_disable_wakeup_source(esp_sleep_source_t_ESP_SLEEP_WAKEUP_ALL) })
+        .map_err(|err| anyhow::anyhow!("Failed to disable wake up sources: {:?}", err))?;
+    result?;
+
+    let cause = unsafe { esp_sleep_get_wakeup_cause() };
+    Ok(match cause {
+        cause if cause == esp_sleep_source_t_ESP_SLEEP_WAKEUP_BT => WakeCause::Bluetooth,
+        cause if cause == esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO => WakeCause::Gpio,
+        cause if cause == esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeCause::Timer,
+        cause => WakeCause::Other(cause),
+    })
+}
+
+fn enable_wakeup(config: &WakeConfig) -> anyhow::Result<()> {
+    if config.bluetooth {
+        enable_bt_wakeup()?;
+    }
+
+    if let Some((gpio, level)) = config.gpio {
+        esp!(unsafe { gpio_wakeup_enable(gpio, level.raw()) }).map_err(|err| {
+            anyhow::anyhow!("Failed to enable wake up on GPIO {}: {:?}", gpio, err)
+        })?;
+        esp!(unsafe { esp_sleep_enable_gpio_wakeup() })
+            .map_err(|err| anyhow::anyhow!("Failed to enable GPIO wake up: {:?}", err))?;
+    }
+
+    if let Some(timeout) = config.timeout {
+        let micros = timeout.as_micros().min(u64::MAX as u128) as u64;
+        esp!(unsafe { esp_sleep_enable_timer_wakeup(micros) })
+            .map_err(|err| anyhow::anyhow!("Failed to enable timer wake up: {:?}", err))?;
+    }
+
+    Ok(())
+}
+
+// Chips without a BT wake up source (ESP32) wake on the timer the controller
+// programs for its next event while in modem sleep
+#[cfg(esp_idf_soc_pm_support_bt_wakeup)]
+fn enable_bt_wakeup() -> anyhow::Result<()> {
+    esp!(unsafe { esp_idf_svc::sys::esp_sleep_enable_bt_wakeup() })
+        .map_err(|err| anyhow::anyhow!("Failed to enable Bluetooth wake up: {:?}", err))
+}
+
+#[cfg(not(esp_idf_soc_pm_support_bt_wakeup))]
+fn enable_bt_wakeup() -> anyhow::Result<()> {
+    Ok(())
+}
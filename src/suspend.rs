@@ -0,0 +1,101 @@
+use esp_idf_svc::bt::ble::gatt::Handle;
+
+/// NVS key of the state saved by `Ble::suspend`
+pub const NVS_KEY: &str = "suspend";
+
+const VERSION: u8 = 2;
+
+/// CCCD value a bonded peer wrote, applied again once the peer reconnects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerSubscription {
+    pub address: [u8; 6],
+    pub handle: Handle,
+    pub cccd: u16,
+}
+
+/// Minimal state needed to continue after deep sleep. Bonds are not part of it,
+/// the stack keeps them in NVS on its own
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuspendState {
+    pub advertising: bool,
+    pub throttle_step: Option<u8>,
+
+    // Subscriptions of bonded peers, other peers subscribe again after reconnecting
+    pub subscriptions: Vec<PeerSubscription>,
+}
+
+impl SuspendState {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![
+            VERSION,
+            self.advertising as u8,
+            self.throttle_step.map_or(u8::MAX, |step| step),
+        ];
+
+        bytes.extend((self.subscriptions.len() as u16).to_le_bytes());
+        for subscription in &self.subscriptions {
+            bytes.extend(subscription.address);
+            bytes.extend(subscription.handle.to_le_bytes());
+            bytes.extend(subscription.cccd.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let truncated = || anyhow::anyhow!("Suspend state is truncated");
+
+        let [
+            version,
+            advertising,
+            throttle_step,
+            count_lo,
+            count_hi,
+            rest @ ..,
+        ] = bytes
+        else {
+            return Err(truncated());
+        };
+
+        if *version != VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported suspend state version: {}",
+                version
+            ));
+        }
+
+        let mut subscriptions = Vec::new();
+        let mut rest = rest;
+        for _ in 0..u16::from_le_bytes([*count_lo, *count_hi]) {
+            let [
+                a0,
+                a1,
+                a2,
+                a3,
+                a4,
+                a5,
+                handle_lo,
+                handle_hi,
+                cccd_lo,
+                cccd_hi,
+                tail @ ..,
+            ] = rest
+            else {
+                return Err(truncated());
+            };
+
+            subscriptions.push(PeerSubscription {
+                address: [*a0, *a1, *a2, *a3, *a4, *a5],
+                handle: Handle::from_le_bytes([*handle_lo, *handle_hi]),
+                cccd: u16::from_le_bytes([*cccd_lo, *cccd_hi]),
+            });
+            rest = tail;
+        }
+
+        Ok(Self {
+            advertising: *advertising != 0,
+            throttle_step: (*throttle_step != u8::MAX).then_some(*throttle_step),
+            subscriptions,
+        })
+    }
+}
@@ -0,0 +1,43 @@
+//! Structured spans of stack operations, compiled in with the `tracing` feature
+//! only. Every span carries a `duration_us` field recorded once the operation
+//! finishes, so subscribers without timing support still see how long it took.
+
+#[cfg(feature = "tracing")]
+pub(crate) struct OperationSpan {
+    span: tracing::span::EnteredSpan,
+    started: std::time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl OperationSpan {
+    pub fn new(span: tracing::Span) -> Self {
+        Self {
+            span: span.entered(),
+            started: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for OperationSpan {
+    fn drop(&mut self) {
+        self.span
+            .record("duration_us", self.started.elapsed().as_micros() as u64);
+    }
+}
+
+/// Enters a span until the end of the enclosing block, e.g.
+/// `trace::span!("gatts.read", conn_id, handle)`. Expands to nothing without
+/// the `tracing` feature, so fields are not evaluated then
+macro_rules! span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = $crate::trace::OperationSpan::new(tracing::info_span!(
+            $name,
+            duration_us = tracing::field::Empty
+            $(, $($fields)*)?
+        ));
+    };
+}
+
+pub(crate) use span;
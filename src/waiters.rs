@@ -0,0 +1,142 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+
+use crate::lock::{LockLevel, OrderedRwLock};
+
+// Events kept while no waiter takes them, oldest are dropped first
+const MAX_PENDING: usize = 8;
+// Pending events older than this are stale, their waiter most likely timed out
+const PENDING_TTL: Duration = Duration::from_millis(500);
+
+struct Pending<K, M> {
+    key: K,
+    message: M,
+    received: Instant,
+}
+
+/// Senders of waiting calls and handler threads keyed by event kind. Events which
+/// could not be delivered, because no sender is registered yet or the registered
+/// one is gone or full, are buffered for a short while and replayed to the next
+/// sender registered for their kind
+pub(crate) struct EventWaiters<K, M> {
+    senders: HashMap<K, Sender<M>>,
+    pending: VecDeque<Pending<K, M>>,
+}
+
+impl<K: Eq + Hash + Copy, M> EventWaiters<K, M> {
+    pub fn get(&self, key: &K) -> Option<&Sender<M>> {
+        self.senders.get(key)
+    }
+
+    /// Registers sender of the given kind, replacing the previous one, and replays
+    /// pending events of that kind to it
+    pub fn insert(&mut self, key: K, sender: Sender<M>) -> Option<Sender<M>> {
+        self.expire();
+
+        let (replay, pending) = self
+            .pending
+            .drain(..)
+            .partition::<VecDeque<_>, _>(|pending| pending.key == key);
+        self.pending = pending;
+
+        for pending in replay {
+            if sender.try_send(pending.message).is_err() {
+                log::warn!("Failed to replay pending event, waiter is full");
+            }
+        }
+
+        self.senders.insert(key, sender)
+    }
+
+    /// Removes sender of the given kind, unless it was replaced by another one since
+    pub fn remove(&mut self, key: &K, sender: &Sender<M>) {
+        if self
+            .senders
+            .get(key)
+            .is_some_and(|registered| registered.same_channel(sender))
+        {
+            self.senders.remove(key);
+        }
+    }
+
+    pub fn buffer(&mut self, key: K, message: M) {
+        self.expire();
+
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+            log::warn!("Pending events buffer is full, dropping the oldest event");
+        }
+
+        self.pending.push_back(Pending {
+            key,
+            message,
+            received: Instant::now(),
+        });
+    }
+
+    fn expire(&mut self) {
+        let before = self.pending.len();
+        self.pending
+            .retain(|pending| pending.received.elapsed() < PENDING_TTL);
+
+        if self.pending.len() != before {
+            log::warn!(
+                "Dropped {} pending events without a waiter",
+                before - self.pending.len()
+            );
+        }
+    }
+}
+
+impl<K, M> Default for EventWaiters<K, M> {
+    fn default() -> Self {
+        Self {
+            senders: HashMap::new(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+/// One-shot waiter for events of a single kind, deregistered once dropped so later
+/// events of that kind are not sent to a dead channel
+pub(crate) struct Waiter<'a, L: LockLevel, K: Eq + Hash + Copy, M> {
+    waiters: &'a OrderedRwLock<L, EventWaiters<K, M>>,
+    key: K,
+    sender: Sender<M>,
+    receiver: Receiver<M>,
+}
+
+impl<'a, L: LockLevel, K: Eq + Hash + Copy, M> Waiter<'a, L, K, M> {
+    pub fn register(
+        waiters: &'a OrderedRwLock<L, EventWaiters<K, M>>,
+        key: K,
+    ) -> anyhow::Result<Self> {
+        let (sender, receiver) = unbounded();
+        waiters.write()?.insert(key, sender.clone());
+
+        Ok(Self {
+            waiters,
+            key,
+            sender,
+            receiver,
+        })
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<M, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+impl<L: LockLevel, K: Eq + Hash + Copy, M> Drop for Waiter<'_, L, K, M> {
+    fn drop(&mut self) {
+        match self.waiters.write() {
+            Ok(mut waiters) => waiters.remove(&self.key, &self.sender),
+            Err(err) => log::error!("Failed to deregister event waiter: {:?}", err),
+        }
+    }
+}